@@ -6,7 +6,7 @@ use std::io;
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use futures::{future, StreamExt};
-use keylogger::{find_keyboards, KeyEventCause, KeyboardDevice, KeyloggerError};
+use keylogger::{find_keyboards, KeyboardDevice, KeyloggerError};
 
 struct Beeper {
     keyboard: KeyboardDevice,
@@ -27,20 +27,14 @@ impl Beeper {
 impl Beeper {
     async fn beep_on_keystroke(mut self) {
         let config: cpal::SupportedStreamConfig = self.cpal.default_output_config().unwrap();
-        while let Some(events) = self.keyboard.next().await {
-            println!(
-                "[{} @ {}]: ev={:?}",
-                self.keyboard.name(),
-                self.keyboard.path().display(),
-                events
-            );
-
-            for e in events {
-                // Only handle key presses
-                if e.cause == KeyEventCause::Release {
-                    continue;
-                }
+        let name = self.keyboard.name().to_owned();
+        let path = self.keyboard.path().to_owned();
+        let mut presses = self.keyboard.presses();
+
+        while let Some(ev) = presses.next().await {
+            println!("[{} @ {}]: ev={:?}", name, path.display(), ev);
 
+            if let Ok(e) = ev {
                 match config.sample_format() {
                     cpal::SampleFormat::F32 => {
                         run::<f32>(&self.cpal, &config.clone().into(), e.code as u16)
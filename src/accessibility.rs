@@ -0,0 +1,401 @@
+//! Tag the captured [`KeyEvent`]s with the kind of widget they were typed into.
+//!
+//! A [`FocusedWidget`] tracks the role of the widget that has the keyboard focus (a text field, a
+//! terminal, a web page...), which is set manually, or (with the `atspi` feature) from the focus
+//! changes reported by the AT-SPI accessibility bus (see `FocusedWidget::watch`). The events of
+//! a stream are tagged with it using [`with_accessible_context`], and a [`RedactionPolicy`]
+//! decides which of the tagged events are kept, redacted or dropped, depending on the widget:
+//!
+//! ```no_run
+//! # #[cfg(feature = "atspi")]
+//! # mod example {
+//! use futures::StreamExt;
+//! use keylogger::accessibility::{
+//!     with_accessible_context, FocusedWidget, Redaction, RedactionPolicy, WidgetRole,
+//! };
+//! use keylogger::{find_keyboards, KeyloggerError};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let focus = FocusedWidget::new();
+//!
+//!     tokio::spawn(focus.clone().watch());
+//!
+//!     // Only keep what is typed into terminals as is
+//!     let policy = RedactionPolicy::new()
+//!         .rule(WidgetRole::TextField, Redaction::Redact)
+//!         .rule(WidgetRole::Browser, Redaction::Redact);
+//!     let keyboard = find_keyboards()?.remove(0);
+//!     let mut events = policy.apply(with_accessible_context(keyboard, focus));
+//!
+//!     while let Some(ev) = events.next().await {
+//!         let (ev, role) = ev?;
+//!
+//!         println!("{:?} in {:?}", ev.code, role);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! # }
+//! ```
+//!
+//! Like the [`window`](crate::window) module, the events are tagged with the widget that has the
+//! focus when they're received, and the focus changes are reported with a short delay, so the
+//! first keystrokes typed into a widget may be attributed to the previous one.
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures::{ready, Stream};
+use pin_project::pin_project;
+
+use crate::filters::redact;
+use crate::keyboard::KeyEvent;
+use crate::KeyloggerResult;
+
+/// The kind of widget that has the keyboard focus, according to its accessible role.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WidgetRole {
+    /// A text field or a text editor.
+    TextField,
+    /// A password field.
+    PasswordField,
+    /// A terminal emulator.
+    Terminal,
+    /// A web page (or a document shown by a browser).
+    Browser,
+    /// Any other widget, like a button or a list.
+    Other,
+}
+
+#[cfg(feature = "atspi")]
+impl From<atspi::Role> for WidgetRole {
+    fn from(role: atspi::Role) -> Self {
+        use atspi::Role;
+
+        match role {
+            Role::Entry
+            | Role::Text
+            | Role::Paragraph
+            | Role::Editbar
+            | Role::SpinButton
+            | Role::DocumentText => Self::TextField,
+            Role::PasswordText => Self::PasswordField,
+            Role::Terminal => Self::Terminal,
+            Role::DocumentWeb | Role::DocumentFrame | Role::Link => Self::Browser,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Tracks the role of the widget that has the keyboard focus.
+///
+/// Cloning a `FocusedWidget` is cheap: the clones share the same state.
+#[derive(Clone, Debug, Default)]
+pub struct FocusedWidget {
+    role: Arc<Mutex<Option<WidgetRole>>>,
+}
+
+impl FocusedWidget {
+    /// Create a tracker that doesn't know which widget has the focus.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the role of the widget that has the focus (`None` if it's unknown, or no widget has
+    /// the focus).
+    pub fn set_role(&self, role: Option<WidgetRole>) {
+        *self.role.lock().unwrap() = role;
+    }
+
+    /// The role of the widget that has the focus, if known.
+    pub fn role(&self) -> Option<WidgetRole> {
+        *self.role.lock().unwrap()
+    }
+
+    /// Update the role of the focused widget whenever the focus changes.
+    ///
+    /// The focus changes are reported by the AT-SPI accessibility bus, so this only knows the
+    /// widgets of the applications that support it (GTK, Qt, Firefox, Chromium...), and only works
+    /// if the process can connect to the session bus of the user. This only returns if the
+    /// connection to the accessibility bus fails.
+    #[cfg(feature = "atspi")]
+    pub async fn watch(self) -> KeyloggerResult<()> {
+        watch_focus(|role| self.set_role(role.map(WidgetRole::from))).await
+    }
+}
+
+/// Call `on_change` with the accessible role of the object that has the focus whenever the focus
+/// changes (`None` if no object has it, or its role can't be queried).
+#[cfg(feature = "atspi")]
+pub(crate) async fn watch_focus(
+    mut on_change: impl FnMut(Option<atspi::Role>),
+) -> KeyloggerResult<()> {
+    use atspi::events::object::StateChangedEvent;
+    use atspi::proxy::accessible::ObjectRefExt;
+    use atspi::{AccessibilityConnection, State};
+    use futures::StreamExt;
+
+    use crate::error::KeyloggerError;
+
+    let a11y_err = |e: &dyn std::error::Error| KeyloggerError::Accessibility(e.to_string());
+
+    let a11y = AccessibilityConnection::new()
+        .await
+        .map_err(|e| a11y_err(&e))?;
+
+    a11y.register_event::<StateChangedEvent>()
+        .await
+        .map_err(|e| a11y_err(&e))?;
+
+    let events = a11y.event_stream();
+    futures::pin_mut!(events);
+
+    // The object that has the focus, if any
+    let mut focused = None;
+
+    while let Some(ev) = events.next().await {
+        let Ok(ev) = StateChangedEvent::try_from(ev.map_err(|e| a11y_err(&e))?) else {
+            continue;
+        };
+
+        if ev.state != State::Focused {
+            continue;
+        }
+
+        if !ev.enabled {
+            if focused.as_ref() == Some(&ev.item) {
+                focused = None;
+                on_change(None);
+            }
+        } else {
+            // An object that can't be queried is most likely gone, so it's not the one that's
+            // being typed into
+            let role = match ev.item.as_accessible_proxy(a11y.connection()).await {
+                Ok(proxy) => proxy.get_role().await.ok(),
+                Err(_) => None,
+            };
+
+            focused = Some(ev.item);
+            on_change(role);
+        }
+    }
+
+    Ok(())
+}
+
+/// Tag each event of the stream with the role of the widget that had the keyboard focus when the
+/// event was received.
+pub fn with_accessible_context<S>(events: S, focus: FocusedWidget) -> WithAccessibleContext<S>
+where
+    S: Stream<Item = KeyloggerResult<KeyEvent>>,
+{
+    WithAccessibleContext { events, focus }
+}
+
+/// A stream of events tagged with the role of the focused widget (see
+/// [`with_accessible_context`]).
+#[pin_project]
+pub struct WithAccessibleContext<S> {
+    #[pin]
+    events: S,
+    focus: FocusedWidget,
+}
+
+impl<S> Stream for WithAccessibleContext<S>
+where
+    S: Stream<Item = KeyloggerResult<KeyEvent>>,
+{
+    type Item = KeyloggerResult<(KeyEvent, Option<WidgetRole>)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let item = ready!(this.events.poll_next(cx));
+
+        Poll::Ready(item.map(|ev| ev.map(|ev| (ev, this.focus.role()))))
+    }
+}
+
+/// What a [`RedactionPolicy`] does with the events typed into a widget.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Redaction {
+    /// Keep the events as they are.
+    Keep,
+    /// Hide the keys that produce text (see
+    /// [`KeyEventStreamExt::redact`](crate::filters::KeyEventStreamExt::redact)).
+    Redact,
+    /// Drop the events.
+    Drop,
+}
+
+/// Decides what happens to the events typed into each kind of widget.
+///
+/// By default, the events typed into password fields are dropped, and all the others are kept.
+#[derive(Clone, Debug)]
+pub struct RedactionPolicy {
+    rules: HashMap<WidgetRole, Redaction>,
+    unknown: Redaction,
+}
+
+impl Default for RedactionPolicy {
+    fn default() -> Self {
+        Self {
+            rules: HashMap::from([(WidgetRole::PasswordField, Redaction::Drop)]),
+            unknown: Redaction::Keep,
+        }
+    }
+}
+
+impl RedactionPolicy {
+    /// Create the default policy, which only drops the events typed into password fields.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Handle the events typed into the widgets with the specified role using `redaction`.
+    pub fn rule(mut self, role: WidgetRole, redaction: Redaction) -> Self {
+        self.rules.insert(role, redaction);
+        self
+    }
+
+    /// Handle the events typed while the role of the focused widget is unknown using
+    /// `redaction`.
+    pub fn unknown(mut self, redaction: Redaction) -> Self {
+        self.unknown = redaction;
+        self
+    }
+
+    /// What happens to the events typed into a widget with the specified role.
+    pub fn redaction(&self, role: Option<WidgetRole>) -> Redaction {
+        match role {
+            Some(role) => self.rules.get(&role).copied().unwrap_or(Redaction::Keep),
+            None => self.unknown,
+        }
+    }
+
+    /// Apply the policy to the specified event, returning `None` if it's dropped.
+    pub fn enforce(&self, ev: KeyEvent, role: Option<WidgetRole>) -> Option<KeyEvent> {
+        match self.redaction(role) {
+            Redaction::Keep => Some(ev),
+            Redaction::Redact => Some(redact(ev)),
+            Redaction::Drop => None,
+        }
+    }
+
+    /// Apply the policy to a stream of tagged events (see [`with_accessible_context`]).
+    ///
+    /// Errors are never dropped.
+    pub fn apply<S>(self, events: S) -> Redacted<S>
+    where
+        S: Stream<Item = KeyloggerResult<(KeyEvent, Option<WidgetRole>)>>,
+    {
+        Redacted {
+            events,
+            policy: self,
+        }
+    }
+}
+
+/// A stream of tagged events with a [`RedactionPolicy`] applied (see [`RedactionPolicy::apply`]).
+#[pin_project]
+pub struct Redacted<S> {
+    #[pin]
+    events: S,
+    policy: RedactionPolicy,
+}
+
+impl<S> Stream for Redacted<S>
+where
+    S: Stream<Item = KeyloggerResult<(KeyEvent, Option<WidgetRole>)>>,
+{
+    type Item = KeyloggerResult<(KeyEvent, Option<WidgetRole>)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            match ready!(this.events.as_mut().poll_next(cx)) {
+                Some(Ok((ev, role))) => {
+                    if let Some(ev) = this.policy.enforce(ev, role) {
+                        return Poll::Ready(Some(Ok((ev, role))));
+                    }
+                }
+                item => return Poll::Ready(item),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_code::KeyCode;
+    use crate::keyboard::KeyEventCause;
+    use crate::KeyloggerError;
+    use futures::executor::block_on;
+    use futures::{stream, StreamExt};
+
+    #[test]
+    fn redact_by_widget() {
+        let focus = FocusedWidget::new();
+        let roles = [
+            None,
+            Some(WidgetRole::Terminal),
+            Some(WidgetRole::PasswordField),
+            Some(WidgetRole::TextField),
+        ];
+        let evs = roles.map(|role| {
+            Ok::<_, KeyloggerError>((
+                KeyEvent {
+                    ts: Default::default(),
+                    cause: KeyEventCause::Press,
+                    code: KeyCode::KEY_A,
+                    scancode: Some(30),
+                },
+                role,
+            ))
+        });
+
+        let setter = focus.clone();
+        let tagged = with_accessible_context(
+            stream::iter(evs).map(move |ev| {
+                let (ev, role) = ev?;
+
+                setter.set_role(role);
+                Ok(ev)
+            }),
+            focus,
+        );
+        let policy = RedactionPolicy::new().rule(WidgetRole::TextField, Redaction::Redact);
+        let redacted = policy
+            .apply(tagged)
+            .map(|ev| {
+                let (ev, role) = ev.unwrap();
+
+                (ev.code, ev.scancode, role)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            block_on(redacted),
+            [
+                (KeyCode::KEY_A, Some(30), None),
+                (KeyCode::KEY_A, Some(30), Some(WidgetRole::Terminal)),
+                (crate::filters::REDACTED, None, Some(WidgetRole::TextField)),
+            ]
+        );
+
+        #[cfg(feature = "atspi")]
+        {
+            assert_eq!(WidgetRole::from(atspi::Role::Entry), WidgetRole::TextField);
+            assert_eq!(
+                WidgetRole::from(atspi::Role::Terminal),
+                WidgetRole::Terminal
+            );
+            assert_eq!(WidgetRole::from(atspi::Role::Button), WidgetRole::Other);
+        }
+    }
+}
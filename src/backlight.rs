@@ -0,0 +1,250 @@
+//! Adjust the keyboard backlight when the backlight keys are pressed.
+//!
+//! Laptops report their keyboard backlight keys as `KEY_KBDILLUMUP`, `KEY_KBDILLUMDOWN` and
+//! `KEY_KBDILLUMTOGGLE` (see [`MediaKey`]), but it's up to the desktop environment to act on them.
+//! A [`KeyboardBacklight`] does it through the LED class device of the backlight in sysfs (e.g.
+//! `/sys/class/leds/tpacpi::kbd_backlight`), for hotkey daemons that run without one:
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use keylogger::backlight::KeyboardBacklight;
+//! use keylogger::{merge_keyboards, KeyloggerError};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let mut keyboards = merge_keyboards()?;
+//!     let Some(mut backlight) = KeyboardBacklight::find()? else {
+//!         return Ok(());
+//!     };
+//!
+//!     while let Some(ev) = keyboards.next().await {
+//!         if let Some(brightness) = backlight.handle(&ev?.event)? {
+//!             println!("the keyboard backlight is at {brightness}");
+//!         }
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! The `brightness` file of the LED is usually only writable by root, so the process needs to run
+//! as root, or be granted access to it using a udev rule.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::key_code::MediaKey;
+use crate::keyboard::{KeyEvent, KeyEventCause};
+use crate::{KeyloggerError, KeyloggerResult};
+
+/// The directory of the LED class devices.
+const SYSFS_LEDS: &str = "/sys/class/leds";
+/// The suffix of the name of the LED class device of a keyboard backlight.
+const KBD_BACKLIGHT_SUFFIX: &str = "::kbd_backlight";
+
+/// The keyboard backlight of a laptop, controlled through sysfs (see the [module docs](self)).
+#[derive(Clone, Debug)]
+pub struct KeyboardBacklight {
+    /// The sysfs directory of the LED.
+    dir: PathBuf,
+    max_brightness: u32,
+    /// How much the brightness is changed by each press of the up and down keys.
+    step: u32,
+    /// The brightness to restore when the backlight is toggled back on.
+    restore: Option<u32>,
+}
+
+impl KeyboardBacklight {
+    /// Find the keyboard backlight of the system, if it has one.
+    ///
+    /// If there are several (e.g. a laptop with an external keyboard that also has one), the
+    /// first one (in alphabetical order) is returned.
+    pub fn find() -> KeyloggerResult<Option<Self>> {
+        let mut dirs = match fs::read_dir(SYSFS_LEDS) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| {
+                    entry
+                        .file_name()
+                        .to_string_lossy()
+                        .ends_with(KBD_BACKLIGHT_SUFFIX)
+                })
+                .map(|entry| entry.path())
+                .collect::<Vec<_>>(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        dirs.sort();
+        dirs.into_iter().next().map(Self::open).transpose()
+    }
+
+    /// Open the LED class device with the specified sysfs directory (e.g.
+    /// `/sys/class/leds/tpacpi::kbd_backlight`).
+    ///
+    /// The brightness is changed by one level for each press of the up and down keys, or by a
+    /// tenth of the maximum brightness if the LED has more than 10 levels.
+    pub fn open(dir: impl AsRef<Path>) -> KeyloggerResult<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let max_brightness = read_value(&dir.join("max_brightness"))?;
+
+        Ok(Self {
+            dir,
+            max_brightness,
+            step: (max_brightness / 10).max(1),
+            restore: None,
+        })
+    }
+
+    /// Change the brightness by `step` for each press of the up and down keys.
+    pub fn step(mut self, step: u32) -> Self {
+        self.step = step.max(1);
+        self
+    }
+
+    /// The sysfs directory of the LED.
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The maximum brightness of the backlight.
+    pub fn max_brightness(&self) -> u32 {
+        self.max_brightness
+    }
+
+    /// The current brightness of the backlight.
+    pub fn brightness(&self) -> KeyloggerResult<u32> {
+        read_value(&self.dir.join("brightness"))
+    }
+
+    /// Set the brightness of the backlight, which is capped at the maximum brightness.
+    ///
+    /// This fails with [`KeyloggerError::PermissionDenied`] if the process isn't allowed to
+    /// change the brightness.
+    pub fn set_brightness(&mut self, brightness: u32) -> KeyloggerResult<u32> {
+        let brightness = brightness.min(self.max_brightness);
+        let path = self.dir.join("brightness");
+
+        fs::write(&path, brightness.to_string()).map_err(|e| match e.kind() {
+            io::ErrorKind::PermissionDenied => KeyloggerError::PermissionDenied {
+                path,
+                hint: "run as root, or make the file writable using a udev rule".into(),
+            },
+            _ => e.into(),
+        })?;
+
+        Ok(brightness)
+    }
+
+    /// Increase the brightness by one step, returning the new brightness.
+    pub fn increase(&mut self) -> KeyloggerResult<u32> {
+        let brightness = self.brightness()?;
+
+        self.set_brightness(brightness.saturating_add(self.step))
+    }
+
+    /// Decrease the brightness by one step, returning the new brightness.
+    pub fn decrease(&mut self) -> KeyloggerResult<u32> {
+        let brightness = self.brightness()?;
+
+        self.set_brightness(brightness.saturating_sub(self.step))
+    }
+
+    /// Turn the backlight off, or back on, returning the new brightness.
+    ///
+    /// The backlight is turned back on at the brightness it had when it was turned off, or at the
+    /// maximum brightness if it was turned off by something else.
+    pub fn toggle(&mut self) -> KeyloggerResult<u32> {
+        match self.brightness()? {
+            0 => {
+                let brightness = self.restore.take().unwrap_or(self.max_brightness);
+
+                self.set_brightness(brightness)
+            }
+            brightness => {
+                self.restore = Some(brightness);
+                self.set_brightness(0)
+            }
+        }
+    }
+
+    /// Adjust the backlight if the specified event is a press of one of the backlight keys,
+    /// returning the new brightness.
+    ///
+    /// Holding down the up or down key keeps changing the brightness (on each autorepeat event),
+    /// while the toggle key only acts when it's pressed. The other events are ignored.
+    pub fn handle(&mut self, ev: &KeyEvent) -> KeyloggerResult<Option<u32>> {
+        let repeated = match ev.cause {
+            KeyEventCause::Press => false,
+            KeyEventCause::Repeat => true,
+            KeyEventCause::Release => return Ok(None),
+        };
+
+        match ev.code.media_key() {
+            Some(MediaKey::KeyboardBacklightUp) => self.increase().map(Some),
+            Some(MediaKey::KeyboardBacklightDown) => self.decrease().map(Some),
+            Some(MediaKey::KeyboardBacklightToggle) if !repeated => self.toggle().map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Read a numeric sysfs attribute.
+fn read_value(path: &Path) -> KeyloggerResult<u32> {
+    let value = fs::read_to_string(path)?;
+
+    value.trim().parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid value in {}: {}", path.display(), value.trim()),
+        )
+        .into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_code::KeyCode::*;
+    use KeyEventCause::*;
+
+    #[test]
+    fn adjust_backlight() {
+        let dir = std::env::temp_dir().join(format!(
+            "keylogger-backlight-{}::kbd_backlight",
+            std::process::id()
+        ));
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("max_brightness"), "3\n").unwrap();
+        fs::write(dir.join("brightness"), "1\n").unwrap();
+
+        let mut backlight = KeyboardBacklight::open(&dir).unwrap();
+        let mut handle = |cause, code| {
+            backlight
+                .handle(&KeyEvent {
+                    ts: Default::default(),
+                    cause,
+                    code,
+                    scancode: None,
+                })
+                .unwrap()
+        };
+
+        assert_eq!(handle(Press, KEY_KBDILLUMUP), Some(2));
+        assert_eq!(handle(Repeat, KEY_KBDILLUMUP), Some(3));
+        assert_eq!(handle(Repeat, KEY_KBDILLUMUP), Some(3));
+        assert_eq!(handle(Release, KEY_KBDILLUMUP), None);
+        assert_eq!(handle(Press, KEY_KBDILLUMDOWN), Some(2));
+        assert_eq!(handle(Press, KEY_A), None);
+
+        // The backlight is turned back on at its previous brightness
+        assert_eq!(handle(Press, KEY_KBDILLUMTOGGLE), Some(0));
+        assert_eq!(handle(Repeat, KEY_KBDILLUMTOGGLE), None);
+        assert_eq!(handle(Press, KEY_KBDILLUMTOGGLE), Some(2));
+        assert_eq!(fs::read_to_string(dir.join("brightness")).unwrap(), "2");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
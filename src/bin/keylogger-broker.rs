@@ -0,0 +1,28 @@
+//! The privileged helper of `keylogger::broker::DeviceBroker`.
+//!
+//! Opens the `/dev/input/event*` devices requested on its standard input (which must be a Unix
+//! socket), and passes them back over the socket. This is meant to be run through `pkexec`, or
+//! installed as a setuid executable, rather than run directly. Either way, it only opens the
+//! devices of the seat the user that runs it is in front of.
+
+use std::os::fd::AsFd;
+use std::os::unix::net::UnixStream;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let stream = match std::io::stdin().as_fd().try_clone_to_owned() {
+        Ok(fd) => UnixStream::from(fd),
+        Err(e) => {
+            eprintln!("error: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match keylogger::broker::serve(stream) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
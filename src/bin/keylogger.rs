@@ -0,0 +1,211 @@
+//! A command-line interface to the keylogger library, for quick diagnostics.
+//!
+//! ```text
+//! keylogger list                      # list the keyboards connected to the system
+//! keylogger watch --name '(?i)usb'    # print the events of the matching keyboards
+//! keylogger record keys.klcp -n 100   # record the next 100 events to a capture file
+//! keylogger record keys.klcp --resume # continue an interrupted recording
+//! keylogger replay keys.klcp          # print the events of a capture file
+//! keylogger replay keys.klcp --inject # replay them through a virtual keyboard
+//! ```
+//!
+//! Reading the events of the keyboards (and creating virtual keyboards) requires root privileges.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Args, Parser, Subcommand};
+use futures::StreamExt;
+use keylogger::capture::{CaptureReader, CaptureWriter};
+use keylogger::macros::Macro;
+use keylogger::sink::{self, format_event};
+use keylogger::{
+    KeyboardDevice, KeyboardFinder, KeyboardSet, KeyloggerError, KeyloggerResult, VirtualKeyboard,
+};
+
+/// The number of events recorded between the checkpoints of a capture file.
+const CHECKPOINT_INTERVAL: u64 = 64;
+
+/// Capture and handle keystroke events.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the keyboards connected to the system.
+    List {
+        #[command(flatten)]
+        devices: DeviceArgs,
+    },
+    /// Print the events of the keyboards.
+    Watch {
+        #[command(flatten)]
+        devices: DeviceArgs,
+    },
+    /// Record the events of the keyboards to a capture file, until interrupted.
+    Record {
+        #[command(flatten)]
+        devices: DeviceArgs,
+        /// The capture file to write.
+        output: PathBuf,
+        /// Stop after recording the specified number of events.
+        #[arg(short = 'n', long)]
+        count: Option<usize>,
+        /// Append to the capture file, after its last checkpoint, instead of overwriting it.
+        #[arg(long)]
+        resume: bool,
+    },
+    /// Print the events of a capture file.
+    Replay {
+        /// The capture file to read.
+        input: PathBuf,
+        /// Inject the events through a virtual keyboard, with their original timing, instead of
+        /// printing them.
+        #[arg(long)]
+        inject: bool,
+    },
+}
+
+/// The options that select the keyboards to use.
+#[derive(Args)]
+struct DeviceArgs {
+    /// Only use the keyboards whose name matches the specified regex.
+    #[arg(long)]
+    name: Option<String>,
+    /// Only use the keyboards whose path matches the specified glob.
+    #[arg(long)]
+    path: Option<String>,
+    /// Capture the hardware scancodes of the keys.
+    #[arg(long)]
+    scancodes: bool,
+}
+
+impl DeviceArgs {
+    /// Find the selected keyboards, failing if there are none.
+    fn find(&self) -> KeyloggerResult<Vec<KeyboardDevice>> {
+        let mut finder = KeyboardFinder::new();
+
+        if let Some(name) = &self.name {
+            finder = finder.name(name);
+        }
+
+        if let Some(path) = &self.path {
+            finder = finder.path(path);
+        }
+
+        if self.scancodes {
+            finder = finder.capture_scancodes();
+        }
+
+        let keyboards = finder.find()?;
+
+        if keyboards.is_empty() {
+            return Err(KeyloggerError::InvalidFilter(
+                "no matching keyboards".into(),
+            ));
+        }
+
+        Ok(keyboards)
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let res = match Cli::parse().command {
+        Command::List { devices } => list(&devices),
+        Command::Watch { devices } => watch(&devices).await,
+        Command::Record {
+            devices,
+            output,
+            count,
+            resume,
+        } => record(&devices, output, count, resume).await,
+        Command::Replay { input, inject } => replay(input, inject).await,
+    };
+
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn list(devices: &DeviceArgs) -> KeyloggerResult<()> {
+    for keyboard in devices.find()? {
+        let info = keyboard.info();
+
+        println!(
+            "{}: {} ({:04x}:{:04x}, {})",
+            keyboard.path().display(),
+            keyboard.name(),
+            info.vendor,
+            info.product,
+            info.phys.as_deref().unwrap_or("unknown location"),
+        );
+    }
+
+    Ok(())
+}
+
+async fn watch(devices: &DeviceArgs) -> KeyloggerResult<()> {
+    let mut keyboards = KeyboardSet::new(devices.find()?);
+
+    while let Some(ev) = keyboards.next().await {
+        let ev = ev?;
+        let name = keyboards.get(ev.device_id).map_or("", |k| k.name());
+
+        println!("[{name}] {}", format_event(&ev.event));
+    }
+
+    Ok(())
+}
+
+async fn record(
+    devices: &DeviceArgs,
+    output: PathBuf,
+    count: Option<usize>,
+    resume: bool,
+) -> KeyloggerResult<()> {
+    let keyboards = KeyboardSet::new(devices.find()?);
+    let writer = if resume {
+        CaptureWriter::resume(output)?
+    } else {
+        CaptureWriter::create(output)?
+    };
+    let mut writer = writer.checkpoint_every(CHECKPOINT_INTERVAL);
+    let events = keyboards
+        .map(|ev| ev.map(|ev| ev.event))
+        .take(count.unwrap_or(usize::MAX));
+
+    tokio::select! {
+        res = sink::forward(events, &mut writer) => res?,
+        res = tokio::signal::ctrl_c() => res?,
+    }
+
+    writer.checkpoint()?;
+
+    Ok(())
+}
+
+async fn replay(input: PathBuf, inject: bool) -> KeyloggerResult<()> {
+    let events = CaptureReader::open(input)?;
+
+    if !inject {
+        for ev in events {
+            println!("{}", format_event(&ev?));
+        }
+
+        return Ok(());
+    }
+
+    let events = events.collect::<KeyloggerResult<Vec<_>>>()?;
+    let mut keyboard = VirtualKeyboard::new("keylogger replay")?;
+
+    Macro::from_events(&events).play(&mut keyboard).await
+}
@@ -0,0 +1,107 @@
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+use crate::keyboard::device::read_error;
+use crate::keyboard::{KeyEvent, KeyboardDevice};
+use crate::KeyloggerResult;
+
+impl KeyboardDevice {
+    /// A blocking iterator over the events of the keyboard.
+    ///
+    /// Unlike the [`Stream`](futures::Stream) implementation of `KeyboardDevice`, the iterator
+    /// doesn't require an async runtime: it waits for the keyboard to become readable using
+    /// `poll(2)`, blocking the current thread. If the keyboard is paused (see
+    /// [`KeyboardDevice::pause`]), the iterator is empty.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use keylogger::{find_keyboards, KeyloggerError};
+    ///
+    /// fn main() -> Result<(), KeyloggerError> {
+    ///     let mut keyboard = find_keyboards()?.remove(0);
+    ///
+    ///     for ev in keyboard.iter_events() {
+    ///         println!("{:?}", ev?);
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn iter_events(&mut self) -> Events<'_> {
+        Events { keyboard: self }
+    }
+}
+
+/// A blocking iterator over the [`KeyEvent`]s of a [`KeyboardDevice`].
+///
+/// See [`KeyboardDevice::iter_events`].
+pub struct Events<'a> {
+    keyboard: &'a mut KeyboardDevice,
+}
+
+impl Iterator for Events<'_> {
+    type Item = KeyloggerResult<KeyEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.keyboard.is_paused() {
+            return None;
+        }
+
+        self.next_event().map(|res| self.keyboard.with_context(res))
+    }
+}
+
+impl Events<'_> {
+    fn next_event(&mut self) -> Option<KeyloggerResult<KeyEvent>> {
+        loop {
+            if let Some(ev) = self.keyboard.0.pop_buffered() {
+                return Some(Ok(ev));
+            }
+
+            // The events are read straight into the (exhausted) buffer of the keyboard
+            let mut evs = self.keyboard.0.take_buffer();
+            let inner = &mut self.keyboard.0.inner;
+            let fd = inner.as_raw_fd();
+
+            let res = if inner.reader.pop_report(&mut evs) {
+                Ok(())
+            } else {
+                wait_readable(fd).and_then(|()| inner.reader.read_key_events(fd, &mut evs))
+            };
+
+            inner.trace_read(&res, evs.len());
+
+            self.keyboard.0.buffer(evs);
+
+            match res {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Some(Err(read_error(self.keyboard.path(), e))),
+            }
+        }
+    }
+}
+
+/// Block until the specified file descriptor is readable.
+fn wait_readable(fd: libc::c_int) -> io::Result<()> {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    loop {
+        let res = unsafe { libc::poll(&mut pollfd, 1, -1) };
+
+        if res >= 0 {
+            return Ok(());
+        }
+
+        let err = io::Error::last_os_error();
+
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! Forward the events of a keyboard into a virtual keyboard.
+//!
+//! A [`Bridge`] injects the events of any stream of key events through a [`VirtualKeyboard`],
+//! optionally transforming them on the way using hooks. The events can come from a keyboard of
+//! the same machine (which is usually grabbed, so its events are only delivered through the
+//! bridge), or from another machine (e.g. through the `net` module), which makes it possible to
+//! share a keyboard between computers, like a software KVM switch:
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use keylogger::bridge::Bridge;
+//! use keylogger::{find_keyboards, KeyCode, KeyloggerError};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let mut keyboard = find_keyboards()?.remove(0);
+//!     let mut bridge = Bridge::new("bridged keyboard")?.hook(|ev| {
+//!         // Don't forward the keys that switch between the machines
+//!         if ev.code == KeyCode::KEY_SCROLLLOCK {
+//!             vec![]
+//!         } else {
+//!             vec![ev]
+//!         }
+//!     });
+//!
+//!     keyboard.grab()?;
+//!     bridge.run(keyboard).await
+//! }
+//! ```
+
+use std::collections::HashSet;
+
+use futures::{Stream, StreamExt};
+
+use crate::key_code::KeyCode;
+use crate::keyboard::{KeyEvent, KeyEventCause};
+use crate::uinput::VirtualKeyboard;
+use crate::KeyloggerResult;
+
+/// A hook whose type was erased, so it can be stored in a [`Bridge`].
+type Hook = Box<dyn FnMut(KeyEvent) -> Vec<KeyEvent> + Send>;
+
+/// Injects the (transformed) events of a stream through a virtual keyboard (see the [module
+/// docs](self)).
+pub struct Bridge {
+    output: VirtualKeyboard,
+    pipeline: Pipeline,
+}
+
+impl Bridge {
+    /// Create a bridge that injects the events through a new virtual keyboard with the specified
+    /// name.
+    pub fn new(name: &str) -> KeyloggerResult<Self> {
+        Ok(Self::with_output(VirtualKeyboard::new(name)?))
+    }
+
+    /// Create a bridge that injects the events through the specified virtual keyboard.
+    pub fn with_output(output: VirtualKeyboard) -> Self {
+        Self {
+            output,
+            pipeline: Default::default(),
+        }
+    }
+
+    /// Transform the events using `hook` before injecting them.
+    ///
+    /// A hook turns each event into any number of events (so it can drop, replace or expand
+    /// them). The hooks are applied in the order they're added, each one to the events produced
+    /// by the previous one.
+    pub fn hook<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(KeyEvent) -> Vec<KeyEvent> + Send + 'static,
+    {
+        self.pipeline.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// The keys held down on the virtual keyboard, according to the events injected so far.
+    pub fn held_keys(&self) -> Vec<KeyCode> {
+        let mut keys = self.pipeline.held.iter().copied().collect::<Vec<_>>();
+
+        keys.sort_by_key(|code| code.code());
+        keys
+    }
+
+    /// Transform the specified event, and inject the results as a single hardware report.
+    pub fn forward(&mut self, ev: KeyEvent) -> KeyloggerResult<()> {
+        let evs = self.pipeline.process(ev);
+
+        if evs.is_empty() {
+            return Ok(());
+        }
+
+        self.output.emit_all(&evs)
+    }
+
+    /// Release all the keys held down on the virtual keyboard.
+    ///
+    /// This is used when the source of the events goes away (or the bridge is switched to a
+    /// different source), so the keys it pressed don't stay stuck.
+    pub fn release_all(&mut self) -> KeyloggerResult<()> {
+        let evs = self.pipeline.release_all();
+
+        if evs.is_empty() {
+            return Ok(());
+        }
+
+        self.output.emit_all(&evs)
+    }
+
+    /// Forward the events of the specified stream until it ends, or yields an error.
+    ///
+    /// Either way, the keys still held down on the virtual keyboard are then released (see
+    /// [`Bridge::release_all`]), and the error (if any) is returned.
+    pub async fn run<S>(&mut self, mut events: S) -> KeyloggerResult<()>
+    where
+        S: Stream<Item = KeyloggerResult<KeyEvent>> + Unpin,
+    {
+        let mut res = Ok(());
+
+        while let Some(ev) = events.next().await {
+            if let Err(e) = ev.and_then(|ev| self.forward(ev)) {
+                res = Err(e);
+                break;
+            }
+        }
+
+        let released = self.release_all();
+
+        res.and(released)
+    }
+}
+
+/// The transformation applied to the events of a [`Bridge`], and the keys it holds down.
+#[derive(Default)]
+struct Pipeline {
+    hooks: Vec<Hook>,
+    held: HashSet<KeyCode>,
+}
+
+impl Pipeline {
+    /// Apply the hooks to the specified event, returning the events to inject.
+    fn process(&mut self, ev: KeyEvent) -> Vec<KeyEvent> {
+        let mut evs = vec![ev];
+
+        for hook in &mut self.hooks {
+            evs = evs.into_iter().flat_map(&mut *hook).collect();
+        }
+
+        for ev in &evs {
+            match ev.cause {
+                KeyEventCause::Press => self.held.insert(ev.code),
+                KeyEventCause::Release => self.held.remove(&ev.code),
+                KeyEventCause::Repeat => false,
+            };
+        }
+
+        evs
+    }
+
+    /// Forget the keys held down, returning their releases.
+    fn release_all(&mut self) -> Vec<KeyEvent> {
+        // The kernel timestamps the injected events itself
+        let mut evs = self
+            .held
+            .drain()
+            .map(|code| KeyEvent {
+                ts: Default::default(),
+                cause: KeyEventCause::Release,
+                code,
+                scancode: None,
+            })
+            .collect::<Vec<_>>();
+
+        evs.sort_by_key(|ev| ev.code.code());
+        evs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_code::KeyCode::*;
+    use crate::remap::KeyMap;
+    use KeyEventCause::*;
+
+    #[test]
+    fn transform_and_release() {
+        let mut pipeline = Pipeline::default();
+
+        pipeline.hooks.push(Box::new(
+            KeyMap::new().map(KEY_CAPSLOCK, KEY_ESC).into_transform(),
+        ));
+        pipeline.hooks.push(Box::new(|ev: KeyEvent| match ev.code {
+            // Dropped
+            KEY_SCROLLLOCK => vec![],
+            // Expanded into Shift+1
+            KEY_F1 => vec![
+                KeyEvent {
+                    code: KEY_LEFTSHIFT,
+                    ..ev
+                },
+                KeyEvent { code: KEY_1, ..ev },
+            ],
+            _ => vec![ev],
+        }));
+
+        let mut process = |cause, code| {
+            pipeline
+                .process(KeyEvent {
+                    ts: Default::default(),
+                    cause,
+                    code,
+                    scancode: None,
+                })
+                .into_iter()
+                .map(|ev| (ev.cause, ev.code))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(process(Press, KEY_CAPSLOCK), [(Press, KEY_ESC)]);
+        assert_eq!(process(Press, KEY_SCROLLLOCK), []);
+        assert_eq!(
+            process(Press, KEY_F1),
+            [(Press, KEY_LEFTSHIFT), (Press, KEY_1)]
+        );
+        assert_eq!(process(Release, KEY_CAPSLOCK), [(Release, KEY_ESC)]);
+
+        let released = pipeline
+            .release_all()
+            .into_iter()
+            .map(|ev| (ev.cause, ev.code))
+            .collect::<Vec<_>>();
+
+        assert_eq!(released, [(Release, KEY_1), (Release, KEY_LEFTSHIFT)]);
+        assert!(pipeline.release_all().is_empty());
+    }
+}
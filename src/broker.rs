@@ -0,0 +1,383 @@
+//! Opening the input devices through a privileged helper process, so the application itself never
+//! needs to run as root.
+//!
+//! A [`DeviceBroker`] spawns the helper (through `pkexec`, which asks the user to authenticate
+//! using polkit, or as a setuid executable), and asks it to open the devices on behalf of the
+//! application. The helper passes the file descriptors of the devices back over a Unix socket
+//! (using `SCM_RIGHTS`), so only the helper has access to the devices that weren't passed to the
+//! application. The helper only opens the `/dev/input/event*` devices, read-only.
+//!
+//! As a setuid executable can be run by any user, the helper checks who's asking (using the
+//! `SO_PEERCRED` credentials of the process at the other end of the socket): it only opens a device
+//! for root, or for the user of the session that's active on the seat of the device (see
+//! [`DeviceInfo::active_session`](crate::DeviceInfo::active_session)), like logind does. The other
+//! users can't read the keystrokes typed by the user in front of the machine, and nobody can read
+//! the devices that aren't assigned to a seat by udev.
+//!
+//! The `keylogger-broker` binary built with the `broker` feature is such a helper. It serves the
+//! requests it receives on its standard input (see [`serve`]), until the application closes the
+//! socket.
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use keylogger::broker::DeviceBroker;
+//! use keylogger::KeyloggerError;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let mut broker = DeviceBroker::pkexec("/usr/libexec/keylogger-broker")?;
+//!     let mut keyboard = broker.find_keyboards()?.remove(0);
+//!
+//!     while let Some(ev) = keyboard.next().await {
+//!         println!("{:?}", ev?);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Protocol
+//!
+//! Each request is the path of a device, followed by a newline. Each response is a status byte
+//! (0 if the device was opened) followed by an errno value (a little-endian `i32`, 0 on success).
+//! The file descriptor of the device is attached to the successful responses.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::ptr;
+
+use crate::error::KeyloggerError;
+use crate::keyboard::device::{
+    find_char_devices, EventReader, InputDevice, INPUT_DIR, KEYBOARD_FLAGS,
+};
+use crate::keyboard::seat::{read_active_session, read_seat};
+use crate::keyboard::{Keyboard, KeyboardDevice};
+use crate::KeyloggerResult;
+
+/// The size of a response, excluding the attached file descriptor.
+const RESPONSE_LEN: usize = 5;
+/// The status of a response to a request that succeeded.
+const STATUS_OK: u8 = 0;
+/// The status of a response to a request that failed.
+const STATUS_ERR: u8 = 1;
+
+/// A privileged helper process that opens the input devices on behalf of the application (see the
+/// [module docs](self)).
+///
+/// The helper exits when the `DeviceBroker` is dropped. The devices it opened stay open.
+#[derive(Debug)]
+pub struct DeviceBroker {
+    stream: UnixStream,
+    child: Child,
+}
+
+impl DeviceBroker {
+    /// Run the specified helper through `pkexec`.
+    ///
+    /// `pkexec` asks the user to authenticate (unless a polkit rule allows the helper to run
+    /// without authentication), so this might block until the user responds.
+    pub fn pkexec(helper: impl AsRef<Path>) -> KeyloggerResult<Self> {
+        let mut command = Command::new("pkexec");
+
+        command.arg(helper.as_ref());
+
+        Self::spawn(command)
+    }
+
+    /// Run the helper using the specified command (e.g. the path of a setuid helper).
+    ///
+    /// The standard input of the helper is replaced with the socket the requests are sent over.
+    pub fn spawn(mut command: Command) -> KeyloggerResult<Self> {
+        let (stream, helper) = UnixStream::pair()?;
+        let child = command.stdin(Stdio::from(OwnedFd::from(helper))).spawn()?;
+
+        Ok(Self { stream, child })
+    }
+
+    /// Ask the helper to open the input device at the specified path.
+    pub fn open_device(&mut self, path: impl AsRef<Path>) -> KeyloggerResult<File> {
+        let path = path.as_ref();
+        let mut request = path.as_os_str().as_bytes().to_vec();
+
+        request.push(b'\n');
+        self.stream.write_all(&request)?;
+
+        let mut response = [0; RESPONSE_LEN];
+        let (n, fd) = recv_fd(&self.stream, &mut response)?;
+
+        self.stream.read_exact(&mut response[n..])?;
+
+        let errno = i32::from_le_bytes(response[1..].try_into().unwrap());
+
+        match (response[0], fd) {
+            (STATUS_OK, Some(fd)) => Ok(File::from(fd)),
+            (STATUS_OK, None) => Err(io::Error::other("the broker didn't pass a device").into()),
+            _ if errno == libc::EACCES || errno == libc::EPERM => {
+                Err(KeyloggerError::PermissionDenied {
+                    path: path.into(),
+                    hint: "the broker isn't allowed to open the device".into(),
+                })
+            }
+            _ => Err(io::Error::from_raw_os_error(errno).into()),
+        }
+    }
+
+    /// Ask the helper to open the keyboard device at the specified path.
+    pub fn open(&mut self, path: impl AsRef<Path>) -> KeyloggerResult<KeyboardDevice> {
+        let path = path.as_ref();
+        let file = self.open_device(path)?;
+        let dev = InputDevice::from_file(file, path, KEYBOARD_FLAGS, EventReader::default())?;
+
+        Ok(KeyboardDevice(Keyboard::new(dev)))
+    }
+
+    /// Auto-detect the keyboard devices, like [`find_keyboards`](crate::find_keyboards), by asking
+    /// the helper to open all the input devices.
+    ///
+    /// The devices the helper can't open (and the devices that aren't keyboards) are skipped. The
+    /// devices are always looked up in `/dev/input`, as that's the only directory the helper opens
+    /// devices from.
+    pub fn find_keyboards(&mut self) -> KeyloggerResult<Vec<KeyboardDevice>> {
+        Ok(find_char_devices(Path::new(INPUT_DIR))?
+            .filter_map(|path| self.open(path).ok())
+            .collect())
+    }
+}
+
+impl Drop for DeviceBroker {
+    fn drop(&mut self) {
+        // The helper exits once it reads the end of the stream
+        let _ = self.stream.shutdown(std::net::Shutdown::Both);
+        let _ = self.child.wait();
+    }
+}
+
+/// Serve the requests of a [`DeviceBroker`] received on the specified socket, until it's closed.
+///
+/// This runs in the privileged helper process. Only the `/dev/input/event*` character devices are
+/// opened, they are opened read-only, and only for the users allowed to read them (see the
+/// [module docs](self)). The requests for the other devices fail with `EACCES`.
+pub fn serve(stream: UnixStream) -> KeyloggerResult<()> {
+    let caller = peer_uid(&stream)?;
+    let reader = BufReader::new(&stream);
+
+    for request in reader.split(b'\n') {
+        let path = PathBuf::from(std::ffi::OsStr::from_bytes(&request?));
+        let mut response = [STATUS_OK; RESPONSE_LEN];
+
+        match open_input_device(&path, caller) {
+            Ok(file) => send_fd(&stream, &response, Some(file.as_raw_fd()))?,
+            Err(e) => {
+                let errno = e.raw_os_error().unwrap_or(libc::EIO);
+
+                response[0] = STATUS_ERR;
+                response[1..].copy_from_slice(&errno.to_le_bytes());
+                send_fd(&stream, &response, None)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Open the specified input device for the user with the specified ID, provided it's one of the
+/// `/dev/input/event*` character devices, and the user is allowed to read it.
+fn open_input_device(path: &Path, caller: libc::uid_t) -> io::Result<File> {
+    if !is_event_device_path(path) {
+        return Err(io::Error::from_raw_os_error(libc::EINVAL));
+    }
+
+    if !may_read_device(path, caller) {
+        return Err(io::Error::from_raw_os_error(libc::EACCES));
+    }
+
+    let file = File::options()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
+        .open(path)?;
+
+    if !file.metadata()?.file_type().is_char_device() {
+        return Err(io::Error::from_raw_os_error(libc::EINVAL));
+    }
+
+    Ok(file)
+}
+
+/// Whether the user with the specified ID is allowed to read the specified device: root, or the
+/// user of the session that's active on the seat of the device.
+fn may_read_device(path: &Path, caller: libc::uid_t) -> bool {
+    caller == 0
+        || read_seat(path)
+            .and_then(|seat| read_active_session(&seat))
+            .is_some_and(|session| session.uid == Some(caller))
+}
+
+/// The ID of the user of the process at the other end of the socket (for a socket pair, the
+/// process that created it).
+fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+    let mut cred: libc::ucred = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let res = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(cred.uid)
+}
+
+/// Whether the path has the form `/dev/input/eventN`.
+fn is_event_device_path(path: &Path) -> bool {
+    // The input directory of the helper deliberately can't be overridden (using
+    // KEYLOGGER_INPUT_DIR), as that would allow anyone to open any device through the helper
+    path.parent() == Some(Path::new(INPUT_DIR))
+        && path
+            .file_name()
+            .and_then(|name| name.to_str()?.strip_prefix("event"))
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// The buffer the control messages are stored in, aligned for `cmsghdr`.
+#[repr(C)]
+union ControlBuffer {
+    buf: [u8; 64],
+    _align: libc::cmsghdr,
+}
+
+/// Send a message, attaching the specified file descriptor (if any).
+fn send_fd(stream: &UnixStream, msg: &[u8], fd: Option<RawFd>) -> io::Result<()> {
+    let mut iov = libc::iovec {
+        iov_base: msg.as_ptr() as *mut libc::c_void,
+        iov_len: msg.len(),
+    };
+
+    let mut control = ControlBuffer { buf: [0; 64] };
+    let mut hdr: libc::msghdr = unsafe { mem::zeroed() };
+
+    hdr.msg_iov = &mut iov;
+    hdr.msg_iovlen = 1;
+
+    if let Some(fd) = fd {
+        unsafe {
+            hdr.msg_control = control.buf.as_mut_ptr() as *mut libc::c_void;
+            hdr.msg_controllen = libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) as _;
+
+            let cmsg = libc::CMSG_FIRSTHDR(&hdr);
+
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+            ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, fd);
+        }
+    }
+
+    if unsafe { libc::sendmsg(stream.as_raw_fd(), &hdr, libc::MSG_NOSIGNAL) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Receive a message, along with the file descriptor attached to it (if any).
+fn recv_fd(stream: &UnixStream, buf: &mut [u8]) -> io::Result<(usize, Option<OwnedFd>)> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+
+    let mut control = ControlBuffer { buf: [0; 64] };
+    let mut hdr: libc::msghdr = unsafe { mem::zeroed() };
+
+    hdr.msg_iov = &mut iov;
+    hdr.msg_iovlen = 1;
+    hdr.msg_control = unsafe { control.buf.as_mut_ptr() } as *mut libc::c_void;
+    hdr.msg_controllen = mem::size_of::<ControlBuffer>() as _;
+
+    let n = unsafe { libc::recvmsg(stream.as_raw_fd(), &mut hdr, libc::MSG_CMSG_CLOEXEC) };
+
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if n == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    let mut fd = None;
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&hdr) };
+
+    while !cmsg.is_null() {
+        unsafe {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let raw = ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const RawFd);
+
+                fd = Some(OwnedFd::from_raw_fd(raw));
+            }
+
+            cmsg = libc::CMSG_NXTHDR(&hdr, cmsg);
+        }
+    }
+
+    // The descriptors that didn't fit were closed by the kernel, so the response is unusable (and
+    // the descriptors that did fit are closed when `fd` is dropped)
+    if hdr.msg_flags & libc::MSG_CTRUNC != 0 {
+        return Err(io::Error::other("the control message was truncated"));
+    }
+
+    Ok((n as usize, fd))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn pass_fds() {
+        assert!(is_event_device_path(Path::new("/dev/input/event12")));
+        assert!(!is_event_device_path(Path::new("/dev/input/event")));
+        assert!(!is_event_device_path(Path::new("/dev/input/mice")));
+        assert!(!is_event_device_path(Path::new("/dev/input/../sda")));
+        assert!(!is_event_device_path(Path::new("/etc/shadow")));
+
+        // Only root and the user in front of the machine are allowed to read the devices
+        let path = Path::new("/dev/input/event0");
+
+        assert!(may_read_device(path, 0));
+        assert!(!may_read_device(path, libc::uid_t::MAX - 1));
+
+        let (a, b) = UnixStream::pair().unwrap();
+
+        assert_eq!(peer_uid(&a).unwrap(), unsafe { libc::getuid() });
+
+        let file = File::open("/dev/null").unwrap();
+
+        send_fd(&a, b"hello", Some(file.as_raw_fd())).unwrap();
+
+        let mut buf = [0; 5];
+        let (n, fd) = recv_fd(&b, &mut buf).unwrap();
+        let passed = File::from(fd.unwrap()).metadata().unwrap();
+        let original = file.metadata().unwrap();
+
+        assert_eq!(&buf[..n], b"hello");
+        assert_eq!(
+            (passed.dev(), passed.ino()),
+            (original.dev(), original.ino())
+        );
+    }
+}
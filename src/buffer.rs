@@ -0,0 +1,385 @@
+//! Decouple the reading of a keyboard from the handling of its events.
+//!
+//! The kernel only buffers a limited number of events for each device: if they aren't read
+//! quickly enough (for example, because the handler of the events is slow), the events that don't
+//! fit are lost. [`bounded`] splits a stream of events into a [`Pump`], which reads the events as
+//! soon as they're available, and a [`Buffered`] stream, which yields them to the handler. The
+//! events are queued in between, and the [`OverflowPolicy`] decides what happens once the queue is
+//! full, so a slow handler can't cause unbounded latency or memory growth. The errors are always
+//! queued, so they reach the handler even if the events around them are dropped.
+//!
+//! The pump must be spawned on its own task, separate from the handler. Each device should get its
+//! own queue, so a flood of events from one device can't crowd out the events of the others:
+//!
+//! ```no_run
+//! use futures::stream::{self, StreamExt};
+//! use keylogger::buffer::{self, OverflowPolicy};
+//! use keylogger::{find_keyboards, KeyloggerError};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let keyboards = find_keyboards()?.into_iter().map(|keyboard| {
+//!         let (pump, events) = buffer::bounded(keyboard, 256, OverflowPolicy::DropOldest);
+//!
+//!         tokio::spawn(pump);
+//!         events
+//!     });
+//!
+//!     let mut events = stream::select_all(keyboards);
+//!
+//!     while let Some(ev) = events.next().await {
+//!         println!("{:?}", ev?);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+use futures::{ready, Stream, TryStream};
+use pin_project::{pin_project, pinned_drop};
+
+use crate::error::KeyloggerError;
+use crate::keyboard::KeyEvent;
+
+/// What to do with the events read while the queue is full.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum OverflowPolicy {
+    /// Drop the oldest queued event to make room for the new one.
+    #[default]
+    DropOldest,
+    /// Drop the new event.
+    DropNewest,
+    /// Stop reading the device until the handler catches up.
+    ///
+    /// The events are left in the buffer of the kernel, which drops them if the handler doesn't
+    /// catch up quickly enough.
+    Block,
+}
+
+/// The default number of events queued for each keyboard by a [`Keylogger`](crate::Keylogger).
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// Split the specified stream into a [`Pump`] and a [`Buffered`] stream, connected by a queue that
+/// holds up to `capacity` events (see the [module-level documentation](self)).
+///
+/// The events don't need to be [`KeyEvent`]s, so they can be tagged (e.g. with the keyboard they
+/// came from) before they're queued. The errors of the stream don't count towards the capacity,
+/// and they're never dropped.
+pub fn bounded<S>(
+    events: S,
+    capacity: usize,
+    policy: OverflowPolicy,
+) -> (Pump<S>, Buffered<S::Ok, S::Error>)
+where
+    S: TryStream,
+{
+    let shared = Arc::new(Mutex::new(Shared {
+        queue: VecDeque::with_capacity(capacity.max(1)),
+        capacity: capacity.max(1),
+        errors: 0,
+        dropped: 0,
+        pump_done: false,
+        receiver_gone: false,
+        pump_waker: None,
+        receiver_waker: None,
+    }));
+
+    let pump = Pump {
+        events,
+        policy,
+        shared: Arc::clone(&shared),
+    };
+
+    (pump, Buffered { shared })
+}
+
+/// The state shared by a [`Pump`] and its [`Buffered`] stream.
+#[derive(Debug)]
+struct Shared<T, E> {
+    queue: VecDeque<Result<T, E>>,
+    /// The number of events the queue holds, not counting the errors.
+    capacity: usize,
+    /// The number of errors in the queue.
+    errors: usize,
+    /// The number of events dropped because the queue was full.
+    dropped: u64,
+    /// Whether the pump finished (or was dropped).
+    pump_done: bool,
+    /// Whether the `Buffered` stream was dropped.
+    receiver_gone: bool,
+    /// The waker of the pump, if it's waiting for the queue to have room.
+    pump_waker: Option<Waker>,
+    /// The waker of the `Buffered` stream, if it's waiting for events.
+    receiver_waker: Option<Waker>,
+}
+
+fn lock<T, E>(shared: &Mutex<Shared<T, E>>) -> MutexGuard<'_, Shared<T, E>> {
+    // The state is never left inconsistent, so a poisoned lock is still usable
+    shared.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Reads the events of a stream into the queue of its [`Buffered`] stream (see [`bounded`]).
+///
+/// The pump completes once the stream ends, or once the `Buffered` stream is dropped.
+#[pin_project(PinnedDrop)]
+pub struct Pump<S: TryStream> {
+    #[pin]
+    events: S,
+    policy: OverflowPolicy,
+    shared: Arc<Mutex<Shared<S::Ok, S::Error>>>,
+}
+
+impl<S: TryStream> Future for Pump<S> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+
+        loop {
+            {
+                let mut shared = lock(this.shared);
+
+                if shared.receiver_gone {
+                    return Poll::Ready(());
+                }
+
+                if *this.policy == OverflowPolicy::Block && shared.is_full() {
+                    shared.pump_waker = Some(cx.waker().clone());
+
+                    return Poll::Pending;
+                }
+            }
+
+            let item = ready!(this.events.as_mut().try_poll_next(cx));
+            let mut shared = lock(this.shared);
+
+            match item {
+                Some(item) => shared.push(item, *this.policy),
+                None => shared.pump_done = true,
+            }
+
+            if let Some(waker) = shared.receiver_waker.take() {
+                waker.wake();
+            }
+
+            if shared.pump_done {
+                return Poll::Ready(());
+            }
+        }
+    }
+}
+
+#[pinned_drop]
+impl<S: TryStream> PinnedDrop for Pump<S> {
+    fn drop(self: Pin<&mut Self>) {
+        let mut shared = lock(&self.shared);
+
+        shared.pump_done = true;
+
+        if let Some(waker) = shared.receiver_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T, E> Shared<T, E> {
+    /// Whether the queue has no room for another event.
+    fn is_full(&self) -> bool {
+        self.queue.len() - self.errors >= self.capacity
+    }
+
+    /// Queue an event or an error, applying the policy if the queue is full of events.
+    fn push(&mut self, item: Result<T, E>, policy: OverflowPolicy) {
+        if item.is_err() {
+            self.errors += 1;
+        } else if self.is_full() {
+            self.dropped += 1;
+
+            match policy {
+                OverflowPolicy::DropOldest => {
+                    // The queue is full, so it holds at least one event
+                    if let Some(oldest) = self.queue.iter().position(Result::is_ok) {
+                        self.queue.remove(oldest);
+                    }
+                }
+                OverflowPolicy::DropNewest => return,
+                // The pump doesn't read any events while the queue is full
+                OverflowPolicy::Block => unreachable!("the queue is full"),
+            }
+        }
+
+        self.queue.push_back(item);
+    }
+
+    /// Take the item at the front of the queue.
+    fn pop(&mut self) -> Option<Result<T, E>> {
+        let item = self.queue.pop_front()?;
+
+        if item.is_err() {
+            self.errors -= 1;
+        }
+
+        Some(item)
+    }
+}
+
+/// The events read by a [`Pump`] (see [`bounded`]).
+///
+/// The stream ends once the pump finishes (or is dropped) and the queued events were returned.
+#[derive(Debug)]
+pub struct Buffered<T = KeyEvent, E = KeyloggerError> {
+    shared: Arc<Mutex<Shared<T, E>>>,
+}
+
+impl<T, E> Buffered<T, E> {
+    /// The number of events dropped so far because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        lock(&self.shared).dropped
+    }
+
+    /// The number of events (and errors) waiting to be returned.
+    pub fn len(&self) -> usize {
+        lock(&self.shared).queue.len()
+    }
+
+    /// Whether there are no events waiting to be returned.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T, E> Stream for Buffered<T, E> {
+    type Item = Result<T, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut shared = lock(&self.shared);
+
+        if let Some(item) = shared.pop() {
+            if let Some(waker) = shared.pump_waker.take() {
+                waker.wake();
+            }
+
+            return Poll::Ready(Some(item));
+        }
+
+        if shared.pump_done {
+            return Poll::Ready(None);
+        }
+
+        shared.receiver_waker = Some(cx.waker().clone());
+
+        Poll::Pending
+    }
+}
+
+impl<T, E> Drop for Buffered<T, E> {
+    fn drop(&mut self) {
+        let mut shared = lock(&self.shared);
+
+        shared.receiver_gone = true;
+
+        if let Some(waker) = shared.pump_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_code::KeyCode;
+    use crate::keyboard::KeyEventCause;
+    use crate::KeyloggerResult;
+    use futures::executor::block_on;
+    use futures::{future, stream, StreamExt};
+
+    fn presses() -> impl Stream<Item = KeyloggerResult<KeyEvent>> {
+        let codes = [
+            KeyCode::KEY_A,
+            KeyCode::KEY_B,
+            KeyCode::KEY_C,
+            KeyCode::KEY_D,
+        ];
+
+        stream::iter(codes.map(|code| {
+            Ok(KeyEvent {
+                ts: Default::default(),
+                cause: KeyEventCause::Press,
+                code,
+                scancode: None,
+            })
+        }))
+    }
+
+    fn codes(events: Buffered) -> Vec<KeyCode> {
+        block_on(events.map(|ev| ev.unwrap().code).collect())
+    }
+
+    #[test]
+    fn overflow_policies() {
+        use KeyCode::*;
+
+        // The pump runs to completion before the handler gets to read any events
+        let (pump, events) = bounded(presses(), 2, OverflowPolicy::DropOldest);
+
+        block_on(pump);
+        assert_eq!(events.dropped(), 2);
+        assert_eq!(codes(events), [KEY_C, KEY_D]);
+
+        let (pump, events) = bounded(presses(), 2, OverflowPolicy::DropNewest);
+
+        block_on(pump);
+        assert_eq!(events.dropped(), 2);
+        assert_eq!(codes(events), [KEY_A, KEY_B]);
+
+        // The pump waits for the handler
+        let (pump, events) = bounded(presses(), 1, OverflowPolicy::Block);
+        let (_, codes) = block_on(future::join(
+            pump,
+            events.map(|ev| ev.unwrap().code).collect::<Vec<_>>(),
+        ));
+
+        assert_eq!(codes, [KEY_A, KEY_B, KEY_C, KEY_D]);
+    }
+
+    #[test]
+    fn errors_are_kept() {
+        use KeyCode::*;
+
+        // An error between the events that fill the queue and the ones that overflow it
+        let with_error = || {
+            let mut evs = block_on(presses().collect::<Vec<_>>());
+
+            evs.insert(2, Err(KeyloggerError::InvalidKeyCode(0xffff)));
+            stream::iter(evs)
+        };
+        let items = |buffered: Buffered| buffered.map(|ev| ev.ok().map(|ev| ev.code)).collect();
+
+        let (pump, buffered) = bounded(with_error(), 2, OverflowPolicy::DropOldest);
+
+        block_on(pump);
+        assert_eq!(buffered.dropped(), 2);
+        assert_eq!(buffered.len(), 3);
+        assert_eq!(block_on(items(buffered)), [None, Some(KEY_C), Some(KEY_D)]);
+
+        let (pump, buffered) = bounded(with_error(), 2, OverflowPolicy::DropNewest);
+
+        block_on(pump);
+        assert_eq!(buffered.dropped(), 2);
+        assert_eq!(block_on(items(buffered)), [Some(KEY_A), Some(KEY_B), None]);
+
+        let (pump, buffered) = bounded(with_error(), 2, OverflowPolicy::Block);
+        let (_, items): (_, Vec<_>) = block_on(future::join(pump, items(buffered)));
+
+        assert_eq!(
+            items,
+            [Some(KEY_A), Some(KEY_B), None, Some(KEY_C), Some(KEY_D)]
+        );
+    }
+}
@@ -0,0 +1,774 @@
+//! Recording the captured events to a compact binary file, and reading them back.
+//!
+//! A [`CaptureWriter`] writes the events to a file (or any other [`Write`]r) in the capture
+//! format described below, and a [`CaptureReader`] reads them back. Unlike the text and JSON
+//! formats of the [`sink`](crate::sink) module, the capture format is lossless (it includes the
+//! scancodes of the events), and it's independent of the architecture and of the layout of the
+//! types of this crate, so the recordings can be replayed on other machines, and by later
+//! versions of the crate.
+//!
+//! # Format
+//!
+//! All the integers are little-endian. A capture file starts with an 8-byte header:
+//!
+//! | Offset | Size | Field                                           |
+//! |--------|------|-------------------------------------------------|
+//! | 0      | 4    | the magic number, `KLCP`                        |
+//! | 4      | 2    | the version of the format (currently 2)         |
+//! | 6      | 2    | reserved (0)                                    |
+//!
+//! The header is followed by the records, one for each event. Like in pcap files, each record has
+//! a header that contains the timestamp of the event (in UTC) and the length of its payload:
+//!
+//! | Offset | Size | Field                                           |
+//! |--------|------|-------------------------------------------------|
+//! | 0      | 8    | the seconds since the Unix epoch (signed)       |
+//! | 8      | 4    | the microseconds                                |
+//! | 12     | 4    | the length of the payload                       |
+//!
+//! The payload describes the event:
+//!
+//! | Offset | Size | Field                                           |
+//! |--------|------|-------------------------------------------------|
+//! | 0      | 2    | the key code (see [`KeyCode::code`])            |
+//! | 2      | 1    | the cause: 0 (press), 1 (release) or 2 (repeat) |
+//! | 3      | 1    | flags: bit 0 is set if the event has a scancode |
+//! | 4      | 4    | the scancode, or 0                              |
+//!
+//! Readers skip any bytes that follow the fields they know about, so future versions of the
+//! format can extend the payload without breaking existing readers.
+//!
+//! Since version 2, bit 1 of the flags marks the record as a checkpoint rather than an event. The
+//! timestamp of a checkpoint is the time it was written, its key code, cause and scancode are 0,
+//! and its payload is extended with the number of events recorded before it (8 bytes, unsigned).
+//!
+//! # Checkpoints
+//!
+//! A capture that's still being written when the process is killed (or when the machine loses
+//! power) usually ends with a partially written record. [`CaptureWriter::checkpoint`] writes a
+//! checkpoint, and flushes the events written before it: when the recording is interrupted, the
+//! events before the last checkpoint are intact. [`CaptureWriter::resume`] continues such a
+//! capture from its last checkpoint, discarding what was written after it, and
+//! [`CaptureReader::resume`] reads a capture starting from a checkpoint (e.g. one saved by a
+//! consumer that was interrupted while processing the capture):
+//!
+//! ```no_run
+//! use keylogger::capture::CaptureWriter;
+//! use keylogger::{find_keyboards, sink, KeyloggerError};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let keyboard = find_keyboards()?.remove(0);
+//!     let mut writer = CaptureWriter::resume("keys.klcp")?.checkpoint_every(64);
+//!
+//!     sink::forward(keyboard, &mut writer).await
+//! }
+//! ```
+//!
+//! # Compression
+//!
+//! With the `zstd` feature, `CaptureWriter::create_compressed` compresses the capture using
+//! zstd, which shrinks the long recordings considerably. The events are compressed in independent
+//! frames, one for each checkpoint, so an interrupted recording is intact up to its last
+//! checkpoint, like an uncompressed one. `CaptureReader::open_compressed` reads the compressed
+//! captures back.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use keylogger::capture::{CaptureReader, CaptureWriter};
+//! use keylogger::{find_keyboards, sink, KeyloggerError};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let keyboard = find_keyboards()?.remove(0);
+//!     let mut writer = CaptureWriter::create("keys.klcp")?;
+//!
+//!     // Record the first 100 events of the keyboard
+//!     sink::forward(keyboard.take(100), &mut writer).await?;
+//!
+//!     for ev in CaptureReader::open("keys.klcp")? {
+//!         println!("{:?}", ev?);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+
+use crate::error::KeyloggerError;
+use crate::key_code::KeyCode;
+use crate::keyboard::{KeyEvent, KeyEventCause};
+use crate::sink::KeyEventSink;
+use crate::KeyloggerResult;
+
+/// The magic number capture files start with.
+const MAGIC: [u8; 4] = *b"KLCP";
+/// The version of the format written by [`CaptureWriter`].
+pub const VERSION: u16 = 2;
+
+/// The size of the header of a capture.
+const HEADER_LEN: u64 = 8;
+/// The size of the header of a record.
+const RECORD_HEADER_LEN: usize = 16;
+/// The size of the payload of a record, in the current version of the format.
+const PAYLOAD_LEN: usize = 8;
+/// The size of the payload of a checkpoint record.
+const CHECKPOINT_PAYLOAD_LEN: usize = PAYLOAD_LEN + 8;
+/// The largest payload accepted by [`CaptureReader`], which guards against allocating huge
+/// buffers for corrupt files.
+const MAX_PAYLOAD_LEN: u32 = 1 << 16;
+
+/// The flag set if the event has a scancode.
+const FLAG_SCANCODE: u8 = 1 << 0;
+/// The flag set if the record is a checkpoint.
+const FLAG_CHECKPOINT: u8 = 1 << 1;
+
+/// A checkpoint of a capture (see the [module docs](self#checkpoints)).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Checkpoint {
+    /// The offset of the checkpoint record, from the start of the capture.
+    pub offset: u64,
+    /// The number of events recorded before the checkpoint.
+    pub events: u64,
+}
+
+/// Writes events in the capture format (see the [module docs](self)).
+#[derive(Debug)]
+pub struct CaptureWriter<W: Write> {
+    writer: W,
+    /// The number of bytes written so far, including the header.
+    offset: u64,
+    /// The number of events written so far.
+    events: u64,
+    /// The number of events after which a checkpoint is written automatically.
+    checkpoint_every: Option<u64>,
+    last_checkpoint: Option<Checkpoint>,
+}
+
+impl CaptureWriter<BufWriter<File>> {
+    /// Create a new capture file at the specified path, truncating it if it already exists.
+    pub fn create(path: impl AsRef<Path>) -> KeyloggerResult<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+
+    /// Continue writing the capture file at the specified path, after its last checkpoint.
+    ///
+    /// Whatever was written after the last checkpoint (including a partially written record, if
+    /// the recording was interrupted) is discarded, so the events written from now on follow the
+    /// events that were intact. If the capture has no checkpoints, only its header is kept, and a
+    /// new capture is started if the file doesn't exist or its header is incomplete.
+    ///
+    /// Returns [`KeyloggerError::InvalidCapture`] if the file contains something other than a
+    /// capture, or a capture written by a newer version of the format.
+    pub fn resume(path: impl AsRef<Path>) -> KeyloggerResult<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        if file.metadata()?.len() < HEADER_LEN {
+            file.set_len(0)?;
+            return Self::new(BufWriter::new(file));
+        }
+
+        let mut reader = CaptureReader::new(BufReader::new(&file))?;
+
+        // Stop at the first record that can't be read, which is usually the one being written
+        // when the recording was interrupted
+        loop {
+            match reader.read_event() {
+                Ok(Some(_)) => {}
+                Ok(None)
+                | Err(KeyloggerError::InvalidCapture(_) | KeyloggerError::InvalidTimestamp(..)) => {
+                    break
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let checkpoint = reader.last_checkpoint();
+        let end = checkpoint.map_or(HEADER_LEN, |c| {
+            c.offset + (RECORD_HEADER_LEN + CHECKPOINT_PAYLOAD_LEN) as u64
+        });
+
+        file.set_len(end)?;
+        // Captures written by older versions of the format are upgraded, as they can't contain
+        // checkpoints
+        file.seek(SeekFrom::Start(4))?;
+        file.write_all(&VERSION.to_le_bytes())?;
+        file.seek(SeekFrom::Start(end))?;
+
+        Ok(Self {
+            writer: BufWriter::new(file),
+            offset: end,
+            events: checkpoint.map_or(0, |c| c.events),
+            checkpoint_every: None,
+            last_checkpoint: checkpoint,
+        })
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl CaptureWriter<ZstdFrames<BufWriter<File>>> {
+    /// Create a new capture file at the specified path, compressed using zstd (see the
+    /// [module docs](self#compression)), truncating it if it already exists.
+    ///
+    /// A checkpoint (and a zstd frame) is written every `events_per_frame` events. Compressed
+    /// captures can't be resumed using [`CaptureWriter::resume`].
+    pub fn create_compressed(
+        path: impl AsRef<Path>,
+        events_per_frame: u64,
+    ) -> KeyloggerResult<Self> {
+        let frames = ZstdFrames::new(
+            BufWriter::new(File::create(path)?),
+            zstd::DEFAULT_COMPRESSION_LEVEL,
+        );
+
+        Ok(Self::new(frames)?.checkpoint_every(events_per_frame))
+    }
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Start a new capture, writing its header to `writer`.
+    pub fn new(mut writer: W) -> KeyloggerResult<Self> {
+        let mut header = [0; HEADER_LEN as usize];
+
+        header[..4].copy_from_slice(&MAGIC);
+        header[4..6].copy_from_slice(&VERSION.to_le_bytes());
+        writer.write_all(&header)?;
+
+        Ok(Self {
+            writer,
+            offset: HEADER_LEN,
+            events: 0,
+            checkpoint_every: None,
+            last_checkpoint: None,
+        })
+    }
+
+    /// Write a checkpoint automatically after every `events` events.
+    pub fn checkpoint_every(mut self, events: u64) -> Self {
+        self.checkpoint_every = Some(events.max(1));
+        self
+    }
+
+    /// Write a checkpoint, and flush the capture.
+    ///
+    /// The checkpoint only protects the capture against the termination of the process: if the
+    /// events must also survive a crash of the system, the file must be synced too.
+    pub fn checkpoint(&mut self) -> KeyloggerResult<Checkpoint> {
+        let ts = Utc::now();
+        let mut record = [0; RECORD_HEADER_LEN + CHECKPOINT_PAYLOAD_LEN];
+        let (header, payload) = record.split_at_mut(RECORD_HEADER_LEN);
+
+        header[..8].copy_from_slice(&ts.timestamp().to_le_bytes());
+        header[8..12].copy_from_slice(&ts.timestamp_subsec_micros().to_le_bytes());
+        header[12..].copy_from_slice(&(CHECKPOINT_PAYLOAD_LEN as u32).to_le_bytes());
+        payload[3] = FLAG_CHECKPOINT;
+        payload[PAYLOAD_LEN..].copy_from_slice(&self.events.to_le_bytes());
+
+        self.writer.write_all(&record)?;
+        self.writer.flush()?;
+
+        let checkpoint = Checkpoint {
+            offset: self.offset,
+            events: self.events,
+        };
+
+        self.offset += record.len() as u64;
+        self.last_checkpoint = Some(checkpoint);
+
+        Ok(checkpoint)
+    }
+
+    /// The last checkpoint of the capture, including the one it was resumed from.
+    pub fn last_checkpoint(&self) -> Option<Checkpoint> {
+        self.last_checkpoint
+    }
+
+    /// The number of events in the capture, including the ones written before it was resumed.
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+
+    /// Flush the capture, and return the underlying writer.
+    pub fn into_inner(mut self) -> KeyloggerResult<W> {
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> KeyEventSink for CaptureWriter<W> {
+    fn write_event(&mut self, ev: &KeyEvent) -> KeyloggerResult<()> {
+        let ts = ev.ts_utc();
+        let mut record = [0; RECORD_HEADER_LEN + PAYLOAD_LEN];
+        let (header, payload) = record.split_at_mut(RECORD_HEADER_LEN);
+
+        header[..8].copy_from_slice(&ts.timestamp().to_le_bytes());
+        header[8..12].copy_from_slice(&ts.timestamp_subsec_micros().to_le_bytes());
+        header[12..].copy_from_slice(&(PAYLOAD_LEN as u32).to_le_bytes());
+
+        payload[..2].copy_from_slice(&ev.code.code().to_le_bytes());
+        payload[2] = match ev.cause {
+            KeyEventCause::Press => 0,
+            KeyEventCause::Release => 1,
+            KeyEventCause::Repeat => 2,
+        };
+
+        if let Some(scancode) = ev.scancode {
+            payload[3] = FLAG_SCANCODE;
+            payload[4..].copy_from_slice(&scancode.to_le_bytes());
+        }
+
+        self.writer.write_all(&record)?;
+        self.offset += record.len() as u64;
+        self.events += 1;
+
+        let since_checkpoint = self.events - self.last_checkpoint.map_or(0, |c| c.events);
+
+        if self.checkpoint_every.is_some_and(|n| since_checkpoint >= n) {
+            self.checkpoint()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> KeyloggerResult<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// Reads the events of a capture (see the [module docs](self)).
+///
+/// The events can be read one at a time using [`CaptureReader::read_event`], or using the
+/// [`Iterator`] implementation of the reader. The checkpoints are skipped, but the last one read
+/// is available from [`CaptureReader::last_checkpoint`].
+#[derive(Debug)]
+pub struct CaptureReader<R: Read> {
+    reader: R,
+    version: u16,
+    /// The buffer the payloads are read into, reused across records.
+    payload: Vec<u8>,
+    /// The number of bytes read so far, including the header.
+    offset: u64,
+    last_checkpoint: Option<Checkpoint>,
+}
+
+/// A record of a capture.
+enum Record {
+    Event(KeyEvent),
+    Checkpoint(Checkpoint),
+}
+
+impl CaptureReader<BufReader<File>> {
+    /// Open the capture file at the specified path.
+    pub fn open(path: impl AsRef<Path>) -> KeyloggerResult<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl CaptureReader<zstd::Decoder<'static, BufReader<File>>> {
+    /// Open the capture file at the specified path, compressed using zstd (see
+    /// [`CaptureWriter::create_compressed`]).
+    pub fn open_compressed(path: impl AsRef<Path>) -> KeyloggerResult<Self> {
+        Self::new(zstd::Decoder::new(File::open(path)?)?)
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Start reading a capture, checking its header.
+    ///
+    /// Returns [`KeyloggerError::InvalidCapture`] if `reader` doesn't contain a capture, or if the
+    /// capture was written by a newer, incompatible version of the format.
+    pub fn new(mut reader: R) -> KeyloggerResult<Self> {
+        let mut header = [0; 8];
+
+        reader.read_exact(&mut header).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => invalid("truncated header"),
+            _ => e.into(),
+        })?;
+
+        if header[..4] != MAGIC {
+            return Err(invalid("not a capture file"));
+        }
+
+        let version = u16::from_le_bytes([header[4], header[5]]);
+
+        if version == 0 || version > VERSION {
+            return Err(invalid(format!("unsupported version {version}")));
+        }
+
+        Ok(Self {
+            reader,
+            version,
+            payload: Vec::with_capacity(PAYLOAD_LEN),
+            offset: HEADER_LEN,
+            last_checkpoint: None,
+        })
+    }
+
+    /// The version of the format the capture was written in.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// The last checkpoint read, including the one the reader was resumed from.
+    ///
+    /// The events read before it were intact when the checkpoint was written (see the
+    /// [module docs](self#checkpoints)).
+    pub fn last_checkpoint(&self) -> Option<Checkpoint> {
+        self.last_checkpoint
+    }
+
+    /// Read the next event, returning `None` at the end of the capture.
+    pub fn read_event(&mut self) -> KeyloggerResult<Option<KeyEvent>> {
+        loop {
+            match self.read_record()? {
+                Some(Record::Event(ev)) => return Ok(Some(ev)),
+                Some(Record::Checkpoint(checkpoint)) => self.last_checkpoint = Some(checkpoint),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Read the next record, returning `None` at the end of the capture.
+    fn read_record(&mut self) -> KeyloggerResult<Option<Record>> {
+        let mut header = [0; RECORD_HEADER_LEN];
+
+        if !read_record_header(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+
+        let secs = i64::from_le_bytes(header[..8].try_into().unwrap());
+        let usecs = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        let len = u32::from_le_bytes(header[12..].try_into().unwrap());
+
+        if !(PAYLOAD_LEN as u32..=MAX_PAYLOAD_LEN).contains(&len) {
+            return Err(invalid(format!("invalid record length {len}")));
+        }
+
+        self.payload.resize(len as usize, 0);
+        self.reader
+            .read_exact(&mut self.payload)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => invalid("truncated record"),
+                _ => e.into(),
+            })?;
+
+        let offset = self.offset;
+        let payload = &self.payload;
+
+        self.offset += (RECORD_HEADER_LEN + payload.len()) as u64;
+
+        if self.version >= 2 && payload[3] & FLAG_CHECKPOINT != 0 {
+            if payload.len() < CHECKPOINT_PAYLOAD_LEN {
+                return Err(invalid(format!("invalid checkpoint length {len}")));
+            }
+
+            let events = u64::from_le_bytes(payload[PAYLOAD_LEN..][..8].try_into().unwrap());
+
+            return Ok(Some(Record::Checkpoint(Checkpoint { offset, events })));
+        }
+
+        let ts = DateTime::from_timestamp(secs, usecs.saturating_mul(1000))
+            .filter(|_| usecs < 1_000_000)
+            .ok_or(KeyloggerError::InvalidTimestamp(secs, usecs.into()))?
+            .naive_utc();
+
+        let cause = match payload[2] {
+            0 => KeyEventCause::Press,
+            1 => KeyEventCause::Release,
+            2 => KeyEventCause::Repeat,
+            cause => return Err(invalid(format!("invalid cause {cause}"))),
+        };
+
+        let scancode = (payload[3] & FLAG_SCANCODE != 0)
+            .then(|| u32::from_le_bytes(payload[4..8].try_into().unwrap()));
+
+        Ok(Some(Record::Event(KeyEvent {
+            ts,
+            cause,
+            code: KeyCode::from_raw(u16::from_le_bytes([payload[0], payload[1]])),
+            scancode,
+        })))
+    }
+
+    /// Return the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read + Seek> CaptureReader<R> {
+    /// Start reading a capture from the specified checkpoint, skipping the events recorded before
+    /// it.
+    ///
+    /// Returns [`KeyloggerError::InvalidCapture`] if the capture doesn't contain the checkpoint.
+    pub fn resume(reader: R, checkpoint: Checkpoint) -> KeyloggerResult<Self> {
+        let mut capture = Self::new(reader)?;
+
+        capture.reader.seek(SeekFrom::Start(checkpoint.offset))?;
+        capture.offset = checkpoint.offset;
+
+        match capture.read_record() {
+            Ok(Some(Record::Checkpoint(read))) if read == checkpoint => {
+                capture.last_checkpoint = Some(checkpoint);
+
+                Ok(capture)
+            }
+            Ok(_)
+            | Err(KeyloggerError::InvalidCapture(_) | KeyloggerError::InvalidTimestamp(..)) => Err(
+                invalid(format!("no checkpoint at offset {}", checkpoint.offset)),
+            ),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = KeyloggerResult<KeyEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_event().transpose()
+    }
+}
+
+/// Compresses the bytes written to it using zstd, in a separate frame for each flush.
+///
+/// The bytes are buffered until the writer is flushed, when they're compressed into a frame and
+/// written to the underlying writer. A [`CaptureWriter`] flushes it at each checkpoint. The bytes
+/// written since the last flush are lost if the writer is dropped without being flushed.
+#[cfg(feature = "zstd")]
+#[derive(Debug)]
+pub struct ZstdFrames<W: Write> {
+    writer: W,
+    level: i32,
+    /// The bytes of the next frame.
+    buf: Vec<u8>,
+}
+
+#[cfg(feature = "zstd")]
+impl<W: Write> ZstdFrames<W> {
+    /// Compress the frames written to `writer` at the specified zstd compression level.
+    pub fn new(writer: W, level: i32) -> Self {
+        Self {
+            writer,
+            level,
+            buf: Vec::new(),
+        }
+    }
+
+    /// The underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Write the last frame, and return the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<W: Write> Write for ZstdFrames<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            self.writer
+                .write_all(&zstd::bulk::compress(&self.buf, self.level)?)?;
+            self.buf.clear();
+        }
+
+        self.writer.flush()
+    }
+}
+
+/// Read the header of the next record, returning `false` if the capture ended cleanly (before the
+/// first byte of the header).
+fn read_record_header(reader: &mut impl Read, header: &mut [u8]) -> KeyloggerResult<bool> {
+    let mut read = 0;
+
+    while read < header.len() {
+        match reader.read(&mut header[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(invalid("truncated record header")),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(true)
+}
+
+fn invalid(msg: impl Into<String>) -> KeyloggerError {
+    KeyloggerError::InvalidCapture(msg.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_roundtrip() {
+        let ev = |cause, scancode| KeyEvent {
+            ts: DateTime::from_timestamp(1_700_000_000, 250_000_000)
+                .unwrap()
+                .naive_utc(),
+            cause,
+            code: KeyCode::KEY_A,
+            scancode,
+        };
+
+        let events = [
+            ev(KeyEventCause::Press, Some(0x70004)),
+            ev(KeyEventCause::Repeat, None),
+            ev(KeyEventCause::Release, Some(0x70004)),
+        ];
+
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+
+        for ev in &events {
+            writer.write_event(ev).unwrap();
+        }
+
+        let capture = writer.into_inner().unwrap();
+
+        assert_eq!(&capture[..8], b"KLCP\x02\x00\x00\x00");
+        assert_eq!(capture.len(), 8 + 3 * 24);
+
+        let reader = CaptureReader::new(&capture[..]).unwrap();
+        let read = reader.collect::<KeyloggerResult<Vec<_>>>().unwrap();
+
+        assert_eq!(read, events);
+
+        // A truncated record is reported, rather than silently ignored
+        let mut reader = CaptureReader::new(&capture[..capture.len() - 1]).unwrap();
+
+        assert!(reader.nth(2).unwrap().is_err());
+        assert!(CaptureReader::new(&b"KLCP\x03\x00\x00\x00"[..]).is_err());
+    }
+
+    #[test]
+    fn checkpoint_resume() {
+        let dir = std::env::temp_dir().join(format!("keylogger-capture-{}", std::process::id()));
+        let path = dir.join("keys.klcp");
+        let ev = |code| KeyEvent {
+            ts: Default::default(),
+            cause: KeyEventCause::Press,
+            code,
+            scancode: None,
+        };
+
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut writer = CaptureWriter::create(&path).unwrap().checkpoint_every(2);
+
+        for code in [KeyCode::KEY_A, KeyCode::KEY_B, KeyCode::KEY_C] {
+            writer.write_event(&ev(code)).unwrap();
+        }
+
+        let checkpoint = writer.last_checkpoint().unwrap();
+
+        assert_eq!(
+            checkpoint,
+            Checkpoint {
+                offset: 8 + 2 * 24,
+                events: 2
+            }
+        );
+
+        // Simulate a crash while the fourth event was being written
+        let mut capture = writer.into_inner().unwrap().into_inner().unwrap();
+
+        capture.write_all(&[0; 5]).unwrap();
+        drop(capture);
+
+        let mut writer = CaptureWriter::resume(&path).unwrap();
+
+        assert_eq!(writer.last_checkpoint(), Some(checkpoint));
+        assert_eq!(writer.events(), 2);
+
+        writer.write_event(&ev(KeyCode::KEY_D)).unwrap();
+        writer.into_inner().unwrap();
+
+        let codes =
+            |reader: CaptureReader<_>| reader.map(|ev| ev.unwrap().code).collect::<Vec<_>>();
+
+        let reader = CaptureReader::open(&path).unwrap();
+
+        assert_eq!(
+            codes(reader),
+            [KeyCode::KEY_A, KeyCode::KEY_B, KeyCode::KEY_D]
+        );
+
+        let file = BufReader::new(File::open(&path).unwrap());
+
+        assert_eq!(
+            codes(CaptureReader::resume(file, checkpoint).unwrap()),
+            [KeyCode::KEY_D]
+        );
+
+        let file = BufReader::new(File::open(&path).unwrap());
+        let bogus = Checkpoint {
+            offset: 8,
+            events: 0,
+        };
+
+        assert!(CaptureReader::resume(file, bogus).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compressed_frames() {
+        let ev = KeyEvent {
+            ts: Default::default(),
+            cause: KeyEventCause::Press,
+            code: KeyCode::KEY_A,
+            scancode: None,
+        };
+
+        let frames = ZstdFrames::new(Vec::new(), zstd::DEFAULT_COMPRESSION_LEVEL);
+        let mut writer = CaptureWriter::new(frames).unwrap().checkpoint_every(100);
+
+        for _ in 0..250 {
+            writer.write_event(&ev).unwrap();
+        }
+
+        // The events after the last checkpoint haven't been compressed yet
+        let written = writer.writer.get_ref().clone();
+        let capture = writer.into_inner().unwrap().into_inner().unwrap();
+
+        assert!(capture.len() < 250 * 24 / 10);
+
+        let read = |capture: &[u8]| {
+            CaptureReader::new(zstd::Decoder::new(capture).unwrap())
+                .unwrap()
+                .map_while(Result::ok)
+                .count()
+        };
+
+        assert_eq!(read(&capture), 250);
+        assert_eq!(read(&written), 200);
+    }
+}
@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use chrono::naive::NaiveDateTime;
+use futures::{ready, Stream};
+use pin_project::pin_project;
+
+use crate::error::KeyloggerError;
+use crate::key_code::KeyCode;
+use crate::keyboard::{KeyEvent, KeyEventCause};
+use crate::KeyloggerResult;
+
+/// A modifier key, which matches both its left and right variants.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Modifier {
+    /// Either Ctrl key.
+    Ctrl,
+    /// Either Shift key.
+    Shift,
+    /// Either Alt key.
+    Alt,
+    /// Either Meta (Super) key.
+    Meta,
+}
+
+impl Modifier {
+    /// The modifier the specified key corresponds to, if any.
+    pub fn from_key_code(code: KeyCode) -> Option<Self> {
+        use KeyCode::*;
+
+        Some(match code {
+            KEY_LEFTCTRL | KEY_RIGHTCTRL => Self::Ctrl,
+            KEY_LEFTSHIFT | KEY_RIGHTSHIFT => Self::Shift,
+            KEY_LEFTALT | KEY_RIGHTALT => Self::Alt,
+            KEY_LEFTMETA | KEY_RIGHTMETA => Self::Meta,
+            _ => return None,
+        })
+    }
+}
+
+/// A key combination, such as `Ctrl+Shift+P`.
+///
+/// A chord is triggered when its final key is pressed while all of its modifiers (and no other
+/// keys) are held down. The modifiers can be pressed in any order, but the final key must be
+/// pressed last.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Chord {
+    modifiers: Vec<Modifier>,
+    key: KeyCode,
+}
+
+impl Chord {
+    /// Create a chord triggered by pressing `key` while holding down `modifiers`.
+    pub fn new(modifiers: impl IntoIterator<Item = Modifier>, key: KeyCode) -> Self {
+        let mut modifiers = modifiers.into_iter().collect::<Vec<_>>();
+
+        modifiers.sort_by_key(|m| *m as u8);
+        modifiers.dedup();
+
+        Self { modifiers, key }
+    }
+
+    /// The modifiers that must be held down.
+    pub fn modifiers(&self) -> &[Modifier] {
+        &self.modifiers
+    }
+
+    /// The key that triggers the chord.
+    pub fn key(&self) -> KeyCode {
+        self.key
+    }
+}
+
+impl FromStr for Chord {
+    type Err = KeyloggerError;
+
+    /// Parse a chord from a `+`-separated list of modifiers followed by a key name (e.g.
+    /// `Ctrl+Shift+P`).
+    ///
+    /// The modifier names are `Ctrl`, `Shift`, `Alt` and `Meta` (or `Super`), and are
+    /// case-insensitive. The key is parsed using the [`FromStr`] implementation of [`KeyCode`],
+    /// and can't be a modifier.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || KeyloggerError::InvalidChord(s.into());
+        let mut parts = s.split('+').map(str::trim).collect::<Vec<_>>();
+        let key = parts.pop().ok_or_else(err)?;
+        let key = key
+            .parse::<KeyCode>()
+            .ok()
+            .filter(|key| Modifier::from_key_code(*key).is_none())
+            .ok_or_else(err)?;
+
+        let modifiers = parts
+            .into_iter()
+            .map(|m| {
+                Some(match m.to_ascii_lowercase().as_str() {
+                    "ctrl" | "control" => Modifier::Ctrl,
+                    "shift" => Modifier::Shift,
+                    "alt" => Modifier::Alt,
+                    "meta" | "super" => Modifier::Meta,
+                    _ => return None,
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(err)?;
+
+        Ok(Self::new(modifiers, key))
+    }
+}
+
+/// Identifies a chord registered with a [`ChordDetector`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ChordId(usize);
+
+/// A registered chord was triggered.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ChordEvent {
+    /// The chord that was triggered.
+    pub id: ChordId,
+    /// The timestamp of the key press that completed the chord.
+    pub ts: NaiveDateTime,
+}
+
+/// Detects the registered [`Chord`]s in a sequence of [`KeyEvent`]s.
+#[derive(Clone, Debug, Default)]
+pub struct ChordDetector {
+    chords: Vec<Chord>,
+    /// The maximum time between the first and last key press of a chord.
+    timeout: Option<Duration>,
+    /// The keys currently held down, and when they were pressed.
+    held: HashMap<KeyCode, NaiveDateTime>,
+    /// Whether a key that isn't part of any chord was pressed since all keys were last released.
+    cancelled: bool,
+}
+
+impl ChordDetector {
+    /// Create a detector with no registered chords.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Only trigger chords whose keys are all pressed within `timeout` of each other.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Register a chord, returning the ID that identifies it in [`ChordEvent`]s.
+    pub fn register(&mut self, chord: Chord) -> ChordId {
+        self.chords.push(chord);
+
+        ChordId(self.chords.len() - 1)
+    }
+
+    /// The chord with the specified ID.
+    pub fn chord(&self, id: ChordId) -> Option<&Chord> {
+        self.chords.get(id.0)
+    }
+
+    /// Process the next event, returning the chord it triggered (if any).
+    ///
+    /// All events (including releases) should be fed to the detector in order, so it can keep
+    /// track of the keys that are held down.
+    pub fn feed(&mut self, ev: &KeyEvent) -> Option<ChordEvent> {
+        match ev.cause {
+            KeyEventCause::Press => {
+                self.held.insert(ev.code, ev.ts);
+            }
+            KeyEventCause::Release => {
+                self.held.remove(&ev.code);
+
+                if self.held.is_empty() {
+                    self.cancelled = false;
+                }
+
+                return None;
+            }
+            KeyEventCause::Repeat => return None,
+        }
+
+        if self.cancelled {
+            return None;
+        }
+
+        let id = self.chords.iter().position(|c| self.is_triggered(c, ev));
+
+        if id.is_none() && Modifier::from_key_code(ev.code).is_none() {
+            // A partial chord was interrupted by an unrelated key
+            self.cancelled = true;
+        }
+
+        Some(ChordEvent {
+            id: ChordId(id?),
+            ts: ev.ts,
+        })
+    }
+
+    /// Turn a stream of key events into a stream of the chords they trigger.
+    pub fn detect<S>(self, events: S) -> Chords<S>
+    where
+        S: Stream<Item = KeyloggerResult<KeyEvent>>,
+    {
+        Chords {
+            events,
+            detector: self,
+        }
+    }
+
+    /// Check whether the key press `ev` completes `chord`.
+    fn is_triggered(&self, chord: &Chord, ev: &KeyEvent) -> bool {
+        if chord.key != ev.code || self.held.len() != chord.modifiers.len() + 1 {
+            return false;
+        }
+
+        let modifiers_held = self
+            .held
+            .keys()
+            .filter(|code| **code != ev.code)
+            .all(|code| {
+                Modifier::from_key_code(*code).is_some_and(|m| chord.modifiers.contains(&m))
+            });
+
+        // Both Ctrl keys held down don't satisfy Ctrl+Shift
+        let all_modifiers_held = chord.modifiers.iter().all(|m| {
+            self.held
+                .keys()
+                .any(|code| Modifier::from_key_code(*code) == Some(*m))
+        });
+
+        let in_time = match (self.timeout, self.held.values().min()) {
+            (Some(timeout), Some(first)) => (ev.ts - *first)
+                .to_std()
+                .map(|elapsed| elapsed <= timeout)
+                .unwrap_or(true),
+            _ => true,
+        };
+
+        modifiers_held && all_modifiers_held && in_time
+    }
+}
+
+/// A [`Stream`] of [`ChordEvent`]s, created using [`ChordDetector::detect`].
+#[pin_project]
+pub struct Chords<S> {
+    #[pin]
+    events: S,
+    detector: ChordDetector,
+}
+
+impl<S> Chords<S> {
+    /// The detector used to detect the chords.
+    pub fn detector(&self) -> &ChordDetector {
+        &self.detector
+    }
+}
+
+impl<S> Stream for Chords<S>
+where
+    S: Stream<Item = KeyloggerResult<KeyEvent>>,
+{
+    type Item = KeyloggerResult<ChordEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            match ready!(this.events.as_mut().poll_next(cx)) {
+                Some(Ok(ev)) => {
+                    if let Some(chord_ev) = this.detector.feed(&ev) {
+                        return Poll::Ready(Some(Ok(chord_ev)));
+                    }
+                }
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use KeyCode::*;
+    use KeyEventCause::*;
+
+    fn ev(cause: KeyEventCause, code: KeyCode, ms: i64) -> KeyEvent {
+        KeyEvent {
+            ts: NaiveDateTime::default() + chrono::Duration::milliseconds(ms),
+            cause,
+            code,
+            scancode: None,
+        }
+    }
+
+    fn triggered(
+        detector: &mut ChordDetector,
+        evs: &[(KeyEventCause, KeyCode, i64)],
+    ) -> Vec<ChordId> {
+        evs.iter()
+            .filter_map(|(cause, code, ms)| detector.feed(&ev(*cause, *code, *ms)))
+            .map(|chord_ev| chord_ev.id)
+            .collect()
+    }
+
+    #[test]
+    fn parse_chord() {
+        let chord: Chord = "ctrl+Shift+p".parse().unwrap();
+
+        assert_eq!(chord, Chord::new([Modifier::Shift, Modifier::Ctrl], KEY_P));
+        assert_eq!("Alt+F4".parse::<Chord>().unwrap().key(), KEY_F4);
+        assert!("Hyper+P".parse::<Chord>().is_err());
+        assert!("Ctrl+".parse::<Chord>().is_err());
+    }
+
+    #[test]
+    fn detect_chords() {
+        let mut detector = ChordDetector::new();
+        let ctrl_shift_p = detector.register("Ctrl+Shift+P".parse().unwrap());
+        let ctrl_c = detector.register("Ctrl+C".parse().unwrap());
+
+        let chords = triggered(
+            &mut detector,
+            &[
+                // Modifiers pressed in any order
+                (Press, KEY_RIGHTSHIFT, 0),
+                (Press, KEY_LEFTCTRL, 1),
+                (Press, KEY_P, 2),
+                (Repeat, KEY_P, 3),
+                (Release, KEY_P, 4),
+                (Release, KEY_RIGHTSHIFT, 5),
+                (Press, KEY_C, 6),
+                (Release, KEY_C, 7),
+                (Release, KEY_LEFTCTRL, 8),
+                // The final key must be pressed last
+                (Press, KEY_C, 9),
+                (Press, KEY_LEFTCTRL, 10),
+                (Release, KEY_LEFTCTRL, 11),
+                (Release, KEY_C, 12),
+            ],
+        );
+
+        assert_eq!(chords, vec![ctrl_shift_p, ctrl_c]);
+    }
+
+    #[test]
+    fn cancel_partial_chord() {
+        let mut detector = ChordDetector::new().with_timeout(Duration::from_millis(100));
+        let ctrl_c = detector.register("Ctrl+C".parse().unwrap());
+
+        let chords = triggered(
+            &mut detector,
+            &[
+                // Interrupted by an unrelated key
+                (Press, KEY_LEFTCTRL, 0),
+                (Press, KEY_X, 1),
+                (Release, KEY_X, 2),
+                (Press, KEY_C, 3),
+                (Release, KEY_C, 4),
+                (Release, KEY_LEFTCTRL, 5),
+                // Too slow
+                (Press, KEY_LEFTCTRL, 10),
+                (Press, KEY_C, 200),
+                (Release, KEY_C, 201),
+                (Release, KEY_LEFTCTRL, 202),
+                (Press, KEY_LEFTCTRL, 300),
+                (Press, KEY_C, 310),
+            ],
+        );
+
+        assert_eq!(chords, vec![ctrl_c]);
+    }
+}
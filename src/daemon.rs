@@ -0,0 +1,146 @@
+//! Integration with systemd, for running the keylogger as a service.
+//!
+//! [`listen_fds`] returns the sockets passed to the service by systemd socket activation, which
+//! can be used to serve or write the captured events (for example, using
+//! [`EventServer::serve_tcp`](crate::net::EventServer::serve_tcp) or a
+//! [`UnixSocketSink`](crate::sink::UnixSocketSink)). The service manager can be notified of the
+//! state of the service using [`notify_ready`] and [`notify_watchdog`].
+//!
+//! All the functions are no-ops when the process isn't run by systemd.
+//!
+//! # Example
+//!
+//! A service of `Type=notify` with `WatchdogSec=` set, which serves the events on a socket
+//! activated by a `.socket` unit:
+//!
+//! ```no_run
+//! # #[cfg(feature = "net")]
+//! # mod example {
+//! use futures::StreamExt;
+//! use keylogger::net::{Encoding, EventServer};
+//! use keylogger::{daemon, merge_keyboards, KeyloggerError};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let server = EventServer::new(1024);
+//!
+//!     for fd in daemon::listen_fds()? {
+//!         let listener = std::net::TcpListener::from(fd);
+//!
+//!         listener.set_nonblocking(true)?;
+//!
+//!         let listener = tokio::net::TcpListener::from_std(listener)?;
+//!
+//!         tokio::spawn(server.clone().serve_tcp(listener, Encoding::JsonLines));
+//!     }
+//!
+//!     let mut keyboards = merge_keyboards()?;
+//!
+//!     daemon::notify_ready()?;
+//!
+//!     if let Some(interval) = daemon::watchdog_interval() {
+//!         std::thread::spawn(move || loop {
+//!             let _ = daemon::notify_watchdog();
+//!             std::thread::sleep(interval / 2);
+//!         });
+//!     }
+//!
+//!     while let Some(ev) = keyboards.next().await {
+//!         server.publish(ev?.event);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! # }
+//! ```
+
+use std::env;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::process;
+use std::time::Duration;
+
+use crate::KeyloggerResult;
+
+/// The first file descriptor passed by systemd (`SD_LISTEN_FDS_START`).
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Take ownership of the file descriptors passed to the process by systemd socket activation.
+///
+/// The file descriptors are returned in the order of the `Listen*=` directives of the socket
+/// unit. The environment variables that describe them are removed, so subsequent calls (and child
+/// processes) don't see them. An empty list is returned if the process wasn't socket-activated.
+pub fn listen_fds() -> KeyloggerResult<Vec<OwnedFd>> {
+    let pid = env::var("LISTEN_PID").ok();
+    let count = env::var("LISTEN_FDS").ok();
+
+    for var in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        env::remove_var(var);
+    }
+
+    // The variables are inherited by child processes, so they might not be meant for us
+    if pid.and_then(|pid| pid.parse::<u32>().ok()) != Some(process::id()) {
+        return Ok(vec![]);
+    }
+
+    let count = count.and_then(|n| n.parse::<RawFd>().ok()).unwrap_or(0);
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(|fd| {
+            // Don't leak the file descriptors to child processes
+            if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+
+            // systemd passes each file descriptor exactly once, and nothing else owns them
+            Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+        })
+        .collect()
+}
+
+/// Notify the service manager of a change in the state of the service (see `sd_notify(3)`).
+///
+/// `state` is a newline-separated list of assignments, such as `READY=1` or `STATUS=...`.
+pub fn notify(state: &str) -> KeyloggerResult<()> {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+
+    let addr = match path.as_encoded_bytes().strip_prefix(b"@") {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(&path)?,
+    };
+
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &addr)?;
+
+    Ok(())
+}
+
+/// Notify the service manager that the service finished starting up (`READY=1`).
+pub fn notify_ready() -> KeyloggerResult<()> {
+    notify("READY=1")
+}
+
+/// Notify the service manager that the service is still alive (`WATCHDOG=1`).
+///
+/// This must be called at least once every [`watchdog_interval`] for the service not to be
+/// considered hung.
+pub fn notify_watchdog() -> KeyloggerResult<()> {
+    notify("WATCHDOG=1")
+}
+
+/// The interval at which the service manager expects [`notify_watchdog`] to be called, if the
+/// watchdog is enabled for the service (`WatchdogSec=`).
+pub fn watchdog_interval() -> Option<Duration> {
+    // If WATCHDOG_PID is set, the watchdog is only enabled for that process
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(process::id()) {
+            return None;
+        }
+    }
+
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+
+    Some(Duration::from_micros(usec)).filter(|interval| !interval.is_zero())
+}
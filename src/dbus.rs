@@ -0,0 +1,108 @@
+//! Publish the captured [`KeyEvent`]s as D-Bus signals.
+//!
+//! A [`DbusEmitter`] emits a `KeyEvent` signal for each event, so that other applications (such
+//! as desktop applets) can subscribe to them without linking this crate. The signals are emitted
+//! by the object [`OBJECT_PATH`], on the interface [`INTERFACE`], with the following arguments:
+//!
+//! * `device` (`s`): the path of the keyboard (e.g. `/dev/input/event4`)
+//! * `timestamp` (`x`): the timestamp of the event, in microseconds since the Unix epoch
+//! * `cause` (`s`): `press`, `release` or `repeat`
+//! * `code` (`q`): the raw key code
+//! * `name` (`s`): the name of the key (e.g. `LEFTSHIFT`, see [`KeyCode`]'s `Display`
+//!   implementation)
+//!
+//! Anyone who can connect to the bus can subscribe to the signals. On the system bus, that's
+//! every user of the machine, unless the bus policy restricts who can receive them.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use keylogger::dbus::DbusEmitter;
+//! use keylogger::{merge_keyboards, KeyloggerError};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let emitter = DbusEmitter::session().await?;
+//!     let mut keyboards = merge_keyboards()?;
+//!
+//!     while let Some(ev) = keyboards.next().await {
+//!         let ev = ev?;
+//!         let path = keyboards.get(ev.device_id).map(|k| k.path().to_owned());
+//!
+//!         if let Some(path) = path {
+//!             emitter.emit(&path, &ev.event).await?;
+//!         }
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! [`KeyCode`]: crate::KeyCode
+
+use std::path::Path;
+
+use crate::error::KeyloggerError;
+use crate::keyboard::{KeyEvent, KeyEventCause};
+use crate::KeyloggerResult;
+
+/// The interface of the `KeyEvent` signal.
+pub const INTERFACE: &str = "io.github.gabi250.Keylogger1";
+/// The path of the object that emits the `KeyEvent` signal.
+pub const OBJECT_PATH: &str = "/io/github/gabi250/Keylogger1";
+/// The name of the signal emitted for each event.
+const SIGNAL_NAME: &str = "KeyEvent";
+
+/// Emits the key events as D-Bus signals (see the [module-level documentation](self)).
+#[derive(Clone, Debug)]
+pub struct DbusEmitter {
+    conn: zbus::Connection,
+}
+
+impl DbusEmitter {
+    /// Create an emitter that emits the signals on the system bus.
+    pub async fn system() -> KeyloggerResult<Self> {
+        let conn = zbus::Connection::system().await.map_err(dbus_err)?;
+
+        Ok(Self::new(conn))
+    }
+
+    /// Create an emitter that emits the signals on the session bus of the current user.
+    pub async fn session() -> KeyloggerResult<Self> {
+        let conn = zbus::Connection::session().await.map_err(dbus_err)?;
+
+        Ok(Self::new(conn))
+    }
+
+    /// Create an emitter that uses the specified connection.
+    pub fn new(conn: zbus::Connection) -> Self {
+        Self { conn }
+    }
+
+    /// Emit the signal for an event of the keyboard with the specified path.
+    pub async fn emit(&self, device: &Path, ev: &KeyEvent) -> KeyloggerResult<()> {
+        let cause = match ev.cause {
+            KeyEventCause::Press => "press",
+            KeyEventCause::Release => "release",
+            KeyEventCause::Repeat => "repeat",
+        };
+
+        let body = (
+            device.to_string_lossy().into_owned(),
+            ev.ts.and_utc().timestamp_micros(),
+            cause,
+            ev.code.code(),
+            ev.code.to_string(),
+        );
+
+        self.conn
+            .emit_signal(None::<()>, OBJECT_PATH, INTERFACE, SIGNAL_NAME, &body)
+            .await
+            .map_err(dbus_err)
+    }
+}
+
+fn dbus_err(e: zbus::Error) -> KeyloggerError {
+    KeyloggerError::Dbus(e.to_string())
+}
@@ -0,0 +1,319 @@
+//! Measure the rollover of a keyboard, and detect its ghosting.
+//!
+//! Most keyboards can't report every combination of keys held down at the same time: the number
+//! of keys they report simultaneously (their rollover) is limited, and pressing some combinations
+//! of keys either causes the keyboard to report keys that weren't pressed (ghosting), or to drop
+//! some of the keys that were (blocking).
+//!
+//! A [`RolloverTest`] asks the user to press a series of key combinations, and compares the keys
+//! the keyboard reported to the keys of each combination. [`run_rollover_test`] runs it
+//! interactively on a stream of key events:
+//!
+//! ```no_run
+//! use keylogger::diagnostics::{run_rollover_test, RolloverTest};
+//! use keylogger::{find_keyboards, KeyloggerError};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let mut keyboard = find_keyboards()?.remove(0);
+//!     let test = RolloverTest::standard();
+//!
+//!     // Keep the keys pressed during the test from reaching other applications
+//!     keyboard.grab()?;
+//!
+//!     let report = run_rollover_test(&mut keyboard, test, |keys| {
+//!         println!("Press and hold {keys:?}, then release them");
+//!     })
+//!     .await?;
+//!
+//!     println!("{}-key rollover", report.max_simultaneous);
+//!
+//!     for result in report.results.iter().filter(|r| !r.passed()) {
+//!         println!("{result:?}");
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::HashSet;
+
+use futures::{Stream, StreamExt};
+
+use crate::key_code::KeyCode;
+use crate::keyboard::{KeyEvent, KeyEventCause};
+use crate::KeyloggerResult;
+
+/// The outcome of pressing one of the combinations of a [`RolloverTest`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ComboResult {
+    /// The keys the user was asked to press.
+    pub expected: Vec<KeyCode>,
+    /// The largest number of keys the keyboard reported held down at the same time.
+    pub max_held: usize,
+    /// The keys of the combination that weren't reported while the others were held down
+    /// (blocked keys, if the user did press them).
+    pub missing: Vec<KeyCode>,
+    /// The keys that were reported, but aren't part of the combination (ghost keys, if the user
+    /// didn't press them).
+    pub ghosts: Vec<KeyCode>,
+}
+
+impl ComboResult {
+    /// Whether the keyboard reported exactly the keys of the combination.
+    pub fn passed(&self) -> bool {
+        self.missing.is_empty() && self.ghosts.is_empty()
+    }
+}
+
+/// The results of a [`RolloverTest`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RolloverReport {
+    /// The largest number of keys the keyboard reported held down at the same time, across all
+    /// the combinations.
+    pub max_simultaneous: usize,
+    /// The results of the combinations that were pressed, in order.
+    pub results: Vec<ComboResult>,
+}
+
+impl RolloverReport {
+    /// The results of the combinations that caused the keyboard to report keys that weren't
+    /// pressed.
+    pub fn ghosted(&self) -> impl Iterator<Item = &ComboResult> {
+        self.results.iter().filter(|r| !r.ghosts.is_empty())
+    }
+
+    /// The results of the combinations some keys of which weren't reported.
+    pub fn blocked(&self) -> impl Iterator<Item = &ComboResult> {
+        self.results.iter().filter(|r| !r.missing.is_empty())
+    }
+}
+
+/// An interactive rollover and ghosting test (see the [module docs](self)).
+///
+/// The combinations are pressed one at a time: each attempt starts when a key is pressed, and ends
+/// once all the keys are released. The events must be fed to the test using
+/// [`RolloverTest::record`].
+#[derive(Clone, Debug)]
+pub struct RolloverTest {
+    combinations: Vec<Vec<KeyCode>>,
+    /// The index of the combination being pressed.
+    current: usize,
+    /// The keys currently held down.
+    held: HashSet<KeyCode>,
+    /// The keys reported during the current attempt.
+    seen: HashSet<KeyCode>,
+    /// The largest set of keys held down during the current attempt.
+    peak: HashSet<KeyCode>,
+    report: RolloverReport,
+}
+
+impl RolloverTest {
+    /// Create a test that asks the user to press the specified combinations, in order.
+    pub fn new(combinations: impl IntoIterator<Item = Vec<KeyCode>>) -> Self {
+        Self {
+            combinations: combinations.into_iter().collect(),
+            current: 0,
+            held: HashSet::new(),
+            seen: HashSet::new(),
+            peak: HashSet::new(),
+            report: RolloverReport::default(),
+        }
+    }
+
+    /// A test of increasingly large combinations of letter keys, followed by the combinations of
+    /// modifiers and letters that commonly cause ghosting on keyboards without anti-ghosting
+    /// (three keys forming the corners of a rectangle in the key matrix of many keyboards).
+    pub fn standard() -> Self {
+        use KeyCode::*;
+
+        Self::new([
+            vec![KEY_A, KEY_S],
+            vec![KEY_A, KEY_S, KEY_D],
+            vec![KEY_A, KEY_S, KEY_D, KEY_F],
+            vec![KEY_A, KEY_S, KEY_D, KEY_F, KEY_J, KEY_K],
+            vec![KEY_Q, KEY_W, KEY_E, KEY_R, KEY_U, KEY_I, KEY_O, KEY_P],
+            vec![KEY_W, KEY_A, KEY_S, KEY_D, KEY_SPACE],
+            vec![KEY_LEFTSHIFT, KEY_W, KEY_D, KEY_SPACE],
+            vec![KEY_LEFTCTRL, KEY_LEFTSHIFT, KEY_Z, KEY_X],
+            vec![KEY_Q, KEY_W, KEY_A],
+            vec![KEY_E, KEY_R, KEY_D],
+        ])
+    }
+
+    /// The combination the user should press next, or `None` once the test is complete.
+    pub fn current(&self) -> Option<&[KeyCode]> {
+        self.combinations.get(self.current).map(Vec::as_slice)
+    }
+
+    /// Whether all the combinations were pressed.
+    pub fn is_complete(&self) -> bool {
+        self.current >= self.combinations.len()
+    }
+
+    /// Record the specified event, returning the result of the current combination if the event
+    /// completes the attempt to press it.
+    ///
+    /// Autorepeat events are ignored, and so are the events received once the test is complete.
+    pub fn record(&mut self, ev: &KeyEvent) -> Option<ComboResult> {
+        if self.is_complete() {
+            return None;
+        }
+
+        match ev.cause {
+            KeyEventCause::Press => {
+                self.held.insert(ev.code);
+                self.seen.insert(ev.code);
+
+                if self.held.len() > self.peak.len() {
+                    self.peak = self.held.clone();
+                }
+
+                self.report.max_simultaneous = self.report.max_simultaneous.max(self.held.len());
+
+                None
+            }
+            KeyEventCause::Release => {
+                // The keys held down when the test started don't start an attempt
+                if !self.held.remove(&ev.code) || !self.held.is_empty() {
+                    return None;
+                }
+
+                let expected = &self.combinations[self.current];
+                let result = ComboResult {
+                    expected: expected.clone(),
+                    max_held: self.peak.len(),
+                    missing: expected
+                        .iter()
+                        .filter(|code| !self.peak.contains(code))
+                        .copied()
+                        .collect(),
+                    ghosts: sorted(self.seen.drain().filter(|code| !expected.contains(code))),
+                };
+
+                self.peak.clear();
+                self.current += 1;
+                self.report.results.push(result.clone());
+
+                Some(result)
+            }
+            KeyEventCause::Repeat => None,
+        }
+    }
+
+    /// The results of the combinations pressed so far.
+    pub fn report(&self) -> &RolloverReport {
+        &self.report
+    }
+
+    /// Consume the test, returning the results of the combinations pressed.
+    pub fn into_report(self) -> RolloverReport {
+        self.report
+    }
+}
+
+/// Run the specified test on a stream of key events, until all its combinations are pressed (or
+/// until the stream ends).
+///
+/// `prompt` is called with each combination the user should press, before the events of the
+/// attempt to press it are read.
+pub async fn run_rollover_test<S>(
+    mut events: S,
+    mut test: RolloverTest,
+    mut prompt: impl FnMut(&[KeyCode]),
+) -> KeyloggerResult<RolloverReport>
+where
+    S: Stream<Item = KeyloggerResult<KeyEvent>> + Unpin,
+{
+    while let Some(combination) = test.current() {
+        prompt(combination);
+
+        loop {
+            let Some(ev) = events.next().await else {
+                return Ok(test.into_report());
+            };
+
+            if test.record(&ev?).is_some() {
+                break;
+            }
+        }
+    }
+
+    Ok(test.into_report())
+}
+
+/// The specified keys, sorted by their code.
+fn sorted(codes: impl Iterator<Item = KeyCode>) -> Vec<KeyCode> {
+    let mut codes = codes.collect::<Vec<_>>();
+
+    codes.sort_by_key(|code| code.code());
+    codes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use KeyCode::*;
+    use KeyEventCause::*;
+
+    fn ev(cause: KeyEventCause, code: KeyCode) -> KeyEvent {
+        KeyEvent {
+            ts: Default::default(),
+            cause,
+            code,
+            scancode: None,
+        }
+    }
+
+    #[test]
+    fn rollover_test() {
+        let mut test = RolloverTest::new([
+            vec![KEY_A, KEY_S],
+            vec![KEY_Q, KEY_W, KEY_A],
+            vec![KEY_A, KEY_S, KEY_D, KEY_F],
+        ]);
+
+        // Released before the test started
+        assert_eq!(test.record(&ev(Release, KEY_ENTER)), None);
+
+        let evs = [
+            ev(Press, KEY_A),
+            ev(Press, KEY_S),
+            ev(Repeat, KEY_S),
+            ev(Release, KEY_A),
+            ev(Release, KEY_S),
+            // The keyboard reports a ghost key
+            ev(Press, KEY_Q),
+            ev(Press, KEY_W),
+            ev(Press, KEY_A),
+            ev(Press, KEY_S),
+            ev(Release, KEY_Q),
+            ev(Release, KEY_W),
+            ev(Release, KEY_A),
+            ev(Release, KEY_S),
+            // The keyboard drops the fourth key
+            ev(Press, KEY_A),
+            ev(Press, KEY_S),
+            ev(Press, KEY_D),
+            ev(Release, KEY_A),
+            ev(Release, KEY_S),
+            ev(Release, KEY_D),
+        ];
+
+        let results = evs
+            .iter()
+            .filter_map(|ev| test.record(ev))
+            .collect::<Vec<_>>();
+
+        assert!(test.is_complete());
+        assert_eq!(results.len(), 3);
+        assert!(results[0].passed());
+        assert_eq!(results[1].ghosts, [KEY_S]);
+        assert_eq!(results[1].max_held, 4);
+        assert_eq!(results[2].missing, [KEY_F]);
+        assert_eq!(test.report().max_simultaneous, 4);
+        assert_eq!(test.report().ghosted().count(), 1);
+        assert_eq!(test.report().blocked().count(), 1);
+        assert_eq!(test.record(&ev(Press, KEY_A)), None);
+    }
+}
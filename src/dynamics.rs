@@ -0,0 +1,476 @@
+//! Extract the timing features used for keystroke dynamics (typing biometrics) research.
+//!
+//! A [`DynamicsSession`] records the key events of a typing session of a single user, and
+//! computes:
+//!
+//! * the dwell time of each keystroke: the time elapsed between pressing and releasing the key
+//!   (see [`Keystroke::dwell`])
+//! * the latency of each digraph (pair of consecutive keystrokes): the time elapsed between
+//!   pressing the first key and pressing the second one (see [`Digraph::latency`])
+//! * the flight time of each digraph: the time elapsed between releasing the first key and
+//!   pressing the second one, which is negative if the keystrokes overlap (see
+//!   [`Digraph::flight`])
+//!
+//! [`DynamicsSession::feature_vector`] aggregates them into a vector of fixed length, which can be
+//! fed to a classifier.
+//!
+//! The time each key is held down can also be computed as the events are read, by turning a
+//! stream of key events into a stream of [`KeyHold`]s (see [`KeyEventStreamExt::holds`]).
+//!
+//! The features identify the keys that were typed, so they should be treated as sensitive as the
+//! keystrokes themselves.
+
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use chrono::naive::NaiveDateTime;
+use futures::{ready, Stream};
+use pin_project::pin_project;
+
+#[cfg(doc)]
+use crate::filters::KeyEventStreamExt;
+use crate::key_code::KeyCode;
+use crate::keyboard::{KeyEvent, KeyEventCause};
+use crate::KeyloggerResult;
+
+/// The default maximum latency of a digraph.
+const DEFAULT_MAX_LATENCY: Duration = Duration::from_secs(1);
+
+/// A key being pressed and released.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Keystroke {
+    /// The key.
+    pub code: KeyCode,
+    /// The timestamp of the key press.
+    pub press: NaiveDateTime,
+    /// The timestamp of the key release.
+    pub release: NaiveDateTime,
+}
+
+impl Keystroke {
+    /// The time elapsed between pressing and releasing the key.
+    pub fn dwell(&self) -> Duration {
+        (self.release - self.press).to_std().unwrap_or_default()
+    }
+}
+
+/// A key being held down (see [`KeyEventStreamExt::holds`]).
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyHold {
+    /// The key.
+    pub code: KeyCode,
+    /// The timestamp of the key press.
+    pub press: NaiveDateTime,
+    /// How long the key was held down.
+    ///
+    /// If the release of the key was missed, this is the time elapsed between the press and the
+    /// last event that showed the key was still held down.
+    pub duration: Duration,
+    /// Whether the release of the key was seen. Otherwise, [`KeyHold::duration`] is a lower
+    /// bound.
+    pub released: bool,
+}
+
+/// A stream adapter that pairs the presses and releases of the keys (see
+/// [`KeyEventStreamExt::holds`]).
+#[pin_project]
+pub struct Holds<S> {
+    #[pin]
+    pub(crate) events: S,
+    pub(crate) tracker: HoldTracker,
+    /// The holds that are ready to be yielded, oldest first.
+    pub(crate) ready: VecDeque<KeyHold>,
+    /// Whether the stream of events ended.
+    pub(crate) done: bool,
+}
+
+impl<S> Stream for Holds<S>
+where
+    S: Stream<Item = KeyloggerResult<KeyEvent>>,
+{
+    type Item = KeyloggerResult<KeyHold>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            if let Some(hold) = this.ready.pop_front() {
+                return Poll::Ready(Some(Ok(hold)));
+            }
+
+            if *this.done {
+                return Poll::Ready(None);
+            }
+
+            match ready!(this.events.as_mut().poll_next(cx)) {
+                Some(Ok(ev)) => this.ready.extend(this.tracker.record(&ev)),
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => {
+                    *this.done = true;
+                    this.ready.extend(this.tracker.finish());
+                }
+            }
+        }
+    }
+}
+
+/// Pairs the presses and releases of the keys, detecting the releases that were missed.
+#[derive(Clone, Debug)]
+pub(crate) struct HoldTracker {
+    timeout: Duration,
+    /// The timestamp of the press of each key held down, and of its last event.
+    held: HashMap<KeyCode, (NaiveDateTime, NaiveDateTime)>,
+}
+
+impl HoldTracker {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            held: Default::default(),
+        }
+    }
+
+    /// Record the specified event, returning the holds it completes, ordered by the time their
+    /// key was pressed.
+    fn record(&mut self, ev: &KeyEvent) -> Vec<KeyHold> {
+        let timeout = chrono::Duration::from_std(self.timeout).unwrap_or(chrono::Duration::MAX);
+        let mut holds = vec![];
+
+        // The keys that didn't send any autorepeat events for too long were released
+        self.held.retain(|code, (press, last)| {
+            let expired = ev.ts - *last > timeout;
+
+            if expired {
+                holds.push(missed_release(*code, *press, *last));
+            }
+
+            !expired
+        });
+
+        match ev.cause {
+            KeyEventCause::Press => {
+                // The key was released in between
+                if let Some((press, last)) = self.held.insert(ev.code, (ev.ts, ev.ts)) {
+                    holds.push(missed_release(ev.code, press, last));
+                }
+            }
+            KeyEventCause::Repeat => {
+                if let Some((_, last)) = self.held.get_mut(&ev.code) {
+                    *last = ev.ts;
+                }
+            }
+            KeyEventCause::Release => {
+                if let Some((press, _)) = self.held.remove(&ev.code) {
+                    holds.push(KeyHold {
+                        code: ev.code,
+                        press,
+                        duration: (ev.ts - press).to_std().unwrap_or_default(),
+                        released: true,
+                    });
+                }
+            }
+        }
+
+        holds.sort_by_key(|hold| hold.press);
+        holds
+    }
+
+    /// Return the holds of the keys that are still held down, ordered by the time they were
+    /// pressed.
+    fn finish(&mut self) -> Vec<KeyHold> {
+        let mut holds = self
+            .held
+            .drain()
+            .map(|(code, (press, last))| missed_release(code, press, last))
+            .collect::<Vec<_>>();
+
+        holds.sort_by_key(|hold| hold.press);
+        holds
+    }
+}
+
+/// The hold of a key whose release was missed.
+fn missed_release(code: KeyCode, press: NaiveDateTime, last: NaiveDateTime) -> KeyHold {
+    KeyHold {
+        code,
+        press,
+        duration: (last - press).to_std().unwrap_or_default(),
+        released: false,
+    }
+}
+
+/// A pair of consecutive keystrokes.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Digraph {
+    /// The key pressed first.
+    pub first: KeyCode,
+    /// The key pressed second.
+    pub second: KeyCode,
+    /// The time elapsed between the two key presses (the "down-down" time).
+    pub latency: Duration,
+    /// The time elapsed between releasing the first key and pressing the second one (the
+    /// "up-down" time). This is negative if the second key was pressed before the first one was
+    /// released.
+    pub flight: chrono::Duration,
+}
+
+/// Collects the keystrokes of a typing session (see the [module-level documentation](self)).
+///
+/// Autorepeat events are ignored, and so are the keys that are still held down.
+#[derive(Clone, Debug)]
+pub struct DynamicsSession {
+    max_latency: Duration,
+    /// The timestamps of the presses of the keys currently held down.
+    held: HashMap<KeyCode, NaiveDateTime>,
+    /// The completed keystrokes, ordered by the time they were pressed.
+    keystrokes: Vec<Keystroke>,
+}
+
+impl DynamicsSession {
+    /// Create an empty session, where the keystrokes pressed more than one second apart don't
+    /// form a digraph.
+    pub fn new() -> Self {
+        Self::with_max_latency(DEFAULT_MAX_LATENCY)
+    }
+
+    /// Create an empty session, where the keystrokes pressed more than `max_latency` apart don't
+    /// form a digraph (as the pause is most likely not part of the typing rhythm).
+    pub fn with_max_latency(max_latency: Duration) -> Self {
+        Self {
+            max_latency,
+            held: Default::default(),
+            keystrokes: vec![],
+        }
+    }
+
+    /// Record the specified event.
+    pub fn record(&mut self, ev: &KeyEvent) {
+        match ev.cause {
+            KeyEventCause::Press => {
+                self.held.entry(ev.code).or_insert(ev.ts);
+            }
+            KeyEventCause::Release => {
+                let Some(press) = self.held.remove(&ev.code) else {
+                    return;
+                };
+
+                let keystroke = Keystroke {
+                    code: ev.code,
+                    press,
+                    release: ev.ts,
+                };
+
+                // Overlapping keystrokes are released out of order
+                let idx = self.keystrokes.partition_point(|k| k.press <= press);
+
+                self.keystrokes.insert(idx, keystroke);
+            }
+            KeyEventCause::Repeat => {}
+        }
+    }
+
+    /// The completed keystrokes, ordered by the time they were pressed.
+    pub fn keystrokes(&self) -> &[Keystroke] {
+        &self.keystrokes
+    }
+
+    /// The pairs of consecutive keystrokes, ordered by the time their first key was pressed.
+    pub fn digraphs(&self) -> impl Iterator<Item = Digraph> + '_ {
+        self.keystrokes.windows(2).filter_map(|pair| {
+            let [first, second] = pair else {
+                return None;
+            };
+
+            let latency = (second.press - first.press).to_std().ok()?;
+
+            (latency <= self.max_latency).then(|| Digraph {
+                first: first.code,
+                second: second.code,
+                latency,
+                flight: second.press - first.release,
+            })
+        })
+    }
+
+    /// Aggregate the features of the session into a vector.
+    ///
+    /// The vector contains the mean dwell time of each of the specified `keys`, followed by the
+    /// mean latency and the mean flight time of each of the specified `digraphs`, in
+    /// milliseconds. The features that couldn't be computed (because the key or digraph wasn't
+    /// typed) are NaN.
+    pub fn feature_vector(&self, keys: &[KeyCode], digraphs: &[(KeyCode, KeyCode)]) -> Vec<f64> {
+        let mut dwell = HashMap::<_, Mean>::new();
+        let mut timing = HashMap::<_, (Mean, Mean)>::new();
+
+        for keystroke in &self.keystrokes {
+            dwell
+                .entry(keystroke.code)
+                .or_default()
+                .add(keystroke.dwell().as_secs_f64() * 1000.0);
+        }
+
+        for digraph in self.digraphs() {
+            let (latency, flight) = timing.entry((digraph.first, digraph.second)).or_default();
+
+            latency.add(digraph.latency.as_secs_f64() * 1000.0);
+            flight.add(digraph.flight.num_microseconds().unwrap_or(i64::MAX) as f64 / 1000.0);
+        }
+
+        let dwell_features = keys
+            .iter()
+            .map(|code| dwell.get(code).map_or(f64::NAN, Mean::get));
+
+        let digraph_features = digraphs.iter().flat_map(|pair| {
+            let (latency, flight) = timing.get(pair).copied().unwrap_or_default();
+
+            [latency.get(), flight.get()]
+        });
+
+        dwell_features.chain(digraph_features).collect()
+    }
+}
+
+impl Default for DynamicsSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A running mean.
+#[derive(Copy, Clone, Debug, Default)]
+struct Mean {
+    sum: f64,
+    count: u32,
+}
+
+impl Mean {
+    fn add(&mut self, value: f64) {
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// The mean of the values, or NaN if there are none.
+    fn get(&self) -> f64 {
+        self.sum / f64::from(self.count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use KeyCode::*;
+
+    fn ev(cause: KeyEventCause, code: KeyCode, ms: i64) -> KeyEvent {
+        KeyEvent {
+            ts: NaiveDateTime::default() + chrono::Duration::milliseconds(ms),
+            cause,
+            code,
+            scancode: None,
+        }
+    }
+
+    #[test]
+    fn key_holds() {
+        use KeyEventCause::*;
+
+        let mut tracker = HoldTracker::new(Duration::from_millis(500));
+        let evs = [
+            ev(Press, KEY_A, 0),
+            ev(Press, KEY_B, 50),
+            ev(Release, KEY_A, 100),
+            // The release of B was missed
+            ev(Press, KEY_B, 150),
+            ev(Repeat, KEY_B, 600),
+            ev(Repeat, KEY_B, 1000),
+            ev(Release, KEY_B, 1050),
+            // The release of C was missed, and C stopped repeating
+            ev(Press, KEY_C, 2000),
+            ev(Press, KEY_D, 3000),
+        ];
+
+        let mut holds = evs
+            .iter()
+            .flat_map(|ev| tracker.record(ev))
+            .collect::<Vec<_>>();
+
+        holds.extend(tracker.finish());
+
+        let holds = holds
+            .into_iter()
+            .map(|hold| (hold.code, hold.duration.as_millis(), hold.released))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            holds,
+            [
+                (KEY_A, 100, true),
+                (KEY_B, 0, false),
+                (KEY_B, 900, true),
+                (KEY_C, 0, false),
+                (KEY_D, 0, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn dynamics_features() {
+        use KeyEventCause::*;
+
+        let mut session = DynamicsSession::new();
+        let evs = [
+            ev(Press, KEY_T, 0),
+            ev(Release, KEY_T, 80),
+            ev(Press, KEY_H, 200),
+            // The keystrokes overlap
+            ev(Press, KEY_E, 250),
+            ev(Repeat, KEY_H, 260),
+            ev(Release, KEY_H, 300),
+            ev(Release, KEY_E, 350),
+            // Too long after the previous keystroke to form a digraph
+            ev(Press, KEY_T, 5000),
+            ev(Release, KEY_T, 5120),
+            // Still held down
+            ev(Press, KEY_H, 5200),
+        ];
+
+        for ev in &evs {
+            session.record(ev);
+        }
+
+        let codes = session
+            .keystrokes()
+            .iter()
+            .map(|k| k.code)
+            .collect::<Vec<_>>();
+
+        assert_eq!(codes, [KEY_T, KEY_H, KEY_E, KEY_T]);
+        assert_eq!(
+            session.digraphs().collect::<Vec<_>>(),
+            [
+                Digraph {
+                    first: KEY_T,
+                    second: KEY_H,
+                    latency: Duration::from_millis(200),
+                    flight: chrono::Duration::milliseconds(120),
+                },
+                Digraph {
+                    first: KEY_H,
+                    second: KEY_E,
+                    latency: Duration::from_millis(50),
+                    flight: chrono::Duration::milliseconds(-50),
+                },
+            ]
+        );
+
+        let features = session.feature_vector(&[KEY_T, KEY_Q], &[(KEY_T, KEY_H), (KEY_E, KEY_T)]);
+
+        assert_eq!(features[0], 100.0);
+        assert!(features[1].is_nan());
+        assert_eq!(features[2..4], [200.0, 120.0]);
+        assert!(features[4..].iter().all(|f| f.is_nan()));
+    }
+}
@@ -1,11 +1,41 @@
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use thiserror::Error;
 
 use crate::key_code::KeyCode;
 
+/// The category of a [`KeyloggerError`] (see [`KeyloggerError::kind`]), for deciding how to
+/// handle it without matching on the variants of the error, or on the OS error codes.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ErrorKind {
+    /// The device (or the other end of a connection) went away, e.g. because the keyboard was
+    /// unplugged. The operation may succeed once the device reappears.
+    Disconnected,
+    /// The operation was interrupted, would have blocked, timed out, or the device was busy. The
+    /// operation may succeed if it's retried.
+    Transient,
+    /// Access to the device was denied, or the other end of a connection failed to authenticate.
+    PermissionDenied,
+    /// The device doesn't support the operation (e.g. it's not a keyboard).
+    Unsupported,
+    /// An input (such as an event, a key name, a layout or a capture file) was invalid.
+    InvalidInput,
+    /// An external service (such as D-Bus, a gRPC collector, the window system or a database)
+    /// reported an error.
+    Service,
+    /// Another I/O error.
+    Io,
+    /// An error that fits none of the other categories.
+    Other,
+}
+
 /// Errors encountered by the keylogger.
+///
+/// The errors can be handled by category using [`KeyloggerError::kind`]: the retryable errors
+/// (see [`KeyloggerError::is_retryable`]) may go away if the operation is retried, the fatal errors
+/// (see [`KeyloggerError::is_fatal`]) don't, and the other errors only affect the item (e.g. the
+/// event) that caused them, so the capture can continue.
 #[derive(Error, Debug)]
 pub enum KeyloggerError {
     #[error("I/O error: {0}")]
@@ -16,12 +46,187 @@ pub enum KeyloggerError {
     InvalidKeyEvent(String),
     #[error("invalid key code: {0}")]
     InvalidKeyCode(u16),
+    #[error("unknown key name: {0}")]
+    UnknownKeyName(String),
     #[error("invalid timestamp: sec={0} usec={1}")]
     InvalidTimestamp(i64, i64),
     #[error("failed to convert key code: {0:?}")]
     KeyCodeConversion(KeyCode),
     #[error("unsuported event type: {0}")]
     UnsupportedEventType(u16),
+    #[error("invalid layout: {0}")]
+    InvalidLayout(String),
+    #[error("invalid device filter: {0}")]
+    InvalidFilter(String),
+    #[error("invalid chord: {0}")]
+    InvalidChord(String),
+    #[error("failed to serialize event: {0}")]
+    Serialization(String),
+    #[error("gRPC error: {0}")]
+    Grpc(String),
+    #[error("accessibility bus error: {0}")]
+    Accessibility(String),
+    #[error("D-Bus error: {0}")]
+    Dbus(String),
+    #[error("window system error: {0}")]
+    WindowSystem(String),
+    #[error("database error: {0}")]
+    Database(String),
+    #[error("invalid capture: {0}")]
+    InvalidCapture(String),
+    #[error("authentication failed: {0}")]
+    Authentication(String),
+    #[error("device disconnected: {0}")]
+    DeviceDisconnected(#[source] io::Error),
+    #[error("device not found: {0}")]
+    DeviceNotFound(#[source] io::Error),
+    #[error("interrupted: {0}")]
+    Interrupted(#[source] io::Error),
     #[error("all logging tasks exited")]
     KeyloggerTasksExited,
+    #[error("permission denied: {}: {hint}", path.display())]
+    PermissionDenied {
+        /// The path of the device that couldn't be opened.
+        path: PathBuf,
+        /// A suggestion on how to gain access to the device.
+        hint: String,
+    },
+    #[error("{name} ({}): {source}", path.display())]
+    Device {
+        /// The path of the device that caused the error.
+        path: PathBuf,
+        /// The name of the device that caused the error.
+        name: String,
+        /// The underlying error.
+        source: Box<KeyloggerError>,
+    },
+}
+
+impl KeyloggerError {
+    /// The path of the device that caused the error, if the error is specific to a device.
+    pub fn device_path(&self) -> Option<&Path> {
+        match self {
+            Self::Device { path, .. } => Some(path),
+            Self::NotAKeyboard(path) => Some(path),
+            Self::PermissionDenied { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// The OS error code of the underlying I/O error, if the error was caused by one.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match self {
+            Self::Io(e)
+            | Self::DeviceDisconnected(e)
+            | Self::DeviceNotFound(e)
+            | Self::Interrupted(e) => e.raw_os_error(),
+            Self::Device { source, .. } => source.raw_os_error(),
+            _ => None,
+        }
+    }
+
+    /// The category of the error.
+    ///
+    /// The errors of a device (see [`KeyloggerError::Device`]) have the category of their
+    /// underlying error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Io(e) => io_error_kind(e),
+            Self::DeviceDisconnected(_) | Self::DeviceNotFound(_) => ErrorKind::Disconnected,
+            Self::Interrupted(_) => ErrorKind::Transient,
+            Self::Device { source, .. } => source.kind(),
+            Self::PermissionDenied { .. } | Self::Authentication(_) => ErrorKind::PermissionDenied,
+            Self::NotAKeyboard(_) | Self::UnsupportedEventType(_) => ErrorKind::Unsupported,
+            Self::InvalidKeyEvent(_)
+            | Self::InvalidKeyCode(_)
+            | Self::UnknownKeyName(_)
+            | Self::InvalidTimestamp(..)
+            | Self::KeyCodeConversion(_)
+            | Self::InvalidLayout(_)
+            | Self::InvalidFilter(_)
+            | Self::InvalidChord(_)
+            | Self::Serialization(_)
+            | Self::InvalidCapture(_) => ErrorKind::InvalidInput,
+            Self::Grpc(_)
+            | Self::Accessibility(_)
+            | Self::Dbus(_)
+            | Self::WindowSystem(_)
+            | Self::Database(_) => ErrorKind::Service,
+            Self::KeyloggerTasksExited => ErrorKind::Other,
+        }
+    }
+
+    /// Whether retrying the operation that failed may succeed, either straight away
+    /// ([`ErrorKind::Transient`]) or once the device reappears ([`ErrorKind::Disconnected`]).
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.kind(), ErrorKind::Transient | ErrorKind::Disconnected)
+    }
+
+    /// Whether the source of the error (e.g. the keyboard) can't be used anymore, and retrying
+    /// won't help.
+    ///
+    /// The errors that are neither fatal nor retryable (such as an invalid event, or an error of
+    /// an external service) only affect the item that caused them.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self.kind(),
+            ErrorKind::PermissionDenied | ErrorKind::Unsupported | ErrorKind::Io | ErrorKind::Other
+        )
+    }
+}
+
+/// The category of an I/O error.
+fn io_error_kind(e: &io::Error) -> ErrorKind {
+    match e.raw_os_error() {
+        Some(libc::ENODEV | libc::ENXIO) => return ErrorKind::Disconnected,
+        Some(libc::EBUSY | libc::ENOBUFS) => return ErrorKind::Transient,
+        Some(libc::ENOTTY) => return ErrorKind::Unsupported,
+        _ => {}
+    }
+
+    match e.kind() {
+        io::ErrorKind::NotFound
+        | io::ErrorKind::BrokenPipe
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::NotConnected
+        | io::ErrorKind::UnexpectedEof => ErrorKind::Disconnected,
+        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted | io::ErrorKind::TimedOut => {
+            ErrorKind::Transient
+        }
+        io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+        io::ErrorKind::Unsupported => ErrorKind::Unsupported,
+        io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => ErrorKind::InvalidInput,
+        _ => ErrorKind::Io,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_kinds() {
+        let os_error = |errno| KeyloggerError::from(io::Error::from_raw_os_error(errno));
+        let unplugged = KeyloggerError::Device {
+            path: "/dev/input/event4".into(),
+            name: "USB Keyboard".into(),
+            source: Box::new(os_error(libc::ENODEV)),
+        };
+
+        assert_eq!(unplugged.kind(), ErrorKind::Disconnected);
+        assert_eq!(unplugged.raw_os_error(), Some(libc::ENODEV));
+        assert!(unplugged.is_retryable() && !unplugged.is_fatal());
+
+        assert_eq!(os_error(libc::EINTR).kind(), ErrorKind::Transient);
+        assert_eq!(os_error(libc::EAGAIN).kind(), ErrorKind::Transient);
+        assert_eq!(os_error(libc::EACCES).kind(), ErrorKind::PermissionDenied);
+        assert!(os_error(libc::EIO).is_fatal());
+
+        let invalid = KeyloggerError::InvalidKeyCode(0xffff);
+
+        assert_eq!(invalid.kind(), ErrorKind::InvalidInput);
+        assert!(!invalid.is_retryable() && !invalid.is_fatal());
+        assert!(KeyloggerError::NotAKeyboard("/dev/input/event0".into()).is_fatal());
+    }
 }
@@ -0,0 +1,486 @@
+//! Stream adapters that filter out unwanted [`KeyEvent`]s.
+//!
+//! The adapters are provided by the [`KeyEventStreamExt`] trait, which is implemented for any
+//! stream of key events (such as a [`KeyboardDevice`](crate::KeyboardDevice)):
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use keylogger::filters::KeyEventStreamExt;
+//! use keylogger::{find_keyboards, KeyloggerError};
+//! use std::time::Duration;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let keyboard = find_keyboards()?.remove(0);
+//!     let mut events = keyboard.debounce(Duration::from_millis(5)).throttle(100);
+//!
+//!     while let Some(ev) = events.next().await {
+//!         println!("{:?}", ev?);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! The filters use the timestamps of the events rather than a timer, so they work with any async
+//! runtime.
+//!
+//! [`KeyEventStreamExt::redact`] hides what was typed, while preserving the timing of the
+//! keystrokes and the modifiers that were held down.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use chrono::naive::NaiveDateTime;
+use futures::{ready, Stream};
+use pin_project::pin_project;
+
+use crate::dynamics::{HoldTracker, Holds};
+use crate::key_code::KeyCode;
+use crate::keyboard::{KeyEvent, KeyEventCause};
+use crate::privacy::{Guarded, PrivacyGuard};
+use crate::KeyloggerResult;
+
+/// Extension methods for streams of key events.
+pub trait KeyEventStreamExt: Stream<Item = KeyloggerResult<KeyEvent>> + Sized {
+    /// Filter out the chatter of faulty switches.
+    ///
+    /// Once a key is pressed (or released), any further presses and releases of the same key are
+    /// dropped for the duration of `window`. Autorepeat events are passed through while the key is
+    /// held down.
+    ///
+    /// `window` must be shorter than the quickest genuine keystroke: if the release that ends a
+    /// keystroke is dropped, the key appears to be held down until it's pressed again.
+    fn debounce(self, window: Duration) -> Debounce<Self> {
+        Debounce {
+            events: self,
+            debouncer: Debouncer::new(window),
+        }
+    }
+
+    /// Drop the events that exceed a rate of `per_second` events per second.
+    ///
+    /// Short bursts of up to `per_second` events are allowed. Errors are never dropped.
+    fn throttle(self, per_second: u32) -> Throttle<Self> {
+        Throttle {
+            events: self,
+            limiter: RateLimiter::new(per_second),
+        }
+    }
+
+    /// Only yield the key presses, dropping the releases and the autorepeat events.
+    ///
+    /// Errors are never dropped.
+    fn presses(self) -> ByCause<Self> {
+        ByCause {
+            events: self,
+            cause: KeyEventCause::Press,
+        }
+    }
+
+    /// Only yield the key releases, dropping the presses and the autorepeat events.
+    ///
+    /// Errors are never dropped.
+    fn releases(self) -> ByCause<Self> {
+        ByCause {
+            events: self,
+            cause: KeyEventCause::Release,
+        }
+    }
+
+    /// Replace the code of the keys that produce text with [`REDACTED`].
+    ///
+    /// The letters, digits, punctuation, whitespace and keypad keys are redacted. The modifiers,
+    /// navigation, editing, function and media keys pass through unchanged. The scancodes of the
+    /// redacted events are dropped too, as they identify the key.
+    fn redact(self) -> Redact<Self> {
+        Redact { events: self }
+    }
+
+    /// Drop the events that occur while the specified guard is sensitive (see the [`privacy`]
+    /// module).
+    ///
+    /// Errors are never dropped.
+    ///
+    /// [`privacy`]: crate::privacy
+    fn with_privacy_guard(self, guard: PrivacyGuard) -> Guarded<Self> {
+        Guarded {
+            events: self,
+            guard,
+        }
+    }
+
+    /// Pair the presses and releases of the keys, turning the events into the
+    /// [`KeyHold`](crate::dynamics::KeyHold)s they describe.
+    ///
+    /// The holds are yielded as the keys are released. A release is considered missed if the key
+    /// is pressed again before it's released, if the key doesn't send an autorepeat event for
+    /// longer than `timeout` (which is noticed when the next event is read), or if the stream
+    /// ends while the key is held down.
+    fn holds(self, timeout: Duration) -> Holds<Self> {
+        Holds {
+            events: self,
+            tracker: HoldTracker::new(timeout),
+            ready: Default::default(),
+            done: false,
+        }
+    }
+}
+
+impl<S: Stream<Item = KeyloggerResult<KeyEvent>>> KeyEventStreamExt for S {}
+
+/// A stream adapter that filters out switch chatter (see [`KeyEventStreamExt::debounce`]).
+#[pin_project]
+pub struct Debounce<S> {
+    #[pin]
+    events: S,
+    debouncer: Debouncer,
+}
+
+impl<S> Stream for Debounce<S>
+where
+    S: Stream<Item = KeyloggerResult<KeyEvent>>,
+{
+    type Item = KeyloggerResult<KeyEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            match ready!(this.events.as_mut().poll_next(cx)) {
+                Some(Ok(ev)) if !this.debouncer.accept(&ev) => continue,
+                item => return Poll::Ready(item),
+            }
+        }
+    }
+}
+
+/// A stream adapter that limits the rate of events (see [`KeyEventStreamExt::throttle`]).
+#[pin_project]
+pub struct Throttle<S> {
+    #[pin]
+    events: S,
+    limiter: RateLimiter,
+}
+
+impl<S> Stream for Throttle<S>
+where
+    S: Stream<Item = KeyloggerResult<KeyEvent>>,
+{
+    type Item = KeyloggerResult<KeyEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            match ready!(this.events.as_mut().poll_next(cx)) {
+                Some(Ok(ev)) if !this.limiter.accept(ev.ts) => continue,
+                item => return Poll::Ready(item),
+            }
+        }
+    }
+}
+
+/// A stream adapter that only yields the events with a particular cause (see
+/// [`KeyEventStreamExt::presses`] and [`KeyEventStreamExt::releases`]).
+#[pin_project]
+pub struct ByCause<S> {
+    #[pin]
+    events: S,
+    cause: KeyEventCause,
+}
+
+impl<S> Stream for ByCause<S>
+where
+    S: Stream<Item = KeyloggerResult<KeyEvent>>,
+{
+    type Item = KeyloggerResult<KeyEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            match ready!(this.events.as_mut().poll_next(cx)) {
+                Some(Ok(ev)) if ev.cause != *this.cause => continue,
+                item => return Poll::Ready(item),
+            }
+        }
+    }
+}
+
+/// The code reported in place of the keys hidden by [`KeyEventStreamExt::redact`].
+pub const REDACTED: KeyCode = KeyCode::KEY_UNKNOWN;
+
+/// A stream adapter that hides the keys that produce text (see [`KeyEventStreamExt::redact`]).
+#[pin_project]
+pub struct Redact<S> {
+    #[pin]
+    events: S,
+}
+
+impl<S> Stream for Redact<S>
+where
+    S: Stream<Item = KeyloggerResult<KeyEvent>>,
+{
+    type Item = KeyloggerResult<KeyEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let ev = ready!(self.project().events.poll_next(cx));
+
+        Poll::Ready(ev.map(|ev| ev.map(redact)))
+    }
+}
+
+pub(crate) fn redact(ev: KeyEvent) -> KeyEvent {
+    use KeyCode::*;
+
+    let is_text = char::try_from(ev.code).is_ok()
+        || matches!(
+            ev.code,
+            KEY_KP0
+                | KEY_KP1
+                | KEY_KP2
+                | KEY_KP3
+                | KEY_KP4
+                | KEY_KP5
+                | KEY_KP6
+                | KEY_KP7
+                | KEY_KP8
+                | KEY_KP9
+                | KEY_KPDOT
+                | KEY_KPCOMMA
+                | KEY_KPPLUS
+                | KEY_KPMINUS
+                | KEY_KPASTERISK
+                | KEY_KPSLASH
+                | KEY_KPEQUAL
+                | KEY_102ND
+        );
+
+    if !is_text {
+        return ev;
+    }
+
+    KeyEvent {
+        code: REDACTED,
+        scancode: None,
+        ..ev
+    }
+}
+
+#[derive(Debug)]
+struct Debouncer {
+    window: Duration,
+    /// The time each key last changed state, and whether it's currently held down.
+    keys: HashMap<KeyCode, (NaiveDateTime, bool)>,
+}
+
+impl Debouncer {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            keys: Default::default(),
+        }
+    }
+
+    /// Check whether the specified event should be let through.
+    fn accept(&mut self, ev: &KeyEvent) -> bool {
+        let pressed = match ev.cause {
+            KeyEventCause::Press => true,
+            KeyEventCause::Release => false,
+            KeyEventCause::Repeat => {
+                return self.keys.get(&ev.code).is_none_or(|(_, pressed)| *pressed);
+            }
+        };
+
+        if let Some((changed, was_pressed)) = self.keys.get(&ev.code) {
+            let elapsed = (ev.ts - *changed).to_std().unwrap_or_default();
+
+            if *was_pressed == pressed || elapsed < self.window {
+                return false;
+            }
+        }
+
+        self.keys.insert(ev.code, (ev.ts, pressed));
+
+        true
+    }
+}
+
+/// A token bucket, refilled based on the timestamps of the events.
+#[derive(Debug)]
+struct RateLimiter {
+    per_second: u32,
+    /// The available tokens, in billionths of a token (so refilling them doesn't lose precision).
+    tokens: u128,
+    last: Option<NaiveDateTime>,
+}
+
+/// The number of units that make up a token.
+const TOKEN: u128 = 1_000_000_000;
+
+impl RateLimiter {
+    fn new(per_second: u32) -> Self {
+        Self {
+            per_second,
+            tokens: u128::from(per_second) * TOKEN,
+            last: None,
+        }
+    }
+
+    /// Check whether an event that occurred at `ts` should be let through.
+    fn accept(&mut self, ts: NaiveDateTime) -> bool {
+        let capacity = u128::from(self.per_second) * TOKEN;
+
+        if let Some(last) = self.last {
+            let elapsed = (ts - last).to_std().unwrap_or_default().as_nanos();
+
+            self.tokens = (self.tokens + elapsed * u128::from(self.per_second)).min(capacity);
+        }
+
+        self.last = Some(ts);
+
+        if self.tokens < TOKEN {
+            return false;
+        }
+
+        self.tokens -= TOKEN;
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::{stream, StreamExt};
+    use KeyCode::*;
+    use KeyEventCause::*;
+
+    fn filter<S>(
+        evs: &[(KeyEventCause, KeyCode, i64)],
+        f: impl FnOnce(stream::Iter<std::vec::IntoIter<KeyloggerResult<KeyEvent>>>) -> S,
+    ) -> Vec<(KeyEventCause, KeyCode, i64)>
+    where
+        S: Stream<Item = KeyloggerResult<KeyEvent>>,
+    {
+        let start = NaiveDateTime::default();
+        let evs = evs
+            .iter()
+            .map(|(cause, code, ms)| {
+                Ok(KeyEvent {
+                    ts: start + chrono::Duration::milliseconds(*ms),
+                    cause: *cause,
+                    code: *code,
+                    scancode: None,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        block_on(f(stream::iter(evs)).collect::<Vec<_>>())
+            .into_iter()
+            .map(|ev| {
+                let ev = ev.unwrap();
+
+                (ev.cause, ev.code, (ev.ts - start).num_milliseconds())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn debounce() {
+        let evs = [
+            (Press, KEY_A, 0),
+            (Release, KEY_A, 1),
+            (Press, KEY_A, 2),
+            (Press, KEY_B, 3),
+            (Repeat, KEY_A, 50),
+            (Release, KEY_A, 60),
+            (Press, KEY_A, 62),
+            (Release, KEY_B, 70),
+        ];
+
+        assert_eq!(
+            filter(&evs, |s| s.debounce(Duration::from_millis(5))),
+            [
+                (Press, KEY_A, 0),
+                (Press, KEY_B, 3),
+                (Repeat, KEY_A, 50),
+                (Release, KEY_A, 60),
+                (Release, KEY_B, 70),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_by_cause() {
+        let evs = [
+            (Press, KEY_A, 0),
+            (Repeat, KEY_A, 500),
+            (Press, KEY_B, 510),
+            (Release, KEY_A, 520),
+            (Release, KEY_B, 530),
+        ];
+
+        assert_eq!(
+            filter(&evs, |s| s.presses()),
+            [(Press, KEY_A, 0), (Press, KEY_B, 510)]
+        );
+        assert_eq!(
+            filter(&evs, |s| s.releases()),
+            [(Release, KEY_A, 520), (Release, KEY_B, 530)]
+        );
+    }
+
+    #[test]
+    fn redact_text_keys() {
+        let evs = [
+            (Press, KEY_LEFTSHIFT, 0),
+            (Press, KEY_A, 10),
+            (Release, KEY_A, 20),
+            (Press, KEY_KP7, 30),
+            (Press, KEY_SPACE, 40),
+            (Press, KEY_LEFT, 50),
+            (Press, KEY_VOLUMEUP, 60),
+        ];
+
+        assert_eq!(
+            filter(&evs, |s| s.redact()),
+            [
+                (Press, KEY_LEFTSHIFT, 0),
+                (Press, REDACTED, 10),
+                (Release, REDACTED, 20),
+                (Press, REDACTED, 30),
+                (Press, REDACTED, 40),
+                (Press, KEY_LEFT, 50),
+                (Press, KEY_VOLUMEUP, 60),
+            ]
+        );
+    }
+
+    #[test]
+    fn throttle() {
+        let evs = (0..10)
+            .map(|i| (Press, KEY_A, i * 100))
+            .chain((0..4).map(|i| (Press, KEY_B, 2000 + i)))
+            .collect::<Vec<_>>();
+
+        let throttled = filter(&evs, |s| s.throttle(2));
+
+        // A burst of 2, followed by one event every 500ms
+        assert_eq!(
+            throttled,
+            [
+                (Press, KEY_A, 0),
+                (Press, KEY_A, 100),
+                (Press, KEY_A, 500),
+                (Press, KEY_B, 2000),
+                (Press, KEY_B, 2001),
+            ]
+        );
+    }
+}
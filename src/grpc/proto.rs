@@ -0,0 +1,104 @@
+//! The messages from `proto/keylogger.proto`.
+
+use std::convert::TryFrom;
+
+use chrono::DateTime;
+
+use crate::error::KeyloggerError;
+use crate::key_code::KeyCode;
+use crate::keyboard;
+
+/// The reason a [`KeyEvent`] fired.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum KeyEventCause {
+    Unspecified = 0,
+    Press = 1,
+    Release = 2,
+    Repeat = 3,
+}
+
+/// A key event.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct KeyEvent {
+    /// The timestamp of the event, in microseconds since the Unix epoch.
+    #[prost(int64, tag = "1")]
+    pub timestamp_us: i64,
+    #[prost(enumeration = "KeyEventCause", tag = "2")]
+    pub cause: i32,
+    /// The Linux key code.
+    #[prost(uint32, tag = "3")]
+    pub code: u32,
+    /// The raw hardware scancode, if it was captured.
+    #[prost(uint32, optional, tag = "4")]
+    pub scancode: Option<u32>,
+}
+
+/// A key event, tagged with its origin.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ExportedEvent {
+    /// Identifies the machine or keyboard the event originates from.
+    #[prost(string, tag = "1")]
+    pub source: String,
+    #[prost(message, optional, tag = "2")]
+    pub event: Option<KeyEvent>,
+}
+
+/// The response of the collector once the exporter is done streaming.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ExportSummary {
+    /// The number of events the collector received.
+    #[prost(uint64, tag = "1")]
+    pub received: u64,
+}
+
+impl From<&keyboard::KeyEvent> for KeyEvent {
+    fn from(ev: &keyboard::KeyEvent) -> Self {
+        let cause = match ev.cause {
+            keyboard::KeyEventCause::Press => KeyEventCause::Press,
+            keyboard::KeyEventCause::Release => KeyEventCause::Release,
+            keyboard::KeyEventCause::Repeat => KeyEventCause::Repeat,
+        };
+
+        Self {
+            timestamp_us: ev.ts.and_utc().timestamp_micros(),
+            cause: cause.into(),
+            code: ev.code.code().into(),
+            scancode: ev.scancode,
+        }
+    }
+}
+
+impl TryFrom<KeyEvent> for keyboard::KeyEvent {
+    type Error = KeyloggerError;
+
+    fn try_from(ev: KeyEvent) -> Result<Self, Self::Error> {
+        let cause = match KeyEventCause::try_from(ev.cause) {
+            Ok(KeyEventCause::Press) => keyboard::KeyEventCause::Press,
+            Ok(KeyEventCause::Release) => keyboard::KeyEventCause::Release,
+            Ok(KeyEventCause::Repeat) => keyboard::KeyEventCause::Repeat,
+            _ => {
+                return Err(KeyloggerError::InvalidKeyEvent(format!(
+                    "invalid cause: {}",
+                    ev.cause
+                )))
+            }
+        };
+
+        let ts = DateTime::from_timestamp_micros(ev.timestamp_us)
+            .ok_or_else(|| {
+                KeyloggerError::InvalidKeyEvent(format!("invalid timestamp: {}", ev.timestamp_us))
+            })?
+            .naive_utc();
+
+        let code = u16::try_from(ev.code)
+            .map_err(|_| KeyloggerError::InvalidKeyEvent(format!("invalid code: {}", ev.code)))?;
+
+        Ok(Self {
+            ts,
+            cause,
+            code: KeyCode::from_raw(code),
+            scancode: ev.scancode,
+        })
+    }
+}
@@ -0,0 +1,318 @@
+//! Run a handler for each event of a group of keyboards, controlling how many invocations of the
+//! handler run at the same time.
+//!
+//! [`dispatch`] reads the events of a stream of [`TaggedKeyEvent`]s and the [`DeviceContext`]s of
+//! their keyboards (such as the stream returned by [`KeyboardSet::with_context`]) and passes each
+//! of them to a [`KeyEventHandler`]. The [`Concurrency`] decides which invocations of the handler
+//! are allowed to overlap: by default, the events of each keyboard are handled one at a time, in
+//! order, while the events of different keyboards are handled concurrently. Handlers that need all
+//! the events in the order they were read (e.g. to detect shortcuts typed across several
+//! keyboards) can funnel them through a single queue using [`Concurrency::Serialized`].
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use keylogger::handler::{dispatch, Concurrency};
+//! use keylogger::{merge_keyboards, DeviceContext, KeyloggerError, TaggedKeyEvent};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let mut keyboards = merge_keyboards()?;
+//!     let handler = |ev: TaggedKeyEvent, device: Arc<DeviceContext>| async move {
+//!         println!("{}: {:?}", device.name(), ev.event);
+//!     };
+//!
+//!     dispatch(keyboards.with_context(), handler, Concurrency::Serialized).await
+//! }
+//! ```
+//!
+//! [`KeyboardSet::with_context`]: crate::KeyboardSet::with_context
+
+use std::collections::{HashSet, VecDeque};
+use std::future::Future;
+use std::sync::Arc;
+use std::task::Poll;
+
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, Stream, StreamExt};
+
+use crate::keyboard::{DeviceContext, DeviceId, TaggedKeyEvent};
+use crate::trace;
+use crate::KeyloggerResult;
+
+/// Handles the events passed to it by [`dispatch`].
+///
+/// This is implemented for the closures that take a [`TaggedKeyEvent`] and the [`DeviceContext`]
+/// of its keyboard, and return a future. What's known about the keyboard is passed as a single
+/// context, so more of it can be exposed without changing the signature of the handlers.
+pub trait KeyEventHandler {
+    /// The future that handles an event.
+    type Future: Future<Output = ()>;
+
+    /// Start handling the specified event of the specified keyboard.
+    fn handle(&self, ev: TaggedKeyEvent, device: Arc<DeviceContext>) -> Self::Future;
+}
+
+impl<F, Fut> KeyEventHandler for F
+where
+    F: Fn(TaggedKeyEvent, Arc<DeviceContext>) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    type Future = Fut;
+
+    fn handle(&self, ev: TaggedKeyEvent, device: Arc<DeviceContext>) -> Fut {
+        self(ev, device)
+    }
+}
+
+/// The default maximum number of events [`dispatch`] queues while their handling can't start.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 256;
+
+/// Which invocations of a [`KeyEventHandler`] are allowed to run at the same time.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Concurrency {
+    /// Handle one event at a time, in the order the events were read, regardless of the keyboard
+    /// they came from.
+    Serialized,
+    /// Handle the events of each keyboard one at a time, in order, and the events of different
+    /// keyboards concurrently.
+    #[default]
+    PerDevice,
+    /// Start handling each event as soon as it's read, without waiting for the previous events to
+    /// be handled. The handler can't rely on the order of the events.
+    Unbounded,
+}
+
+/// Pass each event of the specified stream to the handler, running the invocations of the handler
+/// as allowed by `concurrency` (see the [module docs](self)).
+///
+/// The events are read as soon as they're available. The events whose handling can't start yet
+/// are queued, up to [`DEFAULT_QUEUE_CAPACITY`] events (see [`dispatch_with_capacity`]).
+///
+/// This completes once the stream ends and all its events are handled. If the stream yields an
+/// error, the events read before it are still handled, then the error is returned.
+pub async fn dispatch<S, H>(events: S, handler: H, concurrency: Concurrency) -> KeyloggerResult<()>
+where
+    S: Stream<Item = KeyloggerResult<(TaggedKeyEvent, Arc<DeviceContext>)>> + Unpin,
+    H: KeyEventHandler,
+{
+    dispatch_with_capacity(events, handler, concurrency, DEFAULT_QUEUE_CAPACITY).await
+}
+
+/// Pass each event of the specified stream to the handler like [`dispatch`], queueing up to
+/// `capacity` events whose handling can't start yet.
+///
+/// Once the queue is full, the stream isn't polled until the handler catches up, so a slow handler
+/// slows down the reading of the events instead of using more and more memory. The events then
+/// pile up wherever the stream gets them from: the stream should be buffered (see the
+/// [`buffer`](crate::buffer) module), so the events are still read from the keyboards, and the
+/// [`OverflowPolicy`](crate::buffer::OverflowPolicy) of each keyboard decides which events are
+/// dropped if the handler doesn't catch up. With [`Concurrency::Unbounded`], the handling of each
+/// event starts as soon as it's read, so nothing is queued.
+pub async fn dispatch_with_capacity<S, H>(
+    mut events: S,
+    handler: H,
+    concurrency: Concurrency,
+    capacity: usize,
+) -> KeyloggerResult<()>
+where
+    S: Stream<Item = KeyloggerResult<(TaggedKeyEvent, Arc<DeviceContext>)>> + Unpin,
+    H: KeyEventHandler,
+{
+    let capacity = capacity.max(1);
+    let start = |(ev, device): (TaggedKeyEvent, Arc<DeviceContext>)| {
+        let device_id = ev.device_id;
+        let span = trace::handle_span(device_id);
+
+        trace::instrument(handler.handle(ev, device), span).map(move |()| device_id)
+    };
+
+    let mut queue = VecDeque::new();
+    let mut in_flight = FuturesUnordered::new();
+    // The keyboards whose events are being handled
+    let mut busy = HashSet::new();
+    let mut done = false;
+    let mut error = None;
+
+    futures::future::poll_fn(|cx| {
+        loop {
+            // The stream isn't polled while the queue is full, so it's polled again once the
+            // handling of an event completes
+            while !done && queue.len() < capacity {
+                match events.poll_next_unpin(cx) {
+                    Poll::Ready(Some(Ok(ev))) => queue.push_back(ev),
+                    Poll::Ready(Some(Err(e))) => {
+                        error = Some(e);
+                        done = true;
+                    }
+                    Poll::Ready(None) => done = true,
+                    Poll::Pending => break,
+                }
+            }
+
+            start_queued(&mut queue, &mut busy, in_flight.is_empty(), concurrency)
+                .into_iter()
+                .for_each(|ev| in_flight.push(start(ev)));
+
+            match in_flight.poll_next_unpin(cx) {
+                Poll::Ready(Some(device_id)) => {
+                    busy.remove(&device_id);
+                }
+                Poll::Ready(None) | Poll::Pending => break,
+            }
+        }
+
+        if done && queue.is_empty() && in_flight.is_empty() {
+            Poll::Ready(error.take().map_or(Ok(()), Err))
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Remove the queued events whose handling can start from the queue, given whether any events are
+/// being handled, and the keyboards whose events are being handled.
+fn start_queued<T>(
+    queue: &mut VecDeque<(TaggedKeyEvent, T)>,
+    busy: &mut HashSet<DeviceId>,
+    idle: bool,
+    concurrency: Concurrency,
+) -> Vec<(TaggedKeyEvent, T)> {
+    match concurrency {
+        Concurrency::Serialized if idle => queue.pop_front().into_iter().collect(),
+        Concurrency::Serialized => vec![],
+        Concurrency::PerDevice => {
+            let mut started = vec![];
+            let mut i = 0;
+
+            while i < queue.len() {
+                // Starting an event marks its keyboard as busy, so the later events of the same
+                // keyboard stay queued, in order
+                if busy.insert(queue[i].0.device_id) {
+                    started.extend(queue.remove(i));
+                } else {
+                    i += 1;
+                }
+            }
+
+            started
+        }
+        Concurrency::Unbounded => queue.drain(..).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_code::KeyCode;
+    use crate::keyboard::{DeviceInfo, KeyEvent, KeyEventCause};
+    use futures::executor::block_on;
+    use futures::stream;
+    use std::cell::RefCell;
+
+    /// Handle the events with a handler that yields once per event, returning the log of the
+    /// reads (`?`) of the events, and the starts (`+`) and ends (`-`) of the invocations.
+    fn handle(concurrency: Concurrency, capacity: usize) -> Vec<String> {
+        let evs = [
+            (0, KeyCode::KEY_A),
+            (0, KeyCode::KEY_B),
+            (1, KeyCode::KEY_C),
+        ]
+        .map(|(id, code)| {
+            let ev = TaggedKeyEvent {
+                device_id: DeviceId(id),
+                event: KeyEvent {
+                    ts: Default::default(),
+                    cause: KeyEventCause::Press,
+                    code,
+                    scancode: None,
+                },
+                batch: Default::default(),
+            };
+            let device = DeviceContext {
+                id: DeviceId(id),
+                path: format!("/dev/input/event{id}").into(),
+                name: format!("keyboard {id}"),
+                info: DeviceInfo {
+                    bus_type: 0,
+                    vendor: 0,
+                    product: 0,
+                    version: 0,
+                    phys: None,
+                    uniq: None,
+                    seat: None,
+                },
+                supported_keys: Default::default(),
+            };
+
+            Ok((ev, Arc::new(device)))
+        });
+
+        let log = RefCell::new(vec![]);
+        let handler = |ev: TaggedKeyEvent, _: Arc<DeviceContext>| {
+            let log = &log;
+
+            async move {
+                log.borrow_mut().push(format!("+{}", ev.event.code));
+
+                let mut yielded = false;
+
+                futures::future::poll_fn(|cx| {
+                    if yielded {
+                        return Poll::Ready(());
+                    }
+
+                    yielded = true;
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                })
+                .await;
+
+                log.borrow_mut().push(format!("-{}", ev.event.code));
+            }
+        };
+
+        let events = stream::iter(evs).inspect(|ev| {
+            let code = ev.as_ref().unwrap().0.event.code;
+
+            log.borrow_mut().push(format!("?{code}"));
+        });
+
+        block_on(dispatch_with_capacity(
+            events,
+            handler,
+            concurrency,
+            capacity,
+        ))
+        .unwrap();
+
+        log.into_inner()
+    }
+
+    #[test]
+    fn handler_concurrency() {
+        assert_eq!(
+            handle(Concurrency::Serialized, DEFAULT_QUEUE_CAPACITY),
+            ["?A", "?B", "?C", "+A", "-A", "+B", "-B", "+C", "-C"]
+        );
+
+        // The events aren't read until there's room for them in the queue
+        assert_eq!(
+            handle(Concurrency::Serialized, 1),
+            ["?A", "+A", "?B", "-A", "+B", "?C", "-B", "+C", "-C"]
+        );
+
+        let log = handle(Concurrency::PerDevice, DEFAULT_QUEUE_CAPACITY);
+        let pos = |entry: &str| log.iter().position(|e| e == entry).unwrap();
+
+        assert!(pos("+B") > pos("-A"));
+        assert!(pos("+C") < pos("-A"));
+
+        let log = handle(Concurrency::Unbounded, DEFAULT_QUEUE_CAPACITY);
+        let pos = |entry: &str| log.iter().position(|e| e == entry).unwrap();
+
+        assert!(pos("+B") < pos("-A"));
+        assert!(pos("+C") < pos("-A"));
+    }
+}
@@ -0,0 +1,225 @@
+//! Run callbacks when key combinations are pressed, like a global hotkey facility.
+//!
+//! [`Hotkeys`] is a registry of [`Chord`]s, such as `Ctrl+Alt+L`, and the callbacks to run when
+//! they're pressed. It keeps track of the modifiers held down (see [`ChordDetector`]), and runs
+//! the callbacks concurrently with the reading of the events, on the runtime that drives
+//! [`Hotkeys::run`]. Unlike the hotkeys of a desktop environment, this works on the console and
+//! in daemons, without a display server:
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use keylogger::hotkeys::Hotkeys;
+//! use keylogger::{merge_keyboards, KeyloggerError};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let events = merge_keyboards()?.map(|ev| ev.map(|ev| ev.event));
+//!     let mut hotkeys = Hotkeys::new();
+//!
+//!     hotkeys.register("Ctrl+Alt+L", |_| async { println!("locking the screen") })?;
+//!     hotkeys.register("Meta+Enter", |ev| async move { println!("{ev:?}") })?;
+//!
+//!     hotkeys.run(events).await
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::task::Poll;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, Stream, StreamExt};
+
+use crate::chords::{Chord, ChordDetector, ChordEvent, ChordId};
+use crate::keyboard::KeyEvent;
+use crate::KeyloggerResult;
+
+/// A callback whose type was erased, so it can be stored in [`Hotkeys`].
+type Callback = Box<dyn Fn(ChordEvent) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// A registry of hotkeys and their callbacks (see the [module docs](self)).
+#[derive(Default)]
+pub struct Hotkeys {
+    detector: ChordDetector,
+    callbacks: HashMap<ChordId, Callback>,
+}
+
+impl Hotkeys {
+    /// Create a registry with no hotkeys.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Only trigger the hotkeys whose keys are all pressed within `timeout` of each other (see
+    /// [`ChordDetector::with_timeout`]).
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.detector = self.detector.with_timeout(timeout);
+        self
+    }
+
+    /// Run `callback` whenever the specified hotkey is pressed.
+    ///
+    /// The hotkey is parsed using the [`FromStr`](std::str::FromStr) implementation of [`Chord`]
+    /// (e.g. `Ctrl+Alt+L`), and this fails with [`KeyloggerError::InvalidChord`] if it's invalid.
+    ///
+    /// [`KeyloggerError::InvalidChord`]: crate::KeyloggerError::InvalidChord
+    pub fn register<F, Fut>(&mut self, hotkey: &str, callback: F) -> KeyloggerResult<ChordId>
+    where
+        F: Fn(ChordEvent) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Ok(self.register_chord(hotkey.parse()?, callback))
+    }
+
+    /// Run `callback` whenever the specified chord is pressed.
+    pub fn register_chord<F, Fut>(&mut self, chord: Chord, callback: F) -> ChordId
+    where
+        F: Fn(ChordEvent) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let id = self.detector.register(chord);
+
+        self.callbacks
+            .insert(id, Box::new(move |ev| callback(ev).boxed()));
+
+        id
+    }
+
+    /// Stop running the callback of the specified hotkey, returning whether it was registered.
+    pub fn unregister(&mut self, id: ChordId) -> bool {
+        self.callbacks.remove(&id).is_some()
+    }
+
+    /// The chord of the specified hotkey, if it's registered.
+    pub fn chord(&self, id: ChordId) -> Option<&Chord> {
+        self.detector
+            .chord(id)
+            .filter(|_| self.callbacks.contains_key(&id))
+    }
+
+    /// Process the next event, returning the invocation of the callback of the hotkey it
+    /// triggered (if any).
+    ///
+    /// All events (including releases) should be fed to the registry in order, so it can keep
+    /// track of the keys that are held down. [`Hotkeys::run`] does this for a stream of events.
+    pub fn feed(&mut self, ev: &KeyEvent) -> Option<BoxFuture<'static, ()>> {
+        let chord_ev = self.detector.feed(ev)?;
+
+        self.callbacks
+            .get(&chord_ev.id)
+            .map(|callback| callback(chord_ev))
+    }
+
+    /// Read the events of the specified stream, running the callbacks of the hotkeys they
+    /// trigger.
+    ///
+    /// The events are read while the callbacks run, so a slow callback doesn't delay the
+    /// detection of the next hotkeys (or the invocation of their callbacks). This completes once
+    /// the stream ends and all the callbacks complete. If the stream yields an error, the running
+    /// callbacks are still completed, then the error is returned.
+    pub async fn run<S>(&mut self, mut events: S) -> KeyloggerResult<()>
+    where
+        S: Stream<Item = KeyloggerResult<KeyEvent>> + Unpin,
+    {
+        let mut in_flight = FuturesUnordered::new();
+        let mut done = false;
+        let mut error = None;
+
+        futures::future::poll_fn(|cx| {
+            while !done {
+                match events.poll_next_unpin(cx) {
+                    Poll::Ready(Some(Ok(ev))) => in_flight.extend(self.feed(&ev)),
+                    Poll::Ready(Some(Err(e))) => {
+                        error = Some(e);
+                        done = true;
+                    }
+                    Poll::Ready(None) => done = true,
+                    Poll::Pending => break,
+                }
+            }
+
+            while let Poll::Ready(Some(())) = in_flight.poll_next_unpin(cx) {}
+
+            if done && in_flight.is_empty() {
+                Poll::Ready(error.take().map_or(Ok(()), Err))
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_code::KeyCode::{self, *};
+    use crate::keyboard::KeyEventCause::{self, *};
+    use futures::executor::block_on;
+    use futures::stream;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn run_hotkeys() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut hotkeys = Hotkeys::new();
+        let callback = |name: &'static str| {
+            let log = Arc::clone(&log);
+
+            move |_| {
+                log.lock().unwrap().push(name);
+                async {}
+            }
+        };
+
+        hotkeys.register("Ctrl+Alt+L", callback("lock")).unwrap();
+
+        let quit = hotkeys.register("Ctrl+Q", callback("quit")).unwrap();
+
+        assert!(hotkeys.register("Ctrl+Hyper+Q", callback("hyper")).is_err());
+        assert_eq!(hotkeys.chord(quit).unwrap().key(), KEY_Q);
+
+        let evs = |evs: &[(KeyEventCause, KeyCode)]| {
+            let evs = evs
+                .iter()
+                .map(|(cause, code)| {
+                    Ok(KeyEvent {
+                        ts: Default::default(),
+                        cause: *cause,
+                        code: *code,
+                        scancode: None,
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            stream::iter(evs)
+        };
+
+        let keys = [
+            (Press, KEY_RIGHTALT),
+            (Press, KEY_LEFTCTRL),
+            (Press, KEY_L),
+            (Release, KEY_L),
+            (Press, KEY_Q),
+            (Release, KEY_Q),
+            (Release, KEY_LEFTCTRL),
+            (Release, KEY_RIGHTALT),
+            (Press, KEY_LEFTCTRL),
+            (Press, KEY_Q),
+            (Release, KEY_Q),
+            (Release, KEY_LEFTCTRL),
+        ];
+
+        block_on(hotkeys.run(evs(&keys))).unwrap();
+
+        assert_eq!(*log.lock().unwrap(), ["lock", "quit"]);
+        assert!(hotkeys.unregister(quit));
+        assert_eq!(hotkeys.chord(quit), None);
+
+        block_on(hotkeys.run(evs(&keys[8..]))).unwrap();
+
+        assert_eq!(log.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::{HashSet, VecDeque};
+use std::ffi::{CString, OsStr};
+use std::fs::{self, File};
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::{ready, Stream};
+
+use crate::keyboard::device::{event_number, find_char_devices, input_dir, is_keyboard};
+use crate::keyboard::KeyboardDevice;
+use crate::reactor::AsyncFd;
+use crate::KeyloggerResult;
+
+/// A change in the set of keyboards connected to the system.
+pub enum HotplugEvent {
+    /// A new keyboard was plugged in.
+    KeyboardAdded(Box<KeyboardDevice>),
+    /// The keyboard at the specified path was unplugged.
+    KeyboardRemoved(PathBuf),
+}
+
+/// A [`Stream`] of [`HotplugEvent`]s.
+///
+/// The monitor watches `/dev/input` (or the directory set using the `KEYLOGGER_INPUT_DIR`
+/// environment variable) using inotify, and opens any keyboard devices that appear
+/// after it was created. Keyboards that were already connected when the monitor was created are
+/// only reported when they are removed. If the inotify events of the directory overflow the queue
+/// of the kernel, the directory is rescanned, so the changes they reported aren't missed.
+pub struct KeyboardMonitor {
+    /// The registration of the inotify instance with the reactor. It must be dropped before
+    /// `_inotify`.
+    async_fd: AsyncFd,
+    /// The inotify instance watching the input directory (which owns the file descriptor
+    /// registered in `async_fd`).
+    _inotify: File,
+    /// The directory being watched.
+    input_dir: PathBuf,
+    /// The paths of the keyboards known to be connected.
+    keyboards: HashSet<PathBuf>,
+    /// The events that were read but not yet returned.
+    pending: VecDeque<HotplugEvent>,
+}
+
+impl KeyboardMonitor {
+    /// Start watching `/dev/input` for keyboards being plugged in or unplugged.
+    pub fn new() -> KeyloggerResult<Self> {
+        Self::with_input_dir(input_dir())
+    }
+
+    /// Start watching the specified directory (instead of `/dev/input`) for keyboards being
+    /// plugged in or unplugged (see
+    /// [`KeyboardFinder::input_dir`](crate::KeyboardFinder::input_dir)).
+    pub fn with_input_dir(input_dir: impl Into<PathBuf>) -> KeyloggerResult<Self> {
+        let input_dir = input_dir.into();
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        // Take ownership of the fd straight away, so it gets closed if anything below fails.
+        let file = unsafe { File::from_raw_fd(fd) };
+        let dir = CString::new(input_dir.as_os_str().as_bytes())
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+
+        // Device nodes are created by udev, which might not have set their permissions by the time
+        // IN_CREATE fires, so IN_ATTRIB is needed to retry opening them.
+        let mask = libc::IN_CREATE | libc::IN_ATTRIB | libc::IN_DELETE;
+        let res = unsafe { libc::inotify_add_watch(file.as_raw_fd(), dir.as_ptr(), mask) };
+
+        if res < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let keyboards = find_char_devices(&input_dir)?
+            .filter(|path| is_keyboard(path))
+            .collect();
+
+        Ok(Self {
+            async_fd: AsyncFd::new(file.as_raw_fd())?,
+            _inotify: file,
+            input_dir,
+            keyboards,
+            pending: Default::default(),
+        })
+    }
+
+    /// Convert an inotify event for the specified file into a [`HotplugEvent`], if the file is a
+    /// keyboard that was added or removed.
+    fn handle_inotify_event(&mut self, mask: u32, name: &OsStr) -> Option<HotplugEvent> {
+        if mask & libc::IN_ISDIR != 0 {
+            // Ignore the by-id and by-path subdirectories
+            return None;
+        }
+
+        let path = self.input_dir.join(name);
+
+        // Only the evdev nodes can be keyboards, unlike the legacy mouse and joystick nodes
+        event_number(&path)?;
+
+        if mask & libc::IN_DELETE != 0 {
+            return self
+                .keyboards
+                .remove(&path)
+                .then_some(HotplugEvent::KeyboardRemoved(path));
+        }
+
+        if self.keyboards.contains(&path) || !is_char_device(&path) {
+            return None;
+        }
+
+        let keyboard = KeyboardDevice::open(&path).ok()?;
+        self.keyboards.insert(path);
+
+        Some(HotplugEvent::KeyboardAdded(Box::new(keyboard)))
+    }
+
+    /// Compare the keyboards known to be connected with the devices in the input directory,
+    /// queueing the events of the keyboards that were added or removed.
+    ///
+    /// This is needed when the inotify queue overflows, since the events that didn't fit in the
+    /// queue are lost.
+    fn rescan(&mut self) -> KeyloggerResult<()> {
+        let devices = find_char_devices(&self.input_dir)?
+            .filter(|path| event_number(path).is_some())
+            .collect::<Vec<_>>();
+
+        let mut removed = self
+            .keyboards
+            .iter()
+            .filter(|path| !devices.contains(path))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        removed.sort();
+
+        for path in removed {
+            self.keyboards.remove(&path);
+            self.pending.push_back(HotplugEvent::KeyboardRemoved(path));
+        }
+
+        for path in devices {
+            if self.keyboards.contains(&path) {
+                continue;
+            }
+
+            if let Ok(keyboard) = KeyboardDevice::open(&path) {
+                self.keyboards.insert(path);
+                self.pending
+                    .push_back(HotplugEvent::KeyboardAdded(Box::new(keyboard)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Stream for KeyboardMonitor {
+    type Item = KeyloggerResult<HotplugEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some(ev) = this.pending.pop_front() {
+                return Poll::Ready(Some(Ok(ev)));
+            }
+
+            let inotify_evs = match ready!(this.async_fd.poll_read(cx, read_inotify_events)) {
+                Ok(evs) => evs,
+                Err(e) => return Poll::Ready(Some(Err(e.into()))),
+            };
+
+            let mut overflowed = false;
+
+            for (mask, name) in inotify_evs {
+                if mask & libc::IN_Q_OVERFLOW != 0 {
+                    overflowed = true;
+                } else if let Some(ev) = this.handle_inotify_event(mask, OsStr::from_bytes(&name)) {
+                    this.pending.push_back(ev);
+                }
+            }
+
+            // Some events were lost, so the directory itself is checked for the missed changes
+            if overflowed {
+                if let Err(e) = this.rescan() {
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+        }
+    }
+}
+
+/// Read the `(mask, name)` pairs of the pending inotify events from the specified file descriptor.
+fn read_inotify_events(fd: RawFd) -> io::Result<Vec<(u32, Vec<u8>)>> {
+    const BUF_LEN: usize = 4096;
+
+    let mut buf = [0u8; BUF_LEN];
+    let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut _, BUF_LEN) };
+
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(parse_inotify_events(&buf[..n as usize]))
+}
+
+/// Parse the `(mask, name)` pairs of the inotify events in the specified buffer.
+fn parse_inotify_events(buf: &[u8]) -> Vec<(u32, Vec<u8>)> {
+    const HEADER_LEN: usize = mem::size_of::<libc::inotify_event>();
+
+    let n = buf.len();
+    let mut evs = vec![];
+    let mut offset = 0;
+
+    while offset + HEADER_LEN <= n {
+        // The events in the buffer aren't necessarily aligned:
+        let header =
+            unsafe { (buf.as_ptr().add(offset) as *const libc::inotify_event).read_unaligned() };
+
+        let name_start = offset + HEADER_LEN;
+        let name_end = (name_start + header.len as usize).min(n);
+        // The name is padded with nul bytes:
+        let name = buf[name_start..name_end]
+            .iter()
+            .take_while(|b| **b != 0)
+            .copied()
+            .collect();
+
+        evs.push((header.mask, name));
+        offset = name_end;
+    }
+
+    evs
+}
+
+fn is_char_device(path: &Path) -> bool {
+    fs::metadata(path)
+        .map(|m| m.file_type().is_char_device())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    /// Encode an inotify event, with its name padded with nul bytes to `len` bytes.
+    fn inotify_event(mask: u32, name: &str, len: usize) -> Vec<u8> {
+        let mut buf = vec![];
+
+        buf.extend(1i32.to_ne_bytes());
+        buf.extend(mask.to_ne_bytes());
+        buf.extend(0u32.to_ne_bytes());
+        buf.extend((len as u32).to_ne_bytes());
+        buf.extend(name.as_bytes());
+        buf.resize(buf.len() + len - name.len(), 0);
+
+        buf
+    }
+
+    #[test]
+    fn parse_events() {
+        let buf = [
+            inotify_event(libc::IN_CREATE, "event3", 16),
+            inotify_event(libc::IN_Q_OVERFLOW, "", 0),
+            inotify_event(libc::IN_DELETE, "event12", 16),
+            // A truncated header is ignored
+            vec![0; 4],
+        ]
+        .concat();
+
+        assert_eq!(
+            parse_inotify_events(&buf),
+            [
+                (libc::IN_CREATE, b"event3".to_vec()),
+                (libc::IN_Q_OVERFLOW, vec![]),
+                (libc::IN_DELETE, b"event12".to_vec()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn handle_events() {
+        let dir = std::env::temp_dir().join(format!("keylogger-hotplug-{}", std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut monitor = KeyboardMonitor::with_input_dir(&dir).unwrap();
+        let mut handle = |mask, name: &str| monitor.handle_inotify_event(mask, OsStr::new(name));
+
+        // A character device that isn't a keyboard, a regular file, and a legacy mouse node
+        symlink("/dev/null", dir.join("event3")).unwrap();
+        fs::write(dir.join("event4"), "").unwrap();
+        symlink("/dev/null", dir.join("mouse0")).unwrap();
+
+        for mask in [libc::IN_CREATE, libc::IN_ATTRIB] {
+            for name in ["event3", "event4", "mouse0", "event5"] {
+                assert!(handle(mask, name).is_none());
+            }
+        }
+
+        assert!(handle(libc::IN_CREATE | libc::IN_ISDIR, "by-id").is_none());
+        assert!(handle(libc::IN_DELETE, "event3").is_none());
+        assert!(monitor.keyboards.is_empty());
+
+        // The keyboards that were already connected are reported once they're removed
+        let keyboard = dir.join("event1");
+
+        monitor.keyboards.insert(keyboard.clone());
+        monitor.keyboards.insert(dir.join("mouse1"));
+
+        let mut handle = |mask, name: &str| monitor.handle_inotify_event(mask, OsStr::new(name));
+
+        assert!(matches!(
+            handle(libc::IN_DELETE, "event1"),
+            Some(HotplugEvent::KeyboardRemoved(path)) if path == keyboard
+        ));
+        assert!(handle(libc::IN_DELETE, "event1").is_none());
+        assert!(handle(libc::IN_DELETE, "mouse1").is_none());
+
+        // After an overflow, the keyboards that are gone are found by rescanning the directory
+        let keyboard = dir.join("event7");
+
+        monitor.keyboards = [keyboard.clone(), dir.join("event3")].into();
+        monitor.rescan().unwrap();
+
+        assert!(matches!(
+            monitor.pending.make_contiguous(),
+            [HotplugEvent::KeyboardRemoved(path)] if *path == keyboard
+        ));
+        assert_eq!(monitor.keyboards, [dir.join("event3")].into());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
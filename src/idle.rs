@@ -0,0 +1,211 @@
+//! Detect when the user stops (and resumes) typing.
+//!
+//! An [`IdleDetector`] watches a set of keyboards, and reports an [`IdleEvent::Idle`] event once
+//! no key event occurred for the configured timeout, followed by an [`IdleEvent::Active`] event
+//! when the next key event occurs. This can be used to detect whether someone is at the keyboard,
+//! or to lock the screen after a period of inactivity.
+//!
+//! Unlike the adapters from the [`filters`](crate::filters) module, the detector can't rely on the
+//! timestamps of the events (as the point is to detect the absence of events), so it uses a
+//! `timerfd`, which is registered with the reactor like the keyboards are.
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use keylogger::idle::{IdleDetector, IdleEvent};
+//! use keylogger::{merge_keyboards, KeyloggerError};
+//! use std::time::Duration;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let mut idle = IdleDetector::new(merge_keyboards()?, Duration::from_secs(300))?;
+//!
+//!     while let Some(ev) = idle.next().await {
+//!         match ev? {
+//!             IdleEvent::Idle { .. } => println!("away"),
+//!             IdleEvent::Active { .. } => println!("back"),
+//!         }
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use chrono::naive::NaiveDateTime;
+use futures::{ready, Stream};
+
+use crate::keyboard::KeyboardSet;
+use crate::timer::Timer;
+use crate::KeyloggerResult;
+
+/// A change in the activity of the user.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IdleEvent {
+    /// No key event occurred for the duration of the timeout.
+    Idle {
+        /// The timestamp of the last key event, or `None` if no key event occurred since the
+        /// detector was created.
+        last_activity: Option<NaiveDateTime>,
+    },
+    /// A key event occurred while the user was idle.
+    Active {
+        /// The timestamp of the key event.
+        ts: NaiveDateTime,
+    },
+}
+
+/// A [`Stream`] of [`IdleEvent`]s (see the [module-level documentation](self)).
+///
+/// The errors encountered by the keyboards are passed through, and the stream ends once all the
+/// keyboards are removed from the set.
+pub struct IdleDetector {
+    keyboards: KeyboardSet,
+    /// The timer that expires once the user is idle.
+    timer: Timer,
+    state: IdleState,
+}
+
+impl IdleDetector {
+    /// Watch the specified keyboards, reporting the user idle once none of them emitted an event
+    /// for `timeout`.
+    pub fn new(keyboards: KeyboardSet, timeout: Duration) -> KeyloggerResult<Self> {
+        let timer = Timer::new()?;
+
+        timer.arm(timeout)?;
+
+        Ok(Self {
+            keyboards,
+            timer,
+            state: IdleState::new(timeout),
+        })
+    }
+
+    /// The keyboards being watched.
+    pub fn keyboards(&self) -> &KeyboardSet {
+        &self.keyboards
+    }
+
+    /// The keyboards being watched, for adding the keyboards that are plugged in (see
+    /// [`KeyboardMonitor`](crate::KeyboardMonitor)) or removing the ones that are unplugged.
+    pub fn keyboards_mut(&mut self) -> &mut KeyboardSet {
+        &mut self.keyboards
+    }
+
+    /// The duration of inactivity after which the user is considered idle.
+    pub fn timeout(&self) -> Duration {
+        self.state.timeout
+    }
+
+    /// Whether the user is currently idle.
+    pub fn is_idle(&self) -> bool {
+        self.state.idle
+    }
+}
+
+impl Stream for IdleDetector {
+    type Item = KeyloggerResult<IdleEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        // Process all the available key events first, so the timer isn't reported as expired if
+        // there was some activity since it was last armed
+        while let Poll::Ready(item) = Pin::new(&mut this.keyboards).poll_next(cx) {
+            let ev = match item {
+                Some(Ok(ev)) => ev.event,
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            };
+
+            if let Err(e) = this.timer.arm(this.state.timeout) {
+                return Poll::Ready(Some(Err(e.into())));
+            }
+
+            if let Some(ev) = this.state.activity(ev.ts) {
+                return Poll::Ready(Some(Ok(ev)));
+            }
+        }
+
+        loop {
+            if let Err(e) = ready!(this.timer.poll_expired(cx)) {
+                return Poll::Ready(Some(Err(e.into())));
+            }
+
+            if let Some(ev) = this.state.expired() {
+                return Poll::Ready(Some(Ok(ev)));
+            }
+        }
+    }
+}
+
+/// Tracks whether the user is idle.
+#[derive(Clone, Debug)]
+struct IdleState {
+    timeout: Duration,
+    idle: bool,
+    last_activity: Option<NaiveDateTime>,
+}
+
+impl IdleState {
+    fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            idle: false,
+            last_activity: None,
+        }
+    }
+
+    /// Record a key event that occurred at the specified time, returning the resulting event.
+    fn activity(&mut self, ts: NaiveDateTime) -> Option<IdleEvent> {
+        self.last_activity = Some(ts);
+
+        mem::replace(&mut self.idle, false).then_some(IdleEvent::Active { ts })
+    }
+
+    /// Record that the timeout expired, returning the resulting event.
+    fn expired(&mut self) -> Option<IdleEvent> {
+        (!mem::replace(&mut self.idle, true)).then_some(IdleEvent::Idle {
+            last_activity: self.last_activity,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_transitions() {
+        let ts = |sec| {
+            chrono::DateTime::from_timestamp(sec, 0)
+                .unwrap()
+                .naive_utc()
+        };
+        let mut state = IdleState::new(Duration::from_secs(60));
+
+        assert_eq!(
+            state.expired(),
+            Some(IdleEvent::Idle {
+                last_activity: None
+            })
+        );
+        // The user is only reported idle once
+        assert_eq!(state.expired(), None);
+        assert_eq!(
+            state.activity(ts(100)),
+            Some(IdleEvent::Active { ts: ts(100) })
+        );
+        assert_eq!(state.activity(ts(101)), None);
+        assert_eq!(
+            state.expired(),
+            Some(IdleEvent::Idle {
+                last_activity: Some(ts(101))
+            })
+        );
+    }
+}
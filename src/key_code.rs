@@ -1,10 +1,20 @@
 use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
 
 use crate::KeyloggerError;
 
+/// The highest key code supported by the kernel (`KEY_MAX`).
+pub(crate) const KEY_MAX: u16 = 0x2ff;
+
 /// See /usr/include/linux/input-event-codes.h
+///
+/// The codes that don't have a variant of their own (such as the `BTN_*` codes reported by some
+/// keyboards with built-in pointing devices) are represented by [`KeyCode::Unknown`].
 #[allow(non_camel_case_types)]
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u16)]
 pub enum KeyCode {
     KEY_RESERVED = 0,
     KEY_ESC = 1,
@@ -367,6 +377,7 @@ pub enum KeyCode {
     KEY_NOTIFICATION_CENTER = 0x1bc,
     KEY_PICKUP_PHONE = 0x1bd,
     KEY_HANGUP_PHONE = 0x1be,
+    KEY_LINK_PHONE = 0x1bf,
 
     KEY_DEL_EOL = 0x1c0,
     KEY_DEL_EOS = 0x1c1,
@@ -445,6 +456,7 @@ pub enum KeyCode {
 
     KEY_ALS_TOGGLE = 0x230,
     KEY_ROTATE_LOCK_TOGGLE = 0x231,
+    KEY_REFRESH_RATE_TOGGLE = 0x232,
 
     KEY_BUTTONCONFIG = 0x240,
     KEY_TASKMANAGER = 0x241,
@@ -457,6 +469,11 @@ pub enum KeyCode {
     KEY_KBD_LAYOUT_NEXT = 0x248,
     KEY_EMOJI_PICKER = 0x249,
     KEY_DICTATE = 0x24a,
+    KEY_CAMERA_ACCESS_ENABLE = 0x24b,
+    KEY_CAMERA_ACCESS_DISABLE = 0x24c,
+    KEY_CAMERA_ACCESS_TOGGLE = 0x24d,
+    KEY_ACCESSIBILITY = 0x24e,
+    KEY_DO_NOT_DISTURB = 0x24f,
 
     KEY_BRIGHTNESS_MIN = 0x250,
     KEY_BRIGHTNESS_MAX = 0x251,
@@ -547,6 +564,26 @@ pub enum KeyCode {
     KEY_KBD_LCD_MENU3 = 0x2ba,
     KEY_KBD_LCD_MENU4 = 0x2bb,
     KEY_KBD_LCD_MENU5 = 0x2bc,
+
+    /// A code that isn't mapped to any of the other variants, as reported by the device.
+    Unknown(u16),
+}
+
+impl KeyCode {
+    /// Convert a raw key code, falling back to [`KeyCode::Unknown`] if the code isn't mapped to
+    /// any of the other variants.
+    pub fn from_raw(code: u16) -> Self {
+        Self::try_from(code).unwrap_or(Self::Unknown(code))
+    }
+
+    /// The raw value of the key code.
+    pub fn code(self) -> u16 {
+        match self {
+            Self::Unknown(code) => code,
+            // The enum is `repr(u16)`, so the discriminant is stored at the start of the value
+            _ => unsafe { *(&self as *const Self as *const u16) },
+        }
+    }
 }
 
 impl TryFrom<u16> for KeyCode {
@@ -917,6 +954,7 @@ impl TryFrom<u16> for KeyCode {
             0x1bc => KEY_NOTIFICATION_CENTER,
             0x1bd => KEY_PICKUP_PHONE,
             0x1be => KEY_HANGUP_PHONE,
+            0x1bf => KEY_LINK_PHONE,
 
             0x1c0 => KEY_DEL_EOL,
             0x1c1 => KEY_DEL_EOS,
@@ -995,6 +1033,7 @@ impl TryFrom<u16> for KeyCode {
 
             0x230 => KEY_ALS_TOGGLE,
             0x231 => KEY_ROTATE_LOCK_TOGGLE,
+            0x232 => KEY_REFRESH_RATE_TOGGLE,
 
             0x240 => KEY_BUTTONCONFIG,
             0x241 => KEY_TASKMANAGER,
@@ -1007,6 +1046,11 @@ impl TryFrom<u16> for KeyCode {
             0x248 => KEY_KBD_LAYOUT_NEXT,
             0x249 => KEY_EMOJI_PICKER,
             0x24a => KEY_DICTATE,
+            0x24b => KEY_CAMERA_ACCESS_ENABLE,
+            0x24c => KEY_CAMERA_ACCESS_DISABLE,
+            0x24d => KEY_CAMERA_ACCESS_TOGGLE,
+            0x24e => KEY_ACCESSIBILITY,
+            0x24f => KEY_DO_NOT_DISTURB,
 
             0x250 => KEY_BRIGHTNESS_MIN,
             0x251 => KEY_BRIGHTNESS_MAX,
@@ -1102,6 +1146,125 @@ impl TryFrom<u16> for KeyCode {
     }
 }
 
+impl fmt::Display for KeyCode {
+    /// Format the key code as the name of its `KEY_*` constant, without the `KEY_` prefix (e.g.
+    /// `A` for `KEY_A`). Unknown codes are formatted as hexadecimal numbers (e.g. `0x110`).
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Self::Unknown(code) = self {
+            return write!(f, "{code:#x}");
+        }
+
+        let name = format!("{self:?}");
+
+        f.write_str(name.strip_prefix("KEY_").unwrap_or(&name))
+    }
+}
+
+impl FromStr for KeyCode {
+    type Err = KeyloggerError;
+
+    /// Parse a key code from the name of its `KEY_*` constant, with or without the `KEY_` prefix
+    /// (e.g. `KEY_A`, `A` or `LeftShift`).
+    ///
+    /// Names are case-insensitive. A few common aliases are also supported, such as `Esc`,
+    /// `Return`, `Del`, `PgUp` or `Ctrl`. Modifier aliases refer to the left variant of the key.
+    /// Raw codes can be specified as hexadecimal numbers (e.g. `0x110`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use KeyCode::*;
+
+        if let Some(hex) = s.trim().strip_prefix("0x") {
+            return u16::from_str_radix(hex, 16)
+                .map(KeyCode::from_raw)
+                .map_err(|_| KeyloggerError::UnknownKeyName(s.into()));
+        }
+
+        let name = s.trim().to_ascii_uppercase();
+        let name = name.strip_prefix("KEY_").unwrap_or(&name);
+
+        let alias = match name {
+            "ESCAPE" => Some(KEY_ESC),
+            "RETURN" => Some(KEY_ENTER),
+            "DEL" => Some(KEY_DELETE),
+            "INS" => Some(KEY_INSERT),
+            "PGUP" => Some(KEY_PAGEUP),
+            "PGDN" | "PGDOWN" => Some(KEY_PAGEDOWN),
+            "CAPS" => Some(KEY_CAPSLOCK),
+            "CTRL" | "CONTROL" => Some(KEY_LEFTCTRL),
+            "SHIFT" => Some(KEY_LEFTSHIFT),
+            "ALT" => Some(KEY_LEFTALT),
+            "META" | "SUPER" => Some(KEY_LEFTMETA),
+            _ => None,
+        };
+
+        alias
+            .or_else(|| {
+                (0..=KEY_MAX)
+                    .filter_map(|code| KeyCode::try_from(code).ok())
+                    .find(|code| code.to_string() == name)
+            })
+            .ok_or_else(|| KeyloggerError::UnknownKeyName(s.into()))
+    }
+}
+
+/// The media and hardware control keys, with the alternative codes used by different keyboards
+/// normalized to a single variant (see [`KeyCode::media_key`]).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MediaKey {
+    PlayPause,
+    Play,
+    Pause,
+    Stop,
+    Next,
+    Previous,
+    FastForward,
+    Rewind,
+    Record,
+    Eject,
+    Mute,
+    VolumeUp,
+    VolumeDown,
+    MicMute,
+    BrightnessUp,
+    BrightnessDown,
+    KeyboardBacklightUp,
+    KeyboardBacklightDown,
+    KeyboardBacklightToggle,
+}
+
+impl KeyCode {
+    /// The media key this key code corresponds to, if any.
+    ///
+    /// Keyboards report some media keys using different codes (for example, Play can be reported
+    /// as `KEY_PLAY` or `KEY_PLAYCD`), which are all mapped to the same [`MediaKey`].
+    pub fn media_key(self) -> Option<MediaKey> {
+        use KeyCode::*;
+
+        Some(match self {
+            KEY_PLAYPAUSE => MediaKey::PlayPause,
+            KEY_PLAY | KEY_PLAYCD => MediaKey::Play,
+            KEY_PAUSECD => MediaKey::Pause,
+            KEY_STOPCD => MediaKey::Stop,
+            KEY_NEXTSONG | KEY_NEXT => MediaKey::Next,
+            KEY_PREVIOUSSONG | KEY_PREVIOUS => MediaKey::Previous,
+            KEY_FASTFORWARD => MediaKey::FastForward,
+            KEY_REWIND => MediaKey::Rewind,
+            KEY_RECORD => MediaKey::Record,
+            KEY_EJECTCD | KEY_EJECTCLOSECD => MediaKey::Eject,
+            KEY_MUTE => MediaKey::Mute,
+            KEY_VOLUMEUP => MediaKey::VolumeUp,
+            KEY_VOLUMEDOWN => MediaKey::VolumeDown,
+            KEY_MICMUTE => MediaKey::MicMute,
+            KEY_BRIGHTNESSUP => MediaKey::BrightnessUp,
+            KEY_BRIGHTNESSDOWN => MediaKey::BrightnessDown,
+            KEY_KBDILLUMUP => MediaKey::KeyboardBacklightUp,
+            KEY_KBDILLUMDOWN => MediaKey::KeyboardBacklightDown,
+            KEY_KBDILLUMTOGGLE => MediaKey::KeyboardBacklightToggle,
+            _ => return None,
+        })
+    }
+}
+
 impl TryFrom<KeyCode> for char {
     type Error = KeyloggerError;
 
@@ -1163,3 +1326,55 @@ impl TryFrom<KeyCode> for char {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use KeyCode::*;
+
+    #[test]
+    fn key_code_names() {
+        assert_eq!(KEY_A.to_string(), "A");
+        assert_eq!(KEY_LEFTSHIFT.to_string(), "LEFTSHIFT");
+
+        for (name, code) in [
+            ("A", KEY_A),
+            ("key_a", KEY_A),
+            ("LeftShift", KEY_LEFTSHIFT),
+            ("Esc", KEY_ESC),
+            ("Escape", KEY_ESC),
+            ("Enter", KEY_ENTER),
+            ("Space", KEY_SPACE),
+            ("f12", KEY_F12),
+        ] {
+            assert_eq!(name.parse::<KeyCode>().unwrap(), code);
+        }
+
+        assert!("KEY_NOPE".parse::<KeyCode>().is_err());
+
+        for code in (0..=KEY_MAX).filter_map(|code| KeyCode::try_from(code).ok()) {
+            assert_eq!(code.to_string().parse::<KeyCode>().unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn media_keys() {
+        assert_eq!(KEY_PLAYCD.media_key(), KEY_PLAY.media_key());
+        assert_eq!(KEY_NEXT.media_key(), Some(MediaKey::Next));
+        assert_eq!(KeyCode::try_from(0x24f).unwrap(), KEY_DO_NOT_DISTURB);
+        assert_eq!(KEY_A.media_key(), None);
+    }
+
+    #[test]
+    fn unknown_key_codes() {
+        assert_eq!(KeyCode::from_raw(0x1e), KEY_A);
+        assert_eq!(KEY_A.code(), 0x1e);
+        assert_eq!(KEY_KBD_LCD_MENU5.code(), 0x2bc);
+
+        let btn_left = KeyCode::from_raw(0x110);
+
+        assert_eq!(btn_left, Unknown(0x110));
+        assert_eq!(btn_left.code(), 0x110);
+        assert_eq!(btn_left.to_string().parse::<KeyCode>().unwrap(), btn_left);
+    }
+}
@@ -1,29 +1,58 @@
+mod access;
+mod clock;
+mod connection;
 pub(crate) mod device;
-mod event_codes;
+pub(crate) mod event_codes;
+mod finder;
+mod key_filter;
+mod keys;
+mod led;
+mod raw;
+pub(crate) mod seat;
+mod set;
+mod udev;
 
+use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::fmt;
-use std::io::Cursor;
+use std::io::{self, Cursor};
 use std::marker::Unpin;
-use std::path::Path;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
 use chrono::naive::NaiveDateTime;
+use chrono::{DateTime, Local, Utc};
 use futures::Stream;
 use pin_project::pin_project;
 
 use crate::error::KeyloggerError;
+use crate::filters::{ByCause, KeyEventStreamExt};
 use crate::key_code::KeyCode;
+use crate::text::{CharComposer, Chars};
 use crate::KeyloggerResult;
-use device::InputDevice;
-use event_codes::{EV_KEY, EV_KEY_PRESS, EV_KEY_RELEASE};
+use device::{read_error, InputDevice};
+use event_codes::{EV_KEY, EV_KEY_PRESS, EV_KEY_RELEASE, EV_KEY_REPEAT};
 
-pub use crate::keyboard::device::find_keyboards;
+pub use crate::keyboard::clock::Clock;
+pub use crate::keyboard::connection::ConnectionType;
+pub use crate::keyboard::device::{
+    find_keyboards, find_keyboards_async, find_keyboards_verbose, DeviceInfo,
+};
+pub use crate::keyboard::finder::{KeyboardFinder, SkippedDevice};
+pub use crate::keyboard::key_filter::KeyFilter;
+pub use crate::keyboard::led::Led;
+pub use crate::keyboard::raw::{RawEvent, RawEvents};
+pub use crate::keyboard::seat::SeatSession;
+pub use crate::keyboard::set::{
+    merge_keyboards, DeviceContext, DeviceId, KeyboardSet, TaggedKeyEvent, WithContext,
+};
 
 type KeyEventResult = KeyloggerResult<Vec<KeyEvent>>;
 
-pub struct KeyboardDevice(Keyboard<InputDevice>);
+pub struct KeyboardDevice(pub(crate) Keyboard<InputDevice>);
 
 impl KeyboardDevice {
     /// A human-readable description of the keyboard (e.g. "USB-HID Keyboard").
@@ -35,6 +64,296 @@ impl KeyboardDevice {
     pub fn path(&self) -> &Path {
         self.0.inner.path()
     }
+
+    /// A path of the keyboard that doesn't change across boots, unlike [`KeyboardDevice::path`].
+    ///
+    /// This is one of the symlinks udev creates for the device in `/dev/input/by-id` (which
+    /// identify the device itself) or, failing that, in `/dev/input/by-path` (which identify the
+    /// port it's plugged into). It can be passed to [`KeyboardFinder::with_devices`] to open the
+    /// same keyboard again. Returns `None` if udev didn't create any symlinks for the device.
+    pub fn stable_id(&self) -> Option<PathBuf> {
+        let input_dir = self
+            .path()
+            .parent()
+            .map_or_else(device::input_dir, Path::to_path_buf);
+
+        device::stable_id(&input_dir, self.path())
+    }
+
+    /// Information about the hardware of the keyboard, such as its vendor and product ID.
+    pub fn info(&self) -> &DeviceInfo {
+        &self.0.inner.info
+    }
+
+    /// Grab the keyboard, so that its events are delivered exclusively to this `KeyboardDevice`.
+    ///
+    /// While the keyboard is grabbed, other applications (including the X server or Wayland
+    /// compositor) don't receive its keystrokes. The grab is released when the `KeyboardDevice` is
+    /// dropped, or when [`KeyboardDevice::ungrab`] is called.
+    pub fn grab(&mut self) -> KeyloggerResult<()> {
+        let res = self.0.inner.grab();
+
+        self.with_context(res)
+    }
+
+    /// Release the grab obtained using [`KeyboardDevice::grab`].
+    pub fn ungrab(&mut self) -> KeyloggerResult<()> {
+        let res = self.0.inner.ungrab();
+
+        self.with_context(res)
+    }
+
+    /// Stop capturing the events of the keyboard, without closing it.
+    ///
+    /// While the keyboard is paused, its [`Stream`] doesn't poll the device, and the events that
+    /// occur are dropped: they're not returned once the keyboard is resumed. This is useful for
+    /// suspending the capture while sensitive input (such as a password) is typed. The events
+    /// that were read but not yet returned are dropped too.
+    pub fn pause(&mut self) {
+        self.0.buffer(vec![]);
+        self.0.inner.pause();
+    }
+
+    /// Resume capturing the events of a keyboard paused using [`KeyboardDevice::pause`].
+    pub fn resume(&mut self) -> KeyloggerResult<()> {
+        let res = self.0.inner.resume();
+
+        self.with_context(res)
+    }
+
+    /// Whether the keyboard is paused (see [`KeyboardDevice::pause`]).
+    pub fn is_paused(&self) -> bool {
+        self.0.inner.paused
+    }
+
+    /// Only report the events of the keys that match the specified filter.
+    ///
+    /// The events of the other keys are dropped as soon as they're read from the device. This
+    /// replaces the filter configured using [`KeyboardFinder::key_filter`], if any.
+    pub fn set_key_filter(&mut self, filter: KeyFilter) {
+        self.0.inner.reader.key_filter = filter;
+    }
+
+    /// The filter applied to the events of the keyboard (see [`KeyboardDevice::set_key_filter`]).
+    pub fn key_filter(&self) -> &KeyFilter {
+        &self.0.inner.reader.key_filter
+    }
+
+    /// The keys that are currently held down.
+    ///
+    /// The events only report changes to the state of the keys, so this can be used to find out
+    /// which keys (e.g. modifiers) were already held down when the keyboard was opened.
+    pub fn held_keys(&self) -> KeyloggerResult<Vec<KeyCode>> {
+        let res = self.0.inner.held_keys();
+
+        self.with_context(res)
+    }
+
+    /// The keys the keyboard can report.
+    ///
+    /// This is useful for checking that a keyboard has the keys an application needs (e.g. a
+    /// numeric keypad) before monitoring it. Note some devices claim to support more keys than they
+    /// physically have.
+    pub fn supported_keys(&self) -> KeyloggerResult<HashSet<KeyCode>> {
+        let res = self.0.inner.supported_keys();
+
+        self.with_context(res)
+    }
+
+    /// Turn the specified LED of the keyboard on or off.
+    ///
+    /// This only changes the indicator: it doesn't toggle the state of the corresponding lock key.
+    /// Writing to the device requires write access to its device file.
+    pub fn set_led(&mut self, led: Led, on: bool) -> KeyloggerResult<()> {
+        let res = self.0.inner.set_led(led, on);
+
+        self.with_context(res)
+    }
+
+    /// The LEDs of the keyboard that are currently on.
+    pub fn get_leds(&self) -> KeyloggerResult<Vec<Led>> {
+        let res = self.0.inner.leds();
+
+        self.with_context(res)
+    }
+
+    /// Set the clock used to timestamp the events of the keyboard (see [`Clock`]).
+    ///
+    /// The events that were read but not yet returned are dropped, so all the events returned
+    /// afterwards are timestamped using the new clock.
+    pub fn set_clock(&mut self, clock: Clock) -> KeyloggerResult<()> {
+        self.0.buffer(vec![]);
+
+        let res = self.0.inner.set_clock(clock);
+
+        self.with_context(res)
+    }
+
+    /// The clock used to timestamp the events of the keyboard.
+    pub fn clock(&self) -> Clock {
+        self.0.inner.clock
+    }
+
+    /// Metadata about the batch of the last event returned, such as whether events were dropped
+    /// before it (see [`BatchInfo`]).
+    ///
+    /// The events read using [`KeyboardDevice::try_read_events`] may span several batches, in
+    /// which case this is the batch of the last one.
+    pub fn batch_info(&self) -> BatchInfo {
+        self.0.inner.reader.batch
+    }
+
+    /// A stream of the hardware reports of the keyboard.
+    ///
+    /// Each element contains the key events of one hardware report, i.e. the events the keyboard
+    /// reported at the same time (delimited by `SYN_REPORT`), such as the presses of keys that
+    /// were pressed simultaneously. The events that were already read but not yet returned by the
+    /// [`Stream`] implementation of `KeyboardDevice` are returned first, as a (partial) report.
+    pub fn reports(&mut self) -> Reports<'_> {
+        Reports { keyboard: self }
+    }
+
+    /// A stream of the key presses of the keyboard, without the releases and the autorepeat
+    /// events (see [`KeyEventStreamExt::presses`]).
+    ///
+    /// [`KeyEventStreamExt::presses`]: crate::filters::KeyEventStreamExt::presses
+    pub fn presses(&mut self) -> ByCause<&mut Self> {
+        KeyEventStreamExt::presses(self)
+    }
+
+    /// A stream of the key releases of the keyboard, without the presses and the autorepeat
+    /// events (see [`KeyEventStreamExt::releases`]).
+    ///
+    /// [`KeyEventStreamExt::releases`]: crate::filters::KeyEventStreamExt::releases
+    pub fn releases(&mut self) -> ByCause<&mut Self> {
+        KeyEventStreamExt::releases(self)
+    }
+
+    /// A stream of the characters typed on the keyboard, translated using the US QWERTY layout
+    /// (see [`CharComposer`] for the other layouts, and for the handling of Backspace).
+    pub fn chars(&mut self) -> Chars<&mut Self> {
+        CharComposer::new().chars(self)
+    }
+
+    /// Wait until the keyboard has events to read using [`KeyboardDevice::try_read_events`].
+    ///
+    /// Together with `try_read_events`, this makes it possible to integrate the keyboard into a
+    /// custom event loop (e.g. one that `select!`s on several futures) instead of consuming its
+    /// events through the [`Stream`] implementation. Being ready doesn't guarantee any key events
+    /// will be read (e.g. the device might have only reported events of other types, or events of
+    /// filtered out keys), so `try_read_events` can return an empty batch.
+    ///
+    /// This never completes while the keyboard is paused.
+    ///
+    /// ```no_run
+    /// use keylogger::{find_keyboards, KeyloggerError};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), KeyloggerError> {
+    ///     let mut keyboard = find_keyboards()?.remove(0);
+    ///
+    ///     loop {
+    ///         keyboard.ready().await?;
+    ///
+    ///         for ev in keyboard.try_read_events()? {
+    ///             println!("{ev:?}");
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub async fn ready(&mut self) -> KeyloggerResult<()> {
+        let res = futures::future::poll_fn(|cx| {
+            if self.0.has_buffered() {
+                return Poll::Ready(Ok(()));
+            }
+
+            self.0.inner.poll_ready(cx)
+        })
+        .await;
+        let res = res.map_err(|e| read_error(&self.0.inner.device, e));
+
+        self.with_context(res)
+    }
+
+    /// Read the events that are available without blocking, returning an empty batch if there are
+    /// none.
+    ///
+    /// The events that were read but not yet returned by the [`Stream`] implementation of the
+    /// keyboard are returned first. Nothing is read while the keyboard is paused.
+    pub fn try_read_events(&mut self) -> KeyloggerResult<Vec<KeyEvent>> {
+        let res = self.read_available();
+
+        self.with_context(res)
+    }
+
+    /// Read the events that are available without blocking (see
+    /// [`KeyboardDevice::try_read_events`]).
+    fn read_available(&mut self) -> KeyloggerResult<Vec<KeyEvent>> {
+        let mut evs = vec![];
+
+        while let Some(ev) = self.0.pop_buffered() {
+            evs.push(ev);
+        }
+
+        let inner = &mut self.0.inner;
+
+        if inner.paused {
+            return Ok(evs);
+        }
+
+        let fd = inner.file.as_raw_fd();
+
+        loop {
+            let len = evs.len();
+            let res = inner.reader.read_key_events(fd, &mut evs);
+
+            inner.trace_read(&res, evs.len() - len);
+
+            match res {
+                Ok(()) => continue,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(evs),
+                // The error is reported by the next read, once the events read so far are returned
+                Err(_) if !evs.is_empty() => return Ok(evs),
+                Err(e) => return Err(read_error(&inner.device, e)),
+            }
+        }
+    }
+}
+
+/// A [`Stream`] of the hardware reports of a [`KeyboardDevice`] (see [`KeyboardDevice::reports`]).
+pub struct Reports<'a> {
+    keyboard: &'a mut KeyboardDevice,
+}
+
+impl Stream for Reports<'_> {
+    type Item = KeyloggerResult<Vec<KeyEvent>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let keyboard = &mut *self.get_mut().keyboard;
+
+        keyboard
+            .0
+            .poll_next_batch(cx)
+            .map(|res| Some(keyboard.with_context(res)))
+    }
+}
+
+impl KeyboardDevice {
+    /// Drop the events that were read from the device but not yet returned.
+    pub(crate) fn discard_buffered(&mut self) {
+        self.0.buffer(vec![]);
+
+        self.0.inner.reader.discard_reports();
+    }
+
+    /// Attach the path and name of the keyboard to the error returned by an operation on it.
+    pub(crate) fn with_context<T>(&self, res: KeyloggerResult<T>) -> KeyloggerResult<T> {
+        res.map_err(|e| KeyloggerError::Device {
+            path: self.path().into(),
+            name: self.name().into(),
+            source: Box::new(e),
+        })
+    }
 }
 
 impl Stream for KeyboardDevice {
@@ -44,69 +363,196 @@ impl Stream for KeyboardDevice {
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
-        Pin::new(&mut self.get_mut().0).poll_next(cx)
+        let this = self.get_mut();
+
+        Pin::new(&mut this.0)
+            .poll_next(cx)
+            .map(|item| item.map(|res| this.with_context(res)))
     }
 }
 
-/// A generic keyboard device.
+/// A [`Stream`] of the key events of a custom [`KeyEventSource`].
+///
+/// This is what [`KeyboardDevice`] is built on, so the adapters that work with the events of a
+/// `KeyboardDevice` (such as the ones from the [`filters`](crate::filters) module) also work with
+/// the events of other sources, such as SSH sessions, serial consoles or test harnesses. Like the
+/// events of a `KeyboardDevice`, the stream never ends.
 #[pin_project]
-pub(crate) struct Keyboard<K: KeyEventSource> {
+pub struct Keyboard<K: KeyEventSource> {
     #[pin]
     pub(crate) inner: K,
     pub(crate) buffered_evs: Cursor<Vec<KeyEvent>>,
 }
 
 impl<K: KeyEventSource> Keyboard<K> {
-    fn new(inner: K) -> Self {
+    /// Create a stream of the events of the specified source.
+    pub fn new(inner: K) -> Self {
         Self {
             inner,
             buffered_evs: Default::default(),
         }
     }
+
+    /// The source of the events.
+    pub fn source(&self) -> &K {
+        &self.inner
+    }
+
+    /// The source of the events, mutably.
+    pub fn source_mut(&mut self) -> &mut K {
+        &mut self.inner
+    }
+
+    /// Consume the stream, returning the source of the events.
+    ///
+    /// The events that were read from the source but not yet returned are dropped.
+    pub fn into_source(self) -> K {
+        self.inner
+    }
+
+    /// Poll for the next batch of events, starting with the events that were read from the source
+    /// but not yet returned.
+    pub(crate) fn poll_next_batch(&mut self, cx: &mut Context<'_>) -> Poll<KeyEventResult> {
+        let pos = self.buffered_evs.position() as usize;
+        let buffered = mem::take(&mut self.buffered_evs).into_inner();
+
+        if pos < buffered.len() {
+            return Poll::Ready(Ok(buffered[pos..].to_vec()));
+        }
+
+        loop {
+            match KeyEventSource::poll_next(Pin::new(&mut self.inner), cx) {
+                Poll::Ready(Ok(evs)) if evs.is_empty() => continue,
+                res => return res,
+            }
+        }
+    }
+
+    /// Whether there are events that were read from the source, but not yet returned.
+    pub(crate) fn has_buffered(&self) -> bool {
+        (self.buffered_evs.position() as usize) < self.buffered_evs.get_ref().len()
+    }
+
+    /// Pop the next event that was read from the source, but not yet returned.
+    pub(crate) fn pop_buffered(&mut self) -> Option<KeyEvent> {
+        let pos = self.buffered_evs.position();
+        let ev = self.buffered_evs.get_ref().get(pos as usize).copied()?;
+
+        self.buffered_evs.set_position(pos + 1);
+
+        Some(ev)
+    }
+
+    /// Buffer the specified events, to be returned by [`Keyboard::pop_buffered`].
+    pub(crate) fn buffer(&mut self, evs: Vec<KeyEvent>) {
+        self.buffered_evs = Cursor::new(evs);
+    }
+
+    /// Take the buffer of the events that were read from the source, emptied, so it can be reused
+    /// for reading the next events.
+    ///
+    /// The events that were read but not yet returned are dropped.
+    #[cfg(feature = "blocking")]
+    pub(crate) fn take_buffer(&mut self) -> Vec<KeyEvent> {
+        let mut evs = mem::take(&mut self.buffered_evs).into_inner();
+
+        evs.clear();
+        evs
+    }
 }
 
 impl<K: KeyEventSource> Stream for Keyboard<K> {
     type Item = KeyloggerResult<KeyEvent>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let this = self.project();
-        let current_pos = this.buffered_evs.position();
-        let len = this.buffered_evs.get_ref().len() as u64;
+        let this = self.get_mut();
 
-        if current_pos >= len {
-            let inner_pin = Pin::new(this.inner.get_mut());
-            let evs = match KeyEventSource::poll_next(inner_pin, cx) {
+        loop {
+            if let Some(ev) = this.pop_buffered() {
+                return Poll::Ready(Some(Ok(ev)));
+            }
+
+            match KeyEventSource::poll_next(Pin::new(&mut this.inner), cx) {
                 Poll::Pending => return Poll::Pending,
-                Poll::Ready(Ok(evs)) if evs.is_empty() => return Poll::Pending,
-                Poll::Ready(Ok(evs)) => evs,
+                Poll::Ready(Ok(evs)) => this.buffer(evs),
                 Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e))),
-            };
-
-            *this.buffered_evs = Cursor::new(evs);
-            this.buffered_evs.set_position(0);
+            }
         }
-
-        let pos = this.buffered_evs.position();
-        let ev = this.buffered_evs.get_ref()[pos as usize];
-        this.buffered_evs.set_position(pos + 1);
-
-        Poll::Ready(Some(Ok(ev)))
     }
 }
 
-pub(crate) trait KeyEventSource: fmt::Debug + Unpin + Send + Sync {
+/// A source of key events, such as a keyboard device.
+///
+/// Implementing this trait allows the events of any source to be consumed using a [`Keyboard`].
+///
+/// New methods added to this trait in future versions of the crate will have default
+/// implementations, so implementing it won't break when the crate is upgraded within the same
+/// major version.
+///
+/// # Example
+///
+/// A source fed by a channel (for example, by a task that parses the input of a serial console):
+///
+/// ```
+/// use futures::channel::mpsc::UnboundedReceiver;
+/// use futures::Stream;
+/// use keylogger::{KeyEvent, KeyEventSource, KeyloggerResult};
+/// use std::path::{Path, PathBuf};
+/// use std::pin::Pin;
+/// use std::task::{Context, Poll};
+///
+/// #[derive(Debug)]
+/// struct ChannelSource {
+///     path: PathBuf,
+///     rx: UnboundedReceiver<KeyEvent>,
+/// }
+///
+/// impl KeyEventSource for ChannelSource {
+///     fn name(&self) -> &str {
+///         "serial console"
+///     }
+///
+///     fn path(&self) -> &Path {
+///         &self.path
+///     }
+///
+///     fn poll_next(
+///         self: Pin<&mut Self>,
+///         cx: &mut Context<'_>,
+///     ) -> Poll<KeyloggerResult<Vec<KeyEvent>>> {
+///         match Pin::new(&mut self.get_mut().rx).poll_next(cx) {
+///             Poll::Ready(Some(ev)) => Poll::Ready(Ok(vec![ev])),
+///             // The sender is gone, so there won't be any more events
+///             Poll::Ready(None) | Poll::Pending => Poll::Pending,
+///         }
+///     }
+/// }
+/// ```
+pub trait KeyEventSource: fmt::Debug + Unpin + Send + Sync {
     /// A human-readable description of the event source (e.g. "USB-HID Keyboard").
     fn name(&self) -> &str;
 
-    /// The path of the device (e.g. `/dev/input/event4`)
+    /// The path of the device (e.g. `/dev/input/event4`), or some other identifier of the source
+    /// (such as the path of a serial port).
     fn path(&self) -> &Path;
 
-    /// Poll the event source.
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<KeyEventResult>;
+    /// Poll the event source for the next batch of events.
+    ///
+    /// If the source has a notion of hardware reports, each batch should contain the events of a
+    /// single report (see [`KeyboardDevice::reports`]).
+    ///
+    /// Like [`Stream::poll_next`], this must arrange for the waker of `cx` to be woken up once
+    /// more events are available if it returns [`Poll::Pending`]. An empty batch is skipped, and
+    /// the source is polled again straight away.
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<KeyloggerResult<Vec<KeyEvent>>>;
 }
 
 /// A key event (EV_KEY).
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct KeyEvent {
     /// The timestamp of the event.
     pub ts: NaiveDateTime,
@@ -114,15 +560,53 @@ pub struct KeyEvent {
     pub cause: KeyEventCause,
     /// The key code of the key that triggered the event.
     pub code: KeyCode,
+    /// The raw hardware scancode of the key (from `MSC_SCAN`), if scancode capturing is enabled
+    /// (see [`KeyboardFinder::capture_scancodes`]) and the device reports one.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub scancode: Option<u32>,
+}
+
+impl KeyEvent {
+    /// The timestamp of the event, as a UTC date and time.
+    ///
+    /// [`KeyEvent::ts`] is always in UTC, but doesn't say so in its type (or when it's formatted).
+    pub fn ts_utc(&self) -> DateTime<Utc> {
+        self.ts.and_utc()
+    }
+
+    /// The timestamp of the event, converted to the local timezone.
+    pub fn ts_local(&self) -> DateTime<Local> {
+        self.ts_utc().with_timezone(&Local)
+    }
+}
+
+/// Metadata about a batch of key events (i.e. a hardware report) read from a keyboard (see
+/// [`KeyboardDevice::batch_info`]).
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BatchInfo {
+    /// The sequence number of the batch, which is incremented for each batch read from the
+    /// keyboard, starting at 0.
+    pub seq: u64,
+    /// The number of times the kernel dropped the events of the keyboard (because they weren't
+    /// read fast enough) between the previous batch and this one.
+    ///
+    /// The number of events lost is unknown. After a drop, the keys held down are resynchronized
+    /// with the state of the keyboard, so the batch starts with the (synthesized) releases and
+    /// presses of the keys whose events were lost.
+    pub dropped: u32,
 }
 
 /// The reason a `KeyEvent` fired.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum KeyEventCause {
     /// The key was pressed.
     Press,
     /// The key was released.
     Release,
+    /// The key is being held down, and autorepeat kicked in.
+    Repeat,
 }
 
 impl TryFrom<&libc::input_event> for KeyEvent {
@@ -137,6 +621,7 @@ impl TryFrom<&libc::input_event> for KeyEvent {
         let cause = match ev.value {
             EV_KEY_RELEASE => KeyEventCause::Release,
             EV_KEY_PRESS => KeyEventCause::Press,
+            EV_KEY_REPEAT => KeyEventCause::Repeat,
             n => {
                 return Err(KeyloggerError::InvalidKeyEvent(format!(
                     "invalid value for EV_KEY: {n}"
@@ -144,22 +629,46 @@ impl TryFrom<&libc::input_event> for KeyEvent {
             }
         };
 
-        let nsec = (ev.time.tv_usec * 1000)
-            .try_into()
-            .map_err(|_| KeyloggerError::InvalidTimestamp(ev.time.tv_sec, ev.time.tv_usec))?;
-
-        let ts = NaiveDateTime::from_timestamp_opt(ev.time.tv_sec, nsec).ok_or(
-            KeyloggerError::InvalidTimestamp(ev.time.tv_sec, ev.time.tv_usec),
-        )?;
-
         Ok(Self {
-            ts,
+            ts: timestamp(&ev.time)?,
             cause,
-            code: KeyCode::try_from(ev.code)?,
+            code: KeyCode::from_raw(ev.code),
+            scancode: None,
         })
     }
 }
 
+/// Convert the timestamp of an input event.
+pub(crate) fn timestamp(time: &libc::timeval) -> KeyloggerResult<NaiveDateTime> {
+    let nsec = (time.tv_usec * 1000)
+        .try_into()
+        .map_err(|_| KeyloggerError::InvalidTimestamp(time.tv_sec, time.tv_usec))?;
+
+    DateTime::from_timestamp(time.tv_sec, nsec)
+        .map(|ts| ts.naive_utc())
+        .ok_or(KeyloggerError::InvalidTimestamp(time.tv_sec, time.tv_usec))
+}
+
+impl From<&KeyEvent> for libc::input_event {
+    fn from(ev: &KeyEvent) -> Self {
+        let value = match ev.cause {
+            KeyEventCause::Release => EV_KEY_RELEASE,
+            KeyEventCause::Press => EV_KEY_PRESS,
+            KeyEventCause::Repeat => EV_KEY_REPEAT,
+        };
+
+        libc::input_event {
+            time: libc::timeval {
+                tv_sec: ev.ts.and_utc().timestamp(),
+                tv_usec: ev.ts.and_utc().timestamp_subsec_micros().into(),
+            },
+            type_: EV_KEY as u16,
+            code: ev.code.code(),
+            value,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -179,14 +688,37 @@ mod tests {
             use KeyloggerError::*;
 
             match self {
-                Io(_) => unimplemented!("unexpected error type"),
+                Io(_) | DeviceDisconnected(_) | DeviceNotFound(_) | Interrupted(_) => {
+                    unimplemented!("unexpected error type")
+                }
                 NotAKeyboard(e) => NotAKeyboard(e.clone()),
                 InvalidKeyEvent(e) => InvalidKeyEvent(e.clone()),
                 InvalidKeyCode(e) => InvalidKeyCode(*e),
+                UnknownKeyName(e) => UnknownKeyName(e.clone()),
                 InvalidTimestamp(s, ms) => InvalidTimestamp(*s, *ms),
                 KeyCodeConversion(e) => KeyCodeConversion(*e),
                 UnsupportedEventType(e) => UnsupportedEventType(*e),
+                InvalidLayout(e) => InvalidLayout(e.clone()),
+                InvalidFilter(e) => InvalidFilter(e.clone()),
+                InvalidChord(e) => InvalidChord(e.clone()),
+                Serialization(e) => Serialization(e.clone()),
+                Grpc(e) => Grpc(e.clone()),
+                Accessibility(e) => Accessibility(e.clone()),
+                Dbus(e) => Dbus(e.clone()),
+                WindowSystem(e) => WindowSystem(e.clone()),
+                Database(e) => Database(e.clone()),
+                InvalidCapture(e) => InvalidCapture(e.clone()),
+                Authentication(e) => Authentication(e.clone()),
                 KeyloggerTasksExited => KeyloggerTasksExited,
+                PermissionDenied { path, hint } => PermissionDenied {
+                    path: path.clone(),
+                    hint: hint.clone(),
+                },
+                Device { path, name, source } => Device {
+                    path: path.clone(),
+                    name: name.clone(),
+                    source: source.clone(),
+                },
             }
         }
     }
@@ -196,14 +728,44 @@ mod tests {
             use KeyloggerError::*;
 
             match (self, other) {
-                (Io(_), _) => unimplemented!("unexpected error type"),
+                (Io(_) | DeviceDisconnected(_) | DeviceNotFound(_) | Interrupted(_), _) => {
+                    unimplemented!("unexpected error type")
+                }
                 (NotAKeyboard(e1), NotAKeyboard(e2)) => e1.eq(e2),
                 (InvalidKeyEvent(e1), InvalidKeyEvent(e2)) => e1.eq(e2),
                 (InvalidKeyCode(e1), InvalidKeyCode(e2)) => e1.eq(e2),
+                (UnknownKeyName(e1), UnknownKeyName(e2)) => e1.eq(e2),
                 (InvalidTimestamp(s1, ms1), InvalidTimestamp(s2, ms2)) => s1.eq(s2) && ms1.eq(ms2),
                 (KeyCodeConversion(e1), KeyCodeConversion(e2)) => e1.eq(e2),
                 (UnsupportedEventType(e1), UnsupportedEventType(e2)) => e1.eq(e2),
+                (InvalidLayout(e1), InvalidLayout(e2)) => e1.eq(e2),
+                (InvalidFilter(e1), InvalidFilter(e2)) => e1.eq(e2),
+                (InvalidChord(e1), InvalidChord(e2)) => e1.eq(e2),
+                (Serialization(e1), Serialization(e2)) => e1.eq(e2),
+                (Grpc(e1), Grpc(e2)) => e1.eq(e2),
+                (Accessibility(e1), Accessibility(e2)) => e1.eq(e2),
+                (Dbus(e1), Dbus(e2)) => e1.eq(e2),
+                (WindowSystem(e1), WindowSystem(e2)) => e1.eq(e2),
+                (Database(e1), Database(e2)) => e1.eq(e2),
+                (InvalidCapture(e1), InvalidCapture(e2)) => e1.eq(e2),
+                (Authentication(e1), Authentication(e2)) => e1.eq(e2),
                 (KeyloggerTasksExited, KeyloggerTasksExited) => true,
+                (
+                    PermissionDenied { path: p1, hint: h1 },
+                    PermissionDenied { path: p2, hint: h2 },
+                ) => p1.eq(p2) && h1.eq(h2),
+                (
+                    Device {
+                        path: p1,
+                        name: n1,
+                        source: s1,
+                    },
+                    Device {
+                        path: p2,
+                        name: n2,
+                        source: s2,
+                    },
+                ) => p1.eq(p2) && n1.eq(n2) && s1.eq(s2),
                 _ => false,
             }
         }
@@ -263,6 +825,7 @@ mod tests {
                 ts: Default::default(),
                 cause: KeyEventCause::Press,
                 code,
+                scancode: None,
             }
         }
 
@@ -271,6 +834,7 @@ mod tests {
                 ts: Default::default(),
                 cause: KeyEventCause::Release,
                 code,
+                scancode: None,
             }
         }
     }
@@ -319,4 +883,198 @@ mod tests {
 
         assert_eq!(recorded_events, expected_events);
     }
+
+    #[test]
+    fn device_errors() {
+        let (rx, _tx) = crate::keyboard::device::tests::pipe();
+        let keyboard = KeyboardDevice::fake("USB Keyboard", Path::new("/dev/input/event4"), rx);
+
+        // A pipe doesn't support the ioctls of input devices
+        let err = keyboard.held_keys().unwrap_err();
+
+        assert_eq!(err.device_path(), Some(Path::new("/dev/input/event4")));
+        assert_eq!(err.raw_os_error(), Some(libc::ENOTTY));
+        assert!(err
+            .to_string()
+            .starts_with("USB Keyboard (/dev/input/event4): "));
+    }
+
+    #[test]
+    fn split_reports() {
+        use crate::keyboard::device::EventReader;
+        use crate::keyboard::event_codes::{EV_SYN, SYN_DROPPED, SYN_REPORT};
+        use std::io;
+
+        let syn = libc::input_event {
+            time: libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            type_: EV_SYN as u16,
+            code: SYN_REPORT,
+            value: 0,
+        };
+        let key = |ev: KeyEvent| libc::input_event::from(&ev);
+        let input_evs = [
+            key(KeyEvent::press(KeyCode::KEY_LEFTSHIFT)),
+            key(KeyEvent::press(KeyCode::KEY_A)),
+            syn,
+            // A report without key events
+            syn,
+            key(KeyEvent::release(KeyCode::KEY_A)),
+            syn,
+            // The events up to the next SYN_REPORT are dropped (and the held keys of the pipe
+            // can't be resynchronized)
+            key(KeyEvent::press(KeyCode::KEY_B)),
+            libc::input_event {
+                code: SYN_DROPPED,
+                ..syn
+            },
+            key(KeyEvent::press(KeyCode::KEY_C)),
+            syn,
+            key(KeyEvent::press(KeyCode::KEY_D)),
+            syn,
+            // An incomplete report
+            key(KeyEvent::release(KeyCode::KEY_LEFTSHIFT)),
+        ];
+
+        let mut fds = [0; 2];
+        assert_eq!(
+            unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK) },
+            0
+        );
+
+        let size = mem::size_of_val(&input_evs);
+        let n = unsafe { libc::write(fds[1], input_evs.as_ptr() as *const _, size) };
+        assert_eq!(n as usize, size);
+
+        let mut reader = EventReader::default();
+        let report = |reader: &mut EventReader| {
+            let mut evs = vec![];
+
+            reader.read_key_events(fds[0], &mut evs).map(|()| {
+                evs.into_iter()
+                    .map(|ev| (ev.cause, ev.code))
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(
+            report(&mut reader).unwrap(),
+            [
+                (KeyEventCause::Press, KeyCode::KEY_LEFTSHIFT),
+                (KeyEventCause::Press, KeyCode::KEY_A)
+            ]
+        );
+        assert_eq!(reader.batch, BatchInfo { seq: 0, dropped: 0 });
+        assert_eq!(
+            report(&mut reader).unwrap(),
+            [(KeyEventCause::Release, KeyCode::KEY_A)]
+        );
+        assert_eq!(
+            report(&mut reader).unwrap(),
+            [(KeyEventCause::Press, KeyCode::KEY_D)]
+        );
+        assert_eq!(reader.batch, BatchInfo { seq: 2, dropped: 1 });
+        assert_eq!(
+            report(&mut reader).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+
+        unsafe {
+            libc::close(fds[0]);
+            libc::close(fds[1]);
+        }
+    }
+
+    #[tokio::test]
+    async fn ready_and_try_read() {
+        use crate::keyboard::device::EventReader;
+        use crate::keyboard::event_codes::{EV_SYN, SYN_REPORT};
+        use std::fs::File;
+        use std::os::unix::io::FromRawFd;
+
+        let mut fds = [0; 2];
+        assert_eq!(
+            unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK) },
+            0
+        );
+
+        let (rx, mut tx) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        let mut keyboard = KeyboardDevice(Keyboard::new(InputDevice {
+            name: "test keeb".into(),
+            info: DeviceInfo {
+                bus_type: 0,
+                vendor: 0,
+                product: 0,
+                version: 0,
+                phys: None,
+                uniq: None,
+                seat: None,
+            },
+            device: "/test/keeb".into(),
+            async_fd: None,
+            file: rx,
+            grabbed: false,
+            reader: EventReader::default(),
+            paused: false,
+            resume_waker: None,
+            clock: Clock::Realtime,
+            span: crate::trace::keyboard_span("test keeb", "/test/keeb".as_ref()),
+        }));
+
+        let syn = libc::input_event {
+            time: libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            type_: EV_SYN as u16,
+            code: SYN_REPORT,
+            value: 0,
+        };
+        let write = |tx: &mut File, evs: &[libc::input_event]| {
+            use std::io::Write;
+
+            let buf = unsafe {
+                std::slice::from_raw_parts(evs.as_ptr() as *const u8, mem::size_of_val(evs))
+            };
+
+            tx.write_all(buf).unwrap();
+        };
+
+        assert!(keyboard.try_read_events().unwrap().is_empty());
+
+        write(
+            &mut tx,
+            &[
+                (&KeyEvent::press(KeyCode::KEY_A)).into(),
+                syn,
+                (&KeyEvent::release(KeyCode::KEY_A)).into(),
+                syn,
+            ],
+        );
+
+        keyboard.ready().await.unwrap();
+
+        assert_eq!(
+            keyboard.try_read_events().unwrap(),
+            [
+                KeyEvent::press(KeyCode::KEY_A),
+                KeyEvent::release(KeyCode::KEY_A)
+            ]
+        );
+        assert!(keyboard.try_read_events().unwrap().is_empty());
+
+        // The stale readiness of the reactor doesn't complete the future
+        let pending = futures::poll!(Box::pin(keyboard.ready()));
+        assert!(pending.is_pending());
+
+        write(&mut tx, &[(&KeyEvent::press(KeyCode::KEY_B)).into(), syn]);
+        keyboard.ready().await.unwrap();
+
+        assert_eq!(
+            keyboard.next().await.unwrap().unwrap(),
+            KeyEvent::press(KeyCode::KEY_B)
+        );
+    }
 }
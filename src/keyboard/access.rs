@@ -0,0 +1,151 @@
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use crate::error::KeyloggerError;
+
+/// The group that owns the input devices on most distributions.
+const INPUT_GROUP: &str = "input";
+/// The group database.
+const GROUP_FILE: &str = "/etc/group";
+/// The user database.
+const PASSWD_FILE: &str = "/etc/passwd";
+
+/// Whether the specified error means the process isn't allowed to open a device.
+pub(crate) fn is_permission_error(e: &io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(libc::EACCES) | Some(libc::EPERM))
+}
+
+/// Build a [`KeyloggerError::PermissionDenied`] error for the specified device, with a hint on
+/// how to gain access to it.
+pub(crate) fn permission_denied(device: &Path) -> KeyloggerError {
+    let hint = match fs::metadata(device) {
+        Ok(meta) => {
+            let groups = fs::read_to_string(GROUP_FILE).unwrap_or_default();
+            let group = find_group(&groups, meta.gid());
+            let user = fs::read_to_string(PASSWD_FILE)
+                .ok()
+                .and_then(|passwd| find_user(&passwd, unsafe { libc::geteuid() }));
+            let listed = match (&group, &user) {
+                (Some(group), Some(user)) => group.members.iter().any(|m| m == user),
+                _ => false,
+            };
+
+            hint(
+                group.as_ref().map(|g| g.name.as_str()),
+                listed,
+                process_groups().contains(&meta.gid()),
+            )
+        }
+        Err(_) => "run as root".into(),
+    };
+
+    KeyloggerError::PermissionDenied {
+        path: device.into(),
+        hint,
+    }
+}
+
+/// A hint on how to gain access to a device owned by the specified group, given whether the user
+/// is listed as a member of the group, and whether the process is in the group.
+fn hint(group: Option<&str>, listed: bool, in_group: bool) -> String {
+    match group {
+        Some(group) if in_group => {
+            format!("the device isn't readable by the `{group}` group; run as root")
+        }
+        Some(group) if listed => format!(
+            "you were added to the `{group}` group, but the change only takes effect after logging \
+             out and back in"
+        ),
+        Some(INPUT_GROUP) => format!(
+            "add your user to the `{INPUT_GROUP}` group (`sudo usermod -aG {INPUT_GROUP} $USER`) \
+             and log back in, or run as root"
+        ),
+        Some(group) => format!("run as root, or as a member of the `{group}` group"),
+        None => "run as root".into(),
+    }
+}
+
+/// An entry of the group database.
+#[derive(Debug, PartialEq)]
+struct Group {
+    name: String,
+    members: Vec<String>,
+}
+
+/// Find the group with the specified ID in the group database.
+fn find_group(groups: &str, gid: u32) -> Option<Group> {
+    groups.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        let id = fields.nth(1)?.parse::<u32>().ok()?;
+        let members = fields.next().unwrap_or_default();
+
+        (id == gid).then(|| Group {
+            name: name.into(),
+            members: members
+                .split(',')
+                .filter(|m| !m.is_empty())
+                .map(Into::into)
+                .collect(),
+        })
+    })
+}
+
+/// Find the name of the user with the specified ID in the user database.
+fn find_user(passwd: &str, uid: u32) -> Option<String> {
+    passwd.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        let id = fields.nth(1)?.parse::<u32>().ok()?;
+
+        (id == uid).then(|| name.into())
+    })
+}
+
+/// The IDs of the groups of the process (including its effective group).
+fn process_groups() -> Vec<u32> {
+    let n = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+    let mut groups = vec![0; n.max(0) as usize];
+    let n = unsafe { libc::getgroups(groups.len() as libc::c_int, groups.as_mut_ptr()) };
+
+    groups.truncate(n.max(0) as usize);
+    groups.push(unsafe { libc::getegid() });
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permission_hints() {
+        let groups = "root:x:0:\ninput:x:104:alice,bob\nplugdev:x:46:\n";
+
+        assert_eq!(
+            find_group(groups, 104),
+            Some(Group {
+                name: "input".into(),
+                members: vec!["alice".into(), "bob".into()],
+            })
+        );
+        assert_eq!(
+            find_group(groups, 46).unwrap().members,
+            Vec::<String>::new()
+        );
+        assert_eq!(find_group(groups, 1000), None);
+        assert_eq!(
+            find_user(
+                "root:x:0:0::/root:/bin/sh\nalice:x:1000:1000::/home/alice:/bin/sh",
+                1000
+            ),
+            Some("alice".into())
+        );
+
+        assert!(hint(Some("input"), false, false).contains("usermod -aG input"));
+        assert!(hint(Some("input"), true, false).contains("log"));
+        assert!(hint(Some("input"), true, true).contains("isn't readable"));
+        assert_eq!(hint(None, false, false), "run as root");
+    }
+}
@@ -0,0 +1,110 @@
+use std::io;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+use crate::keyboard::device::{ioc, ioctl, InputDevice, IOC_WRITE};
+use crate::keyboard::KeyEvent;
+use crate::KeyloggerResult;
+
+/// The clock used to timestamp the events of a keyboard.
+///
+/// By default, the kernel timestamps the events using the wall clock, which jumps whenever the
+/// system time is changed (for example, by NTP). The other clocks only ever move forward, so they
+/// are better suited for measuring the time elapsed between events. Their timestamps aren't dates:
+/// [`KeyEvent::ts`] holds them as if they were the time elapsed since the Unix epoch, and
+/// [`KeyEvent::clock_time`] returns them as a [`Duration`].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Clock {
+    /// The wall clock (`CLOCK_REALTIME`).
+    #[default]
+    Realtime,
+    /// The time elapsed since boot, excluding the time the system was suspended
+    /// (`CLOCK_MONOTONIC`).
+    Monotonic,
+    /// The time elapsed since boot, including the time the system was suspended
+    /// (`CLOCK_BOOTTIME`).
+    Boottime,
+}
+
+impl Clock {
+    fn id(self) -> libc::clockid_t {
+        match self {
+            Clock::Realtime => libc::CLOCK_REALTIME,
+            Clock::Monotonic => libc::CLOCK_MONOTONIC,
+            Clock::Boottime => libc::CLOCK_BOOTTIME,
+        }
+    }
+
+    /// The current time of the clock, as the time elapsed since the start of the clock.
+    ///
+    /// This can be compared with [`KeyEvent::clock_time`] to find out how long ago an event
+    /// occurred.
+    pub fn now(self) -> Duration {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+
+        // This can only fail if the clock isn't supported, and all the clocks are supported
+        // since Linux 2.6.39
+        let res = unsafe { libc::clock_gettime(self.id(), &mut ts) };
+        assert_eq!(res, 0, "clock_gettime: {}", io::Error::last_os_error());
+
+        Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+    }
+}
+
+impl KeyEvent {
+    /// The timestamp of the event, as the time elapsed since the start of the clock of the
+    /// keyboard (see [`Clock`]).
+    ///
+    /// For the keyboards that use [`Clock::Realtime`], this is the time elapsed since the Unix
+    /// epoch.
+    pub fn clock_time(&self) -> Duration {
+        (self.ts - chrono::NaiveDateTime::default())
+            .to_std()
+            .unwrap_or_default()
+    }
+}
+
+impl InputDevice {
+    /// Set the clock used to timestamp the events of the device using the `EVIOCSCLOCKID` ioctl.
+    ///
+    /// The events that were read but not yet returned are discarded, so they can't be confused
+    /// with the events timestamped using the new clock.
+    pub(crate) fn set_clock(&mut self, clock: Clock) -> KeyloggerResult<()> {
+        let mut id = clock.id();
+
+        ioctl(
+            self.as_raw_fd(),
+            ioc(IOC_WRITE, 'E', 0xa0, mem::size_of::<libc::c_int>()),
+            &mut id as *mut libc::c_int as *mut libc::c_ulong,
+        )?;
+
+        self.reader.discard_pending(self.as_raw_fd())?;
+        self.clock = clock;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_code::KeyCode;
+    use crate::keyboard::KeyEventCause;
+
+    #[test]
+    fn clock_time() {
+        let ev = KeyEvent {
+            ts: chrono::NaiveDateTime::default() + chrono::Duration::milliseconds(1500),
+            cause: KeyEventCause::Press,
+            code: KeyCode::KEY_A,
+            scancode: None,
+        };
+
+        assert_eq!(ev.clock_time(), Duration::from_millis(1500));
+        assert!(Clock::Monotonic.now() <= Clock::Boottime.now());
+    }
+}
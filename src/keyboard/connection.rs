@@ -0,0 +1,92 @@
+use std::fs;
+use std::path::Path;
+
+use crate::keyboard::event_codes::{
+    BUS_BLUETOOTH, BUS_HOST, BUS_I2C, BUS_I8042, BUS_SPI, BUS_USB, BUS_VIRTUAL,
+};
+
+/// The directory of the power supplies, which include the batteries of wireless devices.
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+/// How a keyboard is connected to the system (see [`DeviceInfo::connection`]).
+///
+/// [`DeviceInfo::connection`]: crate::DeviceInfo::connection
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConnectionType {
+    /// The keyboard is plugged in over USB (including the receivers of wireless keyboards).
+    Usb,
+    /// The keyboard is paired over Bluetooth (classic or Low Energy).
+    Bluetooth,
+    /// The keyboard is built into the system, like the keyboard of a laptop (connected over PS/2,
+    /// I2C or SPI).
+    Internal,
+    /// The keyboard is a virtual device, like the ones created using uinput.
+    Virtual,
+    /// Another bus (one of the `BUS_*` constants from `linux/input.h`).
+    Other(u16),
+}
+
+impl From<u16> for ConnectionType {
+    fn from(bus_type: u16) -> Self {
+        match bus_type {
+            BUS_USB => Self::Usb,
+            BUS_BLUETOOTH => Self::Bluetooth,
+            BUS_I8042 | BUS_I2C | BUS_HOST | BUS_SPI => Self::Internal,
+            BUS_VIRTUAL => Self::Virtual,
+            bus_type => Self::Other(bus_type),
+        }
+    }
+}
+
+/// Read the battery level (in percent) of the device with the specified unique identifier.
+///
+/// The HID driver registers the batteries of the devices that report one as `hid-<uniq>-battery`
+/// power supplies, where the unique identifier of Bluetooth devices is their address.
+pub(crate) fn read_battery_level(uniq: &str) -> Option<u8> {
+    read_battery_level_in(Path::new(POWER_SUPPLY_DIR), uniq)
+}
+
+fn read_battery_level_in(power_supply_dir: &Path, uniq: &str) -> Option<u8> {
+    let name = format!("hid-{uniq}-battery");
+    // The addresses aren't always formatted with the same case as the identifier of the device
+    let dir = fs::read_dir(power_supply_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .find(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .eq_ignore_ascii_case(&name)
+        })?
+        .path();
+    let capacity = fs::read_to_string(dir.join("capacity")).ok()?;
+
+    capacity.trim().parse().ok().filter(|level| *level <= 100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn battery_levels() {
+        let dir = std::env::temp_dir().join(format!("keylogger-power-{}", std::process::id()));
+        let battery = dir.join("hid-AA:BB:CC:DD:EE:FF-battery");
+
+        fs::create_dir_all(&battery).unwrap();
+        fs::write(battery.join("capacity"), "87\n").unwrap();
+
+        assert_eq!(read_battery_level_in(&dir, "aa:bb:cc:dd:ee:ff"), Some(87));
+        assert_eq!(read_battery_level_in(&dir, "11:22:33:44:55:66"), None);
+
+        fs::write(battery.join("capacity"), "unknown\n").unwrap();
+
+        assert_eq!(read_battery_level_in(&dir, "aa:bb:cc:dd:ee:ff"), None);
+        assert_eq!(ConnectionType::from(0x05), ConnectionType::Bluetooth);
+        assert_eq!(ConnectionType::from(0x11), ConnectionType::Internal);
+        assert_eq!(ConnectionType::from(0x13), ConnectionType::Other(0x13));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,20 +1,31 @@
+use std::collections::{HashSet, VecDeque};
 use std::convert::TryFrom;
 use std::fs::{self, File};
 use std::io;
 use std::mem;
 use std::os::unix::fs::FileTypeExt;
-use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
-use std::sync::Arc;
-use std::task::{Context, Poll};
+use std::task::{Context, Poll, Waker};
 
 use futures::ready;
-use tokio::io::unix::AsyncFd;
 
 use crate::error::KeyloggerError;
-use crate::keyboard::event_codes::{EV_KEY, EV_MSC, EV_REP, EV_SYN};
-use crate::keyboard::{KeyEvent, KeyEventResult, KeyEventSource, Keyboard, KeyboardDevice};
+use crate::key_code::KeyCode;
+use crate::keyboard::access::{is_permission_error, permission_denied};
+use crate::keyboard::connection::{read_battery_level, ConnectionType};
+use crate::keyboard::event_codes::{
+    EV_KEY, EV_MSC, EV_REP, EV_SYN, MSC_SCAN, SYN_DROPPED, SYN_REPORT,
+};
+use crate::keyboard::keys::read_held_keys;
+use crate::keyboard::seat::{read_active_session, read_seat, SeatSession};
+use crate::keyboard::{
+    timestamp, BatchInfo, Clock, KeyEvent, KeyEventCause, KeyEventResult, KeyEventSource,
+    KeyFilter, Keyboard, KeyboardDevice, KeyboardFinder, SkippedDevice,
+};
+use crate::reactor::AsyncFd;
+use crate::trace::{self, Span};
 use crate::KeyloggerResult;
 
 const IOC_NRBITS: libc::c_ulong = 8;
@@ -24,44 +35,346 @@ const IOC_NRSHIFT: libc::c_ulong = 0;
 const IOC_TYPESHIFT: libc::c_ulong = IOC_NRSHIFT + IOC_NRBITS;
 const IOC_SIZESHIFT: libc::c_ulong = IOC_TYPESHIFT + IOC_TYPEBITS;
 const IOC_DIRSHIFT: libc::c_ulong = IOC_SIZESHIFT + IOC_SIZEBITS;
-const IOC_READ: libc::c_ulong = 2;
+pub(crate) const IOC_NONE: libc::c_ulong = 0;
+pub(crate) const IOC_WRITE: libc::c_ulong = 1;
+pub(crate) const IOC_READ: libc::c_ulong = 2;
+
+/// The directory where the input device files live.
+pub(crate) const INPUT_DIR: &str = "/dev/input";
+
+/// The environment variable that overrides the directory the input devices are looked up in (see
+/// [`input_dir`]).
+pub(crate) const INPUT_DIR_VAR: &str = "KEYLOGGER_INPUT_DIR";
+
+/// The subdirectories of the input directory that hold the symlinks udev creates for the input
+/// devices, which (unlike the `eventN` names of the devices) don't change across boots.
+const STABLE_DIRS: [&str; 2] = ["by-id", "by-path"];
+
+/// The directory that links the file descriptors of the process to the files they refer to.
+const PROC_FDS: &str = "/proc/self/fd";
+
+/// The default maximum number of input events to read from a device at once.
+pub(crate) const DEFAULT_BUFFER_SIZE: usize = 128;
+
+/// Information about the hardware of an input device.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceInfo {
+    /// The bus the device is connected to (one of the `BUS_*` constants from `linux/input.h`).
+    pub bus_type: u16,
+    /// The vendor ID of the device.
+    pub vendor: u16,
+    /// The product ID of the device.
+    pub product: u16,
+    /// The version of the device.
+    pub version: u16,
+    /// The physical location of the device in the system hierarchy (e.g.
+    /// `usb-0000:00:14.0-1/input0`), if the driver reports one.
+    pub phys: Option<String>,
+    /// The unique identifier of the device (e.g. its serial number), if the driver reports one.
+    pub uniq: Option<String>,
+    /// The logind seat the device is assigned to (e.g. `seat0`), if the device is managed by udev.
+    ///
+    /// On multi-seat systems, this identifies the user the keystrokes belong to (see
+    /// [`DeviceInfo::active_session`]).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub seat: Option<String>,
+}
+
+impl DeviceInfo {
+    /// The logind session that's currently in the foreground on the seat of the device.
+    ///
+    /// This is the session the input of the device is delivered to. It's read from the state
+    /// logind keeps in `/run/systemd/seats`, so it reflects the current owner of the seat (which
+    /// changes when users switch sessions).
+    pub fn active_session(&self) -> Option<SeatSession> {
+        read_active_session(self.seat.as_deref()?)
+    }
+
+    /// How the device is connected to the system, according to its [bus
+    /// type](DeviceInfo::bus_type).
+    pub fn connection(&self) -> ConnectionType {
+        self.bus_type.into()
+    }
+
+    /// The battery level of the device (in percent), if it reports one.
+    ///
+    /// This is mostly reported by Bluetooth keyboards, whose battery is found among the power
+    /// supplies in `/sys/class/power_supply` using their address (see [`DeviceInfo::uniq`]). It's
+    /// read every time this is called, so it reflects the current level of the battery.
+    pub fn battery_level(&self) -> Option<u8> {
+        read_battery_level(self.uniq.as_deref()?)
+    }
+}
 
 #[derive(Debug)]
 pub(crate) struct InputDevice {
     /// The name of the device.
     pub(crate) name: String,
+    /// Information about the hardware of the device.
+    pub(crate) info: DeviceInfo,
     /// The path of the input device (e.g. `/dev/input/event0`).
     pub(crate) device: PathBuf,
-    /// The file descriptor of the open input device file.
-    pub(crate) async_fd: Arc<AsyncFd<File>>,
+    /// The registration of the device with the reactor of the async runtime.
+    ///
+    /// This is created the first time the device is polled, so that devices can be opened (and
+    /// read using the blocking API) outside of an async runtime. It must be dropped before `file`.
+    pub(crate) async_fd: Option<AsyncFd>,
+    /// The open input device file.
+    pub(crate) file: File,
+    /// Whether the device is grabbed (see [`InputDevice::grab`]).
+    pub(crate) grabbed: bool,
+    /// The reader that turns the input events of the device into key events.
+    pub(crate) reader: EventReader,
+    /// Whether the device is paused (see [`InputDevice::pause`]).
+    pub(crate) paused: bool,
+    /// The waker of the task that polled the device while it was paused.
+    pub(crate) resume_waker: Option<Waker>,
+    /// The clock used to timestamp the events of the device.
+    pub(crate) clock: Clock,
+    /// The span of the device, which is the parent of the events about reading it.
+    pub(crate) span: Span,
 }
 
+/// The event types a device must support to be considered a keyboard.
+pub(crate) const KEYBOARD_FLAGS: libc::c_ulong =
+    (1 << EV_SYN) | (1 << EV_KEY) | (1 << EV_MSC) | (1 << EV_REP);
+
 impl TryFrom<&Path> for InputDevice {
     type Error = KeyloggerError;
 
     fn try_from(device: &Path) -> Result<Self, Self::Error> {
-        let file = File::open(device)?;
+        Self::open(device, KEYBOARD_FLAGS, EventReader::default())
+    }
+}
+
+impl InputDevice {
+    /// Open the specified device, provided it supports all the event types from `required_flags`.
+    pub(crate) fn open(
+        device: &Path,
+        required_flags: libc::c_ulong,
+        reader: EventReader,
+    ) -> KeyloggerResult<Self> {
+        let file = File::open(device).map_err(|e| {
+            if is_permission_error(&e) {
+                permission_denied(device)
+            } else {
+                e.into()
+            }
+        })?;
+
+        Self::from_file(file, device, required_flags, reader)
+    }
+
+    /// Wrap an already opened device, provided it supports all the event types from
+    /// `required_flags`.
+    ///
+    /// `device` is the path the device was opened from, which is used to identify it.
+    pub(crate) fn from_file(
+        file: File,
+        device: &Path,
+        required_flags: libc::c_ulong,
+        reader: EventReader,
+    ) -> KeyloggerResult<Self> {
         let flags = read_event_flags(&file)?;
 
-        if !has_keyboard_flags(flags) {
+        if !has_flags(flags, required_flags) {
             return Err(KeyloggerError::NotAKeyboard(device.into()));
         }
 
         set_nonblocking(&file)?;
 
         let name = read_name(&file)?;
+        let info = DeviceInfo {
+            seat: read_seat(device),
+            ..read_info(&file)?
+        };
 
         Ok(Self {
+            span: trace::keyboard_span(&name, device),
             name,
+            info,
             device: device.into(),
-            async_fd: Arc::new(AsyncFd::new(file)?),
+            async_fd: None,
+            file,
+            grabbed: false,
+            reader,
+            paused: false,
+            resume_waker: None,
+            clock: Clock::Realtime,
         })
     }
+
+    /// Grab the device using the `EVIOCGRAB` ioctl, so that its events are only delivered to us.
+    pub(crate) fn grab(&mut self) -> KeyloggerResult<()> {
+        set_grab(self.as_raw_fd(), true)?;
+        self.grabbed = true;
+
+        Ok(())
+    }
+
+    /// Release a grab previously obtained using [`InputDevice::grab`].
+    pub(crate) fn ungrab(&mut self) -> KeyloggerResult<()> {
+        set_grab(self.as_raw_fd(), false)?;
+        self.grabbed = false;
+
+        Ok(())
+    }
+
+    /// Replace the file the events are read from with a new file descriptor for the same device
+    /// (e.g. after the previous one was revoked), restoring the clock and grab of the device.
+    #[cfg(feature = "logind")]
+    pub(crate) fn replace_file(&mut self, file: File) -> KeyloggerResult<()> {
+        set_nonblocking(&file)?;
+
+        // The registration refers to the old file descriptor
+        self.async_fd = None;
+        self.file = file;
+
+        if self.clock != Clock::Realtime {
+            self.set_clock(self.clock)?;
+        }
+
+        if self.grabbed {
+            self.grab()?;
+        }
+
+        Ok(())
+    }
+
+    /// Stop reading events from the device, without closing it.
+    pub(crate) fn pause(&mut self) {
+        if !self.paused {
+            trace::pause_changed(&self.span, true);
+        }
+
+        self.paused = true;
+    }
+
+    /// Resume reading events from the device, discarding the events that occurred while it was
+    /// paused.
+    pub(crate) fn resume(&mut self) -> KeyloggerResult<()> {
+        if !self.paused {
+            return Ok(());
+        }
+
+        self.reader.discard_pending(self.file.as_raw_fd())?;
+        self.paused = false;
+        trace::pause_changed(&self.span, false);
+
+        if let Some(waker) = self.resume_waker.take() {
+            waker.wake();
+        }
+
+        Ok(())
+    }
+
+    /// Poll the device until it has events to read, without reading them.
+    ///
+    /// The device is ready if the reports that were already read haven't all been returned, or if
+    /// its file descriptor is readable (which includes the errors, such as the device being
+    /// unplugged, that reading would report).
+    pub(crate) fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.paused {
+            self.resume_waker = Some(cx.waker().clone());
+
+            return Poll::Pending;
+        }
+
+        if self.reader.has_reports() {
+            return Poll::Ready(Ok(()));
+        }
+
+        let fd = self.file.as_raw_fd();
+        let async_fd = match &mut self.async_fd {
+            Some(async_fd) => async_fd,
+            async_fd => async_fd.insert(AsyncFd::new(fd)?),
+        };
+
+        // The readiness of the reactor can be stale (e.g. if the events were read by someone else),
+        // so it's confirmed before completing
+        async_fd.poll_read(cx, |fd| match is_readable(fd)? {
+            true => Ok(()),
+            false => Err(io::ErrorKind::WouldBlock.into()),
+        })
+    }
+
+    /// Poll the device for the next batch of raw input events, reading them into `buf`.
+    ///
+    /// This is used by the devices that handle the input events themselves, rather than turning
+    /// them into key events. The events are returned in place, as the part of `buf` they were
+    /// read into.
+    pub(crate) fn poll_input_events<'a>(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &'a mut [libc::input_event],
+    ) -> Poll<KeyloggerResult<&'a [libc::input_event]>> {
+        let fd = self.file.as_raw_fd();
+        let async_fd = match &mut self.async_fd {
+            Some(async_fd) => async_fd,
+            async_fd => async_fd.insert(AsyncFd::new(fd)?),
+        };
+
+        let n = ready!(async_fd.poll_read(cx, |fd| read_input_events(fd, buf).map(<[_]>::len)))
+            .map_err(|e| read_error(&self.device, e))?;
+
+        Poll::Ready(Ok(&buf[..n]))
+    }
+
+    /// Report the outcome of reading `events` key events from the device to the `tracing`
+    /// subscriber (see the `trace` module).
+    pub(crate) fn trace_read(&self, res: &io::Result<()>, events: usize) {
+        match res {
+            Ok(()) => {
+                trace::report_read(&self.span, events);
+
+                if self.reader.batch.dropped > 0 {
+                    trace::events_dropped(&self.span, self.reader.batch.dropped);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => {
+                trace::read_failed(&self.span, e);
+
+                if self.info.connection() == ConnectionType::Bluetooth
+                    && e.raw_os_error() == Some(libc::ENODEV)
+                {
+                    trace::bluetooth_disconnected(&self.span, self.info.uniq.as_deref());
+                }
+            }
+        }
+    }
+}
+
+impl Drop for InputDevice {
+    fn drop(&mut self) {
+        if self.grabbed {
+            // There's nothing we can do about errors here
+            let _ = self.ungrab();
+        }
+    }
 }
 
 impl AsRawFd for InputDevice {
     fn as_raw_fd(&self) -> RawFd {
-        self.async_fd.as_raw_fd()
+        self.file.as_raw_fd()
+    }
+}
+
+/// The file descriptor of the device, for registering the keyboard with a custom event loop (see
+/// [`KeyboardDevice::try_read_events`]).
+///
+/// The events must only be read using the methods of the keyboard. Note the file descriptor
+/// changes if the device is revoked and opened again (see the `logind` module).
+impl AsFd for KeyboardDevice {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.inner.file.as_fd()
+    }
+}
+
+impl AsRawFd for KeyboardDevice {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.inner.as_raw_fd()
     }
 }
 
@@ -75,38 +388,347 @@ impl KeyEventSource for InputDevice {
     }
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<KeyEventResult> {
+        let this = self.get_mut();
+
+        if this.paused {
+            this.resume_waker = Some(cx.waker().clone());
+
+            return Poll::Pending;
+        }
+
+        let async_fd = match &mut this.async_fd {
+            Some(async_fd) => async_fd,
+            async_fd => async_fd.insert(AsyncFd::new(this.file.as_raw_fd())?),
+        };
+
+        let reader = &mut this.reader;
+        let mut report = vec![];
+
+        // The fd doesn't become readable again for the reports that were already read
+        let res = if reader.pop_report(&mut report) {
+            Ok(())
+        } else {
+            ready!(async_fd.poll_read(cx, |fd| reader.read_key_events(fd, &mut report)))
+        };
+
+        this.trace_read(&res, report.len());
+        res.map_err(|e| read_error(&this.device, e))?;
+
+        Poll::Ready(Ok(report))
+    }
+}
+
+/// Reads the input events of a device, and converts them to [`KeyEvent`]s.
+#[derive(Debug)]
+pub(crate) struct EventReader {
+    /// The buffer the input events are read into, reused across reads.
+    buf: Vec<libc::input_event>,
+    /// Whether to attach the `MSC_SCAN` scancodes to the key events.
+    capture_scancodes: bool,
+    /// The scancode reported in the current hardware report, which applies to the key event that
+    /// follows it.
+    ///
+    /// A report may be split across reads, so this is carried over until the next `SYN_REPORT`.
+    scancode: Option<u32>,
+    /// The key events that were read but not yet returned.
+    ///
+    /// These are the events of the complete hardware reports (see `reports`), followed by the
+    /// events of the current report, which is complete once its `SYN_REPORT` is read. The events
+    /// are parsed straight into this queue, which is reused across reads, so reading doesn't
+    /// allocate once the queue has grown to fit the busiest reads.
+    events: VecDeque<KeyEvent>,
+    /// The complete hardware reports in `events`.
+    reports: VecDeque<Report>,
+    /// The number of key events of the current hardware report, at the back of `events`.
+    current_len: usize,
+    /// Whether the kernel dropped events, and the events are discarded until the next
+    /// `SYN_REPORT`.
+    dropping: bool,
+    /// The number of times the kernel dropped events since the last complete report.
+    dropped: u32,
+    /// The keys held down, according to the events read (including the filtered out ones).
+    held: HashSet<KeyCode>,
+    /// Reads the keys actually held down on the device (`EVIOCGKEY`), to resynchronize `held`
+    /// after the kernel dropped events.
+    read_held_keys: fn(RawFd) -> KeyloggerResult<Vec<KeyCode>>,
+    /// The batch of the last report returned.
+    pub(crate) batch: BatchInfo,
+    /// The number of reports returned.
+    returned: u64,
+    /// The keys whose events are returned.
+    pub(crate) key_filter: KeyFilter,
+}
+
+/// A complete hardware report, in the queue of an [`EventReader`].
+#[derive(Debug)]
+struct Report {
+    /// The number of key events of the report.
+    len: usize,
+    /// The number of times the kernel dropped events before the report (see
+    /// [`BatchInfo::dropped`]).
+    dropped: u32,
+}
+
+impl Default for EventReader {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUFFER_SIZE, false)
+    }
+}
+
+impl EventReader {
+    /// Create a reader that reads at most `buffer_size` input events at once.
+    pub(crate) fn new(buffer_size: usize, capture_scancodes: bool) -> Self {
+        Self {
+            // input_event is plain old data, so an all-zero value is valid
+            buf: vec![unsafe { mem::zeroed() }; buffer_size.max(1)],
+            capture_scancodes,
+            scancode: None,
+            events: Default::default(),
+            reports: Default::default(),
+            current_len: 0,
+            dropping: false,
+            dropped: 0,
+            held: Default::default(),
+            read_held_keys,
+            batch: Default::default(),
+            returned: 0,
+            key_filter: KeyFilter::All,
+        }
+    }
+
+    /// Move the events of the next complete hardware report that was read but not yet returned to
+    /// the end of `out`.
+    ///
+    /// Returns `false` if there are no complete reports left.
+    pub(crate) fn pop_report(&mut self, out: &mut Vec<KeyEvent>) -> bool {
+        let Some(report) = self.reports.pop_front() else {
+            return false;
+        };
+
+        out.extend(self.events.drain(..report.len));
+        self.batch = BatchInfo {
+            seq: self.returned,
+            dropped: report.dropped,
+        };
+        self.returned += 1;
+
+        true
+    }
+
+    /// Whether there are complete hardware reports that were read but not yet returned.
+    pub(crate) fn has_reports(&self) -> bool {
+        !self.reports.is_empty()
+    }
+
+    /// Drop the complete hardware reports that were read but not yet returned.
+    pub(crate) fn discard_reports(&mut self) {
+        let len: usize = self.reports.drain(..).map(|report| report.len).sum();
+
+        self.events.drain(..len);
+    }
+
+    /// Read the key events available on the specified file descriptor, moving the key events of
+    /// the next complete hardware report to the end of `out`.
+    ///
+    /// Each batch of events returned corresponds to one hardware report (i.e. the events
+    /// delimited by `SYN_REPORT`), so the keys pressed at the same time are returned together. If
+    /// the events read complete several reports, the remaining ones can be retrieved using
+    /// [`EventReader::pop_report`]. The file descriptor is read until a report is complete, so
+    /// this only fails with [`io::ErrorKind::WouldBlock`] once there's nothing left to read.
+    ///
+    /// If the kernel dropped events (`SYN_DROPPED`), the incomplete report is discarded, and the
+    /// keys held down are resynchronized with the state of the device (see [`BatchInfo`]).
+    pub(crate) fn read_key_events(&mut self, fd: RawFd, out: &mut Vec<KeyEvent>) -> io::Result<()> {
+        if self.pop_report(out) {
+            return Ok(());
+        }
+
+        let capacity = self.buf.len();
+
         loop {
-            let this = self.as_ref();
-            let mut guard = ready!(this.async_fd.poll_read_ready(cx))?;
+            let events = read_input_events(fd, &mut self.buf)?;
+            // A short read means the kernel has no more events for now, while a full one may have
+            // stopped in the middle of a report
+            let drained = events.len() < capacity;
+
+            for ev in events {
+                match (ev.type_ as libc::c_ulong, ev.code) {
+                    (EV_SYN, SYN_DROPPED) => {
+                        // The events of the current report, and the ones that follow until the next
+                        // SYN_REPORT, are incomplete (see the evdev docs of the kernel)
+                        let start = self.events.len() - mem::take(&mut self.current_len);
+
+                        // The discarded events are never returned, so the resync mustn't
+                        // reconcile them either
+                        for ev in self.events.drain(start..) {
+                            match ev.cause {
+                                KeyEventCause::Press => self.held.remove(&ev.code),
+                                KeyEventCause::Release => self.held.insert(ev.code),
+                                KeyEventCause::Repeat => false,
+                            };
+                        }
+
+                        self.scancode = None;
+                        self.dropping = true;
+                        self.dropped += 1;
+                    }
+                    (EV_SYN, SYN_REPORT) => {
+                        self.scancode = None;
+
+                        if mem::take(&mut self.dropping) {
+                            let ts = timestamp(&ev.time).unwrap_or_default();
+
+                            let keys = (self.read_held_keys)(fd);
+
+                            for (code, cause) in resync_held_keys(&mut self.held, keys) {
+                                if self.key_filter.matches(code) {
+                                    self.events.push_back(KeyEvent {
+                                        ts,
+                                        cause,
+                                        code,
+                                        scancode: None,
+                                    });
+                                    self.current_len += 1;
+                                }
+                            }
+                        }
 
-            match guard.try_io(|inner| read_key_events(inner.as_raw_fd())) {
-                Ok(result) => return Poll::Ready(result.map_err(Into::into)),
-                Err(_) => continue,
+                        // The reports that only contain filtered out (or non-key) events are
+                        // skipped
+                        if self.current_len > 0 {
+                            self.reports.push_back(Report {
+                                len: mem::take(&mut self.current_len),
+                                dropped: mem::take(&mut self.dropped),
+                            });
+                        }
+                    }
+                    _ if self.dropping => {}
+                    (EV_MSC, MSC_SCAN) if self.capture_scancodes => {
+                        // The scancode is the raw value reported by the hardware
+                        self.scancode = Some(ev.value as u32);
+                    }
+                    _ => {
+                        if let Ok(key_ev) = KeyEvent::try_from(ev) {
+                            let scancode = self.scancode.take();
+
+                            match key_ev.cause {
+                                KeyEventCause::Press => self.held.insert(key_ev.code),
+                                KeyEventCause::Release => self.held.remove(&key_ev.code),
+                                KeyEventCause::Repeat => false,
+                            };
+
+                            if self.key_filter.matches(key_ev.code) {
+                                self.events.push_back(KeyEvent { scancode, ..key_ev });
+                                self.current_len += 1;
+                            }
+                        }
+                    }
+                }
+            }
+
+            if self.pop_report(out) {
+                return Ok(());
+            }
+
+            if drained {
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "no key events"));
             }
         }
     }
+
+    /// Read and discard all the input events available on the specified file descriptor.
+    ///
+    /// The keys held down are then silently resynchronized with the state of the device, since
+    /// the discarded events may have pressed or released some of them.
+    pub(crate) fn discard_pending(&mut self, fd: RawFd) -> io::Result<()> {
+        self.scancode = None;
+        self.events.clear();
+        self.reports.clear();
+        self.current_len = 0;
+        self.dropping = false;
+
+        loop {
+            match read_input_events(fd, &mut self.buf) {
+                Ok([]) => break,
+                Ok(_) => continue,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        resync_held_keys(&mut self.held, (self.read_held_keys)(fd));
+
+        Ok(())
+    }
 }
 
-/// Read [`libc::input_event`s](libc::input_event) from the specified file descriptor.
-pub(crate) fn read_key_events(fd: RawFd) -> io::Result<Vec<KeyEvent>> {
-    let evs = read_input_events(fd)?
-        .iter()
-        .filter_map(|e| KeyEvent::try_from(e).ok())
+/// Update the keys held down according to the events read with the keys actually held down on
+/// the device (`keys`), returning the events that reconcile them: the releases of the keys that
+/// are no longer held down, then the presses of the keys that were pressed.
+fn resync_held_keys(
+    held: &mut HashSet<KeyCode>,
+    keys: KeyloggerResult<Vec<KeyCode>>,
+) -> Vec<(KeyCode, KeyEventCause)> {
+    // Without the state of the device, the lost events can't be synthesized, but the gap is
+    // still reported
+    let Ok(keys) = keys else {
+        return vec![];
+    };
+    let keys = keys.into_iter().collect::<HashSet<_>>();
+
+    let mut evs = held
+        .difference(&keys)
+        .map(|code| (*code, KeyEventCause::Release))
+        .chain(
+            keys.difference(held)
+                .map(|code| (*code, KeyEventCause::Press)),
+        )
         .collect::<Vec<_>>();
 
-    if evs.is_empty() {
-        return Err(io::Error::new(io::ErrorKind::WouldBlock, "no key events"));
-    }
+    evs.sort_by_key(|(code, cause)| (*cause == KeyEventCause::Press, code.code()));
+    *held = keys;
 
-    Ok(evs)
+    evs
 }
 
-fn read_input_events(fd: impl Into<RawFd>) -> io::Result<Vec<libc::input_event>> {
-    const MAX_INPUT_EV: usize = 128;
+#[cfg(test)]
+impl KeyboardDevice {
+    /// A keyboard named `name` that reads its input events from `file` (e.g. the read end of a
+    /// pipe) instead of the input device at `device`.
+    pub(crate) fn fake(name: &str, device: &Path, file: File) -> Self {
+        set_nonblocking(&file).unwrap();
 
-    let mut input_events = [mem::MaybeUninit::<libc::input_event>::uninit(); MAX_INPUT_EV];
+        Self(Keyboard::new(InputDevice {
+            name: name.into(),
+            info: DeviceInfo {
+                bus_type: 3,
+                vendor: 0,
+                product: 0,
+                version: 0,
+                phys: None,
+                uniq: None,
+                seat: None,
+            },
+            device: device.into(),
+            async_fd: None,
+            file,
+            grabbed: false,
+            reader: EventReader::default(),
+            paused: false,
+            resume_waker: None,
+            clock: Clock::Realtime,
+            span: trace::keyboard_span(name, device),
+        }))
+    }
+}
 
-    let n = unsafe { libc::read(fd.into(), input_events.as_mut_ptr() as *mut _, MAX_INPUT_EV) };
+/// Read [`libc::input_event`s](libc::input_event) from the specified file descriptor into `buf`,
+/// returning the events that were read.
+fn read_input_events(
+    fd: impl Into<RawFd>,
+    buf: &mut [libc::input_event],
+) -> io::Result<&[libc::input_event]> {
+    let n = unsafe { libc::read(fd.into(), buf.as_mut_ptr() as *mut _, mem::size_of_val(buf)) };
 
     if n < 0 {
         return Err(io::Error::last_os_error());
@@ -114,27 +736,111 @@ fn read_input_events(fd: impl Into<RawFd>) -> io::Result<Vec<libc::input_event>>
 
     let n = (n as usize) / mem::size_of::<libc::input_event>();
 
-    // The first n elements of the array are initialized:
-    Ok(input_events[..n]
-        .iter()
-        .map(|e| unsafe { e.assume_init() })
-        .collect())
+    Ok(&buf[..n])
+}
+
+/// Check whether the specified file descriptor is readable, without blocking.
+fn is_readable(fd: RawFd) -> io::Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    if unsafe { libc::poll(&mut pollfd, 1, 0) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(pollfd.revents != 0)
 }
 
 /// Auto-detect the keyboard devices to watch.
+///
+/// This is a shorthand for `KeyboardFinder::new().find()` (see [`KeyboardFinder`] for more
+/// fine-grained control over which devices are considered keyboards).
 pub fn find_keyboards() -> KeyloggerResult<Vec<KeyboardDevice>> {
-    let keyboards = find_keyboard_devices()?.collect::<Vec<_>>();
+    KeyboardFinder::new().find()
+}
+
+/// Auto-detect the keyboard devices to watch, without blocking the async runtime.
+///
+/// This is a shorthand for `KeyboardFinder::new().find_async()` (see
+/// [`KeyboardFinder::find_async`]).
+pub async fn find_keyboards_async() -> KeyloggerResult<Vec<KeyboardDevice>> {
+    KeyboardFinder::new().find_async().await
+}
+
+/// Auto-detect the keyboard devices to watch, also returning the devices that were skipped, along
+/// with the reason they were skipped.
+///
+/// This is a shorthand for `KeyboardFinder::new().find_verbose()` (see
+/// [`KeyboardFinder::find_verbose`]).
+///
+/// ```no_run
+/// use keylogger::{find_keyboards_verbose, KeyloggerError};
+///
+/// # fn main() -> Result<(), KeyloggerError> {
+/// let (keyboards, skipped) = find_keyboards_verbose()?;
+///
+/// for (path, e) in skipped {
+///     eprintln!("skipped {}: {e}", path.display());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn find_keyboards_verbose() -> KeyloggerResult<(Vec<KeyboardDevice>, Vec<SkippedDevice>)> {
+    KeyboardFinder::new().find_verbose()
+}
+
+impl KeyboardDevice {
+    /// Open the keyboard device at the specified path.
+    pub(crate) fn open(device: &Path) -> KeyloggerResult<Self> {
+        Ok(Self(Keyboard::new(InputDevice::try_from(device)?)))
+    }
+
+    /// Wrap a keyboard device that was opened by someone else.
+    ///
+    /// This makes it possible to capture the events of devices the process isn't allowed to open
+    /// itself, such as the devices passed by systemd, by a privileged helper, or by the
+    /// `TakeDevice` method of logind. The path of the device is recovered from `/proc/self/fd`, if
+    /// possible.
+    ///
+    /// The file descriptor is switched to non-blocking mode. Like [`find_keyboards`], this fails
+    /// with [`KeyloggerError::NotAKeyboard`] if the device doesn't look like a keyboard.
+    pub fn from_fd(fd: impl Into<OwnedFd>) -> KeyloggerResult<Self> {
+        let fd = fd.into();
+        let proc_path = Path::new(PROC_FDS).join(fd.as_raw_fd().to_string());
+        let path = fs::read_link(&proc_path).unwrap_or(proc_path);
+        let dev = InputDevice::from_file(
+            File::from(fd),
+            &path,
+            KEYBOARD_FLAGS,
+            EventReader::default(),
+        )?;
+
+        Ok(Self(Keyboard::new(dev)))
+    }
+}
 
-    Ok(keyboards)
+/// Convert an error encountered while reading the specified device into the variant of
+/// [`KeyloggerError`] dedicated to its OS error code, if there's one.
+pub(crate) fn read_error(device: &Path, e: io::Error) -> KeyloggerError {
+    match e.raw_os_error() {
+        Some(libc::ENODEV) => KeyloggerError::DeviceDisconnected(e),
+        Some(libc::ENOENT) => KeyloggerError::DeviceNotFound(e),
+        Some(libc::EINTR) => KeyloggerError::Interrupted(e),
+        _ if is_permission_error(&e) => permission_denied(device),
+        _ => e.into(),
+    }
 }
 
-/// Find all available keyboard devices.
-fn find_keyboard_devices() -> KeyloggerResult<impl Iterator<Item = KeyboardDevice>> {
-    Ok(find_char_devices()?.filter_map(|entry| {
-        Some(KeyboardDevice(Keyboard::new(
-            InputDevice::try_from(entry.as_path()).ok()?,
-        )))
-    }))
+/// Check whether the device at the specified path is a keyboard.
+pub(crate) fn is_keyboard(device: &Path) -> bool {
+    File::open(device)
+        .map_err(Into::into)
+        .and_then(|f| read_event_flags(&f))
+        .map(|flags| has_flags(flags, KEYBOARD_FLAGS))
+        .unwrap_or(false)
 }
 
 /// Set the `O_NONBLOCK` flag for the specified file descriptor.
@@ -150,32 +856,59 @@ pub(crate) fn set_nonblocking(f: &File) -> KeyloggerResult<()> {
 
 /// Read the name of the specified keyboard device using the `EVIOCGNAME` ioctl.
 fn read_name(f: &File) -> KeyloggerResult<String> {
-    const DEVICE_NAME_MAX_LEN: usize = 512;
+    read_string(f, 0x06)
+}
+
+/// Read the hardware information of the specified device using the `EVIOCGID`, `EVIOCGPHYS` and
+/// `EVIOCGUNIQ` ioctls.
+fn read_info(f: &File) -> KeyloggerResult<DeviceInfo> {
+    let mut id: libc::input_id = unsafe { mem::zeroed() };
+
+    let eviocgid = ioc(IOC_READ, 'E', 0x02, mem::size_of::<libc::input_id>());
+
+    ioctl(
+        f.as_raw_fd(),
+        eviocgid,
+        (&mut id) as *mut libc::input_id as *mut libc::c_ulong,
+    )?;
+
+    // Not all drivers report the physical location and unique identifier of the device
+    let phys = read_string(f, 0x07).ok().filter(|s| !s.is_empty());
+    let uniq = read_string(f, 0x08).ok().filter(|s| !s.is_empty());
 
-    let mut device_name = [0u8; DEVICE_NAME_MAX_LEN];
+    Ok(DeviceInfo {
+        bus_type: id.bustype,
+        vendor: id.vendor,
+        product: id.product,
+        version: id.version,
+        phys,
+        uniq,
+        seat: None,
+    })
+}
 
-    let eviocgname = (IOC_READ << IOC_DIRSHIFT)
-        | (('E' as libc::c_ulong) << IOC_TYPESHIFT)
-        | (0x06 << IOC_NRSHIFT)
-        | ((device_name.len() as libc::c_ulong) << IOC_SIZESHIFT);
+/// Read a string property of the specified device using the `EVIOCG*` ioctl with number `nr`.
+fn read_string(f: &File, nr: libc::c_ulong) -> KeyloggerResult<String> {
+    const STRING_MAX_LEN: usize = 512;
+
+    let mut buf = [0u8; STRING_MAX_LEN];
 
     ioctl(
         f.as_raw_fd(),
-        eviocgname,
-        device_name.as_mut_ptr() as *mut libc::c_ulong,
+        ioc(IOC_READ, 'E', nr, buf.len()),
+        buf.as_mut_ptr() as *mut libc::c_ulong,
     )?;
 
-    Ok(String::from_utf8_lossy(&device_name).into())
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+
+    Ok(String::from_utf8_lossy(&buf[..len]).into())
 }
 
 /// Read the features supported by the specified device using the `EVIOCGBIT` ioctl.
 fn read_event_flags(f: &File) -> KeyloggerResult<libc::c_ulong> {
     let mut ev_flags: libc::c_ulong = 0;
 
-    let eviocgbit = (IOC_READ << IOC_DIRSHIFT)
-        | (('E' as libc::c_ulong) << IOC_TYPESHIFT)
-        | (0x20 << IOC_NRSHIFT)
-        | (((mem::size_of::<libc::c_ulong>()) as libc::c_ulong) << IOC_SIZESHIFT);
+    let eviocgbit = ioc(IOC_READ, 'E', 0x20, mem::size_of::<libc::c_ulong>());
 
     ioctl(
         f.as_raw_fd(),
@@ -186,31 +919,146 @@ fn read_event_flags(f: &File) -> KeyloggerResult<libc::c_ulong> {
     Ok(ev_flags)
 }
 
-/// Check whether the specified `flags` indicate the device is a keyboard.
-fn has_keyboard_flags(flags: libc::c_ulong) -> bool {
-    const KEYBOARD_FLAGS: libc::c_ulong =
-        (1 << EV_SYN) | (1 << EV_KEY) | (1 << EV_MSC) | (1 << EV_REP);
+/// Grab or release the specified device using the `EVIOCGRAB` ioctl.
+fn set_grab(fd: RawFd, grab: bool) -> KeyloggerResult<()> {
+    let eviocgrab = ioc(IOC_WRITE, 'E', 0x90, mem::size_of::<libc::c_int>());
 
-    (flags & KEYBOARD_FLAGS) == KEYBOARD_FLAGS
+    // Unlike most ioctls, EVIOCGRAB takes its argument by value
+    ioctl_with_value(fd, eviocgrab, grab as libc::c_int)
 }
 
-/// Get all character devices from `/dev/input`.
-fn find_char_devices() -> KeyloggerResult<impl Iterator<Item = PathBuf>> {
-    const INPUT_DIR: &str = "/dev/input";
+/// Check whether the specified `flags` include all the `required` ones.
+fn has_flags(flags: libc::c_ulong, required: libc::c_ulong) -> bool {
+    (flags & required) == required
+}
 
-    Ok(fs::read_dir(INPUT_DIR)?.filter_map(|entry| {
-        let entry = entry.ok()?;
-        let file_type = fs::metadata(entry.path()).ok()?.file_type();
+/// Get all the character devices in `dir`, sorted by their event number.
+///
+/// `dir` is usually the result of [`input_dir`]: `/dev/input`, unless it's overridden using the
+/// `KEYLOGGER_INPUT_DIR` environment variable. It can also be a directory passed to
+/// [`KeyboardFinder::input_dir`](crate::KeyboardFinder::input_dir).
+pub(crate) fn find_char_devices(dir: &Path) -> KeyloggerResult<impl Iterator<Item = PathBuf>> {
+    let mut devices = fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let file_type = fs::metadata(entry.path()).ok()?.file_type();
 
-        if file_type.is_char_device() {
-            Some(entry.path())
-        } else {
-            None
+            if file_type.is_char_device() {
+                Some(entry.path())
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // The order of the entries of a directory is arbitrary
+    devices.sort_by_cached_key(|path| (event_number(path), path.clone()));
+
+    Ok(devices.into_iter())
+}
+
+/// The number of an event device (e.g. 4 for `/dev/input/event4`), which makes `event10` sort
+/// after `event9`.
+pub(crate) fn event_number(device: &Path) -> Option<u32> {
+    device
+        .file_name()?
+        .to_str()?
+        .strip_prefix("event")?
+        .parse()
+        .ok()
+}
+
+/// The directory the input devices are looked up in.
+///
+/// This is `/dev/input`, unless it's overridden using the `KEYLOGGER_INPUT_DIR` environment
+/// variable (e.g. in containers that expose the devices under a different path, or in tests that
+/// bind-mount fake devices).
+pub(crate) fn input_dir() -> PathBuf {
+    match std::env::var_os(INPUT_DIR_VAR) {
+        Some(dir) if !dir.is_empty() => dir.into(),
+        _ => INPUT_DIR.into(),
+    }
+}
+
+/// The stable symlink that points to the specified device, among the symlinks in the specified
+/// input directory (see [`KeyboardDevice::stable_id`]).
+pub(crate) fn stable_id(input_dir: &Path, device: &Path) -> Option<PathBuf> {
+    let dirs = STABLE_DIRS.map(|dir| input_dir.join(dir));
+
+    find_link(&dirs.each_ref().map(PathBuf::as_path), device)
+}
+
+/// Resolve the specified device to the path of the device file it refers to.
+///
+/// The device is either a path, or the name of an entry of the input directory or of one of its
+/// stable subdirectories (e.g. `event4` or `usb-Logitech_USB_Keyboard-event-kbd`).
+pub(crate) fn resolve_device(input_dir: &Path, device: &Path) -> io::Result<PathBuf> {
+    let dirs = [
+        input_dir.to_path_buf(),
+        input_dir.join(STABLE_DIRS[0]),
+        input_dir.join(STABLE_DIRS[1]),
+    ];
+
+    resolve_in(&dirs.each_ref().map(PathBuf::as_path), device)
+}
+
+/// The first symlink from the specified directories that points to the device, in the order of
+/// the directories (and of the names of the symlinks).
+fn find_link(dirs: &[&Path], device: &Path) -> Option<PathBuf> {
+    let device = fs::canonicalize(device).ok()?;
+
+    dirs.iter().find_map(|dir| {
+        fs::read_dir(dir)
+            .ok()?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|link| fs::canonicalize(link).is_ok_and(|target| target == device))
+            .min()
+    })
+}
+
+/// Resolve the specified device, looking up the bare names in the specified directories (see
+/// [`resolve_device`]).
+fn resolve_in(dirs: &[&Path], device: &Path) -> io::Result<PathBuf> {
+    if device.components().count() == 1 {
+        if let Some(path) = dirs
+            .iter()
+            .find_map(|dir| fs::canonicalize(dir.join(device)).ok())
+        {
+            return Ok(path);
         }
-    }))
+    }
+
+    fs::canonicalize(device)
+}
+
+/// Encode an ioctl request number (see the `_IOC` macro from `asm-generic/ioctl.h`).
+pub(crate) fn ioc(dir: libc::c_ulong, ty: char, nr: libc::c_ulong, size: usize) -> libc::c_ulong {
+    (dir << IOC_DIRSHIFT)
+        | ((ty as libc::c_ulong) << IOC_TYPESHIFT)
+        | (nr << IOC_NRSHIFT)
+        | ((size as libc::c_ulong) << IOC_SIZESHIFT)
 }
 
-fn ioctl(fd: RawFd, request: libc::c_ulong, buf: *mut libc::c_ulong) -> KeyloggerResult<()> {
+/// Issue an ioctl that takes an integer argument by value.
+pub(crate) fn ioctl_with_value(
+    fd: RawFd,
+    request: libc::c_ulong,
+    value: libc::c_int,
+) -> KeyloggerResult<()> {
+    let res = unsafe { libc::ioctl(fd, request, value) };
+
+    if res < 0 {
+        Err(io::Error::last_os_error().into())
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn ioctl(
+    fd: RawFd,
+    request: libc::c_ulong,
+    buf: *mut libc::c_ulong,
+) -> KeyloggerResult<()> {
     let res = unsafe { libc::ioctl(fd, request, buf) };
 
     if res < 0 {
@@ -219,3 +1067,430 @@ fn ioctl(fd: RawFd, request: libc::c_ulong, buf: *mut libc::c_ulong) -> Keylogge
         Ok(())
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::os::unix::fs::symlink;
+    use std::os::unix::io::FromRawFd;
+
+    use KeyEventCause::*;
+
+    /// A non-blocking pipe, whose read end stands in for an input device.
+    pub(crate) fn pipe() -> (File, File) {
+        let mut fds = [0; 2];
+
+        assert_eq!(
+            unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) },
+            0
+        );
+
+        unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) }
+    }
+
+    /// Write the specified input events (type, code and value) to the write end of a pipe.
+    pub(crate) fn write_events(mut pipe: &File, evs: &[(libc::c_ulong, u16, i32)]) {
+        let evs = evs
+            .iter()
+            .map(|&(type_, code, value)| libc::input_event {
+                time: libc::timeval {
+                    tv_sec: 1,
+                    tv_usec: 0,
+                },
+                type_: type_ as u16,
+                code,
+                value,
+            })
+            .collect::<Vec<_>>();
+        // input_event is plain old data
+        let bytes = unsafe {
+            std::slice::from_raw_parts(evs.as_ptr() as *const u8, mem::size_of_val(&evs[..]))
+        };
+
+        pipe.write_all(bytes).unwrap();
+    }
+
+    /// Read the next report from the read end of a pipe, returning the code, cause and scancode
+    /// of its events.
+    fn read_report(
+        reader: &mut EventReader,
+        pipe: &File,
+    ) -> io::Result<Vec<(KeyCode, KeyEventCause, Option<u32>)>> {
+        let mut out = vec![];
+
+        reader.read_key_events(pipe.as_raw_fd(), &mut out)?;
+
+        Ok(out
+            .into_iter()
+            .map(|ev| (ev.code, ev.cause, ev.scancode))
+            .collect())
+    }
+
+    pub(crate) fn key(code: KeyCode, value: i32) -> (libc::c_ulong, u16, i32) {
+        (EV_KEY, code.code(), value)
+    }
+
+    const SCAN: (libc::c_ulong, u16, i32) = (EV_MSC, MSC_SCAN, 0x70004);
+    pub(crate) const REPORT: (libc::c_ulong, u16, i32) = (EV_SYN, SYN_REPORT, 0);
+
+    #[test]
+    fn queued_reports() {
+        let (rx, tx) = pipe();
+        let mut reader = EventReader::new(DEFAULT_BUFFER_SIZE, false);
+
+        // Several reports read at once are returned one at a time
+        write_events(
+            &tx,
+            &[
+                key(KeyCode::KEY_LEFTSHIFT, 1),
+                key(KeyCode::KEY_A, 1),
+                REPORT,
+                key(KeyCode::KEY_A, 0),
+                REPORT,
+                key(KeyCode::KEY_LEFTSHIFT, 0),
+                REPORT,
+            ],
+        );
+
+        assert_eq!(
+            read_report(&mut reader, &rx).unwrap(),
+            [
+                (KeyCode::KEY_LEFTSHIFT, Press, None),
+                (KeyCode::KEY_A, Press, None)
+            ]
+        );
+        assert!(reader.has_reports());
+        assert_eq!(
+            read_report(&mut reader, &rx).unwrap(),
+            [(KeyCode::KEY_A, Release, None)]
+        );
+        assert_eq!(
+            read_report(&mut reader, &rx).unwrap(),
+            [(KeyCode::KEY_LEFTSHIFT, Release, None)]
+        );
+        assert!(!reader.has_reports());
+
+        // A report the kernel hasn't finished delivering is held back until its SYN_REPORT
+        write_events(&tx, &[key(KeyCode::KEY_B, 1)]);
+
+        assert_eq!(
+            read_report(&mut reader, &rx).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+
+        write_events(&tx, &[key(KeyCode::KEY_C, 1), REPORT]);
+
+        assert_eq!(
+            read_report(&mut reader, &rx).unwrap(),
+            [(KeyCode::KEY_B, Press, None), (KeyCode::KEY_C, Press, None)]
+        );
+        assert_eq!(
+            read_report(&mut reader, &rx).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+
+        // So is a report split across two reads by the size of the buffer
+        let mut reader = EventReader::new(3, false);
+
+        write_events(
+            &tx,
+            &[
+                key(KeyCode::KEY_B, 0),
+                REPORT,
+                key(KeyCode::KEY_C, 0),
+                REPORT,
+            ],
+        );
+
+        assert_eq!(
+            read_report(&mut reader, &rx).unwrap(),
+            [(KeyCode::KEY_B, Release, None)]
+        );
+        assert!(!reader.has_reports());
+        assert_eq!(
+            read_report(&mut reader, &rx).unwrap(),
+            [(KeyCode::KEY_C, Release, None)]
+        );
+    }
+
+    #[test]
+    fn report_larger_than_buffer() {
+        let (rx, tx) = pipe();
+        let mut reader = EventReader::new(2, false);
+
+        // The first read stops in the middle of the report, which is completed by the next one
+        write_events(&tx, &[SCAN, key(KeyCode::KEY_A, 1), REPORT]);
+
+        assert_eq!(
+            read_report(&mut reader, &rx).unwrap(),
+            [(KeyCode::KEY_A, Press, None)]
+        );
+        assert_eq!(
+            read_report(&mut reader, &rx).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+    }
+
+    #[test]
+    fn single_event_buffer() {
+        let (rx, tx) = pipe();
+
+        for buffer_size in [0, 1] {
+            // A buffer size of 0 is rounded up, so the device can still be read
+            let mut reader = EventReader::new(buffer_size, true);
+
+            assert_eq!(reader.buf.len(), 1);
+
+            write_events(
+                &tx,
+                &[
+                    SCAN,
+                    key(KeyCode::KEY_A, 1),
+                    REPORT,
+                    key(KeyCode::KEY_A, 0),
+                    REPORT,
+                ],
+            );
+
+            assert_eq!(
+                read_report(&mut reader, &rx).unwrap(),
+                [(KeyCode::KEY_A, Press, Some(0x70004))]
+            );
+            assert_eq!(
+                read_report(&mut reader, &rx).unwrap(),
+                [(KeyCode::KEY_A, Release, None)]
+            );
+            assert_eq!(
+                read_report(&mut reader, &rx).unwrap_err().kind(),
+                io::ErrorKind::WouldBlock
+            );
+        }
+    }
+
+    #[test]
+    fn key_repeats() {
+        let (rx, tx) = pipe();
+        let mut reader = EventReader::new(DEFAULT_BUFFER_SIZE, false);
+
+        write_events(
+            &tx,
+            &[
+                key(KeyCode::KEY_A, 1),
+                REPORT,
+                key(KeyCode::KEY_A, 2),
+                REPORT,
+                key(KeyCode::KEY_A, 2),
+                REPORT,
+                key(KeyCode::KEY_A, 0),
+                REPORT,
+            ],
+        );
+
+        for cause in [Press, Repeat, Repeat, Release] {
+            assert_eq!(
+                read_report(&mut reader, &rx).unwrap(),
+                [(KeyCode::KEY_A, cause, None)]
+            );
+        }
+
+        // A repeat doesn't change the keys held down
+        assert!(reader.held.is_empty());
+    }
+
+    #[test]
+    fn scancodes() {
+        let (rx, tx) = pipe();
+        let mut reader = EventReader::new(DEFAULT_BUFFER_SIZE, true);
+
+        // The scancode only belongs to the key event that follows it in the same report
+        write_events(
+            &tx,
+            &[
+                SCAN,
+                key(KeyCode::KEY_A, 1),
+                key(KeyCode::KEY_B, 1),
+                REPORT,
+                SCAN,
+                REPORT,
+                key(KeyCode::KEY_A, 0),
+                REPORT,
+            ],
+        );
+
+        assert_eq!(
+            read_report(&mut reader, &rx).unwrap(),
+            [
+                (KeyCode::KEY_A, Press, Some(0x70004)),
+                (KeyCode::KEY_B, Press, None)
+            ]
+        );
+        assert_eq!(
+            read_report(&mut reader, &rx).unwrap(),
+            [(KeyCode::KEY_A, Release, None)]
+        );
+
+        let mut reader = EventReader::new(DEFAULT_BUFFER_SIZE, false);
+
+        write_events(&tx, &[SCAN, key(KeyCode::KEY_A, 1), REPORT]);
+
+        assert_eq!(
+            read_report(&mut reader, &rx).unwrap(),
+            [(KeyCode::KEY_A, Press, None)]
+        );
+    }
+
+    #[test]
+    fn dropped_events() {
+        let (rx, tx) = pipe();
+        let mut reader = EventReader::new(DEFAULT_BUFFER_SIZE, false);
+
+        reader.read_held_keys = |_| Ok(vec![KeyCode::KEY_B, KeyCode::KEY_LEFTSHIFT]);
+
+        write_events(
+            &tx,
+            &[
+                key(KeyCode::KEY_A, 1),
+                REPORT,
+                // The partial report before the drop, and the events up to the next SYN_REPORT,
+                // are discarded
+                key(KeyCode::KEY_C, 1),
+                (EV_SYN, SYN_DROPPED, 0),
+                key(KeyCode::KEY_D, 1),
+                REPORT,
+                key(KeyCode::KEY_E, 1),
+                REPORT,
+            ],
+        );
+
+        let mut batches = vec![];
+
+        while let Ok(report) = read_report(&mut reader, &rx) {
+            batches.push((reader.batch, report));
+        }
+
+        // The keys whose events were lost are released, then pressed, in the order of their codes
+        assert_eq!(
+            batches,
+            [
+                (
+                    BatchInfo { seq: 0, dropped: 0 },
+                    vec![(KeyCode::KEY_A, Press, None)]
+                ),
+                (
+                    BatchInfo { seq: 1, dropped: 1 },
+                    vec![
+                        (KeyCode::KEY_A, Release, None),
+                        (KeyCode::KEY_LEFTSHIFT, Press, None),
+                        (KeyCode::KEY_B, Press, None),
+                    ]
+                ),
+                (
+                    BatchInfo { seq: 2, dropped: 0 },
+                    vec![(KeyCode::KEY_E, Press, None)]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn dropped_events_without_resync() {
+        let (rx, tx) = pipe();
+        // The keys held down can't be read from a pipe
+        let mut reader = EventReader::new(DEFAULT_BUFFER_SIZE, false);
+
+        write_events(
+            &tx,
+            &[
+                key(KeyCode::KEY_A, 1),
+                (EV_SYN, SYN_DROPPED, 0),
+                REPORT,
+                key(KeyCode::KEY_B, 1),
+                (EV_SYN, SYN_DROPPED, 0),
+                REPORT,
+                key(KeyCode::KEY_C, 1),
+                REPORT,
+            ],
+        );
+
+        // Nothing is synthesized, but the drops are reported with the next batch
+        assert_eq!(
+            read_report(&mut reader, &rx).unwrap(),
+            [(KeyCode::KEY_C, Press, None)]
+        );
+        assert_eq!(reader.batch, BatchInfo { seq: 0, dropped: 2 });
+    }
+
+    #[test]
+    fn read_errors() {
+        let device = Path::new("/dev/input/event3");
+        let read_error = |errno| read_error(device, io::Error::from_raw_os_error(errno));
+
+        assert!(matches!(
+            read_error(libc::ENODEV),
+            KeyloggerError::DeviceDisconnected(_)
+        ));
+        assert!(matches!(
+            read_error(libc::ENOENT),
+            KeyloggerError::DeviceNotFound(_)
+        ));
+        assert!(matches!(
+            read_error(libc::EINTR),
+            KeyloggerError::Interrupted(_)
+        ));
+        assert!(matches!(
+            read_error(libc::EACCES),
+            KeyloggerError::PermissionDenied { .. }
+        ));
+        assert_eq!(read_error(libc::EIO).raw_os_error(), Some(libc::EIO));
+        assert_eq!(read_error(libc::ENODEV).raw_os_error(), Some(libc::ENODEV));
+    }
+
+    #[test]
+    fn stable_names() {
+        assert_eq!(event_number(Path::new("/dev/input/event12")), Some(12));
+        assert_eq!(event_number(Path::new("/dev/input/mice")), None);
+
+        let dir = std::env::temp_dir().join(format!("keylogger-stable-{}", std::process::id()));
+        let by_id = dir.join("by-id");
+        let by_path = dir.join("by-path");
+
+        fs::create_dir_all(&by_id).unwrap();
+        fs::create_dir_all(&by_path).unwrap();
+        symlink("/dev/null", by_id.join("usb-Keeb-event-kbd")).unwrap();
+        symlink(
+            "/dev/null",
+            by_path.join("pci-0000:00:14.0-usb-0:1:1.0-event-kbd"),
+        )
+        .unwrap();
+        symlink(
+            "/dev/zero",
+            by_path.join("platform-i8042-serio-0-event-kbd"),
+        )
+        .unwrap();
+
+        let dirs = [by_id.as_path(), by_path.as_path()];
+
+        assert_eq!(
+            find_link(&dirs, Path::new("/dev/null")),
+            Some(by_id.join("usb-Keeb-event-kbd"))
+        );
+        assert_eq!(
+            find_link(&dirs, Path::new("/dev/zero")),
+            Some(by_path.join("platform-i8042-serio-0-event-kbd"))
+        );
+        assert_eq!(find_link(&dirs, Path::new("/dev/full")), None);
+
+        assert_eq!(
+            resolve_in(&dirs, Path::new("usb-Keeb-event-kbd")).unwrap(),
+            Path::new("/dev/null")
+        );
+        assert_eq!(
+            resolve_in(&dirs, &by_path.join("platform-i8042-serio-0-event-kbd")).unwrap(),
+            Path::new("/dev/zero")
+        );
+        assert!(resolve_in(&dirs, Path::new("usb-Missing-event-kbd")).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -4,10 +4,49 @@
 // [kernel docs]: https://www.kernel.org/doc/html/latest/input/event-codes.html
 pub(crate) const EV_SYN: libc::c_ulong = 0x00;
 pub(crate) const EV_KEY: libc::c_ulong = 0x01;
+pub(crate) const EV_ABS: libc::c_ulong = 0x03;
 pub(crate) const EV_MSC: libc::c_ulong = 0x04;
+pub(crate) const EV_SW: libc::c_ulong = 0x05;
+pub(crate) const EV_LED: libc::c_ulong = 0x11;
 pub(crate) const EV_REP: libc::c_ulong = 0x14;
 
 /// The `value` of an EV_KEY caused by a key being released.
 pub(crate) const EV_KEY_RELEASE: i32 = 0;
 /// The `value` of an EV_KEY caused by a key press.
 pub(crate) const EV_KEY_PRESS: i32 = 1;
+/// The `value` of an EV_KEY caused by a key being held down (autorepeat).
+pub(crate) const EV_KEY_REPEAT: i32 = 2;
+
+/// The EV_SYN code that marks the end of a hardware report.
+pub(crate) const SYN_REPORT: u16 = 0;
+/// The EV_SYN code reported when the buffer of the device overflowed, and events were dropped.
+pub(crate) const SYN_DROPPED: u16 = 3;
+
+/// The EV_MSC code that reports the raw hardware scancode of the key in the same report.
+pub(crate) const MSC_SCAN: u16 = 4;
+
+// The bus types of input devices (see `linux/input.h`).
+pub(crate) const BUS_USB: u16 = 0x03;
+pub(crate) const BUS_BLUETOOTH: u16 = 0x05;
+pub(crate) const BUS_VIRTUAL: u16 = 0x06;
+pub(crate) const BUS_I8042: u16 = 0x11;
+pub(crate) const BUS_I2C: u16 = 0x18;
+pub(crate) const BUS_HOST: u16 = 0x19;
+pub(crate) const BUS_SPI: u16 = 0x1c;
+
+/// The EV_KEY code reported when a finger or stylus touches (or stops touching) the surface.
+pub(crate) const BTN_TOUCH: u16 = 0x14a;
+
+// The EV_ABS codes of single-touch devices (including the pens of graphics tablets).
+pub(crate) const ABS_X: u16 = 0x00;
+pub(crate) const ABS_Y: u16 = 0x01;
+pub(crate) const ABS_PRESSURE: u16 = 0x18;
+
+// The EV_ABS codes of the multi-touch protocol (see the [multi-touch protocol docs]).
+//
+// [multi-touch protocol docs]: https://www.kernel.org/doc/html/latest/input/multi-touch-protocol.html
+pub(crate) const ABS_MT_SLOT: u16 = 0x2f;
+pub(crate) const ABS_MT_POSITION_X: u16 = 0x35;
+pub(crate) const ABS_MT_POSITION_Y: u16 = 0x36;
+pub(crate) const ABS_MT_TRACKING_ID: u16 = 0x39;
+pub(crate) const ABS_MT_PRESSURE: u16 = 0x3a;
@@ -0,0 +1,517 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use futures::channel::oneshot;
+use futures::future::{self, Either};
+use futures::FutureExt;
+use glob::Pattern;
+use regex::Regex;
+
+use crate::error::KeyloggerError;
+use crate::key_code::KeyCode;
+use crate::keyboard::device::{
+    find_char_devices, input_dir, resolve_device, EventReader, InputDevice, DEFAULT_BUFFER_SIZE,
+    KEYBOARD_FLAGS,
+};
+use crate::keyboard::{Clock, KeyFilter, Keyboard, KeyboardDevice};
+use crate::timer::sleep;
+use crate::KeyloggerResult;
+
+#[cfg(feature = "udev")]
+use crate::keyboard::udev::is_udev_keyboard;
+
+/// A device skipped by [`KeyboardFinder::find_verbose`], along with the reason it was skipped.
+pub type SkippedDevice = (PathBuf, KeyloggerError);
+
+/// A builder for discovering the keyboards to watch.
+///
+/// By default, a device is considered a keyboard if it supports the `EV_SYN`, `EV_KEY`, `EV_MSC`
+/// and `EV_REP` event types. The filters configured using the builder methods further narrow down
+/// the set of devices returned by [`KeyboardFinder::find`].
+///
+/// # Example
+///
+/// ```no_run
+/// use keylogger::{KeyboardFinder, KeyloggerError};
+///
+/// # fn main() -> Result<(), KeyloggerError> {
+/// let keyboards = KeyboardFinder::new()
+///     .exclude_name("(?i)power button|video bus")
+///     .find()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Some legitimate keyboards (notably virtual ones) don't support `EV_MSC` or `EV_REP`. These can
+/// be detected by only requiring `EV_SYN` and `EV_KEY`, and telling them apart from the other
+/// devices that report key events (such as mice and power buttons) by the keys they support:
+///
+/// ```no_run
+/// use keylogger::{KeyboardFinder, KeyloggerError};
+///
+/// # fn main() -> Result<(), KeyloggerError> {
+/// let keyboards = KeyboardFinder::new()
+///     .event_types((1 << 0x00) | (1 << 0x01))
+///     .min_alphabetic_keys(26)
+///     .find()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct KeyboardFinder {
+    name: Option<String>,
+    exclude_name: Option<String>,
+    path: Option<String>,
+    input_dir: Option<PathBuf>,
+    devices: Option<Vec<PathBuf>>,
+    vendor: Option<u16>,
+    product: Option<u16>,
+    event_types: libc::c_ulong,
+    min_alphabetic_keys: usize,
+    buffer_size: usize,
+    capture_scancodes: bool,
+    key_filter: KeyFilter,
+    clock: Clock,
+    skip_inaccessible: bool,
+    timeout: Option<Duration>,
+    #[cfg(feature = "udev")]
+    use_udev: bool,
+}
+
+impl Default for KeyboardFinder {
+    fn default() -> Self {
+        Self {
+            name: None,
+            exclude_name: None,
+            path: None,
+            input_dir: None,
+            devices: None,
+            vendor: None,
+            product: None,
+            event_types: KEYBOARD_FLAGS,
+            min_alphabetic_keys: 0,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            capture_scancodes: false,
+            key_filter: KeyFilter::All,
+            clock: Clock::Realtime,
+            skip_inaccessible: false,
+            timeout: None,
+            #[cfg(feature = "udev")]
+            use_udev: false,
+        }
+    }
+}
+
+impl KeyboardFinder {
+    /// Create a finder that returns all the devices that look like keyboards.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Only return the devices whose name matches the specified regular expression.
+    pub fn name(mut self, regex: &str) -> Self {
+        self.name = Some(regex.into());
+        self
+    }
+
+    /// Skip the devices whose name matches the specified regular expression.
+    pub fn exclude_name(mut self, regex: &str) -> Self {
+        self.exclude_name = Some(regex.into());
+        self
+    }
+
+    /// Only return the devices whose path matches the specified glob pattern (e.g.
+    /// `/dev/input/event[0-9]`).
+    pub fn path(mut self, glob: &str) -> Self {
+        self.path = Some(glob.into());
+        self
+    }
+
+    /// Look for the devices in the specified directory, instead of `/dev/input`.
+    ///
+    /// This is useful in containers that expose the input devices under a different path, and in
+    /// tests that bind-mount fake devices. The default directory can also be overridden using the
+    /// `KEYLOGGER_INPUT_DIR` environment variable, which applies to all the ways of finding the
+    /// devices (such as [`KeyboardMonitor`](crate::KeyboardMonitor)).
+    pub fn input_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.input_dir = Some(dir.into());
+        self
+    }
+
+    /// Only consider the specified devices, returning the keyboards in the order the devices are
+    /// specified in (instead of the order of their device numbers).
+    ///
+    /// Each device can be specified by its path (e.g. `/dev/input/event4`), or by one of the
+    /// symlinks udev creates in `/dev/input/by-id` and `/dev/input/by-path`, which don't change
+    /// across boots (see [`KeyboardDevice::stable_id`]). The names are looked up in the input
+    /// directory (see [`KeyboardFinder::input_dir`]). The symlinks can be specified by path, or
+    /// just by name (e.g. `usb-Logitech_USB_Keyboard-event-kbd`). The devices that don't exist
+    /// are skipped, like the ones that can't be opened.
+    pub fn with_devices(mut self, devices: impl IntoIterator<Item = impl AsRef<Path>>) -> Self {
+        self.devices = Some(
+            devices
+                .into_iter()
+                .map(|dev| dev.as_ref().to_path_buf())
+                .collect(),
+        );
+        self
+    }
+
+    /// Only return the devices with the specified vendor ID.
+    pub fn vendor(mut self, vendor: u16) -> Self {
+        self.vendor = Some(vendor);
+        self
+    }
+
+    /// Only return the devices with the specified product ID.
+    pub fn product(mut self, product: u16) -> Self {
+        self.product = Some(product);
+        self
+    }
+
+    /// Only return the devices that support all the specified event types.
+    ///
+    /// `event_types` is a bit mask, where bit `n` corresponds to the event type with value `n`
+    /// (e.g. `1 << 0x01` for `EV_KEY`). This replaces the default mask of
+    /// `EV_SYN | EV_KEY | EV_MSC | EV_REP`.
+    pub fn event_types(mut self, event_types: u32) -> Self {
+        self.event_types = event_types.into();
+        self
+    }
+
+    /// Only return the devices that support at least `n` of the `KEY_A`..`KEY_Z` keys.
+    ///
+    /// This tells keyboards apart from the other devices that report key events (such as mice,
+    /// media remotes and power buttons) based on the keys they support, rather than on the event
+    /// types they support, which is useful in combination with a less strict
+    /// [`KeyboardFinder::event_types`] mask.
+    pub fn min_alphabetic_keys(mut self, n: usize) -> Self {
+        self.min_alphabetic_keys = n;
+        self
+    }
+
+    /// The maximum number of input events to read from a keyboard at once (128 by default).
+    ///
+    /// Each keyboard allocates its read buffer once, when it's opened. A larger buffer reduces the
+    /// number of reads needed to drain the events of high-rate devices. The size is rounded up to
+    /// 1 if it's 0.
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Attach the raw hardware scancode reported by the keyboards (using `MSC_SCAN`) to the key
+    /// events (see [`KeyEvent::scancode`](crate::KeyEvent::scancode)).
+    ///
+    /// This is useful for remapping keys that don't have a key code of their own.
+    pub fn capture_scancodes(mut self) -> Self {
+        self.capture_scancodes = true;
+        self
+    }
+
+    /// Only report the events of the keys that match the specified filter (see
+    /// [`KeyboardDevice::set_key_filter`]).
+    pub fn key_filter(mut self, filter: KeyFilter) -> Self {
+        self.key_filter = filter;
+        self
+    }
+
+    /// Timestamp the events of the keyboards using the specified clock (see
+    /// [`KeyboardDevice::set_clock`]).
+    ///
+    /// The devices whose clock can't be set are skipped.
+    pub fn clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Skip the devices the process isn't allowed to open, instead of failing.
+    ///
+    /// By default, [`KeyboardFinder::find`] fails with [`KeyloggerError::PermissionDenied`] if any
+    /// of the input devices can't be opened, which usually means the process isn't running as root
+    /// or as a member of the `input` group. With this option, only the keyboards that are
+    /// accessible are returned, which is useful if access was granted to some devices only (e.g.
+    /// using udev rules or ACLs).
+    pub fn skip_inaccessible(mut self) -> Self {
+        self.skip_inaccessible = true;
+        self
+    }
+
+    /// Give up on the discovery of [`KeyboardFinder::find_async`] if it takes longer than
+    /// `timeout`.
+    ///
+    /// Opening a device can hang (e.g. if its driver is stuck), which would otherwise hang the
+    /// discovery. This doesn't apply to [`KeyboardFinder::find`], which can't be interrupted.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Use the udev database instead of the supported event types to decide whether a device is
+    /// a keyboard.
+    ///
+    /// A device is considered a keyboard if udev assigned it the `ID_INPUT_KEYBOARD` property,
+    /// which is more accurate for devices that misreport their capabilities. The mask configured
+    /// using [`KeyboardFinder::event_types`] is ignored.
+    #[cfg(feature = "udev")]
+    pub fn use_udev(mut self) -> Self {
+        self.use_udev = true;
+        self
+    }
+
+    /// Find all the keyboards that match the configured filters.
+    ///
+    /// The devices that can't be opened are skipped, unless the process isn't allowed to open them
+    /// (see [`KeyboardFinder::skip_inaccessible`]).
+    pub fn find(&self) -> KeyloggerResult<Vec<KeyboardDevice>> {
+        let (keyboards, skipped) = self.find_verbose()?;
+
+        if !self.skip_inaccessible {
+            let denied = skipped
+                .into_iter()
+                .find(|(_, e)| matches!(e, KeyloggerError::PermissionDenied { .. }));
+
+            if let Some((_, e)) = denied {
+                return Err(e);
+            }
+        }
+
+        Ok(keyboards)
+    }
+
+    /// Find all the keyboards that match the configured filters, like [`KeyboardFinder::find`],
+    /// without blocking the async runtime.
+    ///
+    /// Discovering the keyboards involves blocking filesystem operations and ioctls, so the
+    /// discovery runs on a separate thread. If it takes longer than the timeout configured using
+    /// [`KeyboardFinder::timeout`], this fails with [`io::ErrorKind::TimedOut`] (the keyboards
+    /// found by the abandoned discovery are closed once it completes).
+    pub async fn find_async(&self) -> KeyloggerResult<Vec<KeyboardDevice>> {
+        let (tx, rx) = oneshot::channel();
+        let finder = self.clone();
+
+        thread::Builder::new()
+            .name("keyboard discovery".into())
+            .spawn(move || {
+                // The receiver is gone if the discovery timed out
+                let _ = tx.send(finder.find());
+            })?;
+
+        let discovery = rx.map(|res| {
+            res.unwrap_or_else(|_| Err(io::Error::other("the discovery thread panicked").into()))
+        });
+
+        let Some(timeout) = self.timeout else {
+            return discovery.await;
+        };
+
+        match future::select(Box::pin(discovery), Box::pin(sleep(timeout))).await {
+            Either::Left((res, _)) => res,
+            Either::Right((res, _)) => {
+                res?;
+
+                Err(io::Error::new(io::ErrorKind::TimedOut, "keyboard discovery timed out").into())
+            }
+        }
+    }
+
+    /// Find all the keyboards that match the configured filters, like [`KeyboardFinder::find`],
+    /// also returning the devices that were skipped, along with the reason they were skipped.
+    ///
+    /// This is useful for finding out why a keyboard wasn't detected. The devices that can't be
+    /// opened (including the ones the process isn't allowed to open, regardless of
+    /// [`KeyboardFinder::skip_inaccessible`]) and the devices that don't look like keyboards are
+    /// reported, while the devices that were filtered out by the name, path, vendor or product
+    /// filters aren't.
+    pub fn find_verbose(&self) -> KeyloggerResult<(Vec<KeyboardDevice>, Vec<SkippedDevice>)> {
+        let name = self.name.as_deref().map(compile_regex).transpose()?;
+        let exclude_name = self
+            .exclude_name
+            .as_deref()
+            .map(compile_regex)
+            .transpose()?;
+        let path = self
+            .path
+            .as_deref()
+            .map(|p| Pattern::new(p).map_err(|e| KeyloggerError::InvalidFilter(e.to_string())))
+            .transpose()?;
+
+        let input_dir = self.input_dir.clone().unwrap_or_else(input_dir);
+        let mut skipped = vec![];
+        let candidates = match &self.devices {
+            Some(devices) => {
+                let mut seen = HashSet::new();
+                let mut candidates = vec![];
+
+                for dev in devices {
+                    match resolve_device(&input_dir, dev) {
+                        // The same device might be specified under several names
+                        Ok(path) if !seen.insert(path.clone()) => {}
+                        Ok(path) => candidates.push(path),
+                        Err(e) => skipped.push((dev.clone(), e.into())),
+                    }
+                }
+
+                candidates
+            }
+            None => find_char_devices(&input_dir)?.collect::<Vec<_>>(),
+        };
+
+        let mut devs = vec![];
+
+        for entry in candidates
+            .into_iter()
+            .filter(|entry| path.as_ref().is_none_or(|p| p.matches_path(entry)))
+            .filter(|entry| self.is_candidate(entry))
+        {
+            match self.open(&entry) {
+                Ok(dev) => devs.push(dev),
+                Err(e) => skipped.push((entry, e)),
+            }
+        }
+
+        let keyboards = devs
+            .into_iter()
+            .filter(|dev| name.as_ref().is_none_or(|re| re.is_match(&dev.name)))
+            .filter(|dev| {
+                !exclude_name
+                    .as_ref()
+                    .is_some_and(|re| re.is_match(&dev.name))
+            })
+            .filter(|dev| self.vendor.is_none_or(|v| v == dev.info.vendor))
+            .filter(|dev| self.product.is_none_or(|p| p == dev.info.product))
+            .map(|dev| KeyboardDevice(Keyboard::new(dev)))
+            .collect();
+
+        Ok((keyboards, skipped))
+    }
+}
+
+impl KeyboardFinder {
+    /// Open the specified device, provided it looks like a keyboard.
+    fn open(&self, device: &Path) -> KeyloggerResult<InputDevice> {
+        let mut reader = EventReader::new(self.buffer_size, self.capture_scancodes);
+
+        reader.key_filter = self.key_filter.clone();
+
+        let mut dev = InputDevice::open(device, self.required_flags(), reader)?;
+
+        if self.min_alphabetic_keys > 0
+            && count_alphabetic_keys(&dev.supported_keys()?) < self.min_alphabetic_keys
+        {
+            return Err(KeyloggerError::NotAKeyboard(device.into()));
+        }
+
+        if self.clock != Clock::Realtime {
+            dev.set_clock(self.clock)?;
+        }
+
+        Ok(dev)
+    }
+
+    /// Check whether the specified device should be opened.
+    fn is_candidate(&self, _device: &Path) -> bool {
+        #[cfg(feature = "udev")]
+        if self.use_udev {
+            return is_udev_keyboard(_device);
+        }
+
+        true
+    }
+
+    /// The event types a device must support to be considered a keyboard.
+    fn required_flags(&self) -> libc::c_ulong {
+        #[cfg(feature = "udev")]
+        if self.use_udev {
+            return 0;
+        }
+
+        self.event_types
+    }
+}
+
+fn compile_regex(regex: &str) -> KeyloggerResult<Regex> {
+    Regex::new(regex).map_err(|e| KeyloggerError::InvalidFilter(e.to_string()))
+}
+
+/// The number of `KEY_A`..`KEY_Z` keys in the specified set.
+fn count_alphabetic_keys(keys: &HashSet<KeyCode>) -> usize {
+    keys.iter()
+        .filter(|code| char::try_from(**code).is_ok_and(|c| c.is_ascii_alphabetic()))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alphabetic_keys() {
+        use KeyCode::*;
+
+        let media = HashSet::from([KEY_VOLUMEUP, KEY_VOLUMEDOWN, KEY_PLAYPAUSE]);
+        let keypad = HashSet::from([KEY_KP1, KEY_KP2, KEY_KPENTER, KEY_NUMLOCK]);
+        let keyboard = HashSet::from([KEY_A, KEY_S, KEY_D, KEY_F, KEY_1, KEY_ENTER]);
+
+        assert_eq!(count_alphabetic_keys(&media), 0);
+        assert_eq!(count_alphabetic_keys(&keypad), 0);
+        assert_eq!(count_alphabetic_keys(&keyboard), 4);
+    }
+
+    #[test]
+    fn skipped_devices() {
+        let finder = KeyboardFinder::new().with_devices(["/dev/null", "/nonexistent/keeb"]);
+        let (keyboards, skipped) = finder.find_verbose().unwrap();
+        let skipped = skipped
+            .iter()
+            .map(|(path, e)| (path.to_str().unwrap(), e))
+            .collect::<Vec<_>>();
+
+        assert!(keyboards.is_empty());
+        assert_eq!(skipped.len(), 2);
+        assert!(matches!(
+            skipped[0],
+            ("/nonexistent/keeb", KeyloggerError::Io(_))
+        ));
+        assert_eq!(skipped[1].0, "/dev/null");
+        assert!(finder.find().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn async_discovery() {
+        let finder = KeyboardFinder::new()
+            .with_devices(["/dev/null"])
+            .timeout(Duration::from_secs(10));
+
+        assert!(finder.find_async().await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn custom_input_dir() {
+        let dir = std::env::temp_dir().join(format!("keylogger-input-{}", std::process::id()));
+
+        std::fs::create_dir_all(dir.join("by-id")).unwrap();
+        std::os::unix::fs::symlink("/dev/null", dir.join("event3")).unwrap();
+        std::os::unix::fs::symlink("../event3", dir.join("by-id/usb-Keeb-event-kbd")).unwrap();
+
+        let finder = KeyboardFinder::new().input_dir(&dir);
+        let (_, skipped) = finder.find_verbose().unwrap();
+
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].0, dir.join("event3"));
+
+        let (_, skipped) = finder
+            .with_devices(["usb-Keeb-event-kbd"])
+            .find_verbose()
+            .unwrap();
+
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].0, Path::new("/dev/null"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
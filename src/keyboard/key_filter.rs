@@ -0,0 +1,49 @@
+use std::collections::HashSet;
+
+use crate::key_code::KeyCode;
+
+/// Decides which key codes a keyboard reports.
+///
+/// The filter is applied as soon as the events are read from the device, so the events it rejects
+/// are never buffered or returned by the [`Stream`](futures::Stream) of the keyboard.
+///
+/// ```
+/// use keylogger::{KeyCode, KeyFilter};
+///
+/// // Only report the function keys
+/// let filter = KeyFilter::allow([KeyCode::KEY_F1, KeyCode::KEY_F2, KeyCode::KEY_F3]);
+///
+/// assert!(filter.matches(KeyCode::KEY_F2));
+/// assert!(!filter.matches(KeyCode::KEY_A));
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum KeyFilter {
+    /// Report all the keys.
+    #[default]
+    All,
+    /// Only report the specified keys.
+    Allow(HashSet<KeyCode>),
+    /// Report all the keys except the specified ones.
+    Deny(HashSet<KeyCode>),
+}
+
+impl KeyFilter {
+    /// Create a filter that only reports the specified keys.
+    pub fn allow(codes: impl IntoIterator<Item = KeyCode>) -> Self {
+        Self::Allow(codes.into_iter().collect())
+    }
+
+    /// Create a filter that reports all the keys except the specified ones.
+    pub fn deny(codes: impl IntoIterator<Item = KeyCode>) -> Self {
+        Self::Deny(codes.into_iter().collect())
+    }
+
+    /// Whether the events of the specified key are reported.
+    pub fn matches(&self, code: KeyCode) -> bool {
+        match self {
+            Self::All => true,
+            Self::Allow(codes) => codes.contains(&code),
+            Self::Deny(codes) => !codes.contains(&code),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use crate::key_code::{KeyCode, KEY_MAX};
+use crate::keyboard::device::{ioc, ioctl, InputDevice, IOC_READ};
+use crate::keyboard::event_codes::EV_KEY;
+use crate::KeyloggerResult;
+
+impl InputDevice {
+    /// Read the keys that are currently held down using the `EVIOCGKEY` ioctl.
+    pub(crate) fn held_keys(&self) -> KeyloggerResult<Vec<KeyCode>> {
+        read_held_keys(self.as_raw_fd())
+    }
+
+    /// Read the keys the device can report using the `EVIOCGBIT(EV_KEY)` ioctl.
+    pub(crate) fn supported_keys(&self) -> KeyloggerResult<HashSet<KeyCode>> {
+        Ok(read_key_bits(self.as_raw_fd(), 0x20 + EV_KEY)?
+            .into_iter()
+            .collect())
+    }
+}
+
+/// Read the keys that are currently held down on the specified device using the `EVIOCGKEY`
+/// ioctl.
+pub(crate) fn read_held_keys(fd: RawFd) -> KeyloggerResult<Vec<KeyCode>> {
+    read_key_bits(fd, 0x18)
+}
+
+/// Read a key bitmap of the specified device using the specified `EVIOCG*` ioctl number.
+fn read_key_bits(fd: RawFd, nr: libc::c_ulong) -> KeyloggerResult<Vec<KeyCode>> {
+    let mut bits = [0u8; KEY_MAX as usize / 8 + 1];
+
+    ioctl(
+        fd,
+        ioc(IOC_READ, 'E', nr, bits.len()),
+        bits.as_mut_ptr() as *mut libc::c_ulong,
+    )?;
+
+    Ok(decode_key_bits(&bits))
+}
+
+/// Decode a key bitmap, in which bit `code % 8` of byte `code / 8` is set for each key code
+/// included.
+fn decode_key_bits(bits: &[u8]) -> Vec<KeyCode> {
+    (0..=KEY_MAX)
+        .filter(|code| {
+            bits.get(usize::from(*code) / 8)
+                .is_some_and(|byte| byte & (1 << (code % 8)) != 0)
+        })
+        .map(KeyCode::from_raw)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use KeyCode::*;
+
+    #[test]
+    fn held_key_bits() {
+        let mut bits = [0u8; KEY_MAX as usize / 8 + 1];
+
+        assert!(decode_key_bits(&bits).is_empty());
+
+        // KEY_ESC (1), KEY_LEFTCTRL (29) and KEY_A (30), as EVIOCGKEY reports them
+        bits[0] = 0b0000_0010;
+        bits[3] = 0b0110_0000;
+
+        assert_eq!(decode_key_bits(&bits), [KEY_ESC, KEY_LEFTCTRL, KEY_A]);
+    }
+
+    #[test]
+    fn supported_key_bits() {
+        let mut bits = [0u8; KEY_MAX as usize / 8 + 1];
+
+        // KEY_RESERVED (0) up to KEY_Z (44), a code the kernel doesn't name (0x2fe) and KEY_MAX,
+        // as EVIOCGBIT(EV_KEY) reports them
+        bits[..5].fill(0xff);
+        bits[5] = 0b0001_1111;
+        bits[KEY_MAX as usize / 8] = 0b1100_0000;
+
+        let keys = decode_key_bits(&bits);
+
+        assert_eq!(keys.len(), 47);
+        assert_eq!(keys[..2], [KEY_RESERVED, KEY_ESC]);
+        assert_eq!(keys[44], KEY_Z);
+        assert_eq!(keys[45..], [Unknown(0x2fe), Unknown(KEY_MAX)]);
+
+        // A bitmap shorter than KEY_MAX (from an older kernel) only covers the first codes
+        assert_eq!(decode_key_bits(&bits[..1]).len(), 8);
+    }
+}
@@ -0,0 +1,93 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::slice;
+
+use crate::keyboard::device::{ioc, ioctl, InputDevice, IOC_READ};
+use crate::keyboard::event_codes::{EV_LED, EV_SYN, SYN_REPORT};
+use crate::KeyloggerResult;
+
+/// The highest LED code supported by the kernel (`LED_MAX`).
+const LED_MAX: usize = 0x0f;
+
+/// A keyboard LED (see the `LED_*` constants from `input-event-codes.h`).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u16)]
+pub enum Led {
+    /// The Num Lock indicator (`LED_NUML`).
+    NumLock = 0x00,
+    /// The Caps Lock indicator (`LED_CAPSL`).
+    CapsLock = 0x01,
+    /// The Scroll Lock indicator (`LED_SCROLLL`).
+    ScrollLock = 0x02,
+    /// The Compose indicator (`LED_COMPOSE`).
+    Compose = 0x03,
+    /// The Kana indicator (`LED_KANA`).
+    Kana = 0x04,
+}
+
+impl Led {
+    const ALL: [Led; 5] = [
+        Led::NumLock,
+        Led::CapsLock,
+        Led::ScrollLock,
+        Led::Compose,
+        Led::Kana,
+    ];
+}
+
+impl InputDevice {
+    /// Turn the specified LED on or off by writing an `EV_LED` event to the device.
+    pub(crate) fn set_led(&mut self, led: Led, on: bool) -> KeyloggerResult<()> {
+        // The device is opened read-only, so open it again for writing
+        let mut file = OpenOptions::new().write(true).open(&self.device)?;
+
+        let evs = [
+            input_event(EV_LED as u16, led as u16, on.into()),
+            input_event(EV_SYN as u16, SYN_REPORT, 0),
+        ];
+
+        let buf =
+            unsafe { slice::from_raw_parts(evs.as_ptr() as *const u8, mem::size_of_val(&evs)) };
+
+        file.write_all(buf)?;
+
+        Ok(())
+    }
+
+    /// Read the state of the LEDs of the device using the `EVIOCGLED` ioctl.
+    pub(crate) fn leds(&self) -> KeyloggerResult<Vec<Led>> {
+        let mut state = [0u8; LED_MAX / 8 + 1];
+
+        let eviocgled = ioc(IOC_READ, 'E', 0x19, state.len());
+
+        ioctl(
+            self.as_raw_fd(),
+            eviocgled,
+            state.as_mut_ptr() as *mut libc::c_ulong,
+        )?;
+
+        Ok(Led::ALL
+            .into_iter()
+            .filter(|led| {
+                let code = *led as usize;
+
+                state[code / 8] & (1 << (code % 8)) != 0
+            })
+            .collect())
+    }
+}
+
+fn input_event(type_: u16, code: u16, value: i32) -> libc::input_event {
+    libc::input_event {
+        time: libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        },
+        type_,
+        code,
+        value,
+    }
+}
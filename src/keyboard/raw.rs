@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use chrono::NaiveDateTime;
+use futures::{ready, Stream};
+
+use crate::error::KeyloggerError;
+use crate::keyboard::device::DEFAULT_BUFFER_SIZE;
+use crate::keyboard::event_codes::{EV_SYN, SYN_REPORT};
+use crate::keyboard::{timestamp, KeyboardDevice};
+use crate::KeyloggerResult;
+
+/// An input event, as reported by the kernel (see `struct input_event` in `linux/input.h`).
+///
+/// Unlike [`KeyEvent`](crate::KeyEvent)s, these cover all the event types reported by the device
+/// (such as `EV_SYN`, `EV_MSC` and `EV_LED`), and their codes and values are passed through
+/// without being interpreted.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RawEvent {
+    /// The time the event occurred at.
+    pub ts: NaiveDateTime,
+    /// The type of the event (one of the `EV_*` constants).
+    pub ev_type: u16,
+    /// The code of the event, whose meaning depends on its type (e.g. a `KEY_*` constant for
+    /// `EV_KEY` events, or a `MSC_*` constant for `EV_MSC` events).
+    pub code: u16,
+    /// The value of the event, whose meaning depends on its type.
+    pub value: i32,
+}
+
+impl RawEvent {
+    /// Whether the event marks the end of a hardware report (`EV_SYN`/`SYN_REPORT`).
+    pub fn is_syn_report(&self) -> bool {
+        (self.ev_type as libc::c_ulong, self.code) == (EV_SYN, SYN_REPORT)
+    }
+}
+
+impl TryFrom<&libc::input_event> for RawEvent {
+    type Error = KeyloggerError;
+
+    fn try_from(ev: &libc::input_event) -> Result<Self, Self::Error> {
+        Ok(Self {
+            ts: timestamp(&ev.time)?,
+            ev_type: ev.type_,
+            code: ev.code,
+            value: ev.value,
+        })
+    }
+}
+
+impl From<RawEvent> for libc::input_event {
+    fn from(ev: RawEvent) -> Self {
+        let utc = ev.ts.and_utc();
+
+        libc::input_event {
+            time: libc::timeval {
+                tv_sec: utc.timestamp() as libc::time_t,
+                tv_usec: utc.timestamp_subsec_micros() as libc::suseconds_t,
+            },
+            type_: ev.ev_type,
+            code: ev.code,
+            value: ev.value,
+        }
+    }
+}
+
+impl KeyboardDevice {
+    /// A stream of all the input events of the keyboard, rather than just its key events.
+    ///
+    /// The events are read from the device directly, so they bypass the key filter of the
+    /// keyboard, and the key events that were already read (but not yet returned) by its
+    /// [`Stream`] implementation aren't included. Like the key events, the raw events aren't
+    /// read while the keyboard is paused. The events that were read but not yet returned when the
+    /// stream is dropped are lost.
+    pub fn raw_events(&mut self) -> RawEvents<'_> {
+        RawEvents {
+            keyboard: self,
+            buf: vec![unsafe { mem::zeroed() }; DEFAULT_BUFFER_SIZE],
+            pending: Default::default(),
+        }
+    }
+}
+
+/// A [`Stream`] of the [`RawEvent`]s of a [`KeyboardDevice`] (see
+/// [`KeyboardDevice::raw_events`]).
+pub struct RawEvents<'a> {
+    keyboard: &'a mut KeyboardDevice,
+    /// The buffer the input events are read into, reused across reads.
+    buf: Vec<libc::input_event>,
+    /// The events that were read but not yet returned.
+    pending: VecDeque<libc::input_event>,
+}
+
+impl Stream for RawEvents<'_> {
+    type Item = KeyloggerResult<RawEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some(ev) = this.pending.pop_front() {
+                let res = RawEvent::try_from(&ev);
+
+                return Poll::Ready(Some(this.keyboard.with_context(res)));
+            }
+
+            let inner = &mut this.keyboard.0.inner;
+
+            if inner.paused {
+                inner.resume_waker = Some(cx.waker().clone());
+
+                return Poll::Pending;
+            }
+
+            let res = ready!(inner.poll_input_events(cx, &mut this.buf));
+
+            match this.keyboard.with_context(res) {
+                Ok(evs) => this.pending.extend(evs),
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_event_roundtrip() {
+        let ev = libc::input_event {
+            time: libc::timeval {
+                tv_sec: 1_700_000_000,
+                tv_usec: 250_000,
+            },
+            type_: 0x04,
+            code: 0x04,
+            value: 0x70004,
+        };
+
+        let raw = RawEvent::try_from(&ev).unwrap();
+
+        assert_eq!((raw.ev_type, raw.code, raw.value), (0x04, 0x04, 0x70004));
+        assert!(!raw.is_syn_report());
+
+        let ev: libc::input_event = raw.into();
+
+        assert_eq!((ev.time.tv_sec, ev.time.tv_usec), (1_700_000_000, 250_000));
+    }
+}
@@ -0,0 +1,49 @@
+use std::fs;
+use std::path::Path;
+
+use crate::keyboard::udev::read_udev_properties;
+
+/// The directory where logind stores the state of the seats.
+const LOGIND_SEATS_DIR: &str = "/run/systemd/seats";
+
+/// The seat devices belong to unless udev assigns them to a different one.
+const DEFAULT_SEAT: &str = "seat0";
+
+/// The logind session that's in the foreground on a seat (see [`DeviceInfo::active_session`]).
+///
+/// [`DeviceInfo::active_session`]: crate::DeviceInfo::active_session
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SeatSession {
+    /// The ID of the session (e.g. `2`, or `c1` for the sessions of display managers).
+    pub id: String,
+    /// The ID of the user that owns the session.
+    pub uid: Option<u32>,
+}
+
+/// Find the seat the specified device is assigned to, if it's managed by udev.
+pub(crate) fn read_seat(device: &Path) -> Option<String> {
+    let props = read_udev_properties(device).ok()?;
+
+    Some(
+        props
+            .get("ID_SEAT")
+            .cloned()
+            .unwrap_or_else(|| DEFAULT_SEAT.into()),
+    )
+}
+
+/// Find the session that's active on the specified seat, if any.
+pub(crate) fn read_active_session(seat: &str) -> Option<SeatSession> {
+    let state = fs::read_to_string(Path::new(LOGIND_SEATS_DIR).join(seat)).ok()?;
+    let value = |key: &str| {
+        state
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+    };
+
+    Some(SeatSession {
+        id: value("ACTIVE")?.into(),
+        uid: value("ACTIVE_UID").and_then(|uid| uid.parse().ok()),
+    })
+}
@@ -0,0 +1,534 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::Stream;
+
+use crate::key_code::KeyCode;
+use crate::keyboard::{find_keyboards, BatchInfo, DeviceInfo, KeyEvent, KeyboardDevice};
+use crate::trace;
+use crate::KeyloggerResult;
+
+/// Identifies a keyboard within a [`KeyboardSet`].
+///
+/// The IDs are never reused within a set, so an ID keeps referring to the same keyboard even
+/// after other keyboards are added or removed, and a keyboard that is unplugged and plugged back
+/// in gets a new ID.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceId(pub(crate) usize);
+
+impl DeviceId {
+    /// The numeric value of the ID.
+    pub fn get(self) -> usize {
+        self.0
+    }
+}
+
+/// A [`KeyEvent`], tagged with the [`DeviceId`] of the keyboard of a [`KeyboardSet`] that produced
+/// it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TaggedKeyEvent {
+    /// The keyboard that produced the event.
+    pub device_id: DeviceId,
+    /// The event.
+    pub event: KeyEvent,
+    /// The batch of the event, which tells whether events were dropped before it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub batch: BatchInfo,
+}
+
+/// What's known about the keyboard of a [`KeyboardSet`] that produced an event.
+///
+/// This is passed to the [`KeyEventHandler`](crate::handler::KeyEventHandler)s along with the
+/// events, so they don't need access to the set to tell the keyboards apart (see
+/// [`KeyboardSet::with_context`]).
+#[derive(Clone, Debug)]
+pub struct DeviceContext {
+    pub(crate) id: DeviceId,
+    pub(crate) path: PathBuf,
+    pub(crate) name: String,
+    pub(crate) info: DeviceInfo,
+    pub(crate) supported_keys: HashSet<KeyCode>,
+}
+
+impl DeviceContext {
+    /// Describe the keyboard with the specified ID.
+    fn new(id: DeviceId, keyboard: &KeyboardDevice) -> Self {
+        Self {
+            id,
+            path: keyboard.path().into(),
+            name: keyboard.name().into(),
+            info: keyboard.info().clone(),
+            // The keys are only informative, so a keyboard whose keys can't be read is still used
+            supported_keys: keyboard.supported_keys().unwrap_or_default(),
+        }
+    }
+
+    /// The ID of the keyboard within the set.
+    pub fn id(&self) -> DeviceId {
+        self.id
+    }
+
+    /// The path of the keyboard (see [`KeyboardDevice::path`]).
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The name of the keyboard (see [`KeyboardDevice::name`]).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Information about the hardware of the keyboard (see [`KeyboardDevice::info`]).
+    pub fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    /// The seat the keyboard is assigned to (see [`DeviceInfo::seat`]).
+    pub fn seat(&self) -> Option<&str> {
+        self.info.seat.as_deref()
+    }
+
+    /// The keys the keyboard can report, when it was added to the set (see
+    /// [`KeyboardDevice::supported_keys`]).
+    pub fn supported_keys(&self) -> &HashSet<KeyCode> {
+        &self.supported_keys
+    }
+}
+
+/// A collection of keyboards, whose events are merged into a single [`Stream`].
+///
+/// Each event of the stream is tagged with the [`DeviceId`] of the keyboard that produced it (see
+/// [`TaggedKeyEvent`]). The errors carry the path and name of the keyboard that encountered them
+/// (see [`KeyloggerError::device_path`](crate::KeyloggerError::device_path)), which can be turned
+/// back into its ID using [`KeyboardSet::device_id`]. The keyboards are polled in a round-robin
+/// fashion, so a busy keyboard can't starve the others.
+///
+/// Some keyboards are exposed as several devices (e.g. one per USB interface), which may report
+/// the same key events. Such aliased keyboards are detected using [`KeyboardSet::aliases`], and
+/// their duplicate events can be dropped using [`KeyboardSet::dedup_aliases`].
+#[derive(Default)]
+pub struct KeyboardSet {
+    keyboards: Vec<(DeviceId, KeyboardDevice)>,
+    /// The contexts of the keyboards, which are passed to the handlers of their events.
+    contexts: HashMap<DeviceId, Arc<DeviceContext>>,
+    /// The ID to assign to the next keyboard added to the set.
+    next_id: usize,
+    /// The index of the keyboard to poll first.
+    next_poll: usize,
+    dedup: Dedup,
+}
+
+/// Drops the duplicate events of aliased keyboards (see [`KeyboardSet::dedup_aliases`]).
+#[derive(Default)]
+struct Dedup {
+    /// How far apart the duplicate events can be, if they are dropped.
+    window: Option<Duration>,
+    /// The events yielded within the window, oldest first.
+    recent: VecDeque<TaggedKeyEvent>,
+}
+
+impl KeyboardSet {
+    /// Create a set containing the specified keyboards.
+    pub fn new(keyboards: impl IntoIterator<Item = KeyboardDevice>) -> Self {
+        let mut set = Self::default();
+
+        for keyboard in keyboards {
+            set.insert(keyboard);
+        }
+
+        set
+    }
+
+    /// Add a keyboard to the set, returning the ID assigned to it.
+    pub fn insert(&mut self, keyboard: KeyboardDevice) -> DeviceId {
+        let id = DeviceId(self.next_id);
+
+        self.next_id += 1;
+        trace::membership(id, keyboard.path(), true);
+        self.contexts
+            .insert(id, Arc::new(DeviceContext::new(id, &keyboard)));
+        self.keyboards.push((id, keyboard));
+
+        id
+    }
+
+    /// Remove the keyboard with the specified ID from the set.
+    pub fn remove(&mut self, id: DeviceId) -> Option<KeyboardDevice> {
+        let pos = self.keyboards.iter().position(|(i, _)| *i == id)?;
+        let keyboard = self.keyboards.remove(pos).1;
+
+        trace::membership(id, keyboard.path(), false);
+        self.contexts.remove(&id);
+
+        Some(keyboard)
+    }
+
+    /// The keyboard with the specified ID.
+    pub fn get(&self, id: DeviceId) -> Option<&KeyboardDevice> {
+        self.iter().find(|(i, _)| *i == id).map(|(_, k)| k)
+    }
+
+    /// The keyboard with the specified ID.
+    pub fn get_mut(&mut self, id: DeviceId) -> Option<&mut KeyboardDevice> {
+        self.keyboards
+            .iter_mut()
+            .find(|(i, _)| *i == id)
+            .map(|(_, k)| k)
+    }
+
+    /// The ID of the keyboard with the specified path.
+    pub fn device_id(&self, path: impl AsRef<Path>) -> Option<DeviceId> {
+        self.iter()
+            .find(|(_, k)| k.path() == path.as_ref())
+            .map(|(id, _)| id)
+    }
+
+    /// The context of the keyboard with the specified ID (see [`DeviceContext`]).
+    pub fn context(&self, id: DeviceId) -> Option<Arc<DeviceContext>> {
+        self.contexts.get(&id).cloned()
+    }
+
+    /// A [`Stream`] of the events of the keyboards, along with the context of the keyboard that
+    /// produced each of them, which is what [`dispatch`](crate::handler::dispatch) consumes.
+    pub fn with_context(&mut self) -> WithContext<'_> {
+        WithContext { keyboards: self }
+    }
+
+    /// An iterator over the keyboards in the set, and their IDs.
+    pub fn iter(&self) -> impl Iterator<Item = (DeviceId, &KeyboardDevice)> {
+        self.keyboards.iter().map(|(id, k)| (*id, k))
+    }
+
+    /// Pause all the keyboards in the set (see [`KeyboardDevice::pause`]).
+    pub fn pause_all(&mut self) {
+        for (_, keyboard) in &mut self.keyboards {
+            keyboard.pause();
+        }
+    }
+
+    /// Resume all the keyboards in the set (see [`KeyboardDevice::resume`]).
+    pub fn resume_all(&mut self) -> KeyloggerResult<()> {
+        for (_, keyboard) in &mut self.keyboards {
+            keyboard.resume()?;
+        }
+
+        Ok(())
+    }
+
+    /// The groups of keyboards that are exposed by the same piece of hardware.
+    ///
+    /// Keyboards are considered aliases if they have the same bus type, vendor and product, and
+    /// either the same unique identifier, or the same physical location, disregarding the
+    /// interface (e.g. `usb-0000:00:14.0-1/input0` and `usb-0000:00:14.0-1/input1`). Only the
+    /// groups of at least two keyboards are returned.
+    pub fn aliases(&self) -> Vec<Vec<DeviceId>> {
+        let mut groups: Vec<Vec<DeviceId>> = vec![];
+
+        for (id, keyboard) in self.iter() {
+            let group = groups.iter_mut().find(|group| {
+                self.get(group[0])
+                    .is_some_and(|k| are_aliases(k.info(), keyboard.info()))
+            });
+
+            match group {
+                Some(group) => group.push(id),
+                None => groups.push(vec![id]),
+            }
+        }
+
+        groups.retain(|group| group.len() > 1);
+        groups
+    }
+
+    /// Drop the events of a keyboard that duplicate an event of one of its aliases (see
+    /// [`KeyboardSet::aliases`]) reported at most `window` earlier.
+    ///
+    /// Two events are duplicates if they have the same key code and cause. Each event can only be
+    /// duplicated once, so a key pressed twice in a row on the same keyboard is still reported
+    /// twice. A few milliseconds are usually enough, as the aliases report their events at the
+    /// same time.
+    pub fn dedup_aliases(&mut self, window: Duration) {
+        self.dedup.window = Some(window);
+    }
+
+    /// Split the set into its keyboards, so they can be read separately, and a [`Contexts`] that
+    /// adds the contexts of the keyboards to their events.
+    pub(crate) fn split_keyboards(
+        &mut self,
+    ) -> (
+        impl Iterator<Item = (DeviceId, &mut KeyboardDevice)>,
+        Contexts<'_>,
+    ) {
+        let keyboards = self.keyboards.iter_mut().map(|(id, k)| (*id, k));
+        let contexts = Contexts {
+            contexts: &self.contexts,
+            dedup: &mut self.dedup,
+        };
+
+        (keyboards, contexts)
+    }
+
+    /// The number of keyboards in the set.
+    pub fn len(&self) -> usize {
+        self.keyboards.len()
+    }
+
+    /// Whether the set contains no keyboards.
+    pub fn is_empty(&self) -> bool {
+        self.keyboards.is_empty()
+    }
+}
+
+impl Stream for KeyboardSet {
+    type Item = KeyloggerResult<TaggedKeyEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let mut i = 0;
+
+        while i < this.keyboards.len() {
+            let idx = (this.next_poll + i) % this.keyboards.len();
+            let (id, keyboard) = &mut this.keyboards[idx];
+
+            match Pin::new(&mut *keyboard).poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    let device_id = *id;
+                    let batch = keyboard.batch_info();
+                    let item = item.map(|event| TaggedKeyEvent {
+                        device_id,
+                        event,
+                        batch,
+                    });
+
+                    if item
+                        .as_ref()
+                        .is_ok_and(|ev| this.dedup.is_duplicate(ev, &this.contexts))
+                    {
+                        // Poll the same keyboard again
+                        continue;
+                    }
+
+                    this.next_poll = idx + 1;
+
+                    return Poll::Ready(Some(item));
+                }
+                Poll::Ready(None) => {
+                    let (id, _) = this.keyboards.remove(idx);
+
+                    this.contexts.remove(&id);
+                }
+                Poll::Pending => i += 1,
+            }
+        }
+
+        if this.keyboards.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// A [`Stream`] of the events of a [`KeyboardSet`], and the contexts of their keyboards (see
+/// [`KeyboardSet::with_context`]).
+pub struct WithContext<'a> {
+    keyboards: &'a mut KeyboardSet,
+}
+
+impl Stream for WithContext<'_> {
+    type Item = KeyloggerResult<(TaggedKeyEvent, Arc<DeviceContext>)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let keyboards = &mut *self.get_mut().keyboards;
+
+        Pin::new(&mut *keyboards).poll_next(cx).map(|item| {
+            item.map(|res| {
+                res.map(|ev| {
+                    // The keyboard that produced the event is still in the set
+                    let ctx = Arc::clone(&keyboards.contexts[&ev.device_id]);
+
+                    (ev, ctx)
+                })
+            })
+        })
+    }
+}
+
+/// Adds the contexts of the keyboards of a [`KeyboardSet`] to the events they produced, while the
+/// keyboards are read separately (see [`KeyboardSet::split_keyboards`]).
+pub(crate) struct Contexts<'a> {
+    contexts: &'a HashMap<DeviceId, Arc<DeviceContext>>,
+    dedup: &'a mut Dedup,
+}
+
+impl Contexts<'_> {
+    /// Add the context of its keyboard to the specified event, unless it duplicates an event of
+    /// an alias of the keyboard (see [`KeyboardSet::dedup_aliases`]).
+    pub(crate) fn add(
+        &mut self,
+        ev: TaggedKeyEvent,
+    ) -> Option<(TaggedKeyEvent, Arc<DeviceContext>)> {
+        if self.dedup.is_duplicate(&ev, self.contexts) {
+            return None;
+        }
+
+        // The keyboards aren't removed from the set while they're read separately
+        Some((ev, Arc::clone(&self.contexts[&ev.device_id])))
+    }
+}
+
+impl Dedup {
+    /// Whether the specified event duplicates a recent event of an alias of its keyboard.
+    fn is_duplicate(
+        &mut self,
+        ev: &TaggedKeyEvent,
+        contexts: &HashMap<DeviceId, Arc<DeviceContext>>,
+    ) -> bool {
+        let Some(window) = self.window else {
+            return false;
+        };
+
+        let window = chrono::Duration::from_std(window).unwrap_or(chrono::Duration::MAX);
+
+        self.recent
+            .retain(|recent| (ev.event.ts - recent.event.ts).abs() <= window);
+
+        let info = |id| contexts.get(&id).map(|ctx| ctx.info());
+        let duplicate = self.recent.iter().position(|recent| {
+            recent.device_id != ev.device_id
+                && (recent.event.code, recent.event.cause) == (ev.event.code, ev.event.cause)
+                && info(recent.device_id)
+                    .zip(info(ev.device_id))
+                    .is_some_and(|(a, b)| are_aliases(a, b))
+        });
+
+        match duplicate {
+            Some(pos) => {
+                self.recent.remove(pos);
+                true
+            }
+            None => {
+                self.recent.push_back(*ev);
+                false
+            }
+        }
+    }
+}
+
+/// Whether two devices are exposed by the same piece of hardware (see [`KeyboardSet::aliases`]).
+fn are_aliases(a: &DeviceInfo, b: &DeviceInfo) -> bool {
+    if (a.bus_type, a.vendor, a.product) != (b.bus_type, b.vendor, b.product) {
+        return false;
+    }
+
+    match (&a.uniq, &b.uniq) {
+        (Some(a), Some(b)) if !a.is_empty() => a == b,
+        _ => location(a).is_some_and(|l| !l.is_empty() && Some(l) == location(b)),
+    }
+}
+
+/// The physical location of a device, without the interface (e.g. `usb-0000:00:14.0-1` for
+/// `usb-0000:00:14.0-1/input0`).
+fn location(info: &DeviceInfo) -> Option<&str> {
+    let phys = info.phys.as_deref()?;
+
+    Some(match phys.rsplit_once('/') {
+        Some((location, interface)) if interface.starts_with("input") => location,
+        _ => phys,
+    })
+}
+
+/// Auto-detect the keyboard devices to watch, and merge their events into a single stream.
+pub fn merge_keyboards() -> KeyloggerResult<KeyboardSet> {
+    Ok(KeyboardSet::new(find_keyboards()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::device::tests::{key, pipe, write_events, REPORT};
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn tagged_events() {
+        let (rx1, tx1) = pipe();
+        let (rx2, tx2) = pipe();
+        let mut keyboards = KeyboardSet::new([
+            KeyboardDevice::fake("Keyboard 1", Path::new("/dev/input/event1"), rx1),
+            KeyboardDevice::fake("Keyboard 2", Path::new("/dev/input/event2"), rx2),
+        ]);
+        let id1 = keyboards.device_id("/dev/input/event1").unwrap();
+        let id2 = keyboards.device_id("/dev/input/event2").unwrap();
+
+        assert_ne!(id1, id2);
+
+        write_events(&tx1, &[key(KeyCode::KEY_A, 1), REPORT]);
+        write_events(&tx1, &[key(KeyCode::KEY_A, 0), REPORT]);
+        write_events(&tx2, &[key(KeyCode::KEY_B, 1), REPORT]);
+
+        // The keyboards take turns
+        let evs = keyboards
+            .with_context()
+            .take(3)
+            .map(|res| {
+                let (ev, ctx) = res.unwrap();
+
+                assert_eq!(ctx.id(), ev.device_id);
+
+                (ev.device_id, ctx.name().to_owned(), ev.event.code)
+            })
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            evs,
+            [
+                (id1, "Keyboard 1".to_owned(), KeyCode::KEY_A),
+                (id2, "Keyboard 2".to_owned(), KeyCode::KEY_B),
+                (id1, "Keyboard 1".to_owned(), KeyCode::KEY_A),
+            ]
+        );
+    }
+
+    #[test]
+    fn aliased_devices() {
+        let info = |phys: &str, uniq: &str| DeviceInfo {
+            bus_type: 3,
+            vendor: 0x1532,
+            product: 0x0203,
+            version: 0x111,
+            phys: Some(phys.into()),
+            uniq: Some(uniq.into()),
+            seat: None,
+        };
+
+        let main = info("usb-0000:00:14.0-3/input0", "");
+        let secondary = info("usb-0000:00:14.0-3/input2", "");
+        let other_port = info("usb-0000:00:14.0-4/input0", "");
+
+        assert!(are_aliases(&main, &secondary));
+        assert!(!are_aliases(&main, &other_port));
+        assert!(!are_aliases(&info("", ""), &info("", "")));
+        assert!(are_aliases(
+            &info("usb-0000:00:14.0-3/input0", "SN1"),
+            &info("usb-0000:00:14.0-4/input1", "SN1")
+        ));
+        assert!(!are_aliases(
+            &info("usb-0000:00:14.0-3/input0", "SN1"),
+            &info("usb-0000:00:14.0-3/input1", "SN2")
+        ));
+        assert!(!are_aliases(
+            &main,
+            &DeviceInfo {
+                product: 0x0204,
+                ..secondary
+            }
+        ));
+    }
+}
@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+/// The directory where udev stores the properties of the devices it manages.
+const UDEV_DATA_DIR: &str = "/run/udev/data";
+
+/// Check whether udev has tagged the specified device as a keyboard (`ID_INPUT_KEYBOARD=1`).
+#[cfg(feature = "udev")]
+pub(crate) fn is_udev_keyboard(device: &Path) -> bool {
+    read_udev_properties(device)
+        .map(|props| props.get("ID_INPUT_KEYBOARD").map(String::as_str) == Some("1"))
+        .unwrap_or(false)
+}
+
+/// Read the udev properties of the specified character device.
+///
+/// The properties are read from the udev database (the same source `libudev` uses), which
+/// contains one `E:<key>=<value>` line for each property.
+pub(crate) fn read_udev_properties(device: &Path) -> io::Result<HashMap<String, String>> {
+    let rdev = fs::metadata(device)?.rdev();
+    let (major, minor) = (libc::major(rdev), libc::minor(rdev));
+    let db = fs::read_to_string(Path::new(UDEV_DATA_DIR).join(format!("c{major}:{minor}")))?;
+
+    Ok(db
+        .lines()
+        .filter_map(|line| line.strip_prefix("E:")?.split_once('='))
+        .map(|(k, v)| (k.into(), v.into()))
+        .collect())
+}
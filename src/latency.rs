@@ -0,0 +1,299 @@
+//! Measure the latency of the capture of key events.
+//!
+//! A [`LatencyTest`] creates a [`VirtualKeyboard`], injects key presses into it, and measures how
+//! long it takes for each press to come out of the capture pipeline. Each [`LatencySample`] is
+//! split into the time the kernel took to timestamp the injected event, and the time it took to
+//! deliver the timestamped event through the pipeline. This makes it possible to notice (and
+//! report) the regressions in the latency of the crate, or of the adapters applied to the events:
+//!
+//! ```no_run
+//! use keylogger::filters::KeyEventStreamExt;
+//! use keylogger::latency::LatencyTest;
+//! use keylogger::KeyloggerError;
+//! use std::time::Duration;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let report = LatencyTest::new()
+//!         .samples(1000)
+//!         .run_with(|keyboard| keyboard.debounce(Duration::from_millis(5)))
+//!         .await?;
+//!
+//!     println!("{:?}", report.total());
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Creating the virtual keyboard requires write access to `/dev/uinput`, and reading from it
+//! requires read access to the input devices (see [`VirtualKeyboard`]). The virtual keyboard is
+//! grabbed, so the injected presses don't reach the other applications.
+
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use futures::{Stream, StreamExt};
+
+use crate::key_code::KeyCode;
+use crate::keyboard::{Clock, KeyEvent, KeyEventCause, KeyboardDevice, KeyboardFinder};
+use crate::timer::sleep;
+use crate::uinput::VirtualKeyboard;
+use crate::KeyloggerResult;
+
+/// The `EV_SYN` and `EV_KEY` event types, which are the only ones supported by a
+/// [`VirtualKeyboard`].
+const VIRTUAL_KEYBOARD_FLAGS: u32 = (1 << 0x00) | (1 << 0x01);
+/// How long to wait for the device of the virtual keyboard to appear.
+const DEVICE_TIMEOUT: Duration = Duration::from_secs(2);
+/// How often to look for the device of the virtual keyboard, while waiting for it to appear.
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The number of virtual keyboards created by this process, used to name them uniquely.
+static VIRTUAL_KEYBOARDS: AtomicUsize = AtomicUsize::new(0);
+
+/// The latency of an injected key press.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct LatencySample {
+    /// The time elapsed between the injection of the press and its timestamp.
+    pub kernel: Duration,
+    /// The time elapsed between the timestamp of the press and the pipeline yielding it.
+    pub delivery: Duration,
+}
+
+impl LatencySample {
+    /// The time elapsed between the injection of the press and the pipeline yielding it.
+    pub fn total(&self) -> Duration {
+        self.kernel + self.delivery
+    }
+}
+
+/// The distribution of a set of latencies.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct LatencyStats {
+    /// The lowest latency.
+    pub min: Duration,
+    /// The average latency.
+    pub mean: Duration,
+    /// The median latency.
+    pub p50: Duration,
+    /// The 90th percentile of the latencies.
+    pub p90: Duration,
+    /// The 99th percentile of the latencies.
+    pub p99: Duration,
+    /// The highest latency.
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    /// The distribution of the specified latencies (all zero if there are none).
+    pub fn new(latencies: impl IntoIterator<Item = Duration>) -> Self {
+        let mut latencies = latencies.into_iter().collect::<Vec<_>>();
+
+        if latencies.is_empty() {
+            return Self::default();
+        }
+
+        latencies.sort();
+
+        let sum = latencies.iter().sum::<Duration>();
+
+        Self {
+            min: latencies[0],
+            mean: sum / latencies.len() as u32,
+            p50: percentile(&latencies, 50),
+            p90: percentile(&latencies, 90),
+            p99: percentile(&latencies, 99),
+            max: latencies[latencies.len() - 1],
+        }
+    }
+}
+
+/// The latencies measured by a [`LatencyTest`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LatencyReport {
+    /// The latencies of the injected presses, in the order they were injected.
+    pub samples: Vec<LatencySample>,
+}
+
+impl LatencyReport {
+    /// The distribution of the end-to-end latencies (see [`LatencySample::total`]).
+    pub fn total(&self) -> LatencyStats {
+        LatencyStats::new(self.samples.iter().map(LatencySample::total))
+    }
+
+    /// The distribution of the time the kernel took to timestamp the presses.
+    pub fn kernel(&self) -> LatencyStats {
+        LatencyStats::new(self.samples.iter().map(|s| s.kernel))
+    }
+
+    /// The distribution of the time the pipeline took to deliver the presses.
+    pub fn delivery(&self) -> LatencyStats {
+        LatencyStats::new(self.samples.iter().map(|s| s.delivery))
+    }
+}
+
+/// A latency measurement harness (see the [module docs](self)).
+#[derive(Clone, Debug)]
+pub struct LatencyTest {
+    samples: usize,
+    interval: Duration,
+    key: KeyCode,
+}
+
+impl Default for LatencyTest {
+    fn default() -> Self {
+        Self {
+            samples: 100,
+            interval: Duration::from_millis(10),
+            key: KeyCode::KEY_F24,
+        }
+    }
+}
+
+impl LatencyTest {
+    /// Create a test that injects 100 presses of `F24`, 10ms apart.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The number of presses to inject.
+    pub fn samples(mut self, samples: usize) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// The time to wait after each press is received, before injecting the next one.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// The key to press.
+    pub fn key(mut self, key: KeyCode) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// Measure the latency of reading the events straight from the keyboard.
+    pub async fn run(&self) -> KeyloggerResult<LatencyReport> {
+        self.run_with(|keyboard| keyboard).await
+    }
+
+    /// Measure the latency of the pipeline built by `pipeline` on top of the virtual keyboard.
+    ///
+    /// The pipeline must yield the presses of the key (along with any other events). The
+    /// timestamps of the events it yields are expected to be the ones assigned by the keyboard,
+    /// which uses [`Clock::Monotonic`].
+    pub async fn run_with<F, S>(&self, pipeline: F) -> KeyloggerResult<LatencyReport>
+    where
+        F: FnOnce(KeyboardDevice) -> S,
+        S: Stream<Item = KeyloggerResult<KeyEvent>> + Unpin,
+    {
+        let n = VIRTUAL_KEYBOARDS.fetch_add(1, Ordering::Relaxed);
+        let name = format!("keylogger latency test {}-{n}", std::process::id());
+        let mut virtual_keyboard = VirtualKeyboard::new(&name)?;
+        let mut keyboard = find_virtual_keyboard(&name).await?;
+
+        keyboard.grab()?;
+        keyboard.set_clock(Clock::Monotonic)?;
+
+        let mut events = pipeline(keyboard);
+        let mut report = LatencyReport::default();
+
+        for _ in 0..self.samples {
+            let injected = Clock::Monotonic.now();
+
+            virtual_keyboard.tap(self.key)?;
+
+            let ev = loop {
+                let ev = events.next().await.ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "the pipeline ended before yielding the injected press",
+                    )
+                })??;
+
+                if ev.cause == KeyEventCause::Press && ev.code == self.key {
+                    break ev;
+                }
+            };
+
+            let received = Clock::Monotonic.now();
+            let timestamped = ev.clock_time();
+
+            report.samples.push(LatencySample {
+                kernel: timestamped.saturating_sub(injected),
+                delivery: received.saturating_sub(timestamped),
+            });
+
+            sleep(self.interval).await?;
+        }
+
+        Ok(report)
+    }
+}
+
+/// Open the device of the virtual keyboard with the specified name, waiting for it to appear.
+async fn find_virtual_keyboard(name: &str) -> KeyloggerResult<KeyboardDevice> {
+    let finder = KeyboardFinder::new()
+        .name(&format!("^{}$", regex::escape(name)))
+        .event_types(VIRTUAL_KEYBOARD_FLAGS);
+    let attempts = DEVICE_TIMEOUT.as_millis() / DEVICE_POLL_INTERVAL.as_millis();
+
+    for _ in 0..attempts {
+        if let Some(keyboard) = finder.find()?.pop() {
+            return Ok(keyboard);
+        }
+
+        sleep(DEVICE_POLL_INTERVAL).await?;
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "the device of the virtual keyboard didn't appear",
+    )
+    .into())
+}
+
+/// The latency below which the specified percentage of the sorted latencies fall (using the
+/// nearest-rank method).
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+
+    sorted[rank - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_stats() {
+        let ms = Duration::from_millis;
+        let stats = LatencyStats::new((1..=100).rev().map(ms));
+
+        assert_eq!(stats.min, ms(1));
+        assert_eq!(stats.mean, Duration::from_micros(50_500));
+        assert_eq!(stats.p50, ms(50));
+        assert_eq!(stats.p90, ms(90));
+        assert_eq!(stats.p99, ms(99));
+        assert_eq!(stats.max, ms(100));
+
+        let stats = LatencyStats::new([ms(3)]);
+
+        assert_eq!((stats.min, stats.p50, stats.p99), (ms(3), ms(3), ms(3)));
+        assert_eq!(LatencyStats::new([]), LatencyStats::default());
+
+        let report = LatencyReport {
+            samples: vec![LatencySample {
+                kernel: ms(1),
+                delivery: ms(2),
+            }],
+        };
+
+        assert_eq!(report.total().max, ms(3));
+        assert_eq!(report.kernel().max, ms(1));
+        assert_eq!(report.delivery().max, ms(2));
+    }
+}
@@ -0,0 +1,633 @@
+mod builtin;
+#[cfg(feature = "xkb")]
+mod xkb;
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::error::KeyloggerError;
+use crate::key_code::KeyCode;
+use crate::keyboard::{KeyEvent, KeyEventCause};
+use crate::KeyloggerResult;
+
+pub use builtin::BuiltinLayout;
+#[cfg(feature = "xkb")]
+pub use xkb::{XkbNames, XkbTranslator};
+
+/// The state of the modifier keys that affect the character produced by a key.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Modifiers {
+    /// Whether either Shift key is held.
+    pub shift: bool,
+    /// Whether either Ctrl key is held.
+    pub ctrl: bool,
+    /// Whether the left Alt key is held.
+    pub alt: bool,
+    /// Whether the right Alt (AltGr) key is held.
+    pub alt_gr: bool,
+    /// Whether Caps Lock is on.
+    pub caps_lock: bool,
+    /// Whether NumLock is on.
+    pub num_lock: bool,
+}
+
+impl Modifiers {
+    /// Update the modifier state based on the specified event.
+    ///
+    /// Returns `true` if `ev` was caused by a modifier key.
+    pub fn update(&mut self, ev: &KeyEvent) -> bool {
+        use KeyCode::*;
+
+        let pressed = match ev.cause {
+            KeyEventCause::Press => true,
+            KeyEventCause::Release => false,
+            // Holding a modifier down doesn't change its state
+            KeyEventCause::Repeat => {
+                return matches!(
+                    ev.code,
+                    KEY_LEFTSHIFT
+                        | KEY_RIGHTSHIFT
+                        | KEY_LEFTCTRL
+                        | KEY_RIGHTCTRL
+                        | KEY_LEFTALT
+                        | KEY_RIGHTALT
+                        | KEY_CAPSLOCK
+                        | KEY_NUMLOCK
+                )
+            }
+        };
+
+        match ev.code {
+            KEY_LEFTSHIFT | KEY_RIGHTSHIFT => self.shift = pressed,
+            KEY_LEFTCTRL | KEY_RIGHTCTRL => self.ctrl = pressed,
+            KEY_LEFTALT => self.alt = pressed,
+            KEY_RIGHTALT => self.alt_gr = pressed,
+            KEY_CAPSLOCK => {
+                if pressed {
+                    self.caps_lock = !self.caps_lock;
+                }
+            }
+            KEY_NUMLOCK => {
+                if pressed {
+                    self.num_lock = !self.num_lock;
+                }
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// The key the specified key acts as in this state.
+    ///
+    /// The digit keys of the keypad act as navigation keys (e.g. `KEY_KP7` acts as `KEY_HOME`)
+    /// while NumLock is off, or while Shift is held and NumLock is on. The other keys are returned
+    /// unchanged.
+    pub fn keypad_key(&self, code: KeyCode) -> KeyCode {
+        use KeyCode::*;
+
+        if !is_num_lock_key(code) || self.num_lock != self.shift {
+            return code;
+        }
+
+        match code {
+            KEY_KP0 => KEY_INSERT,
+            KEY_KP1 => KEY_END,
+            KEY_KP2 => KEY_DOWN,
+            KEY_KP3 => KEY_PAGEDOWN,
+            KEY_KP4 => KEY_LEFT,
+            KEY_KP6 => KEY_RIGHT,
+            KEY_KP7 => KEY_HOME,
+            KEY_KP8 => KEY_UP,
+            KEY_KP9 => KEY_PAGEUP,
+            KEY_KPDOT => KEY_DELETE,
+            // The middle key doesn't do anything
+            code => code,
+        }
+    }
+}
+
+/// Whether the specified key only produces a symbol while NumLock is on (see
+/// [`Modifiers::keypad_key`]).
+fn is_num_lock_key(code: KeyCode) -> bool {
+    use KeyCode::*;
+
+    matches!(
+        code,
+        KEY_KP0
+            | KEY_KP1
+            | KEY_KP2
+            | KEY_KP3
+            | KEY_KP4
+            | KEY_KP5
+            | KEY_KP6
+            | KEY_KP7
+            | KEY_KP8
+            | KEY_KP9
+            | KEY_KPDOT
+    )
+}
+
+/// The symbols a key produces at each shift level.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct KeySymbols {
+    /// The symbol produced when no modifiers are active.
+    pub base: Option<char>,
+    /// The symbol produced while Shift is held.
+    pub shift: Option<char>,
+    /// The symbol produced while AltGr is held.
+    pub alt_gr: Option<char>,
+    /// The symbol produced while both Shift and AltGr are held.
+    pub shift_alt_gr: Option<char>,
+    /// Whether Caps Lock has the same effect as Shift on this key.
+    pub caps_lock: bool,
+}
+
+impl KeySymbols {
+    /// Create the symbols of a key with a base and a shifted level.
+    ///
+    /// Caps Lock affects the key if its base symbol is alphabetic.
+    pub fn new(base: char, shift: char) -> Self {
+        Self {
+            base: Some(base),
+            shift: Some(shift),
+            alt_gr: None,
+            shift_alt_gr: None,
+            caps_lock: base.is_alphabetic(),
+        }
+    }
+
+    /// Set the symbols produced while AltGr is held.
+    pub fn with_alt_gr(mut self, alt_gr: char, shift_alt_gr: Option<char>) -> Self {
+        self.alt_gr = Some(alt_gr);
+        self.shift_alt_gr = shift_alt_gr;
+        self
+    }
+
+    /// The symbol produced by the key when the specified modifiers are active.
+    fn resolve(&self, modifiers: &Modifiers) -> Option<char> {
+        let shift = modifiers.shift ^ (self.caps_lock && modifiers.caps_lock);
+
+        match (shift, modifiers.alt_gr) {
+            (false, false) => self.base,
+            (true, false) => self.shift.or(self.base),
+            (false, true) => self.alt_gr,
+            (true, true) => self.shift_alt_gr.or(self.alt_gr),
+        }
+    }
+}
+
+/// A keyboard layout, mapping [`KeyCode`]s to the symbols they produce.
+///
+/// The built-in layouts can be selected using [`BuiltinLayout`]. Other layouts can be loaded from
+/// a file (see [`Layout::from_file`]) or constructed programmatically using [`Layout::insert`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Layout {
+    keys: HashMap<KeyCode, KeySymbols>,
+    /// The symbols that are produced by dead keys, and the keys that produce them.
+    dead_keys: HashSet<(KeyCode, char)>,
+}
+
+impl Layout {
+    /// Create an empty layout.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The US QWERTY layout.
+    pub fn us_qwerty() -> Self {
+        use KeyCode::*;
+
+        let mut layout = Self::new();
+        let keys = [
+            (KEY_GRAVE, '`', '~'),
+            (KEY_1, '1', '!'),
+            (KEY_2, '2', '@'),
+            (KEY_3, '3', '#'),
+            (KEY_4, '4', '$'),
+            (KEY_5, '5', '%'),
+            (KEY_6, '6', '^'),
+            (KEY_7, '7', '&'),
+            (KEY_8, '8', '*'),
+            (KEY_9, '9', '('),
+            (KEY_0, '0', ')'),
+            (KEY_MINUS, '-', '_'),
+            (KEY_EQUAL, '=', '+'),
+            (KEY_Q, 'q', 'Q'),
+            (KEY_W, 'w', 'W'),
+            (KEY_E, 'e', 'E'),
+            (KEY_R, 'r', 'R'),
+            (KEY_T, 't', 'T'),
+            (KEY_Y, 'y', 'Y'),
+            (KEY_U, 'u', 'U'),
+            (KEY_I, 'i', 'I'),
+            (KEY_O, 'o', 'O'),
+            (KEY_P, 'p', 'P'),
+            (KEY_LEFTBRACE, '[', '{'),
+            (KEY_RIGHTBRACE, ']', '}'),
+            (KEY_BACKSLASH, '\\', '|'),
+            (KEY_A, 'a', 'A'),
+            (KEY_S, 's', 'S'),
+            (KEY_D, 'd', 'D'),
+            (KEY_F, 'f', 'F'),
+            (KEY_G, 'g', 'G'),
+            (KEY_H, 'h', 'H'),
+            (KEY_J, 'j', 'J'),
+            (KEY_K, 'k', 'K'),
+            (KEY_L, 'l', 'L'),
+            (KEY_SEMICOLON, ';', ':'),
+            (KEY_APOSTROPHE, '\'', '"'),
+            (KEY_Z, 'z', 'Z'),
+            (KEY_X, 'x', 'X'),
+            (KEY_C, 'c', 'C'),
+            (KEY_V, 'v', 'V'),
+            (KEY_B, 'b', 'B'),
+            (KEY_N, 'n', 'N'),
+            (KEY_M, 'm', 'M'),
+            (KEY_COMMA, ',', '<'),
+            (KEY_DOT, '.', '>'),
+            (KEY_SLASH, '/', '?'),
+        ];
+
+        for (code, base, shift) in keys {
+            layout.insert(code, KeySymbols::new(base, shift));
+        }
+
+        layout.insert_common_keys();
+        layout
+    }
+
+    /// Load a layout from the specified file (see [`Layout::from_str`] for the format).
+    pub fn from_file(path: impl AsRef<Path>) -> KeyloggerResult<Self> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Set the symbols produced by the specified key.
+    pub fn insert(&mut self, code: KeyCode, symbols: KeySymbols) {
+        self.keys.insert(code, symbols);
+    }
+
+    /// The symbols produced by the specified key, if the layout maps it.
+    pub fn get(&self, code: KeyCode) -> Option<&KeySymbols> {
+        self.keys.get(&code)
+    }
+
+    /// Mark the specified symbol of a key as a dead key, which modifies the next character typed
+    /// instead of producing a character (see [`CharComposer`](crate::text::CharComposer)).
+    pub fn insert_dead_key(&mut self, code: KeyCode, symbol: char) {
+        self.dead_keys.insert((code, symbol));
+    }
+
+    /// Whether the specified symbol of a key is a dead key.
+    pub fn is_dead_key(&self, code: KeyCode, symbol: char) -> bool {
+        self.dead_keys.contains(&(code, symbol))
+    }
+
+    /// The key that produces the specified symbol without any modifiers (`false`) or while Shift
+    /// is held (`true`).
+    ///
+    /// If several keys produce the symbol, the one with the lowest code is returned (which
+    /// prefers the main block of the keyboard over the keypad).
+    #[cfg(feature = "test-util")]
+    pub(crate) fn key_for(&self, c: char) -> Option<(KeyCode, bool)> {
+        self.keys
+            .iter()
+            .filter_map(|(code, symbols)| match (symbols.base, symbols.shift) {
+                (Some(base), _) if base == c => Some((*code, false)),
+                (_, Some(shift)) if shift == c => Some((*code, true)),
+                _ => None,
+            })
+            .min_by_key(|(code, _)| code.code())
+    }
+
+    /// Map the keys whose symbols are the same in every layout (whitespace and the keypad).
+    fn insert_common_keys(&mut self) {
+        use KeyCode::*;
+
+        let keys = [
+            (KEY_SPACE, ' '),
+            (KEY_TAB, '\t'),
+            (KEY_ENTER, '\n'),
+            (KEY_KPENTER, '\n'),
+            (KEY_KP0, '0'),
+            (KEY_KP1, '1'),
+            (KEY_KP2, '2'),
+            (KEY_KP3, '3'),
+            (KEY_KP4, '4'),
+            (KEY_KP5, '5'),
+            (KEY_KP6, '6'),
+            (KEY_KP7, '7'),
+            (KEY_KP8, '8'),
+            (KEY_KP9, '9'),
+            (KEY_KPDOT, '.'),
+            (KEY_KPSLASH, '/'),
+            (KEY_KPASTERISK, '*'),
+            (KEY_KPMINUS, '-'),
+            (KEY_KPPLUS, '+'),
+        ];
+
+        for (code, c) in keys {
+            self.keys.entry(code).or_insert(KeySymbols {
+                base: Some(c),
+                ..Default::default()
+            });
+        }
+    }
+}
+
+impl FromStr for Layout {
+    type Err = KeyloggerError;
+
+    /// Parse a layout from its textual description.
+    ///
+    /// Each non-empty line that doesn't start with `#` describes a key, and consists of
+    /// whitespace-separated columns: the numeric key code, followed by the symbols produced at the
+    /// base, Shift, AltGr and Shift+AltGr levels. Trailing levels can be omitted. A symbol is
+    /// either a single character, `U+XXXX` (the hexadecimal code point of the character), or
+    /// `none`.
+    ///
+    /// The whitespace and keypad keys are mapped automatically, unless the description overrides
+    /// them.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut layout = Self::new();
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let err = |msg: &str| KeyloggerError::InvalidLayout(format!("line {}: {msg}", i + 1));
+            let mut columns = line.split_whitespace();
+
+            let code = columns
+                .next()
+                .and_then(|c| c.parse::<u16>().ok())
+                .ok_or_else(|| err("expected a key code"))?;
+            let code = KeyCode::try_from(code)?;
+
+            let mut levels = [None; 4];
+
+            for level in levels.iter_mut() {
+                if let Some(symbol) = columns.next() {
+                    *level = parse_symbol(symbol).ok_or_else(|| err("invalid symbol"))?;
+                }
+            }
+
+            if columns.next().is_some() {
+                return Err(err("too many columns"));
+            }
+
+            let [base, shift, alt_gr, shift_alt_gr] = levels;
+
+            layout.insert(
+                code,
+                KeySymbols {
+                    base,
+                    shift,
+                    alt_gr,
+                    shift_alt_gr,
+                    caps_lock: base.map(char::is_alphabetic).unwrap_or(false),
+                },
+            );
+        }
+
+        layout.insert_common_keys();
+
+        Ok(layout)
+    }
+}
+
+/// Parse a symbol column of a layout description.
+fn parse_symbol(symbol: &str) -> Option<Option<char>> {
+    if symbol == "none" {
+        return Some(None);
+    }
+
+    if let Some(hex) = symbol.strip_prefix("U+") {
+        return u32::from_str_radix(hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .map(Some);
+    }
+
+    let mut chars = symbol.chars();
+
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(Some(c)),
+        _ => None,
+    }
+}
+
+/// Translates [`KeyEvent`]s into the characters they produce according to a [`Layout`].
+#[derive(Clone, Debug)]
+pub struct KeymapTranslator {
+    layout: Layout,
+    modifiers: Modifiers,
+}
+
+impl KeymapTranslator {
+    /// Create a translator for the specified layout.
+    pub fn new(layout: Layout) -> Self {
+        Self {
+            layout,
+            modifiers: Default::default(),
+        }
+    }
+
+    /// Set the initial state of NumLock (off by default).
+    ///
+    /// The translator only learns about the changes of the state made by the key presses it's fed,
+    /// so the initial state should match the state of the keyboard (see
+    /// [`KeyboardDevice::get_leds`](crate::KeyboardDevice::get_leds)).
+    pub fn with_num_lock(mut self, on: bool) -> Self {
+        self.modifiers.num_lock = on;
+        self
+    }
+
+    /// The current modifier state.
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    /// The layout used to translate the keys.
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// Translate a key code into the character it produces when the specified modifiers are
+    /// active.
+    ///
+    /// Returns `None` if the layout doesn't map the key, if Ctrl or Alt is held (in which case
+    /// the key is part of a shortcut rather than text), or if the key is a keypad key that acts as
+    /// a navigation key (see [`Modifiers::keypad_key`]).
+    pub fn translate(&self, code: KeyCode, modifiers: &Modifiers) -> Option<char> {
+        if modifiers.ctrl || modifiers.alt {
+            return None;
+        }
+
+        if is_num_lock_key(code) {
+            // Shift reverses the effect of NumLock instead of selecting the shifted symbol
+            return (modifiers.num_lock != modifiers.shift)
+                .then(|| self.layout.get(code)?.base)
+                .flatten();
+        }
+
+        self.layout.get(code)?.resolve(modifiers)
+    }
+
+    /// Process the next event, returning the character it produces (if any).
+    ///
+    /// This keeps track of the state of the modifier keys, so all events (including releases)
+    /// should be fed to the translator in order.
+    pub fn feed(&mut self, ev: &KeyEvent) -> Option<char> {
+        if self.modifiers.update(ev) || ev.cause == KeyEventCause::Release {
+            return None;
+        }
+
+        self.translate(ev.code, &self.modifiers)
+    }
+}
+
+impl Default for KeymapTranslator {
+    fn default() -> Self {
+        Self::new(Layout::us_qwerty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ev(cause: KeyEventCause, code: KeyCode) -> KeyEvent {
+        KeyEvent {
+            ts: Default::default(),
+            cause,
+            code,
+            scancode: None,
+        }
+    }
+
+    fn type_keys(translator: &mut KeymapTranslator, evs: &[(KeyEventCause, KeyCode)]) -> String {
+        evs.iter()
+            .filter_map(|(cause, code)| translator.feed(&ev(*cause, *code)))
+            .collect()
+    }
+
+    #[test]
+    fn us_qwerty_modifiers() {
+        use KeyCode::*;
+        use KeyEventCause::*;
+
+        let mut translator = KeymapTranslator::default();
+        let typed = type_keys(
+            &mut translator,
+            &[
+                (Press, KEY_H),
+                (Release, KEY_H),
+                (Press, KEY_LEFTSHIFT),
+                (Press, KEY_I),
+                (Press, KEY_1),
+                (Release, KEY_LEFTSHIFT),
+                (Press, KEY_CAPSLOCK),
+                (Release, KEY_CAPSLOCK),
+                (Press, KEY_O),
+                (Press, KEY_2),
+                (Press, KEY_LEFTCTRL),
+                (Press, KEY_C),
+                (Release, KEY_LEFTCTRL),
+                (Press, KEY_SPACE),
+            ],
+        );
+
+        assert_eq!(typed, "hI!O2 ");
+    }
+
+    #[test]
+    fn num_lock() {
+        use KeyCode::*;
+        use KeyEventCause::*;
+
+        let mut translator = KeymapTranslator::default();
+        let typed = type_keys(
+            &mut translator,
+            &[
+                (Press, KEY_KP1),
+                (Press, KEY_NUMLOCK),
+                (Release, KEY_NUMLOCK),
+                (Press, KEY_KP1),
+                (Press, KEY_KPDOT),
+                (Press, KEY_KPPLUS),
+                (Press, KEY_LEFTSHIFT),
+                (Press, KEY_KP2),
+                (Release, KEY_LEFTSHIFT),
+                (Press, KEY_KP3),
+            ],
+        );
+
+        assert_eq!(typed, "1.+3");
+
+        let num_lock = Modifiers {
+            num_lock: true,
+            ..Default::default()
+        };
+
+        assert_eq!(Modifiers::default().keypad_key(KEY_KP7), KEY_HOME);
+        assert_eq!(Modifiers::default().keypad_key(KEY_KPPLUS), KEY_KPPLUS);
+        assert_eq!(num_lock.keypad_key(KEY_KP7), KEY_KP7);
+
+        let translator = KeymapTranslator::default().with_num_lock(true);
+
+        assert_eq!(translator.modifiers(), num_lock);
+        assert_eq!(translator.translate(KEY_KP0, &num_lock), Some('0'));
+    }
+
+    #[test]
+    fn parse_layout() {
+        use KeyCode::*;
+
+        let layout: Layout = "
+            # A partial AZERTY layout
+            16 a A
+            30 q Q
+            3 é 2 ~ U+00B2
+            17 z Z none
+        "
+        .parse()
+        .unwrap();
+
+        let translator = KeymapTranslator::new(layout);
+        let shift = Modifiers {
+            shift: true,
+            ..Default::default()
+        };
+        let alt_gr = Modifiers {
+            alt_gr: true,
+            ..Default::default()
+        };
+        let caps_lock = Modifiers {
+            caps_lock: true,
+            ..Default::default()
+        };
+
+        assert_eq!(translator.translate(KEY_Q, &Default::default()), Some('a'));
+        assert_eq!(translator.translate(KEY_A, &caps_lock), Some('Q'));
+        assert_eq!(translator.translate(KEY_2, &shift), Some('2'));
+        assert_eq!(translator.translate(KEY_2, &caps_lock), Some('2'));
+        assert_eq!(translator.translate(KEY_2, &alt_gr), Some('~'));
+        assert_eq!(translator.translate(KEY_W, &alt_gr), None);
+        assert_eq!(translator.translate(KEY_SPACE, &shift), Some(' '));
+        assert_eq!(translator.translate(KEY_B, &Default::default()), None);
+    }
+
+    #[test]
+    fn parse_invalid_layout() {
+        assert!("16 a A b c d".parse::<Layout>().is_err());
+        assert!("KEY_A a A".parse::<Layout>().is_err());
+        assert!("16 ab".parse::<Layout>().is_err());
+    }
+}
@@ -0,0 +1,387 @@
+use std::str::FromStr;
+
+use crate::error::KeyloggerError;
+use crate::key_code::KeyCode;
+use crate::layout::{KeySymbols, Layout};
+
+/// The keyboard layouts bundled with the crate.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum BuiltinLayout {
+    /// The US QWERTY layout (see [`Layout::us_qwerty`]).
+    #[default]
+    UsQwerty,
+    /// The US Dvorak layout (see [`Layout::dvorak`]).
+    Dvorak,
+    /// The Colemak layout (see [`Layout::colemak`]).
+    Colemak,
+    /// The French AZERTY layout (see [`Layout::french_azerty`]).
+    FrenchAzerty,
+    /// The German QWERTZ layout (see [`Layout::german_qwertz`]).
+    GermanQwertz,
+}
+
+impl BuiltinLayout {
+    /// All the bundled layouts.
+    pub const ALL: [Self; 5] = [
+        Self::UsQwerty,
+        Self::Dvorak,
+        Self::Colemak,
+        Self::FrenchAzerty,
+        Self::GermanQwertz,
+    ];
+
+    /// The name of the layout, which is also accepted by its [`FromStr`] implementation.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::UsQwerty => "us",
+            Self::Dvorak => "dvorak",
+            Self::Colemak => "colemak",
+            Self::FrenchAzerty => "fr",
+            Self::GermanQwertz => "de",
+        }
+    }
+
+    /// The symbols of the layout.
+    pub fn layout(&self) -> Layout {
+        match self {
+            Self::UsQwerty => Layout::us_qwerty(),
+            Self::Dvorak => Layout::dvorak(),
+            Self::Colemak => Layout::colemak(),
+            Self::FrenchAzerty => Layout::french_azerty(),
+            Self::GermanQwertz => Layout::german_qwertz(),
+        }
+    }
+}
+
+impl FromStr for BuiltinLayout {
+    type Err = KeyloggerError;
+
+    /// Parse the name of a layout (see [`BuiltinLayout::name`]), case-insensitively.
+    ///
+    /// The names of the arrangements of the keys (`qwerty`, `azerty` and `qwertz`) are accepted
+    /// too.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "us" | "qwerty" => Self::UsQwerty,
+            "dvorak" => Self::Dvorak,
+            "colemak" => Self::Colemak,
+            "fr" | "azerty" => Self::FrenchAzerty,
+            "de" | "qwertz" => Self::GermanQwertz,
+            _ => return Err(KeyloggerError::InvalidLayout(format!("unknown layout {s}"))),
+        })
+    }
+}
+
+impl From<BuiltinLayout> for Layout {
+    fn from(layout: BuiltinLayout) -> Self {
+        layout.layout()
+    }
+}
+
+impl Layout {
+    /// The US Dvorak layout.
+    pub fn dvorak() -> Self {
+        use KeyCode::*;
+
+        Self::us_qwerty().with_keys([
+            (KEY_MINUS, '[', '{'),
+            (KEY_EQUAL, ']', '}'),
+            (KEY_Q, '\'', '"'),
+            (KEY_W, ',', '<'),
+            (KEY_E, '.', '>'),
+            (KEY_R, 'p', 'P'),
+            (KEY_T, 'y', 'Y'),
+            (KEY_Y, 'f', 'F'),
+            (KEY_U, 'g', 'G'),
+            (KEY_I, 'c', 'C'),
+            (KEY_O, 'r', 'R'),
+            (KEY_P, 'l', 'L'),
+            (KEY_LEFTBRACE, '/', '?'),
+            (KEY_RIGHTBRACE, '=', '+'),
+            (KEY_A, 'a', 'A'),
+            (KEY_S, 'o', 'O'),
+            (KEY_D, 'e', 'E'),
+            (KEY_F, 'u', 'U'),
+            (KEY_G, 'i', 'I'),
+            (KEY_H, 'd', 'D'),
+            (KEY_J, 'h', 'H'),
+            (KEY_K, 't', 'T'),
+            (KEY_L, 'n', 'N'),
+            (KEY_SEMICOLON, 's', 'S'),
+            (KEY_APOSTROPHE, '-', '_'),
+            (KEY_Z, ';', ':'),
+            (KEY_X, 'q', 'Q'),
+            (KEY_C, 'j', 'J'),
+            (KEY_V, 'k', 'K'),
+            (KEY_B, 'x', 'X'),
+            (KEY_N, 'b', 'B'),
+            (KEY_M, 'm', 'M'),
+            (KEY_COMMA, 'w', 'W'),
+            (KEY_DOT, 'v', 'V'),
+            (KEY_SLASH, 'z', 'Z'),
+        ])
+    }
+
+    /// The Colemak layout.
+    pub fn colemak() -> Self {
+        use KeyCode::*;
+
+        Self::us_qwerty().with_keys([
+            (KEY_E, 'f', 'F'),
+            (KEY_R, 'p', 'P'),
+            (KEY_T, 'g', 'G'),
+            (KEY_Y, 'j', 'J'),
+            (KEY_U, 'l', 'L'),
+            (KEY_I, 'u', 'U'),
+            (KEY_O, 'y', 'Y'),
+            (KEY_P, ';', ':'),
+            (KEY_S, 'r', 'R'),
+            (KEY_D, 's', 'S'),
+            (KEY_F, 't', 'T'),
+            (KEY_G, 'd', 'D'),
+            (KEY_J, 'n', 'N'),
+            (KEY_K, 'e', 'E'),
+            (KEY_L, 'i', 'I'),
+            (KEY_SEMICOLON, 'o', 'O'),
+            (KEY_N, 'k', 'K'),
+        ])
+    }
+
+    /// The French AZERTY layout.
+    pub fn french_azerty() -> Self {
+        use KeyCode::*;
+
+        let mut layout = Self::new().with_keys([
+            (KEY_1, '&', '1'),
+            (KEY_2, 'é', '2'),
+            (KEY_3, '"', '3'),
+            (KEY_4, '\'', '4'),
+            (KEY_5, '(', '5'),
+            (KEY_6, '-', '6'),
+            (KEY_7, 'è', '7'),
+            (KEY_8, '_', '8'),
+            (KEY_9, 'ç', '9'),
+            (KEY_0, 'à', '0'),
+            (KEY_MINUS, ')', '°'),
+            (KEY_EQUAL, '=', '+'),
+            (KEY_Q, 'a', 'A'),
+            (KEY_W, 'z', 'Z'),
+            (KEY_E, 'e', 'E'),
+            (KEY_R, 'r', 'R'),
+            (KEY_T, 't', 'T'),
+            (KEY_Y, 'y', 'Y'),
+            (KEY_U, 'u', 'U'),
+            (KEY_I, 'i', 'I'),
+            (KEY_O, 'o', 'O'),
+            (KEY_P, 'p', 'P'),
+            (KEY_LEFTBRACE, '^', '¨'),
+            (KEY_RIGHTBRACE, '$', '£'),
+            (KEY_A, 'q', 'Q'),
+            (KEY_S, 's', 'S'),
+            (KEY_D, 'd', 'D'),
+            (KEY_F, 'f', 'F'),
+            (KEY_G, 'g', 'G'),
+            (KEY_H, 'h', 'H'),
+            (KEY_J, 'j', 'J'),
+            (KEY_K, 'k', 'K'),
+            (KEY_L, 'l', 'L'),
+            (KEY_SEMICOLON, 'm', 'M'),
+            (KEY_APOSTROPHE, 'ù', '%'),
+            (KEY_BACKSLASH, '*', 'µ'),
+            (KEY_102ND, '<', '>'),
+            (KEY_Z, 'w', 'W'),
+            (KEY_X, 'x', 'X'),
+            (KEY_C, 'c', 'C'),
+            (KEY_V, 'v', 'V'),
+            (KEY_B, 'b', 'B'),
+            (KEY_N, 'n', 'N'),
+            (KEY_M, ',', '?'),
+            (KEY_COMMA, ';', '.'),
+            (KEY_DOT, ':', '/'),
+            (KEY_SLASH, '!', '§'),
+        ]);
+
+        layout.insert(
+            KEY_GRAVE,
+            KeySymbols {
+                base: Some('²'),
+                ..Default::default()
+            },
+        );
+
+        layout.insert_dead_keys([(KEY_LEFTBRACE, '^'), (KEY_LEFTBRACE, '¨')]);
+
+        layout.with_alt_gr([
+            (KEY_2, '~'),
+            (KEY_3, '#'),
+            (KEY_4, '{'),
+            (KEY_5, '['),
+            (KEY_6, '|'),
+            (KEY_7, '`'),
+            (KEY_8, '\\'),
+            (KEY_9, '^'),
+            (KEY_0, '@'),
+            (KEY_MINUS, ']'),
+            (KEY_EQUAL, '}'),
+            (KEY_E, '€'),
+            (KEY_RIGHTBRACE, '¤'),
+        ])
+    }
+
+    /// The German QWERTZ layout.
+    pub fn german_qwertz() -> Self {
+        use KeyCode::*;
+
+        let mut layout = Self::new()
+            .with_keys([
+                (KEY_GRAVE, '^', '°'),
+                (KEY_1, '1', '!'),
+                (KEY_2, '2', '"'),
+                (KEY_3, '3', '§'),
+                (KEY_4, '4', '$'),
+                (KEY_5, '5', '%'),
+                (KEY_6, '6', '&'),
+                (KEY_7, '7', '/'),
+                (KEY_8, '8', '('),
+                (KEY_9, '9', ')'),
+                (KEY_0, '0', '='),
+                (KEY_MINUS, 'ß', '?'),
+                (KEY_EQUAL, '´', '`'),
+                (KEY_Q, 'q', 'Q'),
+                (KEY_W, 'w', 'W'),
+                (KEY_E, 'e', 'E'),
+                (KEY_R, 'r', 'R'),
+                (KEY_T, 't', 'T'),
+                (KEY_Y, 'z', 'Z'),
+                (KEY_U, 'u', 'U'),
+                (KEY_I, 'i', 'I'),
+                (KEY_O, 'o', 'O'),
+                (KEY_P, 'p', 'P'),
+                (KEY_LEFTBRACE, 'ü', 'Ü'),
+                (KEY_RIGHTBRACE, '+', '*'),
+                (KEY_A, 'a', 'A'),
+                (KEY_S, 's', 'S'),
+                (KEY_D, 'd', 'D'),
+                (KEY_F, 'f', 'F'),
+                (KEY_G, 'g', 'G'),
+                (KEY_H, 'h', 'H'),
+                (KEY_J, 'j', 'J'),
+                (KEY_K, 'k', 'K'),
+                (KEY_L, 'l', 'L'),
+                (KEY_SEMICOLON, 'ö', 'Ö'),
+                (KEY_APOSTROPHE, 'ä', 'Ä'),
+                (KEY_BACKSLASH, '#', '\''),
+                (KEY_102ND, '<', '>'),
+                (KEY_Z, 'y', 'Y'),
+                (KEY_X, 'x', 'X'),
+                (KEY_C, 'c', 'C'),
+                (KEY_V, 'v', 'V'),
+                (KEY_B, 'b', 'B'),
+                (KEY_N, 'n', 'N'),
+                (KEY_M, 'm', 'M'),
+                (KEY_COMMA, ',', ';'),
+                (KEY_DOT, '.', ':'),
+                (KEY_SLASH, '-', '_'),
+            ])
+            .with_alt_gr([
+                (KEY_2, '²'),
+                (KEY_3, '³'),
+                (KEY_7, '{'),
+                (KEY_8, '['),
+                (KEY_9, ']'),
+                (KEY_0, '}'),
+                (KEY_MINUS, '\\'),
+                (KEY_Q, '@'),
+                (KEY_E, '€'),
+                (KEY_RIGHTBRACE, '~'),
+                (KEY_102ND, '|'),
+                (KEY_M, 'µ'),
+            ]);
+
+        layout.insert_dead_keys([(KEY_GRAVE, '^'), (KEY_EQUAL, '´'), (KEY_EQUAL, '`')]);
+        layout
+    }
+
+    /// Map the specified keys to their base and shifted symbols, and map the common keys (see
+    /// [`Layout::insert_common_keys`]).
+    fn with_keys(mut self, keys: impl IntoIterator<Item = (KeyCode, char, char)>) -> Self {
+        for (code, base, shift) in keys {
+            self.insert(code, KeySymbols::new(base, shift));
+        }
+
+        self.insert_common_keys();
+        self
+    }
+
+    /// Mark the specified symbols of the keys as dead keys.
+    fn insert_dead_keys(&mut self, keys: impl IntoIterator<Item = (KeyCode, char)>) {
+        for (code, symbol) in keys {
+            self.insert_dead_key(code, symbol);
+        }
+    }
+
+    /// Add the symbols produced by the specified keys while AltGr is held.
+    fn with_alt_gr(mut self, keys: impl IntoIterator<Item = (KeyCode, char)>) -> Self {
+        for (code, alt_gr) in keys {
+            if let Some(symbols) = self.keys.get_mut(&code) {
+                *symbols = symbols.with_alt_gr(alt_gr, None);
+            }
+        }
+
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{KeymapTranslator, Modifiers};
+    use KeyCode::*;
+
+    #[test]
+    fn builtin_layouts() {
+        let typed = |layout: BuiltinLayout, keys: &[KeyCode], modifiers: Modifiers| {
+            let translator = KeymapTranslator::new(layout.into());
+
+            keys.iter()
+                .filter_map(|code| translator.translate(*code, &modifiers))
+                .collect::<String>()
+        };
+        let none = Modifiers::default();
+        let alt_gr = Modifiers {
+            alt_gr: true,
+            ..Default::default()
+        };
+        let keys = [
+            KEY_Q,
+            KEY_W,
+            KEY_E,
+            KEY_R,
+            KEY_T,
+            KEY_Y,
+            KEY_SEMICOLON,
+            KEY_2,
+            KEY_SPACE,
+        ];
+
+        assert_eq!(typed(BuiltinLayout::UsQwerty, &keys, none), "qwerty;2 ");
+        assert_eq!(typed(BuiltinLayout::Dvorak, &keys, none), "',.pyfs2 ");
+        assert_eq!(typed(BuiltinLayout::Colemak, &keys, none), "qwfpgjo2 ");
+        assert_eq!(typed(BuiltinLayout::FrenchAzerty, &keys, none), "azertymé ");
+        assert_eq!(typed(BuiltinLayout::GermanQwertz, &keys, none), "qwertzö2 ");
+        assert_eq!(
+            typed(BuiltinLayout::GermanQwertz, &[KEY_Q, KEY_E], alt_gr),
+            "@€"
+        );
+
+        for layout in BuiltinLayout::ALL {
+            assert_eq!(layout.name().parse::<BuiltinLayout>().unwrap(), layout);
+        }
+
+        assert_eq!(
+            "AZERTY".parse::<BuiltinLayout>().unwrap(),
+            BuiltinLayout::FrenchAzerty
+        );
+        assert!("klingon".parse::<BuiltinLayout>().is_err());
+    }
+}
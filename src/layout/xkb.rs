@@ -0,0 +1,465 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::process::Command;
+
+use xkbcommon::xkb;
+
+use crate::error::KeyloggerError;
+use crate::key_code::KeyCode;
+use crate::keyboard::{KeyEvent, KeyEventCause};
+use crate::layout::{is_num_lock_key, KeySymbols, Layout};
+use crate::KeyloggerResult;
+
+/// The file the keyboard configuration is stored in on Debian-based systems.
+const DEFAULT_KEYBOARD: &str = "/etc/default/keyboard";
+/// The file `systemd-localed` stores the keyboard configuration in.
+const XORG_KEYBOARD_CONF: &str = "/etc/X11/xorg.conf.d/00-keyboard.conf";
+
+/// The schema of the input sources of GNOME sessions.
+const GSETTINGS_SCHEMA: &str = "org.gnome.desktop.input-sources";
+
+/// The evdev key codes are offset by 8 in XKB keymaps.
+const EVDEV_OFFSET: u32 = 8;
+
+/// The rules, model, layout, variant and options (RMLVO) an XKB keymap is compiled from.
+///
+/// Empty names are replaced with their defaults by libxkbcommon, which reads them from the
+/// `XKB_DEFAULT_RULES`, `XKB_DEFAULT_MODEL`, `XKB_DEFAULT_LAYOUT`, `XKB_DEFAULT_VARIANT` and
+/// `XKB_DEFAULT_OPTIONS` environment variables, if set.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct XkbNames {
+    /// The rules file (`evdev` by default).
+    pub rules: String,
+    /// The keyboard model, such as `pc105`.
+    pub model: String,
+    /// A comma-separated list of layouts, such as `us,de`.
+    pub layout: String,
+    /// A comma-separated list of variants, one for each layout, such as `dvorak,`.
+    pub variant: String,
+    /// A comma-separated list of options, such as `ctrl:nocaps`.
+    pub options: Option<String>,
+}
+
+impl XkbNames {
+    /// The names of the layout configured for the system.
+    ///
+    /// These are read from `/etc/default/keyboard` (on Debian-based systems) or
+    /// `/etc/X11/xorg.conf.d/00-keyboard.conf` (on systems that use `systemd-localed`). If neither
+    /// file exists, all the names are left empty, and the defaults of libxkbcommon are used.
+    pub fn system() -> Self {
+        if let Ok(conf) = fs::read_to_string(DEFAULT_KEYBOARD) {
+            return Self::parse_default_keyboard(&conf);
+        }
+
+        if let Ok(conf) = fs::read_to_string(XORG_KEYBOARD_CONF) {
+            return Self::parse_xorg_conf(&conf);
+        }
+
+        Default::default()
+    }
+
+    /// The names of the layout of the current session.
+    ///
+    /// The input sources of the GNOME session are queried using `gsettings`, falling back to the
+    /// X11 layout reported by `localectl`, and then to the layout configured for the system (see
+    /// [`XkbNames::system`]). All the layouts of the session are returned, in the order they were
+    /// configured in, so the first one is the default.
+    pub fn session() -> Self {
+        Self::gsettings()
+            .or_else(Self::localectl)
+            .unwrap_or_else(Self::system)
+    }
+
+    /// The names of the input sources of the GNOME session.
+    fn gsettings() -> Option<Self> {
+        let sources = command_output("gsettings", &[GSETTINGS_SCHEMA, "sources"])?;
+        let options = command_output("gsettings", &[GSETTINGS_SCHEMA, "xkb-options"]);
+
+        Self::parse_gsettings(&sources, options.as_deref().unwrap_or_default())
+    }
+
+    /// The names of the X11 layout reported by `localectl`.
+    fn localectl() -> Option<Self> {
+        Self::parse_localectl(&command_output("localectl", &["status"])?)
+    }
+
+    /// Parse the input sources (e.g. `[('xkb', 'de+nodeadkeys'), ('xkb', 'us')]`) and the XKB
+    /// options (e.g. `['ctrl:nocaps']`) of a GNOME session.
+    ///
+    /// The input sources that aren't XKB layouts (such as input methods) are skipped.
+    fn parse_gsettings(sources: &str, options: &str) -> Option<Self> {
+        let sources = quoted_strings(sources);
+        let (layouts, variants): (Vec<_>, Vec<_>) = sources
+            .chunks_exact(2)
+            .filter(|source| source[0] == "xkb")
+            .map(|source| source[1].split_once('+').unwrap_or((source[1], "")))
+            .unzip();
+
+        if layouts.is_empty() {
+            return None;
+        }
+
+        let options = quoted_strings(options).join(",");
+
+        Some(Self {
+            layout: layouts.join(","),
+            variant: variants.join(","),
+            options: Some(options).filter(|o| !o.is_empty()),
+            ..Default::default()
+        })
+    }
+
+    /// Parse the `X11 *` fields of the output of `localectl status`.
+    fn parse_localectl(status: &str) -> Option<Self> {
+        let mut names = Self::default();
+
+        for line in status.lines() {
+            let Some((field, value)) = line.trim().split_once(':') else {
+                continue;
+            };
+
+            let value = value.trim().to_string();
+
+            match field {
+                "X11 Model" => names.model = value,
+                "X11 Layout" => names.layout = value,
+                "X11 Variant" => names.variant = value,
+                "X11 Options" => names.options = Some(value).filter(|o| !o.is_empty()),
+                _ => {}
+            }
+        }
+
+        (!names.layout.is_empty() && names.layout != "n/a").then_some(names)
+    }
+
+    /// Parse the `XKB*` variables of an `/etc/default/keyboard` file.
+    fn parse_default_keyboard(conf: &str) -> Self {
+        let mut names = Self::default();
+
+        for line in conf.lines() {
+            let Some((var, value)) = line.trim().split_once('=') else {
+                continue;
+            };
+
+            let value = value.trim().trim_matches('"').to_string();
+
+            match var.trim() {
+                "XKBMODEL" => names.model = value,
+                "XKBLAYOUT" => names.layout = value,
+                "XKBVARIANT" => names.variant = value,
+                "XKBOPTIONS" => names.options = Some(value).filter(|o| !o.is_empty()),
+                _ => {}
+            }
+        }
+
+        names
+    }
+
+    /// Parse the `Xkb*` options of the `InputClass` section of an xorg.conf file.
+    fn parse_xorg_conf(conf: &str) -> Self {
+        let mut names = Self::default();
+
+        for line in conf.lines() {
+            let mut fields = line.split('"').skip(1).step_by(2);
+            let (Some(option), Some(value)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+
+            let value = value.to_string();
+
+            match option {
+                "XkbRules" => names.rules = value,
+                "XkbModel" => names.model = value,
+                "XkbLayout" => names.layout = value,
+                "XkbVariant" => names.variant = value,
+                "XkbOptions" => names.options = Some(value).filter(|o| !o.is_empty()),
+                _ => {}
+            }
+        }
+
+        names
+    }
+}
+
+/// Translates [`KeyEvent`]s into the text they produce using an XKB keymap.
+///
+/// Unlike [`KeymapTranslator`](super::KeymapTranslator), which relies on static
+/// [`Layout`](super::Layout) tables, this compiles the keymap using libxkbcommon, so it supports
+/// all the layouts, variants and options known to the system (including the level 3 and 5 shifts
+/// and the lock modifiers). Dead keys don't produce any text.
+pub struct XkbTranslator {
+    state: xkb::State,
+}
+
+impl XkbTranslator {
+    /// Create a translator for the keymap compiled from the specified names.
+    pub fn new(names: &XkbNames) -> KeyloggerResult<Self> {
+        Ok(Self {
+            state: xkb::State::new(&compile_keymap(names)?),
+        })
+    }
+
+    /// Create a translator for the layout configured for the system (see [`XkbNames::system`]).
+    pub fn system() -> KeyloggerResult<Self> {
+        Self::new(&XkbNames::system())
+    }
+
+    /// Create a translator for the layout of the current session (see [`XkbNames::session`]).
+    pub fn session() -> KeyloggerResult<Self> {
+        Self::new(&XkbNames::session())
+    }
+
+    /// The name of the keysym the specified key produces in the current state, such as `a`,
+    /// `Return` or `dead_acute`.
+    pub fn keysym_name(&self, code: KeyCode) -> Option<String> {
+        let sym = self.state.key_get_one_sym(xkb_keycode(code));
+
+        (sym.raw() != xkb::keysyms::KEY_NoSymbol).then(|| xkb::keysym_get_name(sym))
+    }
+
+    /// The text the specified key produces in the current state.
+    ///
+    /// Returns `None` if the key doesn't produce any text, or if Ctrl or Alt is active (in which
+    /// case the key is part of a shortcut rather than text).
+    pub fn translate(&self, code: KeyCode) -> Option<String> {
+        let shortcut = [xkb::MOD_NAME_CTRL, xkb::MOD_NAME_ALT]
+            .into_iter()
+            .any(|m| self.state.mod_name_is_active(m, xkb::STATE_MODS_EFFECTIVE));
+
+        if shortcut {
+            return None;
+        }
+
+        let text = self.state.key_get_utf8(xkb_keycode(code));
+
+        match text.as_str() {
+            // Return produces a carriage return
+            "\r" => Some("\n".into()),
+            "\t" => Some(text),
+            _ if text.is_empty() || text.chars().any(char::is_control) => None,
+            _ => Some(text),
+        }
+    }
+
+    /// Process the next event, returning the text it produces (if any).
+    ///
+    /// This keeps track of the state of the keyboard (the active modifiers and layout), so all
+    /// events (including releases) should be fed to the translator in order.
+    pub fn feed(&mut self, ev: &KeyEvent) -> Option<String> {
+        let direction = match ev.cause {
+            KeyEventCause::Press => xkb::KeyDirection::Down,
+            KeyEventCause::Release => xkb::KeyDirection::Up,
+            // Holding a key down doesn't change the state
+            KeyEventCause::Repeat => return self.translate(ev.code),
+        };
+
+        // The text is produced by the key in the state before it was pressed
+        let text = self.translate(ev.code);
+
+        self.state.update_key(xkb_keycode(ev.code), direction);
+
+        match ev.cause {
+            KeyEventCause::Release => None,
+            _ => text,
+        }
+    }
+}
+
+impl Layout {
+    /// Build a layout from the first layout of the XKB keymap compiled from the specified names.
+    ///
+    /// The symbols of the first four shift levels of each key are used as its base, Shift, AltGr
+    /// and Shift+AltGr symbols. Unlike an [`XkbTranslator`], the layout doesn't support dead keys,
+    /// the level 5 shift or switching between the layouts of the keymap.
+    pub fn from_xkb(names: &XkbNames) -> KeyloggerResult<Self> {
+        let keymap = compile_keymap(names)?;
+        let mut layout = Self::new();
+
+        for raw in keymap.min_keycode().raw()..=keymap.max_keycode().raw() {
+            let Some(code) = raw
+                .checked_sub(EVDEV_OFFSET)
+                .and_then(|code| u16::try_from(code).ok())
+                .and_then(|code| KeyCode::try_from(code).ok())
+            else {
+                continue;
+            };
+
+            // The keypad keys that depend on NumLock are mapped by `insert_common_keys`
+            if is_num_lock_key(code) {
+                continue;
+            }
+
+            let key = xkb::Keycode::new(raw);
+            let levels = keymap.num_levels_for_key(key, 0);
+            let symbol = |level| {
+                let sym = *keymap.key_get_syms_by_level(key, 0, level).first()?;
+
+                match char::from_u32(xkb::keysym_to_utf32(sym))? {
+                    '\r' => Some('\n'),
+                    c if c.is_control() && c != '\t' => None,
+                    c => Some(c),
+                }
+            };
+            let [base, shift, alt_gr, shift_alt_gr] =
+                [0, 1, 2, 3].map(|level| (level < levels).then(|| symbol(level)).flatten());
+
+            if base.is_none() && alt_gr.is_none() {
+                continue;
+            }
+
+            layout.insert(
+                code,
+                KeySymbols {
+                    base,
+                    shift,
+                    alt_gr,
+                    shift_alt_gr,
+                    caps_lock: base.is_some_and(char::is_alphabetic),
+                },
+            );
+        }
+
+        layout.insert_common_keys();
+
+        Ok(layout)
+    }
+
+    /// The layout of the current session (see [`XkbNames::session`]), or the US QWERTY layout if
+    /// the keymap of the session can't be compiled.
+    ///
+    /// This lets [`TextReconstructor`](crate::text::TextReconstructor) produce the right text on
+    /// systems that don't use the US layout:
+    ///
+    /// ```no_run
+    /// use keylogger::layout::{KeymapTranslator, Layout};
+    /// use keylogger::text::TextReconstructor;
+    ///
+    /// let reconstructor =
+    ///     TextReconstructor::with_translator(KeymapTranslator::new(Layout::detect()));
+    /// ```
+    pub fn detect() -> Self {
+        Self::from_xkb(&XkbNames::session()).unwrap_or_else(|_| Self::us_qwerty())
+    }
+}
+
+/// Compile the XKB keymap described by the specified names.
+fn compile_keymap(names: &XkbNames) -> KeyloggerResult<xkb::Keymap> {
+    let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+
+    xkb::Keymap::new_from_names(
+        &context,
+        &names.rules,
+        &names.model,
+        &names.layout,
+        &names.variant,
+        names.options.clone(),
+        xkb::KEYMAP_COMPILE_NO_FLAGS,
+    )
+    .ok_or_else(|| {
+        KeyloggerError::InvalidLayout(format!("failed to compile the XKB keymap: {names:?}"))
+    })
+}
+
+/// The standard output of the specified command, if it succeeds.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8(output.stdout).ok())
+        .flatten()
+}
+
+/// The single-quoted strings of a GVariant value, such as `[('xkb', 'us')]`.
+fn quoted_strings(value: &str) -> Vec<&str> {
+    value.split('\'').skip(1).step_by(2).collect()
+}
+
+/// The XKB key code of the specified evdev key code.
+fn xkb_keycode(code: KeyCode) -> xkb::Keycode {
+    xkb::Keycode::new(u32::from(code.code()) + EVDEV_OFFSET)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn system_names() {
+        let names = XkbNames::parse_default_keyboard(
+            "# KEYBOARD CONFIGURATION FILE\n\
+             XKBMODEL=\"pc105\"\n\
+             XKBLAYOUT=\"us,de\"\n\
+             XKBVARIANT=\"dvorak,\"\n\
+             XKBOPTIONS=\"\"\n\
+             BACKSPACE=\"guess\"\n",
+        );
+
+        assert_eq!(
+            names,
+            XkbNames {
+                rules: String::new(),
+                model: "pc105".into(),
+                layout: "us,de".into(),
+                variant: "dvorak,".into(),
+                options: None,
+            }
+        );
+
+        let names = XkbNames::parse_xorg_conf(
+            "Section \"InputClass\"\n\
+             \tIdentifier \"system-keyboard\"\n\
+             \tMatchIsKeyboard \"on\"\n\
+             \tOption \"XkbLayout\" \"fr\"\n\
+             \tOption \"XkbOptions\" \"ctrl:nocaps\"\n\
+             EndSection\n",
+        );
+
+        assert_eq!(
+            names,
+            XkbNames {
+                layout: "fr".into(),
+                options: Some("ctrl:nocaps".into()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn session_names() {
+        assert_eq!(
+            XkbNames::parse_gsettings(
+                "[('xkb', 'de+nodeadkeys'), ('ibus', 'mozc-jp'), ('xkb', 'us')]",
+                "['ctrl:nocaps', 'compose:ralt']",
+            ),
+            Some(XkbNames {
+                layout: "de,us".into(),
+                variant: "nodeadkeys,".into(),
+                options: Some("ctrl:nocaps,compose:ralt".into()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(XkbNames::parse_gsettings("@a(ss) []", "@as []"), None);
+
+        assert_eq!(
+            XkbNames::parse_localectl(
+                "   System Locale: LANG=fr_FR.UTF-8\n\
+                 \x20      VC Keymap: fr\n\
+                 \x20     X11 Layout: fr\n\
+                 \x20      X11 Model: pc105\n\
+                 \x20    X11 Variant: azerty\n",
+            ),
+            Some(XkbNames {
+                model: "pc105".into(),
+                layout: "fr".into(),
+                variant: "azerty".into(),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            XkbNames::parse_localectl("   System Locale: LANG=C\n      X11 Layout: n/a\n"),
+            None
+        );
+    }
+}
@@ -1,7 +1,96 @@
 //! This crate provides the necessary scaffolding for handling keyboard input events on Linux.
 //!
-//! The installed [`KeyboardDevice`]s can be detected using [`find_keyboards`]. [`KeyboardDevice`]
-//! implements [`Stream`], where each element is a [`KeyEvent`].
+//! The installed [`KeyboardDevice`]s can be detected using [`find_keyboards`], or using a
+//! [`KeyboardFinder`] for more control over which devices are returned. [`KeyboardDevice`]
+//! implements [`Stream`], where each element is a [`KeyEvent`]. The events of multiple keyboards
+//! can be merged into a single stream using a [`KeyboardSet`] (see [`merge_keyboards`]), and the
+//! [`timestamps`] module stamps the merged events with times that can be compared across
+//! keyboards. Events from other sources (such as SSH sessions or serial consoles) can be consumed
+//! the same way by implementing [`KeyEventSource`] (see [`Keyboard`]).
+//!
+//! Keyboards plugged in after [`find_keyboards`] was called can be detected using a
+//! [`KeyboardMonitor`], and a [`ReconnectingKeyboard`] resumes capturing the events of a
+//! keyboard that is unplugged and plugged back in. The [`idle`] module detects when the user stops
+//! typing. Touchscreens and graphics tablets can be monitored using the [`touch`] module, and
+//! switches (such as the lid of a laptop) using the [`switch`] module.
+//!
+//! A [`Keylogger`] bundles the keyboards with a handler for their events, and is configured using
+//! a [`KeyloggerBuilder`]. The [`buffer`] module decouples the reading of the keyboards from the
+//! handling of their events, so a slow handler doesn't cause events to be lost, and the
+//! [`handler`] module controls which invocations of a handler run concurrently.
+//!
+//! Switch chatter and bursts of events can be filtered out using the adapters from the [`filters`]
+//! module, and the capture can be suspended while a password is typed using the [`privacy`]
+//! module. The [`accessibility`] module tags the events with the kind of widget they were typed
+//! into, and redacts them depending on it. The [`layout`] module translates key codes into the
+//! characters they produce, and the [`chords`] module detects key combinations such as
+//! `Ctrl+Shift+P`, which the [`hotkeys`] module binds to callbacks. Double taps and long presses
+//! are detected using the [`gestures`] module, and the [`backlight`] module adjusts the keyboard
+//! backlight of a laptop when its keys are pressed. The [`text`] module reconstructs the typed
+//! words and lines, and the [`window`] module attributes them to the focused application. Typing
+//! statistics can be collected using [`stats::TypingStats`], and the timing features used for
+//! keystroke dynamics research using the [`dynamics`] module. The rollover and ghosting of a
+//! keyboard can be measured using the [`diagnostics`] module, and the latency of the capture using
+//! the [`latency`] module.
+//!
+//! The captured events can be written to a file, a socket or the system logger using the
+//! [`sink`] module, or stored in a SQLite database using the `store` module. Recordings that
+//! can be read back on any machine are written using the [`capture`] module. Key events can be
+//! injected back into the kernel using a [`VirtualKeyboard`], the keys of a keyboard can be
+//! remapped using the [`remap`] module, and key sequences can be recorded and played back using
+//! the [`macros`] module. The [`bridge`] module forwards the events of a keyboard (which may be
+//! captured on another machine, see the `remote` module) into a virtual keyboard. The code that
+//! handles the events can be tested without a real keyboard using a [`MockKeyboard`].
+//!
+//! # Features
+//!
+//! * `tokio` (default): integrate with the tokio reactor.
+//! * `async-io`: integrate with the `async-io` reactor, for use with async-std or smol. If both
+//!   `tokio` and `async-io` are enabled, tokio is used.
+//! * `blocking`: provide a blocking, runtime-independent alternative to the `Stream` API (see
+//!   the `blocking` module).
+//! * `udev`: allow [`KeyboardFinder`] to detect keyboards using the properties assigned to them by
+//!   udev (see `KeyboardFinder::use_udev`).
+//! * `net`: serve the captured events to remote clients over TCP (see the `net` module).
+//!   Implies `tokio` and `serde`.
+//! * `websocket`: also serve the events over WebSocket. Implies `net`.
+//! * `remote`: send the events of a keyboard to a virtual keyboard on another machine (see the
+//!   `remote` module). Implies `net`.
+//! * `grpc`: export the captured events to a central collector over gRPC (see the `grpc` module
+//!   and `proto/keylogger.proto`). Implies `tokio`.
+//! * `daemon`: integrate with systemd socket activation and service notifications (see the
+//!   `daemon` module).
+//! * `dbus`: publish the captured events as D-Bus signals (see the `dbus` module). Implies
+//!   `tokio`.
+//! * `x11`: report the focused X11 window (see the `window` module).
+//! * `wayland`: report the focused window of wlroots-based Wayland compositors (see the `window`
+//!   module).
+//! * `xkb`: translate key codes into text using the XKB keymap configured for the system, through
+//!   libxkbcommon (see `layout::XkbTranslator`).
+//! * `json`: write the captured events to JSON Lines files (see `sink::JsonLinesSink`). Implies
+//!   `serde`.
+//! * `gzip`: compress the files rotated by `sink::JsonLinesSink`. Implies `json`.
+//! * `sqlite`: store the captured events in a SQLite database (see the `store` module).
+//! * `cli`: build the `keylogger` binary, which lists, watches, records and replays the events of
+//!   the keyboards from the command line. Implies `tokio`.
+//! * `tui`: render the live key activity of the keyboards in a terminal, using a ratatui widget
+//!   (see the `tui` module).
+//! * `broker`: open the input devices through a privileged helper process (see the `broker`
+//!   module), and build the `keylogger-broker` helper binary.
+//! * `logind`: open the input devices through logind, which doesn't require root privileges, and
+//!   pause the keyboards while the session is inactive (see the `logind` module). Implies `tokio`.
+//! * `atspi`: detect when a password field has the focus using the AT-SPI accessibility bus (see
+//!   `privacy::PrivacyGuard::watch_password_fields`), and track the role of the focused widget
+//!   (see `accessibility::FocusedWidget::watch`). Implies `tokio`.
+//! * `test-util`: provide a fake keyboard that types scripted key sequences through uinput, for
+//!   end-to-end tests of the discovery and capture of the keyboards (see the `test_util` module).
+//! * `zstd`: compress capture files using zstd (see `capture::CaptureWriter::create_compressed`).
+//! * `tracing`: report what the crate does (the reads and errors of each keyboard, the invocations
+//!   of the handlers, the keyboards removed or reconnected) as `tracing` spans and events, so it
+//!   can be observed using the subscriber of the application. The counters are reported as fields
+//!   with the `monotonic_counter.keylogger.` prefix (e.g. `events_read` and `read_errors`).
+//! * `serde`: implement `Serialize` and `Deserialize` for [`KeyEvent`], [`KeyCode`],
+//!   [`KeyEventCause`], [`DeviceInfo`] and [`Led`].
 //!
 //! # Example
 //!
@@ -29,12 +118,77 @@
 #[cfg(not(target_os = "linux"))]
 compile_error!("This crate only works on Linux");
 
+pub mod accessibility;
+pub mod backlight;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod bridge;
+#[cfg(feature = "broker")]
+pub mod broker;
+pub mod buffer;
+pub mod capture;
+pub mod chords;
+#[cfg(feature = "daemon")]
+pub mod daemon;
+#[cfg(feature = "dbus")]
+pub mod dbus;
+pub mod diagnostics;
+pub mod dynamics;
 mod error;
+pub mod filters;
+pub mod gestures;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod handler;
+pub mod hotkeys;
+mod hotplug;
+pub mod idle;
 pub(crate) mod key_code;
 mod keyboard;
+pub mod latency;
+pub mod layout;
+mod logger;
+#[cfg(feature = "logind")]
+pub mod logind;
+pub mod macros;
+mod mock;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod privacy;
+mod reactor;
+mod reconnect;
+pub mod remap;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod sink;
+pub mod stats;
+#[cfg(feature = "sqlite")]
+pub mod store;
+pub mod switch;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+pub mod text;
+mod timer;
+pub mod timestamps;
+pub mod touch;
+mod trace;
+#[cfg(feature = "tui")]
+pub mod tui;
+mod uinput;
+pub mod window;
 
-pub use error::KeyloggerError;
-pub use key_code::KeyCode;
-pub use keyboard::{find_keyboards, KeyEvent, KeyEventCause, KeyboardDevice};
+pub use error::{ErrorKind, KeyloggerError};
+pub use hotplug::{HotplugEvent, KeyboardMonitor};
+pub use key_code::{KeyCode, MediaKey};
+pub use keyboard::{
+    find_keyboards, find_keyboards_async, find_keyboards_verbose, merge_keyboards, BatchInfo,
+    Clock, ConnectionType, DeviceContext, DeviceId, DeviceInfo, KeyEvent, KeyEventCause,
+    KeyEventSource, KeyFilter, Keyboard, KeyboardDevice, KeyboardFinder, KeyboardSet, Led,
+    RawEvent, RawEvents, Reports, SeatSession, SkippedDevice, TaggedKeyEvent, WithContext,
+};
+pub use logger::{ErrorPolicy, HandlerSwitch, Keylogger, KeyloggerBuilder, PauseHandle};
+pub use mock::MockKeyboard;
+pub use reconnect::{ReconnectingKeyboard, RetryPolicy};
+pub use uinput::VirtualKeyboard;
 
 pub type KeyloggerResult<T> = Result<T, KeyloggerError>;
@@ -0,0 +1,741 @@
+use std::future::{ready, Future};
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::future::{self, BoxFuture};
+use futures::task::AtomicWaker;
+use futures::{stream, FutureExt, Stream, StreamExt};
+
+use crate::buffer::{self, OverflowPolicy};
+use crate::handler::{dispatch, Concurrency, KeyEventHandler};
+use crate::keyboard::{
+    DeviceContext, DeviceId, KeyboardDevice, KeyboardFinder, KeyboardSet, TaggedKeyEvent,
+};
+use crate::privacy::PrivacyGuard;
+use crate::trace;
+use crate::KeyloggerResult;
+
+/// A handler whose type was erased, so it can be stored in a [`Keylogger`].
+type BoxedHandler =
+    Arc<dyn Fn(TaggedKeyEvent, Arc<DeviceContext>) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Erase the type of the specified handler.
+fn boxed<H>(handler: H) -> BoxedHandler
+where
+    H: KeyEventHandler + Send + Sync + 'static,
+    H::Future: Send + 'static,
+{
+    Arc::new(move |ev, device| handler.handle(ev, device).boxed())
+}
+
+/// What a [`Keylogger`] does when one of its keyboards encounters an error.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum ErrorPolicy {
+    /// Stop capturing, returning the error from [`Keylogger::run`].
+    #[default]
+    Stop,
+    /// Remove the keyboard that encountered the error, and keep capturing the events of the other
+    /// keyboards. The errors that aren't caused by a particular keyboard still stop the capture.
+    RemoveDevice,
+}
+
+/// A builder for a [`Keylogger`].
+///
+/// ```no_run
+/// use std::sync::Arc;
+///
+/// use keylogger::handler::Concurrency;
+/// use keylogger::{DeviceContext, Keylogger, KeyboardFinder, KeyloggerError, TaggedKeyEvent};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), KeyloggerError> {
+///     let mut keylogger = Keylogger::builder()
+///         .finder(KeyboardFinder::new().exclude_name("(?i)power button"))
+///         .handler(|ev: TaggedKeyEvent, device: Arc<DeviceContext>| async move {
+///             println!("{}: {:?}", device.name(), ev.event)
+///         })
+///         .concurrency(Concurrency::Serialized)
+///         .build()?;
+///
+///     keylogger.run().await
+/// }
+/// ```
+pub struct KeyloggerBuilder {
+    finder: KeyboardFinder,
+    keyboards: Option<Vec<KeyboardDevice>>,
+    handler: BoxedHandler,
+    concurrency: Concurrency,
+    error_policy: ErrorPolicy,
+    privacy_guard: Option<PrivacyGuard>,
+    dedup_window: Option<Duration>,
+    queue_capacity: usize,
+    overflow_policy: OverflowPolicy,
+}
+
+impl Default for KeyloggerBuilder {
+    fn default() -> Self {
+        Self {
+            finder: KeyboardFinder::new(),
+            keyboards: None,
+            handler: Arc::new(|_, _| ready(()).boxed()),
+            concurrency: Concurrency::default(),
+            error_policy: ErrorPolicy::default(),
+            privacy_guard: None,
+            dedup_window: None,
+            queue_capacity: buffer::DEFAULT_CAPACITY,
+            overflow_policy: OverflowPolicy::default(),
+        }
+    }
+}
+
+impl KeyloggerBuilder {
+    /// Create a builder for a keylogger that watches all the keyboards, and ignores their events.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Watch the keyboards found by the specified finder (see [`KeyboardFinder::find`]).
+    ///
+    /// This replaces the finder configured previously, including its buffer size (see
+    /// [`KeyloggerBuilder::buffer_size`]).
+    pub fn finder(mut self, finder: KeyboardFinder) -> Self {
+        self.finder = finder;
+        self
+    }
+
+    /// Watch the specified keyboards, instead of the keyboards found by the finder.
+    pub fn keyboards(mut self, keyboards: impl IntoIterator<Item = KeyboardDevice>) -> Self {
+        self.keyboards = Some(keyboards.into_iter().collect());
+        self
+    }
+
+    /// The maximum number of input events to read from a keyboard at once (see
+    /// [`KeyboardFinder::buffer_size`]).
+    ///
+    /// This only applies to the keyboards found by the finder.
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.finder = self.finder.buffer_size(buffer_size);
+        self
+    }
+
+    /// Pass the events of the keyboards to the specified handler.
+    ///
+    /// The handler can be replaced while the keylogger is running (see [`HandlerSwitch`]).
+    pub fn handler<H>(mut self, handler: H) -> Self
+    where
+        H: KeyEventHandler + Send + Sync + 'static,
+        H::Future: Send + 'static,
+    {
+        self.handler = boxed(handler);
+        self
+    }
+
+    /// Which invocations of the handler are allowed to run at the same time
+    /// ([`Concurrency::PerDevice`] by default).
+    pub fn concurrency(mut self, concurrency: Concurrency) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// What to do when one of the keyboards encounters an error ([`ErrorPolicy::Stop`] by
+    /// default).
+    pub fn error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.error_policy = policy;
+        self
+    }
+
+    /// Drop the events read while the specified guard is sensitive, instead of passing them to the
+    /// handler (see the [`privacy`](crate::privacy) module).
+    pub fn privacy_guard(mut self, guard: PrivacyGuard) -> Self {
+        self.privacy_guard = Some(guard);
+        self
+    }
+
+    /// Drop the duplicate events of the keyboards that are exposed as several devices (see
+    /// [`KeyboardSet::dedup_aliases`]).
+    pub fn dedup_aliases(mut self, window: Duration) -> Self {
+        self.dedup_window = Some(window);
+        self
+    }
+
+    /// The maximum number of events queued for each keyboard while the handler catches up
+    /// ([`buffer::DEFAULT_CAPACITY`] by default).
+    ///
+    /// The events of each keyboard are read as soon as they're available, and queued until they
+    /// can be handled (see the [`buffer`] module), so a slow handler doesn't cause the kernel to
+    /// drop events. What happens once the queue of a keyboard is full is decided by the
+    /// [`overflow_policy`](KeyloggerBuilder::overflow_policy).
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = capacity;
+        self
+    }
+
+    /// What to do with the events of a keyboard read while its queue is full
+    /// ([`OverflowPolicy::DropOldest`] by default).
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// Open the keyboards, and build the keylogger.
+    pub fn build(self) -> KeyloggerResult<Keylogger> {
+        let keyboards = match self.keyboards {
+            Some(keyboards) => keyboards,
+            None => self.finder.find()?,
+        };
+
+        let mut keyboards = KeyboardSet::new(keyboards);
+
+        if let Some(window) = self.dedup_window {
+            keyboards.dedup_aliases(window);
+        }
+
+        Ok(Keylogger {
+            keyboards,
+            handler: HandlerSwitch(Arc::new(RwLock::new(self.handler))),
+            pause: PauseHandle::default(),
+            concurrency: self.concurrency,
+            error_policy: self.error_policy,
+            privacy_guard: self.privacy_guard,
+            queue_capacity: self.queue_capacity,
+            overflow_policy: self.overflow_policy,
+        })
+    }
+}
+
+/// Captures the events of a group of keyboards, passing them to a handler.
+///
+/// A `Keylogger` ties together the discovery of the keyboards (see [`KeyboardFinder`]), the
+/// dispatching of their events (see the [`handler`](crate::handler) module), the handling of their
+/// errors (see [`ErrorPolicy`]) and the suppression of sensitive input (see the
+/// [`privacy`](crate::privacy) module). It's configured using a [`KeyloggerBuilder`].
+///
+/// Each keyboard is read into its own bounded queue (see the [`buffer`] module), and the events of
+/// the queues are passed to the handler. When the handler falls behind, the events pile up in the
+/// queues instead of the buffers of the kernel, and the [`OverflowPolicy`] decides which ones are
+/// dropped, so a flood of events from one keyboard can't crowd out the events of the others.
+pub struct Keylogger {
+    keyboards: KeyboardSet,
+    handler: HandlerSwitch,
+    pause: PauseHandle,
+    concurrency: Concurrency,
+    error_policy: ErrorPolicy,
+    privacy_guard: Option<PrivacyGuard>,
+    queue_capacity: usize,
+    overflow_policy: OverflowPolicy,
+}
+
+impl Keylogger {
+    /// Create a builder for a keylogger.
+    pub fn builder() -> KeyloggerBuilder {
+        KeyloggerBuilder::new()
+    }
+
+    /// The keyboards being watched.
+    pub fn keyboards(&self) -> &KeyboardSet {
+        &self.keyboards
+    }
+
+    /// The keyboards being watched.
+    ///
+    /// Keyboards can be added to (or removed from) the set between the runs of the keylogger.
+    pub fn keyboards_mut(&mut self) -> &mut KeyboardSet {
+        &mut self.keyboards
+    }
+
+    /// Replace the handler the events are passed to (see [`HandlerSwitch::set_handler`]).
+    pub fn set_handler<H>(&self, handler: H)
+    where
+        H: KeyEventHandler + Send + Sync + 'static,
+        H::Future: Send + 'static,
+    {
+        self.handler.set_handler(handler);
+    }
+
+    /// A [`HandlerSwitch`] that replaces the handler of the keylogger while it's running.
+    pub fn handler_switch(&self) -> HandlerSwitch {
+        self.handler.clone()
+    }
+
+    /// Pause all the keyboards (see [`PauseHandle::pause`]).
+    pub fn pause(&self) {
+        self.pause.pause();
+    }
+
+    /// Resume the keyboards paused using [`Keylogger::pause`] (see [`PauseHandle::resume`]).
+    pub fn resume(&self) {
+        self.pause.resume();
+    }
+
+    /// Whether the keylogger is paused (see [`PauseHandle::is_paused`]).
+    pub fn is_paused(&self) -> bool {
+        self.pause.is_paused()
+    }
+
+    /// A [`PauseHandle`] that pauses and resumes the keylogger while it's running.
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.pause.clone()
+    }
+
+    /// Capture the events of the keyboards, passing them to the handler, until all the keyboards
+    /// are removed, or until a keyboard encounters an error the [`ErrorPolicy`] doesn't recover
+    /// from.
+    ///
+    /// The future is `Send`, so the keylogger can be run on a task of a multi-threaded runtime
+    /// (e.g. using `tokio::spawn`).
+    pub async fn run(&mut self) -> KeyloggerResult<()> {
+        let switch = self.handler.clone();
+
+        self.run_local(move |ev, device| switch.current()(ev, device))
+            .await
+    }
+
+    /// Capture the events of the keyboards like [`Keylogger::run`], passing them to the specified
+    /// handler instead of the handler of the keylogger.
+    ///
+    /// Unlike the handler of the keylogger, `handler` doesn't need to be `Send` or `Sync`, so it
+    /// can hold state that can't be shared between threads (such as the `Rc`-based state of a
+    /// GUI). The invocations of the handler are run by the returned future itself, so the future
+    /// isn't `Send` either: it must be awaited on the thread that created it (e.g. on a
+    /// current-thread runtime, or on a `LocalSet`) rather than spawned using `tokio::spawn`.
+    ///
+    /// ```no_run
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    /// use std::sync::Arc;
+    ///
+    /// use keylogger::{DeviceContext, Keylogger, KeyloggerError, TaggedKeyEvent};
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// async fn main() -> Result<(), KeyloggerError> {
+    ///     let mut keylogger = Keylogger::builder().build()?;
+    ///     let count = Rc::new(Cell::new(0));
+    ///
+    ///     let local = tokio::task::LocalSet::new();
+    ///     let handler = |_: TaggedKeyEvent, _: Arc<DeviceContext>| {
+    ///         count.set(count.get() + 1);
+    ///
+    ///         async {}
+    ///     };
+    ///
+    ///     local.run_until(keylogger.run_local(handler)).await
+    /// }
+    /// ```
+    pub async fn run_local<H: KeyEventHandler>(&mut self, handler: H) -> KeyloggerResult<()> {
+        let handler = |ev, device| handler.handle(ev, device);
+        let span = trace::keylogger_span(self.keyboards.len());
+        // Whether the stream of each keyboard ended, so the keyboard is removed from the set
+        let mut ended: Vec<_> = self.keyboards.iter().map(|(id, _)| (id, false)).collect();
+
+        let res = {
+            let (keyboards, mut contexts) = self.keyboards.split_keyboards();
+            let (mut pumps, queues): (Vec<_>, Vec<_>) = keyboards
+                .zip(ended.iter_mut())
+                .map(|((id, keyboard), (_, ended))| {
+                    let events = DeviceEvents {
+                        id,
+                        keyboard,
+                        pause: &self.pause,
+                        paused: None,
+                        guard: self.privacy_guard.as_ref(),
+                        error_policy: self.error_policy,
+                        failed: false,
+                        ended,
+                    };
+
+                    buffer::bounded(events, self.queue_capacity, self.overflow_policy)
+                })
+                .unzip();
+
+            // The keyboards are read into their queues by the same task that handles the events,
+            // so the future stays `Send` (and can run a handler that isn't)
+            let reading = future::poll_fn(|cx| {
+                pumps.retain_mut(|pump| Pin::new(pump).poll(cx).is_pending());
+
+                if pumps.is_empty() {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            })
+            .then(|()| future::pending());
+
+            let events = stream::select_all(queues)
+                .filter_map(move |item| ready(item.map(|ev| contexts.add(ev)).transpose()));
+            let handling = trace::instrument(dispatch(events, handler, self.concurrency), span);
+
+            // The queues end once all the pumps are done, so the events are handled first
+            future::select(pin!(handling), pin!(reading))
+                .await
+                .factor_first()
+                .0
+        };
+
+        for (id, _) in ended.into_iter().filter(|(_, ended)| *ended) {
+            self.keyboards.remove(id);
+        }
+
+        res
+    }
+}
+
+/// The events of a keyboard of a [`Keylogger`], which are read into the queue of the keyboard.
+struct DeviceEvents<'a> {
+    id: DeviceId,
+    keyboard: &'a mut KeyboardDevice,
+    pause: &'a PauseHandle,
+    /// Whether the keyboard was paused by the pause handle, if it was used yet.
+    paused: Option<bool>,
+    guard: Option<&'a PrivacyGuard>,
+    error_policy: ErrorPolicy,
+    /// Whether an error was returned, which stops the capture.
+    ///
+    /// The stream ends after the error, since the errors of a device tend to persist (e.g. once
+    /// it's unplugged), and polling it again would just return the same error in a busy loop.
+    failed: bool,
+    /// Set once the stream ends, so the keyboard is removed from the set.
+    ended: &'a mut bool,
+}
+
+impl Stream for DeviceEvents<'_> {
+    type Item = KeyloggerResult<TaggedKeyEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if this.failed {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            let changed = match this.pause.poll_change(&mut this.paused, cx) {
+                Some(true) => {
+                    this.keyboard.pause();
+                    Ok(())
+                }
+                Some(false) => this.keyboard.resume(),
+                None => Ok(()),
+            };
+
+            let item = match changed {
+                Ok(()) => futures::ready!(this.keyboard.poll_next_unpin(cx)),
+                Err(e) => Some(Err(e)),
+            };
+
+            match item {
+                Some(Ok(event)) => {
+                    if this.guard.is_some_and(|g| g.is_sensitive()) {
+                        continue;
+                    }
+
+                    return Poll::Ready(Some(Ok(TaggedKeyEvent {
+                        device_id: this.id,
+                        event,
+                        batch: this.keyboard.batch_info(),
+                    })));
+                }
+                Some(Err(e))
+                    if this.error_policy == ErrorPolicy::RemoveDevice
+                        && e.device_path() == Some(this.keyboard.path()) =>
+                {
+                    trace::device_dropped(this.keyboard.path(), &e);
+                    *this.ended = true;
+
+                    return Poll::Ready(None);
+                }
+                Some(Err(e)) => {
+                    this.failed = true;
+
+                    return Poll::Ready(Some(Err(e)));
+                }
+                None => {
+                    *this.ended = true;
+
+                    return Poll::Ready(None);
+                }
+            }
+        }
+    }
+}
+
+/// Replaces the handler of a running [`Keylogger`] (see [`Keylogger::handler_switch`]).
+///
+/// Cloning a `HandlerSwitch` is cheap: the clones replace the handler of the same keylogger. This
+/// is useful for changing what's done with the events (e.g. where they are written to) without
+/// stopping the capture:
+///
+/// ```no_run
+/// use std::sync::Arc;
+///
+/// use keylogger::{DeviceContext, Keylogger, KeyloggerError, TaggedKeyEvent};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), KeyloggerError> {
+///     let mut keylogger = Keylogger::builder()
+///         .handler(|ev: TaggedKeyEvent, _: Arc<DeviceContext>| async move {
+///             println!("{:?}", ev.event.code)
+///         })
+///         .build()?;
+///     let switch = keylogger.handler_switch();
+///
+///     std::thread::spawn(move || {
+///         std::thread::sleep(std::time::Duration::from_secs(60));
+///         switch.set_handler(|ev: TaggedKeyEvent, device: Arc<DeviceContext>| async move {
+///             println!("{}: {:?}", device.path().display(), ev)
+///         });
+///     });
+///
+///     keylogger.run().await
+/// }
+/// ```
+#[derive(Clone)]
+pub struct HandlerSwitch(Arc<RwLock<BoxedHandler>>);
+
+impl HandlerSwitch {
+    /// Pass the events read from now on to the specified handler.
+    ///
+    /// The events whose handling already started are still handled by the previous handler.
+    pub fn set_handler<H>(&self, handler: H)
+    where
+        H: KeyEventHandler + Send + Sync + 'static,
+        H::Future: Send + 'static,
+    {
+        // The handler is always left in a usable state, so a poisoned lock is still usable
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = boxed(handler);
+    }
+
+    /// The current handler.
+    fn current(&self) -> BoxedHandler {
+        Arc::clone(&self.0.read().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// Pauses and resumes a running [`Keylogger`] (see [`Keylogger::pause_handle`]).
+///
+/// Cloning a `PauseHandle` is cheap: the clones control the same keylogger. The keyboards of the
+/// keylogger are paused (see [`KeyboardDevice::pause`]) or resumed (see
+/// [`KeyboardDevice::resume`]) by the keylogger itself, as soon as it's woken up by the handle, so
+/// the handle can be used from another task or thread while the keylogger runs:
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use keylogger::{Keylogger, KeyloggerError};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), KeyloggerError> {
+///     let mut keylogger = Keylogger::builder().build()?;
+///     let pause = keylogger.pause_handle();
+///
+///     std::thread::spawn(move || {
+///         // Stop capturing for a minute
+///         pause.pause();
+///         std::thread::sleep(Duration::from_secs(60));
+///         pause.resume();
+///     });
+///
+///     keylogger.run().await
+/// }
+/// ```
+///
+/// If the keyboards fail to resume, the error is handled according to the [`ErrorPolicy`] of the
+/// keylogger.
+#[derive(Clone, Debug, Default)]
+pub struct PauseHandle(Arc<PauseState>);
+
+#[derive(Debug, Default)]
+struct PauseState {
+    paused: AtomicBool,
+    /// The task running the keylogger, which applies the changes.
+    waker: AtomicWaker,
+}
+
+impl PauseHandle {
+    /// Pause the keyboards of the keylogger (see [`KeyboardDevice::pause`]).
+    ///
+    /// The events that occur while the keylogger is paused are dropped.
+    pub fn pause(&self) {
+        self.set_paused(true);
+    }
+
+    /// Resume the keyboards of the keylogger.
+    ///
+    /// This also resumes the keyboards that were paused individually (see
+    /// [`Keylogger::keyboards_mut`]).
+    pub fn resume(&self) {
+        self.set_paused(false);
+    }
+
+    /// Whether the keylogger is paused.
+    pub fn is_paused(&self) -> bool {
+        self.0.paused.load(Ordering::SeqCst)
+    }
+
+    fn set_paused(&self, paused: bool) {
+        self.0.paused.store(paused, Ordering::SeqCst);
+        self.0.waker.wake();
+    }
+
+    /// Whether a keyboard needs to be paused (`Some(true)`) or resumed (`Some(false)`), because
+    /// the handle was used since it was last `paused` or resumed, and wake up the current task the
+    /// next time the handle is used.
+    fn poll_change(&self, paused: &mut Option<bool>, cx: &mut Context<'_>) -> Option<bool> {
+        self.0.waker.register(cx.waker());
+
+        let pause = self.is_paused();
+
+        if *paused == Some(pause) {
+            return None;
+        }
+
+        // The keyboards are left alone until the handle is used
+        let change = (pause || paused.is_some()).then_some(pause);
+
+        *paused = Some(pause);
+
+        change
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::cell::Cell;
+    use std::fs::File;
+    use std::os::unix::io::FromRawFd;
+    use std::path::Path;
+    use std::rc::Rc;
+    use std::sync::mpsc;
+
+    #[test]
+    fn run_local_handler() {
+        fn assert_send<T: Send>(_: &T) {}
+
+        let mut keylogger = Keylogger::builder().keyboards([]).build().unwrap();
+
+        assert_send(&keylogger.run());
+
+        // A handler that isn't Send, and the future of the keylogger that runs it
+        let count = Rc::new(Cell::new(0));
+        let handler = |_: TaggedKeyEvent, _: Arc<DeviceContext>| {
+            count.set(count.get() + 1);
+
+            ready(())
+        };
+
+        block_on(keylogger.run_local(handler)).unwrap();
+
+        assert_eq!(count.get(), 0);
+    }
+    #[test]
+    fn pause_handle() {
+        use futures::task::{waker, ArcWake};
+        use std::sync::atomic::AtomicUsize;
+
+        struct CountWakes(AtomicUsize);
+
+        impl ArcWake for CountWakes {
+            fn wake_by_ref(this: &Arc<Self>) {
+                this.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let wakes = Arc::new(CountWakes(AtomicUsize::new(0)));
+        let waker = waker(Arc::clone(&wakes));
+        let mut cx = Context::from_waker(&waker);
+
+        let keylogger = Keylogger::builder().keyboards([]).build().unwrap();
+        let handle = keylogger.pause_handle();
+        let mut paused = None;
+
+        // The keyboards are left alone until the handle is used
+        assert_eq!(handle.poll_change(&mut paused, &mut cx), None);
+        assert_eq!(paused, Some(false));
+
+        handle.pause();
+        assert!(keylogger.is_paused());
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+
+        assert_eq!(handle.poll_change(&mut paused, &mut cx), Some(true));
+        assert_eq!(handle.poll_change(&mut paused, &mut cx), None);
+
+        keylogger.resume();
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 2);
+
+        assert_eq!(handle.poll_change(&mut paused, &mut cx), Some(false));
+        assert_eq!(paused, Some(false));
+    }
+
+    /// The master of a pseudoterminal whose slave was closed, which stands in for an unplugged
+    /// device: it's always readable, but reading it fails with `EIO`.
+    fn hung_up_pty() -> File {
+        unsafe {
+            let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC);
+
+            assert!(master >= 0);
+            assert_eq!(libc::grantpt(master), 0);
+            assert_eq!(libc::unlockpt(master), 0);
+
+            let slave = libc::open(libc::ptsname(master), libc::O_RDWR | libc::O_NOCTTY);
+
+            assert!(slave >= 0);
+            libc::close(slave);
+
+            File::from_raw_fd(master)
+        }
+    }
+
+    #[test]
+    fn failing_device() {
+        let (tx, rx) = mpsc::channel();
+
+        // The keylogger runs on a separate thread, so the test fails instead of hanging if the
+        // error isn't returned
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_io()
+                .build()
+                .unwrap();
+            let keyboard =
+                KeyboardDevice::fake("Keyboard", Path::new("/dev/input/event1"), hung_up_pty());
+            let mut keylogger = Keylogger::builder().keyboards([keyboard]).build().unwrap();
+
+            tx.send(runtime.block_on(keylogger.run())).unwrap();
+        });
+
+        let err = rx
+            .recv_timeout(Duration::from_secs(10))
+            .expect("the error of the keyboard wasn't returned")
+            .unwrap_err();
+
+        assert_eq!(err.device_path(), Some(Path::new("/dev/input/event1")));
+        assert_eq!(err.raw_os_error(), Some(libc::EIO));
+    }
+
+    #[tokio::test]
+    async fn failing_device_events() {
+        let keylogger = Keylogger::builder().keyboards([]).build().unwrap();
+        let mut keyboard =
+            KeyboardDevice::fake("Keyboard", Path::new("/dev/input/event1"), hung_up_pty());
+        let mut ended = false;
+        let mut events = DeviceEvents {
+            id: DeviceId(0),
+            keyboard: &mut keyboard,
+            pause: &keylogger.pause,
+            paused: None,
+            guard: None,
+            error_policy: ErrorPolicy::Stop,
+            failed: false,
+            ended: &mut ended,
+        };
+
+        assert!(events.next().await.unwrap().is_err());
+        // The error isn't returned over and over, but the keyboard stays in the set
+        assert!(events.next().await.is_none());
+        assert!(!ended);
+    }
+}
@@ -0,0 +1,352 @@
+//! Acquiring the keyboards through logind, without root privileges.
+//!
+//! A process that controls a logind session (see [`LogindSession::take_control`]) can ask logind
+//! to open the input devices of the seat of the session using the `TakeDevice` method. logind
+//! revokes the devices when the session is switched away from (e.g. on a VT switch), and passes
+//! new file descriptors for them when the session becomes active again. These transitions are
+//! reported by [`LogindSession::device_events`], and applied to the keyboards using
+//! [`LogindSession::apply`]: the keyboards are paused while the session is inactive (see
+//! [`KeyboardDevice::pause`]), and resume capturing events once it's active again.
+//!
+//! Only one process can control a session at a time (usually the display server, or the
+//! compositor).
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use keylogger::logind::LogindSession;
+//! use keylogger::KeyloggerError;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let session = LogindSession::take_control().await?;
+//!     let mut keyboard = session.find_keyboards().await?.remove(0);
+//!     let mut device_events = Box::pin(session.device_events().await?);
+//!
+//!     loop {
+//!         tokio::select! {
+//!             Some(ev) = keyboard.next() => println!("{:?}", ev?),
+//!             Some(ev) = device_events.next() => session.apply(&mut keyboard, ev?).await?,
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::fs::File;
+use std::io;
+use std::os::fd::OwnedFd;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use futures::{Stream, StreamExt};
+use zbus::message::Type;
+use zbus::zvariant::{self, OwnedObjectPath};
+use zbus::{MatchRule, MessageStream};
+
+use crate::error::KeyloggerError;
+use crate::keyboard::device::{find_char_devices, input_dir};
+use crate::keyboard::KeyboardDevice;
+use crate::KeyloggerResult;
+
+/// The bus name of logind.
+const LOGIND: &str = "org.freedesktop.login1";
+/// The path of the logind manager object.
+const MANAGER_PATH: &str = "/org/freedesktop/login1";
+/// The interface of the logind manager object.
+const MANAGER_INTERFACE: &str = "org.freedesktop.login1.Manager";
+/// The interface of the logind session objects.
+const SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+
+/// The major and minor numbers of a device.
+pub type DeviceNumber = (u32, u32);
+
+/// A change in the state of a device taken from logind (see
+/// [`LogindSession::device_events`]).
+#[derive(Debug)]
+pub enum SessionDeviceEvent {
+    /// The device was paused, because the session is no longer active.
+    Paused {
+        /// The device.
+        device: DeviceNumber,
+        /// Whether the device was already revoked. Otherwise, logind waits for the pause to be
+        /// acknowledged (see [`LogindSession::apply`]) before revoking it.
+        forced: bool,
+    },
+    /// The session became active again, and logind opened the device again.
+    Resumed {
+        /// The device.
+        device: DeviceNumber,
+        /// The new file descriptor of the device.
+        fd: OwnedFd,
+    },
+    /// The device was removed.
+    Removed {
+        /// The device.
+        device: DeviceNumber,
+    },
+}
+
+impl SessionDeviceEvent {
+    /// The device the event is about.
+    pub fn device(&self) -> DeviceNumber {
+        match self {
+            Self::Paused { device, .. }
+            | Self::Resumed { device, .. }
+            | Self::Removed { device } => *device,
+        }
+    }
+
+    /// Whether the event is about the specified keyboard.
+    pub fn is_for(&self, keyboard: &KeyboardDevice) -> bool {
+        device_number(&keyboard.0.inner.file).is_ok_and(|dev| dev == self.device())
+    }
+}
+
+/// A logind session controlled by this process (see the [module docs](self)).
+#[derive(Clone, Debug)]
+pub struct LogindSession {
+    conn: zbus::Connection,
+    session: OwnedObjectPath,
+}
+
+impl LogindSession {
+    /// Take control of the session of this process, using a connection to the system bus.
+    ///
+    /// This fails if another process already controls the session.
+    pub async fn take_control() -> KeyloggerResult<Self> {
+        let conn = zbus::Connection::system().await.map_err(dbus_err)?;
+
+        Self::with_connection(conn).await
+    }
+
+    /// Take control of the session of this process, using the specified connection to the system
+    /// bus.
+    pub async fn with_connection(conn: zbus::Connection) -> KeyloggerResult<Self> {
+        let session = conn
+            .call_method(
+                Some(LOGIND),
+                MANAGER_PATH,
+                Some(MANAGER_INTERFACE),
+                "GetSessionByPID",
+                &(std::process::id()),
+            )
+            .await
+            .map_err(dbus_err)?
+            .body()
+            .deserialize::<OwnedObjectPath>()
+            .map_err(dbus_err)?;
+
+        let session = Self { conn, session };
+
+        session.call("TakeControl", &(false)).await?;
+
+        Ok(session)
+    }
+
+    /// Ask logind to open the keyboard device at the specified path.
+    ///
+    /// If the session isn't active, the keyboard is paused until it's resumed (see
+    /// [`LogindSession::apply`]).
+    pub async fn take_keyboard(&self, path: impl AsRef<Path>) -> KeyloggerResult<KeyboardDevice> {
+        let (major, minor) = device_number(path.as_ref())?;
+        let (fd, inactive) = self
+            .call("TakeDevice", &(major, minor))
+            .await?
+            .body()
+            .deserialize::<(zvariant::OwnedFd, bool)>()
+            .map_err(dbus_err)?;
+
+        let mut keyboard = match KeyboardDevice::from_fd(OwnedFd::from(fd)) {
+            Ok(keyboard) => keyboard,
+            Err(e) => {
+                // Don't keep the devices that turned out not to be keyboards
+                let _ = self.call("ReleaseDevice", &(major, minor)).await;
+
+                return Err(e);
+            }
+        };
+
+        if inactive {
+            keyboard.pause();
+        }
+
+        Ok(keyboard)
+    }
+
+    /// Auto-detect the keyboard devices, like [`find_keyboards`](crate::find_keyboards), by asking
+    /// logind to open all the input devices of the seat.
+    ///
+    /// The devices logind can't open (and the devices that aren't keyboards) are skipped.
+    pub async fn find_keyboards(&self) -> KeyloggerResult<Vec<KeyboardDevice>> {
+        let mut keyboards = vec![];
+
+        for path in find_char_devices(&input_dir())? {
+            if let Ok(keyboard) = self.take_keyboard(&path).await {
+                keyboards.push(keyboard);
+            }
+        }
+
+        Ok(keyboards)
+    }
+
+    /// Tell logind the keyboard is no longer needed.
+    pub async fn release(&self, keyboard: KeyboardDevice) -> KeyloggerResult<()> {
+        let (major, minor) = device_number(&keyboard.0.inner.file)?;
+
+        drop(keyboard);
+        self.call("ReleaseDevice", &(major, minor)).await?;
+
+        Ok(())
+    }
+
+    /// A stream of the changes in the state of the devices taken from logind.
+    pub async fn device_events(
+        &self,
+    ) -> KeyloggerResult<impl Stream<Item = KeyloggerResult<SessionDeviceEvent>>> {
+        let rule = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .sender(LOGIND)
+            .and_then(|rule| rule.interface(SESSION_INTERFACE))
+            .and_then(|rule| rule.path(self.session.clone()))
+            .map_err(dbus_err)?
+            .build();
+
+        let messages = MessageStream::for_match_rule(rule, &self.conn, None)
+            .await
+            .map_err(dbus_err)?;
+
+        Ok(messages.filter_map(|msg| async move {
+            let msg = match msg {
+                Ok(msg) => msg,
+                Err(e) => return Some(Err(dbus_err(e))),
+            };
+
+            let body = msg.body();
+            let ev = match msg.header().member()?.as_str() {
+                "PauseDevice" => {
+                    body.deserialize::<(u32, u32, String)>()
+                        .map(|(major, minor, kind)| match kind.as_str() {
+                            "gone" => SessionDeviceEvent::Removed {
+                                device: (major, minor),
+                            },
+                            kind => SessionDeviceEvent::Paused {
+                                device: (major, minor),
+                                forced: kind == "force",
+                            },
+                        })
+                }
+                "ResumeDevice" => {
+                    body.deserialize::<(u32, u32, zvariant::OwnedFd)>()
+                        .map(|(major, minor, fd)| SessionDeviceEvent::Resumed {
+                            device: (major, minor),
+                            fd: fd.into(),
+                        })
+                }
+                _ => return None,
+            };
+
+            Some(ev.map_err(dbus_err))
+        }))
+    }
+
+    /// Apply a change in the state of a device to the keyboard it's about.
+    ///
+    /// The keyboard is paused when its device is paused (acknowledging the pause, so logind can
+    /// proceed with the session switch), and resumes reading from the new file descriptor passed
+    /// by logind when it's resumed. Once the device is removed, this returns the same error as
+    /// reading from an unplugged keyboard (`ENODEV`). The events about other devices are ignored
+    /// (see [`SessionDeviceEvent::is_for`]).
+    pub async fn apply(
+        &self,
+        keyboard: &mut KeyboardDevice,
+        ev: SessionDeviceEvent,
+    ) -> KeyloggerResult<()> {
+        if !ev.is_for(keyboard) {
+            return Ok(());
+        }
+
+        match ev {
+            SessionDeviceEvent::Paused { device, forced } => {
+                keyboard.pause();
+
+                if !forced {
+                    self.call("PauseDeviceComplete", &device).await?;
+                }
+
+                Ok(())
+            }
+            SessionDeviceEvent::Resumed { fd, .. } => {
+                let res = keyboard.0.inner.replace_file(File::from(fd));
+
+                keyboard.with_context(res)?;
+                keyboard.resume()
+            }
+            SessionDeviceEvent::Removed { .. } => {
+                let e = io::Error::from_raw_os_error(libc::ENODEV);
+                let res = Err(KeyloggerError::DeviceDisconnected(e));
+
+                keyboard.with_context(res)
+            }
+        }
+    }
+
+    /// Call a method of the session object.
+    async fn call<B>(&self, method: &str, body: &B) -> KeyloggerResult<zbus::Message>
+    where
+        B: zbus::export::serde::Serialize + zvariant::DynamicType,
+    {
+        self.conn
+            .call_method(
+                Some(LOGIND),
+                &self.session,
+                Some(SESSION_INTERFACE),
+                method,
+                body,
+            )
+            .await
+            .map_err(dbus_err)
+    }
+}
+
+/// The major and minor numbers of the specified device.
+fn device_number(device: impl DeviceMetadata) -> io::Result<DeviceNumber> {
+    let rdev = device.metadata()?.rdev();
+    let major = ((rdev >> 8) & 0xfff) | ((rdev >> 32) & !0xfff);
+    let minor = (rdev & 0xff) | ((rdev >> 12) & !0xff);
+
+    Ok((major as u32, minor as u32))
+}
+
+/// Something the metadata of a device can be read from.
+trait DeviceMetadata {
+    fn metadata(&self) -> io::Result<std::fs::Metadata>;
+}
+
+impl DeviceMetadata for &Path {
+    fn metadata(&self) -> io::Result<std::fs::Metadata> {
+        std::fs::metadata(self)
+    }
+}
+
+impl DeviceMetadata for &File {
+    fn metadata(&self) -> io::Result<std::fs::Metadata> {
+        File::metadata(self)
+    }
+}
+
+fn dbus_err(e: zbus::Error) -> KeyloggerError {
+    KeyloggerError::Dbus(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_numbers() {
+        assert_eq!(device_number(Path::new("/dev/null")).unwrap(), (1, 3));
+        assert_eq!(
+            device_number(&File::open("/dev/zero").unwrap()).unwrap(),
+            (1, 5)
+        );
+    }
+}
@@ -0,0 +1,352 @@
+//! Record key sequences and play them back.
+//!
+//! A [`MacroStore`] watches the key events of a keyboard for the [`Chord`]s bound to its macros:
+//! the record chord of a macro starts recording the keys that are typed (with their timing), and
+//! pressing it again stops the recording. The play chord of a macro requests its playback, which
+//! is performed through a [`VirtualKeyboard`].
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use keylogger::macros::{MacroEvent, MacroStore};
+//! use keylogger::{find_keyboards, KeyloggerError, VirtualKeyboard};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let mut keyboard = find_keyboards()?.remove(0);
+//!     let mut output = VirtualKeyboard::new("macro keyboard")?;
+//!     let mut store = MacroStore::new();
+//!
+//!     store.bind_record("Ctrl+Alt+F1".parse()?, "greeting");
+//!     store.bind_play("Ctrl+F1".parse()?, "greeting");
+//!
+//!     while let Some(ev) = keyboard.next().await {
+//!         if let Some(MacroEvent::Play(name)) = store.feed(&ev?) {
+//!             if let Some(m) = store.get(&name) {
+//!                 m.play(&mut output).await?;
+//!             }
+//!         }
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use crate::chords::{Chord, ChordDetector, ChordId};
+use crate::key_code::KeyCode;
+use crate::keyboard::{KeyEvent, KeyEventCause};
+use crate::timer::sleep;
+use crate::uinput::VirtualKeyboard;
+use crate::KeyloggerResult;
+
+/// A recorded key sequence.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Macro {
+    steps: Vec<(Duration, KeyEvent)>,
+}
+
+impl Macro {
+    /// Create a macro from a list of `(delay, event)` pairs, where each delay is relative to the
+    /// previous event.
+    pub fn new(steps: impl IntoIterator<Item = (Duration, KeyEvent)>) -> Self {
+        Self {
+            steps: steps.into_iter().collect(),
+        }
+    }
+
+    /// Create a macro from a list of events, using their timestamps to compute the delays between
+    /// them.
+    pub fn from_events(evs: &[KeyEvent]) -> Self {
+        let delays = evs.iter().scan(None, |prev, ev| {
+            let delay = prev
+                .replace(ev.ts)
+                .and_then(|prev| (ev.ts - prev).to_std().ok())
+                .unwrap_or_default();
+
+            Some(delay)
+        });
+
+        Self::new(delays.zip(evs.iter().copied()))
+    }
+
+    /// The `(delay, event)` pairs of the macro.
+    pub fn steps(&self) -> &[(Duration, KeyEvent)] {
+        &self.steps
+    }
+
+    /// The time it takes to play the macro back.
+    pub fn duration(&self) -> Duration {
+        self.steps.iter().map(|(delay, _)| *delay).sum()
+    }
+
+    /// Play the macro back through the specified virtual keyboard, respecting its timing.
+    ///
+    /// The delays are measured using a timer registered with the reactor of the async runtime.
+    pub async fn play(&self, output: &mut VirtualKeyboard) -> KeyloggerResult<()> {
+        for (delay, ev) in &self.steps {
+            sleep(*delay).await?;
+            output.emit(ev)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// What a key event fed to a [`MacroStore`] caused.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MacroEvent {
+    /// The recording of the specified macro started.
+    RecordingStarted(String),
+    /// The recording of the specified macro stopped, and the macro was saved.
+    RecordingStopped(String),
+    /// The specified macro should be played back.
+    ///
+    /// This is only reported once all the keys are released, so the keys of the play chord don't
+    /// interfere with the playback.
+    Play(String),
+}
+
+/// What a chord registered with the detector of a [`MacroStore`] does.
+#[derive(Clone, Debug)]
+enum Binding {
+    Record(String),
+    Play(String),
+}
+
+/// A macro being recorded.
+#[derive(Clone, Debug)]
+struct Recording {
+    name: String,
+    events: Vec<KeyEvent>,
+    /// The keys pressed since the recording started (the keys that were already held down when
+    /// it started aren't recorded).
+    pressed: HashSet<KeyCode>,
+}
+
+/// Named macros, and the chords that record and play them (see the [module-level
+/// documentation](self)).
+#[derive(Clone, Debug, Default)]
+pub struct MacroStore {
+    macros: HashMap<String, Macro>,
+    detector: ChordDetector,
+    bindings: HashMap<ChordId, Binding>,
+    recording: Option<Recording>,
+    /// The macro to play once all the keys are released.
+    pending_play: Option<String>,
+    /// The keys currently held down.
+    held: HashSet<KeyCode>,
+}
+
+impl MacroStore {
+    /// Create an empty store, with no bindings.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Start (or stop) recording the specified macro when `chord` is pressed.
+    ///
+    /// Recording a macro replaces the macro with the same name, if any.
+    pub fn bind_record(&mut self, chord: Chord, name: &str) {
+        let id = self.detector.register(chord);
+
+        self.bindings.insert(id, Binding::Record(name.into()));
+    }
+
+    /// Play the specified macro when `chord` is pressed.
+    pub fn bind_play(&mut self, chord: Chord, name: &str) {
+        let id = self.detector.register(chord);
+
+        self.bindings.insert(id, Binding::Play(name.into()));
+    }
+
+    /// Save a macro under the specified name, replacing the macro with the same name, if any.
+    pub fn insert(&mut self, name: &str, m: Macro) -> Option<Macro> {
+        self.macros.insert(name.into(), m)
+    }
+
+    /// The macro with the specified name.
+    pub fn get(&self, name: &str) -> Option<&Macro> {
+        self.macros.get(name)
+    }
+
+    /// Remove the macro with the specified name.
+    pub fn remove(&mut self, name: &str) -> Option<Macro> {
+        self.macros.remove(name)
+    }
+
+    /// The names of the saved macros.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.macros.keys().map(String::as_str)
+    }
+
+    /// The name of the macro being recorded, if any.
+    pub fn recording(&self) -> Option<&str> {
+        self.recording.as_ref().map(|r| r.name.as_str())
+    }
+
+    /// Process the next event, returning what it caused (if anything).
+    ///
+    /// All events (including releases) should be fed to the store in order. The events injected
+    /// while playing a macro back must not be fed to it.
+    pub fn feed(&mut self, ev: &KeyEvent) -> Option<MacroEvent> {
+        match ev.cause {
+            KeyEventCause::Press => {
+                self.held.insert(ev.code);
+            }
+            KeyEventCause::Release => {
+                self.held.remove(&ev.code);
+            }
+            KeyEventCause::Repeat => {}
+        }
+
+        if let Some(chord_ev) = self.detector.feed(ev) {
+            return match self.bindings.get(&chord_ev.id)?.clone() {
+                Binding::Record(name) => match self.recording.take() {
+                    Some(recording) => Some(self.finish(recording)),
+                    None => {
+                        self.recording = Some(Recording {
+                            name: name.clone(),
+                            events: vec![],
+                            pressed: Default::default(),
+                        });
+
+                        Some(MacroEvent::RecordingStarted(name))
+                    }
+                },
+                Binding::Play(name) => {
+                    if self.recording.is_none() && self.macros.contains_key(&name) {
+                        self.pending_play = Some(name);
+                    }
+
+                    None
+                }
+            };
+        }
+
+        if let Some(recording) = &mut self.recording {
+            let record = match ev.cause {
+                KeyEventCause::Press => {
+                    recording.pressed.insert(ev.code);
+                    true
+                }
+                KeyEventCause::Repeat | KeyEventCause::Release => {
+                    recording.pressed.contains(&ev.code)
+                }
+            };
+
+            if record {
+                recording.events.push(*ev);
+            }
+        }
+
+        if self.held.is_empty() {
+            return self.pending_play.take().map(MacroEvent::Play);
+        }
+
+        None
+    }
+
+    /// Save a macro that stopped being recorded.
+    fn finish(&mut self, mut recording: Recording) -> MacroEvent {
+        // The keys of the chord that stopped the recording are still held down: drop their
+        // presses, which don't have a matching release
+        for code in &self.held {
+            let last_press = recording
+                .events
+                .iter()
+                .rposition(|ev| ev.code == *code && ev.cause == KeyEventCause::Press);
+
+            if let Some(idx) = last_press {
+                let mut i = 0;
+
+                recording.events.retain(|ev| {
+                    i += 1;
+                    i <= idx || ev.code != *code
+                });
+            }
+        }
+
+        self.macros.insert(
+            recording.name.clone(),
+            Macro::from_events(&recording.events),
+        );
+
+        MacroEvent::RecordingStopped(recording.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use KeyCode::*;
+    use KeyEventCause::*;
+
+    fn ev(cause: KeyEventCause, code: KeyCode, ms: i64) -> KeyEvent {
+        KeyEvent {
+            ts: chrono::NaiveDateTime::default() + chrono::Duration::milliseconds(ms),
+            cause,
+            code,
+            scancode: None,
+        }
+    }
+
+    #[test]
+    fn record_and_play() {
+        let mut store = MacroStore::new();
+
+        store.bind_record("Ctrl+F1".parse().unwrap(), "hi");
+        store.bind_play("F1".parse().unwrap(), "hi");
+
+        let evs = [
+            ev(Press, KEY_LEFTCTRL, 0),
+            ev(Press, KEY_F1, 10),
+            ev(Release, KEY_F1, 20),
+            ev(Release, KEY_LEFTCTRL, 30),
+            ev(Press, KEY_H, 100),
+            ev(Release, KEY_H, 150),
+            ev(Press, KEY_I, 200),
+            ev(Release, KEY_I, 260),
+            ev(Press, KEY_LEFTCTRL, 1000),
+            ev(Press, KEY_F1, 1010),
+            ev(Release, KEY_F1, 1020),
+            ev(Release, KEY_LEFTCTRL, 1030),
+            ev(Press, KEY_F1, 2000),
+            ev(Release, KEY_F1, 2050),
+        ];
+
+        let macro_evs = evs
+            .iter()
+            .filter_map(|ev| store.feed(ev))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            macro_evs,
+            [
+                MacroEvent::RecordingStarted("hi".into()),
+                MacroEvent::RecordingStopped("hi".into()),
+                MacroEvent::Play("hi".into()),
+            ]
+        );
+
+        let steps = store
+            .get("hi")
+            .unwrap()
+            .steps()
+            .iter()
+            .map(|(delay, ev)| (delay.as_millis(), ev.cause, ev.code))
+            .collect::<Vec<_>>();
+
+        // The releases of the keys of the record chord aren't recorded
+        assert_eq!(
+            steps,
+            [
+                (0, Press, KEY_H),
+                (50, Release, KEY_H),
+                (50, Press, KEY_I),
+                (60, Release, KEY_I),
+            ]
+        );
+    }
+}
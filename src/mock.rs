@@ -0,0 +1,167 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::{ready, Stream};
+
+use crate::keyboard::KeyEvent;
+use crate::timer::Timer;
+use crate::KeyloggerResult;
+
+/// A fake keyboard that replays a scripted sequence of [`KeyEvent`]s.
+///
+/// Like [`KeyboardDevice`](crate::KeyboardDevice), a `MockKeyboard` implements [`Stream`], so the
+/// code that handles the events of a keyboard can be tested without access to a real input
+/// device. Each event of the script is preceded by a delay, relative to the previous event. The
+/// events are returned as they were scripted: their timestamps aren't updated to reflect the
+/// time they were replayed at.
+///
+/// Unlike a real keyboard, the stream ends once the script is exhausted.
+///
+/// ```
+/// use futures::StreamExt;
+/// use keylogger::{KeyCode, KeyEvent, KeyEventCause, MockKeyboard};
+/// use std::time::Duration;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let ev = |cause| KeyEvent {
+///     ts: Default::default(),
+///     cause,
+///     code: KeyCode::KEY_A,
+///     scancode: None,
+/// };
+///
+/// let keyboard = MockKeyboard::new([
+///     (Duration::ZERO, ev(KeyEventCause::Press)),
+///     (Duration::from_millis(80), ev(KeyEventCause::Release)),
+/// ]);
+///
+/// let evs = keyboard.map(|ev| ev.unwrap().cause).collect::<Vec<_>>().await;
+///
+/// assert_eq!(evs, [KeyEventCause::Press, KeyEventCause::Release]);
+/// # }
+/// ```
+///
+/// The delays are measured using a timer registered with the reactor of the async runtime, so a
+/// script with non-zero delays must be replayed from within the runtime, like a real keyboard.
+#[derive(Debug)]
+pub struct MockKeyboard {
+    name: String,
+    path: PathBuf,
+    script: VecDeque<(Duration, KeyEvent)>,
+    /// The timer that expires once the delay of the next event elapsed, created on demand.
+    timer: Option<Timer>,
+    /// Whether the timer is armed for the next event.
+    armed: bool,
+}
+
+impl MockKeyboard {
+    /// Create a keyboard that replays the specified `(delay, event)` pairs.
+    pub fn new(script: impl IntoIterator<Item = (Duration, KeyEvent)>) -> Self {
+        Self {
+            name: "mock keyboard".into(),
+            path: PathBuf::from("/dev/input/mock"),
+            script: script.into_iter().collect(),
+            timer: None,
+            armed: false,
+        }
+    }
+
+    /// Create a keyboard that returns the specified events straight away.
+    pub fn from_events(evs: impl IntoIterator<Item = KeyEvent>) -> Self {
+        Self::new(evs.into_iter().map(|ev| (Duration::ZERO, ev)))
+    }
+
+    /// Set the name reported by the keyboard (which defaults to "mock keyboard").
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Set the path reported by the keyboard (which defaults to `/dev/input/mock`).
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    /// A human-readable description of the keyboard.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The path of the keyboard.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of events left to replay.
+    pub fn remaining(&self) -> usize {
+        self.script.len()
+    }
+}
+
+impl Stream for MockKeyboard {
+    type Item = KeyloggerResult<KeyEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        let Some((delay, _)) = this.script.front() else {
+            return Poll::Ready(None);
+        };
+
+        if !delay.is_zero() {
+            let timer = match &mut this.timer {
+                Some(timer) => timer,
+                timer => timer.insert(Timer::new()?),
+            };
+
+            if !this.armed {
+                timer.arm(*delay)?;
+                this.armed = true;
+            }
+
+            ready!(timer.poll_expired(cx))?;
+            this.armed = false;
+        }
+
+        Poll::Ready(this.script.pop_front().map(|(_, ev)| Ok(ev)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_code::KeyCode;
+    use crate::keyboard::KeyEventCause;
+    use futures::StreamExt;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn replay_script() {
+        let ev = |code| KeyEvent {
+            ts: Default::default(),
+            cause: KeyEventCause::Press,
+            code,
+            scancode: None,
+        };
+
+        let keyboard = MockKeyboard::new([
+            (Duration::from_millis(20), ev(KeyCode::KEY_A)),
+            (Duration::ZERO, ev(KeyCode::KEY_B)),
+            (Duration::from_millis(30), ev(KeyCode::KEY_C)),
+        ]);
+
+        let start = Instant::now();
+        let codes = keyboard
+            .map(|ev| ev.unwrap().code)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(codes, [KeyCode::KEY_A, KeyCode::KEY_B, KeyCode::KEY_C]);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
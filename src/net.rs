@@ -0,0 +1,431 @@
+//! Serve the captured [`KeyEvent`]s to remote subscribers over the network.
+//!
+//! An [`EventServer`] broadcasts the events [published](EventServer::publish) to it to every
+//! connected client. Clients can subscribe over plain TCP, where each event is encoded using the
+//! configured [`Encoding`], or (with the `websocket` feature) over WebSocket, where each event is
+//! sent as a JSON text message.
+//!
+//! Clients that can't keep up miss the oldest events rather than slowing down the capture.
+//!
+//! Anyone who can connect to the server can see the keys that are typed, so unless it only
+//! listens on a trusted interface, the server should require the clients to authenticate using a
+//! pre-shared key (see [`EventServer::with_key`]), and the clients should connect using an
+//! [`EventClient`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use keylogger::net::{Encoding, EventServer};
+//! use keylogger::{merge_keyboards, KeyloggerError};
+//! use tokio::net::TcpListener;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let server = EventServer::new(1024);
+//!     let listener = TcpListener::bind("127.0.0.1:7878").await?;
+//!
+//!     tokio::spawn(server.clone().serve_tcp(listener, Encoding::JsonLines));
+//!
+//!     let mut keyboards = merge_keyboards()?;
+//!
+//!     while let Some(ev) = keyboards.next().await {
+//!         server.publish(ev?.event);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+pub(crate) mod auth;
+
+use std::fmt;
+use std::sync::Arc;
+
+use futures::Stream;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::error::KeyloggerError;
+use crate::keyboard::KeyEvent;
+use crate::KeyloggerResult;
+use auth::Session;
+
+/// The tag of the protocol, which the server starts the handshake with.
+const PROTOCOL: &[u8; 4] = b"KLEV";
+/// The maximum number of TCP clients that can be authenticating at the same time.
+const MAX_PENDING_HANDSHAKES: usize = 32;
+
+/// How the events are encoded on the wire.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Encoding {
+    /// Each event is a JSON object, followed by a newline.
+    #[default]
+    JsonLines,
+    /// Each event is encoded using bincode, and prefixed by its length (as a big-endian `u32`).
+    Bincode,
+}
+
+/// Encode an event for sending over the wire.
+pub fn encode(ev: &KeyEvent, encoding: Encoding) -> KeyloggerResult<Vec<u8>> {
+    let mut payload = serialize(ev, encoding)?;
+
+    match encoding {
+        Encoding::JsonLines => {
+            payload.push(b'\n');
+
+            Ok(payload)
+        }
+        Encoding::Bincode => {
+            let len = u32::try_from(payload.len())
+                .map_err(|_| KeyloggerError::Serialization("event too large".into()))?;
+
+            Ok([&len.to_be_bytes()[..], &payload].concat())
+        }
+    }
+}
+
+/// Serialize an event, without the delimiter or the length prefix added by [`encode`].
+pub(crate) fn serialize(ev: &KeyEvent, encoding: Encoding) -> KeyloggerResult<Vec<u8>> {
+    match encoding {
+        Encoding::JsonLines => serde_json::to_vec(ev).map_err(|e| serialization_err(&e)),
+        Encoding::Bincode => bincode::serialize(ev).map_err(|e| serialization_err(&e)),
+    }
+}
+
+/// Deserialize an event serialized using [`serialize`].
+pub(crate) fn deserialize(payload: &[u8], encoding: Encoding) -> KeyloggerResult<KeyEvent> {
+    match encoding {
+        Encoding::JsonLines => serde_json::from_slice(payload).map_err(|e| serialization_err(&e)),
+        Encoding::Bincode => bincode::deserialize(payload).map_err(|e| serialization_err(&e)),
+    }
+}
+
+fn serialization_err(e: &dyn std::error::Error) -> KeyloggerError {
+    KeyloggerError::Serialization(e.to_string())
+}
+
+/// Broadcasts key events to the clients connected over the network.
+///
+/// Cloning an `EventServer` is cheap: the clones publish to the same set of clients.
+#[derive(Clone)]
+pub struct EventServer {
+    tx: broadcast::Sender<KeyEvent>,
+    /// The key the clients authenticate with, if they have to.
+    key: Option<Arc<[u8]>>,
+}
+
+impl fmt::Debug for EventServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The key isn't printed
+        f.debug_struct("EventServer")
+            .field("tx", &self.tx)
+            .field("authenticated", &self.key.is_some())
+            .finish()
+    }
+}
+
+impl EventServer {
+    /// Create a server that buffers up to `capacity` events for each client.
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity.max(1));
+
+        Self { tx, key: None }
+    }
+
+    /// Require the TCP clients to authenticate using the specified pre-shared key.
+    ///
+    /// The clients that don't know the key are disconnected, and the events are sent to the ones
+    /// that do encrypted, and authenticated with a sequence number, so they can't be read, forged
+    /// or replayed by anyone who doesn't know the key (see [`EventClient`]).
+    ///
+    /// The WebSocket clients can't authenticate, so [`EventServer::serve_websocket`] refuses to
+    /// serve the events if a key is set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is empty, since anyone could authenticate using it.
+    pub fn with_key(mut self, key: &[u8]) -> Self {
+        assert!(!key.is_empty(), "the key of an EventServer can't be empty");
+
+        self.key = Some(key.into());
+        self
+    }
+
+    /// Send an event to all the connected clients.
+    pub fn publish(&self, ev: KeyEvent) {
+        // An error means nobody is subscribed, which is fine
+        let _ = self.tx.send(ev);
+    }
+
+    /// The number of connected clients.
+    pub fn client_count(&self) -> usize {
+        self.tx.receiver_count()
+    }
+
+    /// Accept TCP clients on the specified listener, and stream the events to them using the
+    /// specified encoding.
+    ///
+    /// Each client is served by a separate task. This only returns if accepting a connection
+    /// fails.
+    ///
+    /// If the clients are required to authenticate (see [`EventServer::with_key`]), the ones that
+    /// don't complete the handshake within a few seconds are disconnected, and only a limited
+    /// number of them can be authenticating at a time: the connections accepted beyond that limit
+    /// are closed straight away, so the clients that never authenticate can't tie up the server.
+    pub async fn serve_tcp(self, listener: TcpListener, encoding: Encoding) -> KeyloggerResult<()> {
+        let handshakes = Arc::new(Semaphore::new(MAX_PENDING_HANDSHAKES));
+
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let handshake = match &self.key {
+                Some(_) => match Arc::clone(&handshakes).try_acquire_owned() {
+                    Ok(permit) => Some(permit),
+                    // Too many clients are authenticating already
+                    Err(_) => continue,
+                },
+                None => None,
+            };
+            let rx = self.tx.subscribe();
+            let key = self.key.clone();
+
+            tokio::spawn(async move {
+                // The client is dropped if the authentication or the connection fails
+                let _ = match key.zip(handshake) {
+                    Some((key, handshake)) => {
+                        serve_authenticated_client(stream, rx, encoding, &key, handshake).await
+                    }
+                    None => serve_tcp_client(stream, rx, encoding).await,
+                };
+            });
+        }
+    }
+
+    /// Accept WebSocket clients on the specified listener, and stream the events to them as JSON
+    /// text messages.
+    ///
+    /// Each client is served by a separate task. This only returns if accepting a connection
+    /// fails, or straight away (with [`KeyloggerError::Authentication`]) if the clients are
+    /// required to authenticate (see [`EventServer::with_key`]).
+    #[cfg(feature = "websocket")]
+    pub async fn serve_websocket(self, listener: TcpListener) -> KeyloggerResult<()> {
+        if self.key.is_some() {
+            return Err(KeyloggerError::Authentication(
+                "WebSocket clients can't authenticate".into(),
+            ));
+        }
+
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let rx = self.tx.subscribe();
+
+            tokio::spawn(async move {
+                // The client is dropped if the handshake or the connection fails
+                let _ = serve_websocket_client(stream, rx).await;
+            });
+        }
+    }
+}
+
+async fn serve_tcp_client(
+    mut stream: TcpStream,
+    mut rx: broadcast::Receiver<KeyEvent>,
+    encoding: Encoding,
+) -> KeyloggerResult<()> {
+    while let Some(ev) = recv(&mut rx).await {
+        stream.write_all(&encode(&ev, encoding)?).await?;
+    }
+
+    Ok(())
+}
+
+async fn serve_authenticated_client(
+    stream: TcpStream,
+    mut rx: broadcast::Receiver<KeyEvent>,
+    encoding: Encoding,
+    key: &[u8],
+    handshake: OwnedSemaphorePermit,
+) -> KeyloggerResult<()> {
+    let mut session = Session::accept(stream, key, PROTOCOL).await?;
+
+    // The client is authenticated, so it no longer counts towards the pending handshakes
+    drop(handshake);
+
+    while let Some(ev) = recv(&mut rx).await {
+        session.send(&serialize(&ev, encoding)?).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "websocket")]
+async fn serve_websocket_client(
+    stream: TcpStream,
+    mut rx: broadcast::Receiver<KeyEvent>,
+) -> KeyloggerResult<()> {
+    use futures::SinkExt;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let ws_err =
+        |e: tokio_tungstenite::tungstenite::Error| KeyloggerError::Io(std::io::Error::other(e));
+
+    let mut ws = tokio_tungstenite::accept_async(stream)
+        .await
+        .map_err(ws_err)?;
+
+    while let Some(ev) = recv(&mut rx).await {
+        let json =
+            serde_json::to_string(&ev).map_err(|e| KeyloggerError::Serialization(e.to_string()))?;
+
+        ws.send(Message::Text(json)).await.map_err(ws_err)?;
+    }
+
+    Ok(())
+}
+
+/// Receives the events of an [`EventServer`] that requires its clients to authenticate (see
+/// [`EventServer::with_key`]).
+///
+/// ```no_run
+/// use keylogger::net::{Encoding, EventClient};
+/// use keylogger::KeyloggerError;
+/// use tokio::net::TcpStream;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), KeyloggerError> {
+///     let stream = TcpStream::connect("192.168.1.20:7878").await?;
+///     let mut client = EventClient::handshake(stream, b"a long shared secret", Encoding::Bincode)
+///         .await?;
+///
+///     while let Some(ev) = client.recv().await? {
+///         println!("{ev:?}");
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct EventClient<T> {
+    session: Session<T>,
+    encoding: Encoding,
+}
+
+impl<T> EventClient<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Authenticate with the server at the other end of `transport`, using the shared `key`.
+    ///
+    /// The encoding must be the one the server was [started](EventServer::serve_tcp) with. This
+    /// fails with [`KeyloggerError::Authentication`] if the server doesn't know the key (so the
+    /// events aren't received from an impostor), or with an I/O error of kind `TimedOut` if the
+    /// handshake doesn't complete within a few seconds.
+    pub async fn handshake(transport: T, key: &[u8], encoding: Encoding) -> KeyloggerResult<Self> {
+        Ok(Self {
+            session: Session::connect(transport, key, PROTOCOL).await?,
+            encoding,
+        })
+    }
+
+    /// Receive the next event, or `None` if the server closed the connection.
+    ///
+    /// This fails with [`KeyloggerError::Authentication`] if the event wasn't sent by the server
+    /// (or was tampered with, or replayed), in which case the connection shouldn't be used
+    /// anymore.
+    pub async fn recv(&mut self) -> KeyloggerResult<Option<KeyEvent>> {
+        match self.session.recv().await? {
+            Some(payload) => deserialize(&payload, self.encoding).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Turn the client into a stream of the events it receives, which ends once the server
+    /// closes the connection (or after the first error).
+    pub fn into_events(self) -> impl Stream<Item = KeyloggerResult<KeyEvent>> {
+        futures::stream::try_unfold(self, |mut client| async move {
+            Ok(client.recv().await?.map(|ev| (ev, client)))
+        })
+    }
+
+    /// Close the connection, returning the transport.
+    pub fn into_inner(self) -> T {
+        self.session.into_inner()
+    }
+}
+
+/// Receive the next event, skipping over the events the client was too slow to receive.
+async fn recv(rx: &mut broadcast::Receiver<KeyEvent>) -> Option<KeyEvent> {
+    loop {
+        match rx.recv().await {
+            Ok(ev) => return Some(ev),
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_code::KeyCode;
+    use crate::keyboard::KeyEventCause;
+
+    #[test]
+    fn encode_events() {
+        let ev = KeyEvent {
+            ts: Default::default(),
+            cause: KeyEventCause::Press,
+            code: KeyCode::KEY_A,
+            scancode: Some(0x1e),
+        };
+
+        let json = encode(&ev, Encoding::JsonLines).unwrap();
+
+        assert_eq!(json.last(), Some(&b'\n'));
+        assert_eq!(serde_json::from_slice::<KeyEvent>(&json).unwrap(), ev);
+
+        let bin = encode(&ev, Encoding::Bincode).unwrap();
+        let len = u32::from_be_bytes(bin[..4].try_into().unwrap()) as usize;
+
+        assert_eq!(len, bin.len() - 4);
+        assert_eq!(bincode::deserialize::<KeyEvent>(&bin[4..]).unwrap(), ev);
+    }
+
+    #[tokio::test]
+    async fn authenticated_clients() {
+        let server = EventServer::new(16).with_key(b"secret");
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(server.clone().serve_tcp(listener, Encoding::JsonLines));
+
+        // A client that doesn't know the key is disconnected
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let client = EventClient::handshake(stream, b"guess", Encoding::JsonLines).await;
+
+        assert!(matches!(client, Err(KeyloggerError::Authentication(_))));
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut client = EventClient::handshake(stream, b"secret", Encoding::JsonLines)
+            .await
+            .unwrap();
+        let ev = KeyEvent {
+            ts: Default::default(),
+            cause: KeyEventCause::Press,
+            code: KeyCode::KEY_A,
+            scancode: None,
+        };
+
+        server.publish(ev);
+
+        assert_eq!(client.recv().await.unwrap(), Some(ev));
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be empty")]
+    fn empty_key() {
+        let _ = EventServer::new(1).with_key(b"");
+    }
+}
@@ -0,0 +1,481 @@
+//! The authenticated connections of the [`net`](crate::net) and `remote` modules.
+//!
+//! When the connection is established, the listening end sends the tag and version of the protocol
+//! and a random challenge, and the connecting end answers with its own challenge and a MAC
+//! (HMAC-SHA256, keyed with the pre-shared key) of the protocol and both challenges, which the
+//! listening end checks before sending back its own MAC of them. The handshake must complete
+//! within [`HANDSHAKE_TIMEOUT`], so a peer can't hold on to a connection without authenticating.
+//! Since the MACs cover the protocol, a handshake can't be passed off as one of another protocol
+//! (or version) that shares the key.
+//!
+//! The messages are then encrypted and authenticated using ChaCha20-Poly1305, with a key for each
+//! direction derived (using HKDF-SHA256) from the pre-shared key, the protocol and both
+//! challenges. Each message
+//! is sent as the length of its payload (as a big-endian `u32`), followed by the encrypted payload
+//! and its tag. The nonce of a message is its sequence number, so the messages can't be read
+//! without the key, or forged, reflected back to the end that sent them, or replayed within the
+//! same connection or another one.
+
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::pin;
+use std::time::Duration;
+
+use chacha20poly1305::aead::{Aead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, KeyInit, Nonce};
+use futures::future::{self, Either};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::error::KeyloggerError;
+use crate::timer::sleep;
+use crate::KeyloggerResult;
+
+/// The version of the protocol, which follows its tag in the handshake.
+const VERSION: u8 = 2;
+/// The length of the challenges exchanged during the handshake.
+const CHALLENGE_LEN: usize = 16;
+/// The length of an HMAC-SHA256.
+const MAC_LEN: usize = 32;
+/// The length of the tag that authenticates an encrypted message.
+const TAG_LEN: usize = 16;
+/// The maximum length of the payload of a message, which is far more than an event needs.
+const MAX_PAYLOAD_LEN: usize = 4096;
+/// How long the peer has to complete the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// An encrypted connection whose ends authenticated each other using a pre-shared key.
+pub(crate) struct Session<T> {
+    transport: T,
+    /// The cipher of the messages sent by this end.
+    sending: ChaCha20Poly1305,
+    /// The cipher of the messages sent by the peer, whose key is different (so a message can't be
+    /// reflected back to the end that sent it).
+    receiving: ChaCha20Poly1305,
+    /// The sequence number of the next message sent.
+    sent: u64,
+    /// The sequence number of the next message received.
+    received: u64,
+}
+
+impl<T: fmt::Debug> fmt::Debug for Session<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The keys aren't printed
+        f.debug_struct("Session")
+            .field("transport", &self.transport)
+            .field("sent", &self.sent)
+            .field("received", &self.received)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Session<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Authenticate the end that connected to `transport`, using the shared `key`.
+    ///
+    /// The `protocol` tag prevents the ends of different protocols from talking to each other.
+    pub(crate) async fn accept(
+        transport: T,
+        key: &[u8],
+        protocol: &[u8; 4],
+    ) -> KeyloggerResult<Self> {
+        with_deadline(Self::handshake_accept(transport, key, protocol)).await
+    }
+
+    /// Authenticate with the end listening on the other end of `transport`, using the shared
+    /// `key`.
+    pub(crate) async fn connect(
+        transport: T,
+        key: &[u8],
+        protocol: &[u8; 4],
+    ) -> KeyloggerResult<Self> {
+        with_deadline(Self::handshake_connect(transport, key, protocol)).await
+    }
+
+    async fn handshake_accept(
+        mut transport: T,
+        key: &[u8],
+        protocol: &[u8; 4],
+    ) -> KeyloggerResult<Self> {
+        let protocol = versioned(protocol);
+        let mut challenges = [0; 2 * CHALLENGE_LEN];
+
+        fill_random(&mut challenges[..CHALLENGE_LEN])?;
+
+        transport
+            .write_all(&[&protocol[..], &challenges[..CHALLENGE_LEN]].concat())
+            .await?;
+        transport.flush().await?;
+
+        let mut reply = [0; CHALLENGE_LEN + MAC_LEN];
+
+        transport.read_exact(&mut reply).await?;
+        challenges[CHALLENGE_LEN..].copy_from_slice(&reply[..CHALLENGE_LEN]);
+
+        if hmac(key, &[b"connect", &protocol, &challenges])
+            .verify_slice(&reply[CHALLENGE_LEN..])
+            .is_err()
+        {
+            return Err(authentication_err("the peer doesn't know the key"));
+        }
+
+        transport
+            .write_all(
+                &hmac(key, &[b"accept", &protocol, &challenges])
+                    .finalize()
+                    .into_bytes(),
+            )
+            .await?;
+        transport.flush().await?;
+
+        Ok(Self::new(transport, key, &protocol, challenges, true))
+    }
+
+    async fn handshake_connect(
+        mut transport: T,
+        key: &[u8],
+        protocol: &[u8; 4],
+    ) -> KeyloggerResult<Self> {
+        let protocol = versioned(protocol);
+        let mut hello = [0; 4 + 1 + CHALLENGE_LEN];
+
+        transport.read_exact(&mut hello).await?;
+
+        if hello[..4] != protocol[..4] {
+            return Err(authentication_err("the peer speaks a different protocol"));
+        }
+
+        if hello[4] != VERSION {
+            return Err(authentication_err("unsupported protocol version"));
+        }
+
+        let mut challenges = [0; 2 * CHALLENGE_LEN];
+
+        challenges[..CHALLENGE_LEN].copy_from_slice(&hello[5..]);
+        fill_random(&mut challenges[CHALLENGE_LEN..])?;
+
+        let mac = hmac(key, &[b"connect", &protocol, &challenges])
+            .finalize()
+            .into_bytes();
+
+        transport
+            .write_all(&[&challenges[CHALLENGE_LEN..], &mac[..]].concat())
+            .await?;
+        transport.flush().await?;
+
+        let mut peer_mac = [0; MAC_LEN];
+
+        // The peer closes the connection if it rejects the key
+        transport
+            .read_exact(&mut peer_mac)
+            .await
+            .map_err(|_| authentication_err("the peer rejected the key"))?;
+
+        if hmac(key, &[b"accept", &protocol, &challenges])
+            .verify_slice(&peer_mac)
+            .is_err()
+        {
+            return Err(authentication_err("the peer doesn't know the key"));
+        }
+
+        Ok(Self::new(transport, key, &protocol, challenges, false))
+    }
+
+    /// Set up the session once the handshake of the (versioned) `protocol` completed.
+    fn new(
+        transport: T,
+        key: &[u8],
+        protocol: &[u8; 5],
+        challenges: [u8; 2 * CHALLENGE_LEN],
+        listening: bool,
+    ) -> Self {
+        let hkdf = Hkdf::<Sha256>::new(Some(&challenges), key);
+        let cipher = |from: &[u8]| {
+            let mut key = [0; 32];
+
+            hkdf.expand(
+                &[b"message key ", &protocol[..], b" ", from].concat(),
+                &mut key,
+            )
+            .expect("a ChaCha20 key is shorter than the maximum HKDF output");
+
+            ChaCha20Poly1305::new(&key.into())
+        };
+        let (accept, connect) = (cipher(b"accept"), cipher(b"connect"));
+        let (sending, receiving) = if listening {
+            (accept, connect)
+        } else {
+            (connect, accept)
+        };
+
+        Self {
+            transport,
+            sending,
+            receiving,
+            sent: 0,
+            received: 0,
+        }
+    }
+
+    /// Send a message with the specified payload.
+    pub(crate) async fn send(&mut self, payload: &[u8]) -> KeyloggerResult<()> {
+        let len = u32::try_from(payload.len())
+            .ok()
+            .filter(|len| *len as usize <= MAX_PAYLOAD_LEN)
+            .ok_or_else(|| KeyloggerError::Serialization("message too large".into()))?;
+        let len = len.to_be_bytes();
+        let ciphertext = self
+            .sending
+            .encrypt(
+                &nonce(self.sent),
+                Payload {
+                    msg: payload,
+                    aad: &len,
+                },
+            )
+            .map_err(|_| KeyloggerError::Serialization("failed to encrypt a message".into()))?;
+
+        self.sent += 1;
+        self.transport
+            .write_all(&[&len[..], &ciphertext].concat())
+            .await?;
+        // The messages are sent straight away, rather than whenever the buffer of a (TLS)
+        // transport fills up
+        self.transport.flush().await?;
+
+        Ok(())
+    }
+
+    /// Receive the payload of the next message, or `None` if the peer closed the connection.
+    ///
+    /// This fails with [`KeyloggerError::Authentication`] if the message wasn't sent by the peer
+    /// in this session (or was tampered with, or replayed), in which case the connection
+    /// shouldn't be used anymore.
+    pub(crate) async fn recv(&mut self) -> KeyloggerResult<Option<Vec<u8>>> {
+        let len = match self.transport.read_u32().await {
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        if len as usize > MAX_PAYLOAD_LEN {
+            return Err(KeyloggerError::Serialization(format!(
+                "message too large: {len} bytes"
+            )));
+        }
+
+        let mut ciphertext = vec![0; len as usize + TAG_LEN];
+
+        self.transport.read_exact(&mut ciphertext).await?;
+
+        let payload = self
+            .receiving
+            .decrypt(
+                &nonce(self.received),
+                Payload {
+                    msg: &ciphertext,
+                    aad: &len.to_be_bytes(),
+                },
+            )
+            .map_err(|_| authentication_err("invalid message tag"))?;
+
+        self.received += 1;
+
+        Ok(Some(payload))
+    }
+
+    /// Close the session, returning the transport.
+    pub(crate) fn into_inner(self) -> T {
+        self.transport
+    }
+
+    /// The transport, for capturing (or tampering with) the messages in the tests.
+    #[cfg(test)]
+    pub(crate) fn transport_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+}
+
+/// The tag of the specified protocol, followed by the version of the handshake, as sent in the
+/// hello of the listening end.
+fn versioned(protocol: &[u8; 4]) -> [u8; 5] {
+    let [a, b, c, d] = *protocol;
+
+    [a, b, c, d, VERSION]
+}
+
+/// The nonce of the message with the specified sequence number.
+fn nonce(seq: u64) -> Nonce {
+    let mut nonce = Nonce::default();
+
+    nonce[4..].copy_from_slice(&seq.to_be_bytes());
+    nonce
+}
+
+fn authentication_err(msg: &str) -> KeyloggerError {
+    KeyloggerError::Authentication(msg.into())
+}
+
+/// Fail the specified handshake if it doesn't complete within [`HANDSHAKE_TIMEOUT`].
+async fn with_deadline<S>(
+    handshake: impl Future<Output = KeyloggerResult<S>>,
+) -> KeyloggerResult<S> {
+    match future::select(pin!(handshake), pin!(sleep(HANDSHAKE_TIMEOUT))).await {
+        Either::Left((res, _)) => res,
+        Either::Right((res, _)) => {
+            res?;
+
+            Err(io::Error::new(io::ErrorKind::TimedOut, "the handshake timed out").into())
+        }
+    }
+}
+
+/// The HMAC-SHA256 of the concatenation of `parts`, keyed with `key`.
+///
+/// The MAC is finalized by the caller, or checked in constant time using [`Mac::verify_slice`].
+fn hmac(key: &[u8], parts: &[&[u8]]) -> HmacSha256 {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC takes keys of any length");
+
+    for part in parts {
+        mac.update(part);
+    }
+
+    mac
+}
+
+/// Fill the specified buffer with random bytes, using the `getrandom` syscall.
+fn fill_random(buf: &mut [u8]) -> KeyloggerResult<()> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        let rest = &mut buf[filled..];
+        let n = unsafe { libc::getrandom(rest.as_mut_ptr() as *mut libc::c_void, rest.len(), 0) };
+
+        if n < 0 {
+            let e = io::Error::last_os_error();
+
+            if e.kind() != io::ErrorKind::Interrupted {
+                return Err(e.into());
+            }
+        } else {
+            filled += n as usize;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::DuplexStream;
+
+    #[tokio::test]
+    async fn authenticated_sessions() {
+        // RFC 4231, test case 1
+        assert_eq!(
+            hmac(&[0x0b; 20], &[b"Hi ", b"There"])
+                .finalize()
+                .into_bytes()[..],
+            [
+                0xb0, 0x34, 0x4c, 0x61, 0xd8, 0xdb, 0x38, 0x53, 0x5c, 0xa8, 0xaf, 0xce, 0xaf, 0x0b,
+                0xf1, 0x2b, 0x88, 0x1d, 0xc2, 0x00, 0xc9, 0x83, 0x3d, 0xa7, 0x26, 0xe9, 0x37, 0x6c,
+                0x2e, 0x32, 0xcf, 0xf7
+            ]
+        );
+
+        let sessions = |connect_key: &'static [u8], protocol: &'static [u8; 4]| async move {
+            let (a, b) = tokio::io::duplex(1024);
+
+            tokio::join!(
+                Session::<DuplexStream>::accept(a, b"secret", b"TEST"),
+                Session::<DuplexStream>::connect(b, connect_key, protocol),
+            )
+        };
+
+        let (server, client) = sessions(b"secret", b"TEST").await;
+        let (mut server, mut client) = (server.unwrap(), client.unwrap());
+
+        server.send(b"one").await.unwrap();
+        client.send(b"two").await.unwrap();
+
+        assert_eq!(client.recv().await.unwrap().unwrap(), b"one");
+        assert_eq!(server.recv().await.unwrap().unwrap(), b"two");
+
+        // A message reflected back to the end that sent it is rejected
+        client.send(b"333").await.unwrap();
+
+        let mut reflected = vec![0; 4 + 3 + TAG_LEN];
+
+        server.transport.read_exact(&mut reflected).await.unwrap();
+        server.transport.write_all(&reflected).await.unwrap();
+
+        assert!(matches!(
+            client.recv().await,
+            Err(KeyloggerError::Authentication(_))
+        ));
+
+        // The payload is encrypted, and a message can only be received once
+        let (server, client) = sessions(b"secret", b"TEST").await;
+        let (mut server, mut client) = (server.unwrap(), client.unwrap());
+        let mut captured = vec![0; 4 + 6 + TAG_LEN];
+
+        server.send(b"secret").await.unwrap();
+        client.transport.read_exact(&mut captured).await.unwrap();
+
+        assert!(!captured.windows(6).any(|w| w == b"secret"));
+
+        server.transport.write_all(&captured).await.unwrap();
+        server.transport.write_all(&captured).await.unwrap();
+
+        assert_eq!(client.recv().await.unwrap().unwrap(), b"secret");
+        assert!(matches!(
+            client.recv().await,
+            Err(KeyloggerError::Authentication(_))
+        ));
+
+        drop(server);
+
+        // The peers that don't know the key, or speak another protocol, are rejected
+        let (server, client) = sessions(b"guess", b"TEST").await;
+
+        assert!(matches!(server, Err(KeyloggerError::Authentication(_))));
+        assert!(matches!(client, Err(KeyloggerError::Authentication(_))));
+
+        let (_, client) = sessions(b"secret", b"ELSE").await;
+
+        assert!(matches!(client, Err(KeyloggerError::Authentication(_))));
+    }
+
+    #[tokio::test]
+    async fn relayed_handshake() {
+        // A relay that passes the hello of a server off as the hello of another protocol, which
+        // uses the same key
+        let (a, mut relay_a) = tokio::io::duplex(1024);
+        let (b, mut relay_b) = tokio::io::duplex(1024);
+        let relay = async move {
+            let mut hello = [0; 4 + 1 + CHALLENGE_LEN];
+
+            relay_a.read_exact(&mut hello).await?;
+            hello[..4].copy_from_slice(b"ELSE");
+            relay_b.write_all(&hello).await?;
+            tokio::io::copy_bidirectional(&mut relay_a, &mut relay_b).await
+        };
+
+        let (server, client, _) = tokio::join!(
+            Session::<DuplexStream>::accept(a, b"secret", b"TEST"),
+            Session::<DuplexStream>::connect(b, b"secret", b"ELSE"),
+            relay,
+        );
+
+        assert!(matches!(server, Err(KeyloggerError::Authentication(_))));
+        assert!(matches!(client, Err(KeyloggerError::Authentication(_))));
+    }
+}
@@ -0,0 +1,150 @@
+//! Suppress the capture while sensitive input, such as a password, is being typed.
+//!
+//! A [`PrivacyGuard`] is a flag shared between the code that detects sensitive input and the
+//! streams it protects: while the guard is [sensitive](PrivacyGuard::is_sensitive), the events of
+//! the streams guarded using [`KeyEventStreamExt::with_privacy_guard`] are dropped.
+//!
+//! The guard can be raised and lowered manually, or (with the `atspi` feature) automatically,
+//! whenever a password field gains or loses the focus (see
+//! [`PrivacyGuard::watch_password_fields`]).
+//!
+//! # Example
+//!
+//! ```no_run
+//! # #[cfg(feature = "atspi")]
+//! # mod example {
+//! use futures::StreamExt;
+//! use keylogger::filters::KeyEventStreamExt;
+//! use keylogger::privacy::PrivacyGuard;
+//! use keylogger::{find_keyboards, KeyloggerError};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let guard = PrivacyGuard::new();
+//!
+//!     tokio::spawn(guard.clone().watch_password_fields());
+//!
+//!     let mut events = find_keyboards()?.remove(0).with_privacy_guard(guard);
+//!
+//!     while let Some(ev) = events.next().await {
+//!         println!("{:?}", ev?);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! # }
+//! ```
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::{ready, Stream};
+use pin_project::pin_project;
+
+#[cfg(doc)]
+use crate::filters::KeyEventStreamExt;
+use crate::keyboard::KeyEvent;
+use crate::KeyloggerResult;
+
+/// Tracks whether sensitive input is being typed.
+///
+/// Cloning a `PrivacyGuard` is cheap: the clones share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct PrivacyGuard {
+    sensitive: Arc<AtomicBool>,
+}
+
+impl PrivacyGuard {
+    /// Create a guard that isn't sensitive.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Start or stop suppressing the events of the guarded streams.
+    pub fn set_sensitive(&self, sensitive: bool) {
+        self.sensitive.store(sensitive, Ordering::SeqCst);
+    }
+
+    /// Whether the events of the guarded streams are being suppressed.
+    pub fn is_sensitive(&self) -> bool {
+        self.sensitive.load(Ordering::SeqCst)
+    }
+
+    /// Mark the guard sensitive whenever a password field has the focus.
+    ///
+    /// The focus changes are reported by the AT-SPI accessibility bus, so this only detects the
+    /// password fields of the applications that support it (GTK, Qt, Firefox, Chromium...), and
+    /// only works if the process can connect to the session bus of the user. This only returns if
+    /// the connection to the accessibility bus fails.
+    ///
+    /// There's a short delay between a password field gaining the focus and the guard becoming
+    /// sensitive, so the first keystrokes typed into it may not be suppressed.
+    #[cfg(feature = "atspi")]
+    pub async fn watch_password_fields(self) -> KeyloggerResult<()> {
+        crate::accessibility::watch_focus(|role| {
+            self.set_sensitive(role == Some(atspi::Role::PasswordText))
+        })
+        .await
+    }
+}
+
+/// A stream adapter that drops the events that occur while a [`PrivacyGuard`] is sensitive (see
+/// [`KeyEventStreamExt::with_privacy_guard`]).
+#[pin_project]
+pub struct Guarded<S> {
+    #[pin]
+    pub(crate) events: S,
+    pub(crate) guard: PrivacyGuard,
+}
+
+impl<S> Stream for Guarded<S>
+where
+    S: Stream<Item = KeyloggerResult<KeyEvent>>,
+{
+    type Item = KeyloggerResult<KeyEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            match ready!(this.events.as_mut().poll_next(cx)) {
+                Some(Ok(_)) if this.guard.is_sensitive() => continue,
+                item => return Poll::Ready(item),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::KeyEventStreamExt;
+    use crate::key_code::KeyCode;
+    use crate::keyboard::KeyEventCause;
+    use futures::executor::block_on;
+    use futures::{stream, StreamExt};
+
+    #[test]
+    fn suppress_sensitive_input() {
+        let guard = PrivacyGuard::new();
+        let evs = [KeyCode::KEY_A, KeyCode::KEY_B, KeyCode::KEY_C].map(|code| {
+            Ok(KeyEvent {
+                ts: Default::default(),
+                cause: KeyEventCause::Press,
+                code,
+                scancode: None,
+            })
+        });
+
+        let toggle = guard.clone();
+        let codes = stream::iter(evs)
+            .inspect(move |ev| toggle.set_sensitive(ev.as_ref().unwrap().code == KeyCode::KEY_A))
+            .with_privacy_guard(guard)
+            .map(|ev| ev.unwrap().code)
+            .collect::<Vec<_>>();
+
+        assert_eq!(block_on(codes), [KeyCode::KEY_B, KeyCode::KEY_C]);
+    }
+}
@@ -0,0 +1,93 @@
+//! The integration with the reactor of the async runtime.
+//!
+//! The reactor is selected using the `tokio` (default) and `async-io` features. If both are
+//! enabled, tokio is used. If neither is enabled, registering a file descriptor fails with
+//! [`io::ErrorKind::Unsupported`], and only the blocking API can be used.
+
+use std::io;
+use std::os::unix::io::RawFd;
+use std::task::{Context, Poll};
+
+/// A file descriptor registered with the reactor of the async runtime.
+///
+/// The file descriptor is borrowed: the owner of the file descriptor must ensure the `AsyncFd` is
+/// dropped before the file descriptor is closed.
+#[derive(Debug)]
+pub(crate) struct AsyncFd {
+    fd: RawFd,
+    #[cfg(feature = "tokio")]
+    registration: tokio::io::unix::AsyncFd<RawFd>,
+    #[cfg(all(feature = "async-io", not(feature = "tokio")))]
+    registration: async_io::Async<BorrowedRawFd>,
+}
+
+impl AsyncFd {
+    /// Register the specified (non-blocking) file descriptor with the reactor.
+    pub(crate) fn new(fd: RawFd) -> io::Result<Self> {
+        #[cfg(feature = "tokio")]
+        return Ok(Self {
+            fd,
+            registration: tokio::io::unix::AsyncFd::new(fd)?,
+        });
+
+        #[cfg(all(feature = "async-io", not(feature = "tokio")))]
+        return Ok(Self {
+            fd,
+            registration: async_io::Async::new_nonblocking(BorrowedRawFd(fd))?,
+        });
+
+        #[cfg(not(any(feature = "tokio", feature = "async-io")))]
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("cannot register fd {fd}: no async runtime feature is enabled"),
+        ));
+    }
+
+    /// Attempt to perform the specified non-blocking read once the file descriptor is readable.
+    ///
+    /// `read` is retried until it stops failing with [`io::ErrorKind::WouldBlock`].
+    pub(crate) fn poll_read<T>(
+        &self,
+        cx: &mut Context<'_>,
+        mut read: impl FnMut(RawFd) -> io::Result<T>,
+    ) -> Poll<io::Result<T>> {
+        #[cfg(feature = "tokio")]
+        loop {
+            let mut guard = futures::ready!(self.registration.poll_read_ready(cx))?;
+
+            if let Ok(result) = guard.try_io(|_| read(self.fd)) {
+                return Poll::Ready(result);
+            }
+        }
+
+        #[cfg(all(feature = "async-io", not(feature = "tokio")))]
+        loop {
+            futures::ready!(self.registration.poll_readable(cx))?;
+
+            match read(self.fd) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                result => return Poll::Ready(result),
+            }
+        }
+
+        #[cfg(not(any(feature = "tokio", feature = "async-io")))]
+        {
+            let _ = (cx, &mut read, self.fd);
+
+            unreachable!("AsyncFd can't be constructed without an async runtime feature");
+        }
+    }
+}
+
+/// A file descriptor owned by someone else.
+#[cfg(all(feature = "async-io", not(feature = "tokio")))]
+#[derive(Debug)]
+struct BorrowedRawFd(RawFd);
+
+#[cfg(all(feature = "async-io", not(feature = "tokio")))]
+impl std::os::unix::io::AsFd for BorrowedRawFd {
+    fn as_fd(&self) -> std::os::unix::io::BorrowedFd<'_> {
+        // The owner of the file descriptor outlives the AsyncFd (see the docs of AsyncFd)
+        unsafe { std::os::unix::io::BorrowedFd::borrow_raw(self.0) }
+    }
+}
@@ -0,0 +1,341 @@
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::{ready, Stream};
+
+use crate::error::{ErrorKind, KeyloggerError};
+use crate::hotplug::{HotplugEvent, KeyboardMonitor};
+use crate::keyboard::device::{find_char_devices, input_dir, is_keyboard};
+use crate::keyboard::{Clock, DeviceInfo, KeyEvent, KeyFilter, KeyboardDevice};
+use crate::timer::Timer;
+use crate::trace;
+use crate::KeyloggerResult;
+
+/// How a [`ReconnectingKeyboard`] handles its keyboard being unplugged.
+///
+/// By default, the keyboard is waited for indefinitely, however many times it's unplugged.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct RetryPolicy {
+    timeout: Option<Duration>,
+    max_reconnects: Option<u32>,
+}
+
+impl RetryPolicy {
+    /// Create a policy that waits for the keyboard indefinitely.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Give up if the keyboard doesn't reappear within `timeout` of being unplugged.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Give up once the keyboard was reconnected `max_reconnects` times.
+    pub fn max_reconnects(mut self, max_reconnects: u32) -> Self {
+        self.max_reconnects = Some(max_reconnects);
+        self
+    }
+
+    /// Whether the policy allows reconnecting the specified keyboard after it caused the specified
+    /// error, given the number of times it was already reconnected.
+    fn allows(&self, keyboard: &KeyboardDevice, e: &KeyloggerError, reconnects: u32) -> bool {
+        let info = keyboard.info();
+
+        e.kind() == ErrorKind::Disconnected
+            && (info.phys.is_some() || info.uniq.is_some())
+            && self.max_reconnects.is_none_or(|max| reconnects < max)
+    }
+}
+
+/// The settings of a keyboard that are restored when it's reconnected.
+#[derive(Clone, Debug)]
+struct Settings {
+    info: DeviceInfo,
+    key_filter: KeyFilter,
+    clock: Clock,
+    grabbed: bool,
+}
+
+impl Settings {
+    fn new(keyboard: &KeyboardDevice) -> Self {
+        Self {
+            info: keyboard.info().clone(),
+            key_filter: keyboard.key_filter().clone(),
+            clock: keyboard.clock(),
+            grabbed: keyboard.0.inner.grabbed,
+        }
+    }
+
+    /// Apply the settings to the reconnected keyboard.
+    fn restore(&self, keyboard: &mut KeyboardDevice) -> KeyloggerResult<()> {
+        keyboard.set_key_filter(self.key_filter.clone());
+
+        if self.clock != Clock::default() {
+            keyboard.set_clock(self.clock)?;
+        }
+
+        if self.grabbed {
+            keyboard.grab()?;
+        }
+
+        Ok(())
+    }
+}
+
+enum State {
+    Connected(KeyboardDevice),
+    /// The keyboard was unplugged, and the monitor is waiting for it to reappear.
+    Waiting {
+        /// The settings of the keyboard, to restore once it reappears.
+        settings: Settings,
+        monitor: KeyboardMonitor,
+        /// The timer that expires once the timeout of the retry policy elapsed, if any.
+        timer: Option<Timer>,
+        /// The error that signalled the keyboard was unplugged, which is returned if the keyboard
+        /// doesn't reappear.
+        error: KeyloggerError,
+    },
+    /// The retry policy gave up on the keyboard.
+    Failed,
+}
+
+/// A keyboard that is transparently reopened when it's unplugged and plugged back in.
+///
+/// When the keyboard is unplugged (which makes reading from it fail with an
+/// [`ErrorKind::Disconnected`] error), a `ReconnectingKeyboard` waits for a keyboard with the same
+/// physical location or unique identifier (see [`DeviceInfo::phys`] and [`DeviceInfo::uniq`]) to
+/// appear, and resumes capturing its events. The key filter, clock and grab of the keyboard are
+/// restored. The events that occur while the keyboard is disconnected are lost.
+///
+/// If the keyboard doesn't reappear before the [`RetryPolicy`] gives up, the error caused by the
+/// unplugging is returned, and the stream ends. The other errors are passed through, and the
+/// keyboards that report neither a physical location nor a unique identifier are never
+/// reconnected.
+pub struct ReconnectingKeyboard {
+    state: State,
+    policy: RetryPolicy,
+    reconnects: u32,
+}
+
+impl ReconnectingKeyboard {
+    /// Reconnect the specified keyboard according to `policy`.
+    pub fn new(keyboard: KeyboardDevice, policy: RetryPolicy) -> Self {
+        Self {
+            state: State::Connected(keyboard),
+            policy,
+            reconnects: 0,
+        }
+    }
+
+    /// The keyboard, unless it's currently disconnected.
+    pub fn keyboard(&self) -> Option<&KeyboardDevice> {
+        match &self.state {
+            State::Connected(keyboard) => Some(keyboard),
+            _ => None,
+        }
+    }
+
+    /// The keyboard, unless it's currently disconnected.
+    ///
+    /// The key filter, clock and grab set through the returned keyboard are restored when it's
+    /// reconnected.
+    pub fn keyboard_mut(&mut self) -> Option<&mut KeyboardDevice> {
+        match &mut self.state {
+            State::Connected(keyboard) => Some(keyboard),
+            _ => None,
+        }
+    }
+
+    /// The number of times the keyboard was reconnected.
+    pub fn reconnects(&self) -> u32 {
+        self.reconnects
+    }
+
+    /// Start waiting for the unplugged keyboard to reappear.
+    ///
+    /// If this fails, the retry policy gives up, unless the keyboard was reconnected.
+    fn wait(&mut self, settings: Settings, error: KeyloggerError) -> KeyloggerResult<()> {
+        self.state = State::Failed;
+
+        let monitor = KeyboardMonitor::new()?;
+
+        // The keyboard might have reappeared before the monitor was created, in which case the
+        // monitor never reports it
+        if let Some(keyboard) = find_keyboard(&settings.info)? {
+            return self.reconnect(keyboard, &settings);
+        }
+
+        let timer = match self.policy.timeout {
+            Some(timeout) => {
+                let timer = Timer::new()?;
+
+                timer.arm(timeout)?;
+                Some(timer)
+            }
+            None => None,
+        };
+
+        self.state = State::Waiting {
+            settings,
+            monitor,
+            timer,
+            error,
+        };
+
+        Ok(())
+    }
+
+    /// Resume capturing the events of the reconnected keyboard.
+    ///
+    /// The keyboard is captured even if its settings can't be restored.
+    fn reconnect(
+        &mut self,
+        mut keyboard: KeyboardDevice,
+        settings: &Settings,
+    ) -> KeyloggerResult<()> {
+        let res = settings.restore(&mut keyboard);
+        let res = keyboard.with_context(res);
+
+        self.reconnects += 1;
+        trace::reconnected(keyboard.path(), self.reconnects);
+        self.state = State::Connected(keyboard);
+
+        res
+    }
+}
+
+impl Stream for ReconnectingKeyboard {
+    type Item = KeyloggerResult<KeyEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            match &mut this.state {
+                State::Connected(keyboard) => {
+                    let e = match ready!(Pin::new(&mut *keyboard).poll_next(cx)) {
+                        Some(Err(e)) if this.policy.allows(keyboard, &e, this.reconnects) => e,
+                        item => return Poll::Ready(item),
+                    };
+
+                    trace::unplugged(keyboard.path(), &e);
+
+                    let settings = Settings::new(keyboard);
+
+                    if let Err(e) = this.wait(settings, e) {
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                }
+                State::Waiting {
+                    settings,
+                    monitor,
+                    timer,
+                    ..
+                } => {
+                    let expired = timer
+                        .as_ref()
+                        .is_some_and(|timer| timer.poll_expired(cx).is_ready());
+
+                    if expired {
+                        let State::Waiting { error, .. } =
+                            mem::replace(&mut this.state, State::Failed)
+                        else {
+                            unreachable!();
+                        };
+
+                        trace::reconnect_timed_out(&error);
+
+                        return Poll::Ready(Some(Err(error)));
+                    }
+
+                    match ready!(Pin::new(monitor).poll_next(cx)) {
+                        Some(Ok(HotplugEvent::KeyboardAdded(keyboard)))
+                            if same_device(&settings.info, keyboard.info()) =>
+                        {
+                            let settings = settings.clone();
+
+                            this.reconnect(*keyboard, &settings)?;
+                        }
+                        Some(Ok(_)) => {}
+                        Some(Err(e)) => {
+                            this.state = State::Failed;
+
+                            return Poll::Ready(Some(Err(e)));
+                        }
+                        None => this.state = State::Failed,
+                    }
+                }
+                State::Failed => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+/// Find the keyboard with the specified hardware among the keyboards connected to the system.
+fn find_keyboard(info: &DeviceInfo) -> KeyloggerResult<Option<KeyboardDevice>> {
+    Ok(find_char_devices(&input_dir())?
+        .filter(|path| is_keyboard(path))
+        .filter_map(|path| KeyboardDevice::open(&path).ok())
+        .find(|keyboard| same_device(info, keyboard.info())))
+}
+
+/// Whether two devices are the same piece of hardware, based on their unique identifier (or
+/// their physical location, if they don't have one).
+fn same_device(a: &DeviceInfo, b: &DeviceInfo) -> bool {
+    if (a.bus_type, a.vendor, a.product) != (b.bus_type, b.vendor, b.product) {
+        return false;
+    }
+
+    match (&a.uniq, &b.uniq) {
+        (Some(a), Some(b)) if !a.is_empty() => a == b,
+        _ => a.phys.is_some() && a.phys == b.phys,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn match_devices() {
+        let info = |phys: Option<&str>, uniq: Option<&str>| DeviceInfo {
+            bus_type: 3,
+            vendor: 0x046d,
+            product: 0xc31c,
+            version: 0x111,
+            phys: phys.map(Into::into),
+            uniq: uniq.map(Into::into),
+            seat: None,
+        };
+
+        let port1 = info(Some("usb-0000:00:14.0-1/input0"), None);
+        let port2 = info(Some("usb-0000:00:14.0-2/input0"), None);
+
+        assert!(same_device(&port1, &port1.clone()));
+        assert!(!same_device(&port1, &port2));
+        assert!(!same_device(&info(None, None), &info(None, None)));
+
+        // A keyboard with a serial number is recognised on any port
+        let serial1 = info(Some("usb-0000:00:14.0-1/input0"), Some("ABC123"));
+        let serial2 = info(Some("usb-0000:00:14.0-2/input0"), Some("ABC123"));
+
+        assert!(same_device(&serial1, &serial2));
+
+        let unplugged = KeyloggerError::Device {
+            path: "/dev/input/event4".into(),
+            name: "USB Keyboard".into(),
+            source: Box::new(io::Error::from_raw_os_error(libc::ENODEV).into()),
+        };
+
+        assert_eq!(unplugged.kind(), ErrorKind::Disconnected);
+        assert_ne!(
+            KeyloggerError::from(io::Error::other("oops")).kind(),
+            ErrorKind::Disconnected
+        );
+    }
+}
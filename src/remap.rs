@@ -0,0 +1,173 @@
+//! Remap the keys of a keyboard.
+//!
+//! A [`Remapper`] grabs a keyboard, so that its events aren't delivered to the rest of the
+//! system, transforms each of its events, and injects the results through a [`VirtualKeyboard`].
+//! The transformation is either a closure that turns each event into any number of events, or a
+//! [`KeyMap`] that replaces some keys with others.
+//!
+//! ```no_run
+//! use keylogger::remap::{KeyMap, Remapper};
+//! use keylogger::{find_keyboards, KeyCode, KeyloggerError};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let keyboard = find_keyboards()?.remove(0);
+//!     let map = KeyMap::new()
+//!         .map(KeyCode::KEY_CAPSLOCK, KeyCode::KEY_ESC)
+//!         .map(KeyCode::KEY_ESC, KeyCode::KEY_CAPSLOCK);
+//!
+//!     Remapper::new(keyboard, map.into_transform())?.run().await
+//! }
+//! ```
+
+use std::collections::HashMap;
+
+use futures::StreamExt;
+
+use crate::key_code::KeyCode;
+use crate::keyboard::{KeyEvent, KeyboardDevice};
+use crate::uinput::VirtualKeyboard;
+use crate::KeyloggerResult;
+
+/// A table of key replacements.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct KeyMap {
+    map: HashMap<KeyCode, KeyCode>,
+}
+
+impl KeyMap {
+    /// Create a map that leaves all the keys unchanged.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Replace the key `from` with `to`.
+    pub fn map(mut self, from: KeyCode, to: KeyCode) -> Self {
+        self.map.insert(from, to);
+        self
+    }
+
+    /// The key that replaces the specified key.
+    pub fn get(&self, code: KeyCode) -> KeyCode {
+        self.map.get(&code).copied().unwrap_or(code)
+    }
+
+    /// Replace the key of the specified event.
+    pub fn apply(&self, ev: KeyEvent) -> KeyEvent {
+        KeyEvent {
+            code: self.get(ev.code),
+            // The scancode identifies the original key
+            scancode: None,
+            ..ev
+        }
+    }
+
+    /// Turn the map into a transformation that can be used by a [`Remapper`].
+    pub fn into_transform(self) -> impl FnMut(KeyEvent) -> Vec<KeyEvent> {
+        move |ev| vec![self.apply(ev)]
+    }
+}
+
+/// Grabs a keyboard and injects its transformed events (see the [module-level
+/// documentation](self)).
+pub struct Remapper<F> {
+    keyboard: KeyboardDevice,
+    output: VirtualKeyboard,
+    transform: F,
+}
+
+impl<F> Remapper<F>
+where
+    F: FnMut(KeyEvent) -> Vec<KeyEvent>,
+{
+    /// Create a remapper that transforms the events of the specified keyboard using `transform`.
+    ///
+    /// This creates the virtual keyboard the transformed events are injected through, which is
+    /// named after the remapped keyboard. The keyboard is only grabbed once the remapper is
+    /// [run](Remapper::run).
+    pub fn new(keyboard: KeyboardDevice, transform: F) -> KeyloggerResult<Self> {
+        let output = VirtualKeyboard::new(&format!("{} (remapped)", keyboard.name()))?;
+
+        Ok(Self {
+            keyboard,
+            output,
+            transform,
+        })
+    }
+
+    /// The keyboard being remapped.
+    pub fn keyboard(&self) -> &KeyboardDevice {
+        &self.keyboard
+    }
+
+    /// Grab the keyboard, and remap its events until an error occurs.
+    ///
+    /// The keyboard is only grabbed once none of its keys are held down: otherwise, the release of
+    /// a key that was pressed before the grab wouldn't reach the applications that saw it being
+    /// pressed, and the key would appear to be stuck. The events of each hardware report are
+    /// transformed and injected together, so the keys pressed simultaneously stay simultaneous.
+    ///
+    /// The grab is released when the remapper is dropped.
+    pub async fn run(&mut self) -> KeyloggerResult<()> {
+        while !self.keyboard.held_keys()?.is_empty() {
+            if let Some(ev) = self.keyboard.next().await {
+                ev?;
+            }
+        }
+
+        self.keyboard.grab()?;
+        // The events read before the grab were already delivered to the rest of the system
+        self.keyboard.discard_buffered();
+
+        let mut reports = self.keyboard.reports();
+
+        while let Some(evs) = reports.next().await {
+            let evs = evs?
+                .into_iter()
+                .flat_map(&mut self.transform)
+                .collect::<Vec<_>>();
+
+            if !evs.is_empty() {
+                self.output.emit_all(&evs)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::KeyEventCause;
+
+    #[test]
+    fn swap_keys() {
+        let ev = |code| KeyEvent {
+            ts: Default::default(),
+            cause: KeyEventCause::Press,
+            code,
+            scancode: Some(0x70039),
+        };
+
+        let mut transform = KeyMap::new()
+            .map(KeyCode::KEY_CAPSLOCK, KeyCode::KEY_ESC)
+            .map(KeyCode::KEY_ESC, KeyCode::KEY_CAPSLOCK)
+            .into_transform();
+
+        let codes = [KeyCode::KEY_CAPSLOCK, KeyCode::KEY_ESC, KeyCode::KEY_A]
+            .into_iter()
+            .flat_map(|code| transform(ev(code)))
+            .map(|ev| (ev.code, ev.scancode))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            codes,
+            [
+                (KeyCode::KEY_ESC, None),
+                (KeyCode::KEY_CAPSLOCK, None),
+                (KeyCode::KEY_A, None)
+            ]
+        );
+    }
+}
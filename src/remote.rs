@@ -0,0 +1,254 @@
+//! Type on another machine: send the events of a local keyboard to a virtual keyboard on a remote
+//! machine, like a network KVM switch.
+//!
+//! A [`RemoteSender`] streams the events of a keyboard (usually grabbed, so they aren't also
+//! delivered locally) over a connection, and a [`RemoteReceiver`] on the other end injects them
+//! through a [`Bridge`]. Both ends share a secret key, which they use to authenticate each other
+//! when the connection is established, and to authenticate every event sent over it, so nobody
+//! else can inject keystrokes into the receiver, or have the sender send them its keystrokes. The
+//! events are also encrypted, so the keys that are typed can't be read off the connection.
+//!
+//! On the machine with the keyboard:
+//!
+//! ```no_run
+//! use keylogger::remote::RemoteSender;
+//! use keylogger::{find_keyboards, KeyloggerError};
+//! use tokio::net::TcpStream;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let mut keyboard = find_keyboards()?.remove(0);
+//!     let stream = TcpStream::connect("192.168.1.20:7879").await?;
+//!     let mut sender = RemoteSender::handshake(stream, b"a long shared secret").await?;
+//!
+//!     keyboard.grab()?;
+//!     sender.run(keyboard).await
+//! }
+//! ```
+//!
+//! On the machine the keystrokes are typed on:
+//!
+//! ```no_run
+//! use keylogger::bridge::Bridge;
+//! use keylogger::remote::RemoteReceiver;
+//! use keylogger::KeyloggerError;
+//! use tokio::net::TcpListener;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let listener = TcpListener::bind("0.0.0.0:7879").await?;
+//!     let mut bridge = Bridge::new("remote keyboard")?;
+//!
+//!     loop {
+//!         let (stream, _) = listener.accept().await?;
+//!
+//!         match RemoteReceiver::handshake(stream, b"a long shared secret").await {
+//!             Ok(receiver) => {
+//!                 if let Err(e) = receiver.inject(&mut bridge).await {
+//!                     eprintln!("the sender disconnected: {e}");
+//!                 }
+//!             }
+//!             Err(e) => eprintln!("rejected a sender: {e}"),
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Both ends work with any transport that implements `AsyncRead` and `AsyncWrite`, so the
+//! connection can also go through a serial line, a Unix socket or an SSH tunnel instead of the
+//! `TcpStream`.
+//!
+//! The connection is authenticated and encrypted using the same protocol as the authenticated
+//! clients of an [`EventServer`](crate::net::EventServer) (see [`EventServer::with_key`]), with the
+//! events encoded using bincode.
+//!
+//! [`EventServer::with_key`]: crate::net::EventServer::with_key
+
+use futures::{Stream, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::bridge::Bridge;
+use crate::keyboard::KeyEvent;
+use crate::net::auth::Session;
+use crate::net::{deserialize, serialize, Encoding};
+use crate::KeyloggerResult;
+
+/// The tag of the protocol, which the receiver starts the handshake with.
+const PROTOCOL: &[u8; 4] = b"KLRK";
+
+/// Sends key events to a [`RemoteReceiver`] (see the [module docs](self)).
+#[derive(Debug)]
+pub struct RemoteSender<T> {
+    session: Session<T>,
+}
+
+impl<T> RemoteSender<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Authenticate with the receiver at the other end of `transport`, using the shared `key`.
+    ///
+    /// This fails with [`KeyloggerError::Authentication`] if the receiver doesn't know the key,
+    /// or with an I/O error of kind `TimedOut` if the handshake doesn't complete within a few
+    /// seconds.
+    ///
+    /// [`KeyloggerError::Authentication`]: crate::KeyloggerError::Authentication
+    pub async fn handshake(transport: T, key: &[u8]) -> KeyloggerResult<Self> {
+        Ok(Self {
+            session: Session::connect(transport, key, PROTOCOL).await?,
+        })
+    }
+
+    /// Send the specified event to the receiver.
+    pub async fn send(&mut self, ev: &KeyEvent) -> KeyloggerResult<()> {
+        self.session.send(&serialize(ev, Encoding::Bincode)?).await
+    }
+
+    /// Send the events of the specified stream until it ends, or yields an error.
+    pub async fn run<S>(&mut self, mut events: S) -> KeyloggerResult<()>
+    where
+        S: Stream<Item = KeyloggerResult<KeyEvent>> + Unpin,
+    {
+        while let Some(ev) = events.next().await {
+            self.send(&ev?).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Close the connection, returning the transport.
+    pub fn into_inner(self) -> T {
+        self.session.into_inner()
+    }
+}
+
+/// Receives the key events sent by a [`RemoteSender`] (see the [module docs](self)).
+#[derive(Debug)]
+pub struct RemoteReceiver<T> {
+    session: Session<T>,
+}
+
+impl<T> RemoteReceiver<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Authenticate the sender at the other end of `transport`, using the shared `key`.
+    ///
+    /// This fails with [`KeyloggerError::Authentication`] if the sender doesn't know the key,
+    /// or with an I/O error of kind `TimedOut` if the handshake doesn't complete within a few
+    /// seconds, so a sender that never authenticates can't keep other senders waiting.
+    ///
+    /// [`KeyloggerError::Authentication`]: crate::KeyloggerError::Authentication
+    pub async fn handshake(transport: T, key: &[u8]) -> KeyloggerResult<Self> {
+        Ok(Self {
+            session: Session::accept(transport, key, PROTOCOL).await?,
+        })
+    }
+
+    /// Receive the next event, or `None` if the sender closed the connection.
+    ///
+    /// This fails with [`KeyloggerError::Authentication`] if the event wasn't sent by the
+    /// authenticated sender (or was tampered with, or replayed), in which case the connection
+    /// shouldn't be used anymore.
+    ///
+    /// [`KeyloggerError::Authentication`]: crate::KeyloggerError::Authentication
+    pub async fn recv(&mut self) -> KeyloggerResult<Option<KeyEvent>> {
+        match self.session.recv().await? {
+            Some(payload) => deserialize(&payload, Encoding::Bincode).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Turn the receiver into a stream of the events it receives, which ends once the sender
+    /// closes the connection (or after the first error).
+    pub fn into_events(self) -> impl Stream<Item = KeyloggerResult<KeyEvent>> {
+        futures::stream::try_unfold(self, |mut receiver| async move {
+            Ok(receiver.recv().await?.map(|ev| (ev, receiver)))
+        })
+    }
+
+    /// Inject the events received into the virtual keyboard of the specified bridge, until the
+    /// sender closes the connection (see [`Bridge::run`]).
+    ///
+    /// The keys still held down by the sender are released when the connection ends, so they
+    /// don't get stuck if the sender goes away while a key is pressed.
+    pub async fn inject(self, bridge: &mut Bridge) -> KeyloggerResult<()> {
+        bridge.run(Box::pin(self.into_events())).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_code::KeyCode;
+    use crate::keyboard::KeyEventCause;
+    use crate::KeyloggerError;
+    use futures::stream;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn send_and_receive() {
+        let ev = |cause, code| KeyEvent {
+            ts: Default::default(),
+            cause,
+            code,
+            scancode: None,
+        };
+        let evs = [
+            ev(KeyEventCause::Press, KeyCode::KEY_A),
+            ev(KeyEventCause::Release, KeyCode::KEY_A),
+        ];
+
+        let (tx, rx) = tokio::io::duplex(1024);
+        let (sender, receiver) = tokio::join!(
+            RemoteSender::handshake(tx, b"secret"),
+            RemoteReceiver::handshake(rx, b"secret"),
+        );
+        let mut sender = sender.unwrap();
+
+        sender
+            .run(stream::iter(evs.into_iter().map(Ok)))
+            .await
+            .unwrap();
+        drop(sender);
+
+        let received = receiver.unwrap().into_events().collect::<Vec<_>>().await;
+
+        assert_eq!(
+            received.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+            evs
+        );
+
+        // A sender that doesn't know the key is rejected
+        let (tx, rx) = tokio::io::duplex(1024);
+        let (sender, receiver) = tokio::join!(
+            RemoteSender::handshake(tx, b"guess"),
+            RemoteReceiver::handshake(rx, b"secret"),
+        );
+
+        assert!(matches!(receiver, Err(KeyloggerError::Authentication(_))));
+        assert!(matches!(sender, Err(KeyloggerError::Authentication(_))));
+
+        // The keys typed can't be read off the connection
+        let (tx, rx) = tokio::io::duplex(1024);
+        let (sender, receiver) = tokio::join!(
+            RemoteSender::handshake(tx, b"secret"),
+            RemoteReceiver::handshake(rx, b"secret"),
+        );
+        let (mut sender, mut receiver) = (sender.unwrap(), receiver.unwrap());
+        let plaintext = serialize(&evs[0], Encoding::Bincode).unwrap();
+        let key_code = bincode::serialize(&KeyCode::KEY_A).unwrap();
+        let mut captured = vec![0; 4 + plaintext.len() + 16];
+
+        sender.send(&evs[0]).await.unwrap();
+        receiver
+            .session
+            .transport_mut()
+            .read_exact(&mut captured)
+            .await
+            .unwrap();
+
+        assert!(plaintext.windows(key_code.len()).any(|w| w == key_code));
+        assert!(!captured.windows(key_code.len()).any(|w| w == key_code));
+    }
+}
@@ -0,0 +1,155 @@
+//! Destinations the captured [`KeyEvent`]s can be written to.
+//!
+//! A [`KeyEventSink`] receives the events one at a time. The built-in sinks write each event as
+//! a line of text (see [`format_event`]) to an append-only file ([`FileSink`]), a Unix domain
+//! socket ([`UnixSocketSink`]) or the system logger ([`SyslogSink`]). With the `json` feature,
+//! `JsonLinesSink` writes each event as a JSON object instead, along with the metadata of the
+//! keyboard that produced it. A stream of events can be written to a sink using [`forward`]. By
+//! default, the timestamps don't include a timezone (see [`Timezone`]). [`RingBufferSink`] keeps
+//! the most recent events in memory instead, to be inspected on demand.
+//!
+//! The built-in sinks perform blocking writes.
+
+mod file;
+#[cfg(feature = "json")]
+mod json;
+mod ring;
+mod socket;
+mod syslog;
+
+use futures::{Stream, StreamExt};
+
+use crate::keyboard::KeyEvent;
+use crate::KeyloggerResult;
+
+pub use file::FileSink;
+#[cfg(feature = "json")]
+pub use json::JsonLinesSink;
+pub use ring::RingBufferSink;
+pub use socket::UnixSocketSink;
+pub use syslog::SyslogSink;
+
+/// A destination for key events.
+pub trait KeyEventSink {
+    /// Write the specified event to the sink.
+    fn write_event(&mut self, ev: &KeyEvent) -> KeyloggerResult<()>;
+
+    /// Flush any events buffered by the sink.
+    fn flush(&mut self) -> KeyloggerResult<()> {
+        Ok(())
+    }
+}
+
+impl<T: KeyEventSink + ?Sized> KeyEventSink for Box<T> {
+    fn write_event(&mut self, ev: &KeyEvent) -> KeyloggerResult<()> {
+        (**self).write_event(ev)
+    }
+
+    fn flush(&mut self) -> KeyloggerResult<()> {
+        (**self).flush()
+    }
+}
+
+/// Write all the events of the stream to the specified sink.
+///
+/// Returns when the stream ends, or when either the stream or the sink returns an error.
+///
+/// # Example
+///
+/// ```no_run
+/// use keylogger::sink::{self, FileSink};
+/// use keylogger::{merge_keyboards, KeyloggerError};
+/// use futures::StreamExt;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), KeyloggerError> {
+///     let events = merge_keyboards()?.map(|ev| ev.map(|ev| ev.event));
+///     let mut sink = FileSink::open("/var/log/keys.log")?.rotate_at(1 << 20, 5);
+///
+///     sink::forward(events, &mut sink).await
+/// }
+/// ```
+pub async fn forward<S, K>(events: S, sink: &mut K) -> KeyloggerResult<()>
+where
+    S: Stream<Item = KeyloggerResult<KeyEvent>>,
+    K: KeyEventSink + ?Sized,
+{
+    futures::pin_mut!(events);
+
+    while let Some(ev) = events.next().await {
+        sink.write_event(&ev?)?;
+    }
+
+    sink.flush()
+}
+
+/// The timezone the timestamps of the events are formatted in (see [`format_event_in`]).
+///
+/// The timestamps of the events are UTC, but the default format doesn't say so. Formatting them
+/// with an explicit offset makes it possible to merge the logs of machines in different timezones
+/// unambiguously.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Timezone {
+    /// Don't include the offset (e.g. `2022-11-05T14:02:11.482915`).
+    #[default]
+    Naive,
+    /// Include the `Z` (UTC) suffix (e.g. `2022-11-05T14:02:11.482915Z`).
+    Utc,
+    /// Convert the timestamp to the local timezone, and include its offset (e.g.
+    /// `2022-11-05T15:02:11.482915+01:00`).
+    Local,
+}
+
+/// Format an event as a single line of text (without the trailing newline).
+///
+/// The line consists of the timestamp of the event (in RFC 3339 format, without a timezone), its
+/// cause and the name of its key code, separated by spaces (e.g.
+/// `2022-11-05T14:02:11.482915 Press LEFTSHIFT`).
+pub fn format_event(ev: &KeyEvent) -> String {
+    format_event_in(ev, Timezone::Naive)
+}
+
+/// Format an event like [`format_event`], with its timestamp in the specified timezone.
+pub fn format_event_in(ev: &KeyEvent, tz: Timezone) -> String {
+    format!("{} {:?} {}", format_ts(ev, tz), ev.cause, ev.code)
+}
+
+/// Format the timestamp of an event in RFC 3339 format, in the specified timezone.
+fn format_ts(ev: &KeyEvent, tz: Timezone) -> String {
+    const FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.6f";
+
+    match tz {
+        Timezone::Naive => ev.ts.format(FORMAT).to_string(),
+        Timezone::Utc => ev.ts_utc().format(&format!("{FORMAT}Z")).to_string(),
+        Timezone::Local => ev.ts_local().format(&format!("{FORMAT}%:z")).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_code::KeyCode;
+    use crate::keyboard::KeyEventCause;
+
+    #[test]
+    fn format_timezones() {
+        let ev = KeyEvent {
+            ts: chrono::DateTime::from_timestamp(1_667_656_931, 482_915_000)
+                .unwrap()
+                .naive_utc(),
+            cause: KeyEventCause::Press,
+            code: KeyCode::KEY_LEFTSHIFT,
+            scancode: None,
+        };
+
+        assert_eq!(
+            format_event(&ev),
+            "2022-11-05T14:02:11.482915 Press LEFTSHIFT"
+        );
+        assert_eq!(
+            format_event_in(&ev, Timezone::Utc),
+            "2022-11-05T14:02:11.482915Z Press LEFTSHIFT"
+        );
+        assert!(format_event_in(&ev, Timezone::Local).contains(&ev.ts_local().to_rfc3339()[..19]));
+    }
+}
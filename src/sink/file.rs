@@ -0,0 +1,152 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::keyboard::KeyEvent;
+use crate::sink::{format_event_in, KeyEventSink, Timezone};
+use crate::KeyloggerResult;
+
+/// A sink that appends the events to a file, one per line.
+///
+/// The file can optionally be rotated once it grows past a certain size (see
+/// [`FileSink::rotate_at`]).
+#[derive(Debug)]
+pub struct FileSink {
+    path: PathBuf,
+    file: File,
+    /// The current size of the file.
+    size: u64,
+    /// The size after which the file is rotated, and the number of rotated files to keep.
+    rotation: Option<(u64, usize)>,
+    tz: Timezone,
+}
+
+impl FileSink {
+    /// Open the specified file for appending, creating it if it doesn't exist.
+    pub fn open(path: impl AsRef<Path>) -> KeyloggerResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            file,
+            size,
+            rotation: None,
+            tz: Timezone::Naive,
+        })
+    }
+
+    /// Rotate the file once writing an event would grow it past `max_size` bytes.
+    ///
+    /// When the file is rotated, `<path>` is renamed to `<path>.1`, `<path>.1` to `<path>.2`, and
+    /// so on. At most `max_files` rotated files are kept (if `max_files` is 0, the file is simply
+    /// truncated).
+    pub fn rotate_at(mut self, max_size: u64, max_files: usize) -> Self {
+        self.rotation = Some((max_size, max_files));
+        self
+    }
+
+    /// Format the timestamps of the events in the specified timezone (see [`Timezone`]).
+    pub fn timezone(mut self, tz: Timezone) -> Self {
+        self.tz = tz;
+        self
+    }
+
+    /// The path of the file the events are written to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Move the current file out of the way, and start writing to an empty one.
+    fn rotate(&mut self, max_files: usize) -> KeyloggerResult<()> {
+        for i in (1..max_files).rev() {
+            let from = rotated_path(&self.path, i);
+
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.path, i + 1))?;
+            }
+        }
+
+        if max_files > 0 {
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        } else {
+            fs::remove_file(&self.path)?;
+        }
+
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+impl KeyEventSink for FileSink {
+    fn write_event(&mut self, ev: &KeyEvent) -> KeyloggerResult<()> {
+        let line = format_event_in(ev, self.tz) + "\n";
+
+        if let Some((max_size, max_files)) = self.rotation {
+            if self.size > 0 && self.size + line.len() as u64 > max_size {
+                self.rotate(max_files)?;
+            }
+        }
+
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> KeyloggerResult<()> {
+        Ok(self.file.flush()?)
+    }
+}
+
+pub(super) fn open_append(path: &Path) -> KeyloggerResult<File> {
+    Ok(OpenOptions::new().create(true).append(true).open(path)?)
+}
+
+/// The path of the `n`-th rotated file.
+pub(super) fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+
+    rotated.push(format!(".{n}"));
+    rotated.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_code::KeyCode;
+    use crate::keyboard::KeyEventCause;
+
+    #[test]
+    fn rotate_file() {
+        let dir = std::env::temp_dir().join(format!("keylogger-sink-{}", std::process::id()));
+        let path = dir.join("keys.log");
+        let ev = KeyEvent {
+            ts: Default::default(),
+            cause: KeyEventCause::Press,
+            code: KeyCode::KEY_A,
+            scancode: None,
+        };
+        let line_len = crate::sink::format_event(&ev).len() as u64 + 1;
+
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut sink = FileSink::open(&path).unwrap().rotate_at(2 * line_len, 2);
+
+        for _ in 0..7 {
+            sink.write_event(&ev).unwrap();
+        }
+
+        let lines = |path: &Path| fs::read_to_string(path).unwrap().lines().count();
+
+        assert_eq!(lines(&path), 1);
+        assert_eq!(lines(&rotated_path(&path, 1)), 2);
+        assert_eq!(lines(&rotated_path(&path, 2)), 2);
+        assert!(!rotated_path(&path, 3).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,286 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::error::KeyloggerError;
+use crate::key_code::KeyCode;
+use crate::keyboard::{DeviceInfo, KeyEvent, KeyEventCause, KeyboardDevice};
+use crate::sink::file::{open_append, rotated_path};
+use crate::sink::{format_ts, KeyEventSink, Timezone};
+use crate::KeyloggerResult;
+
+/// The number of rotated files kept by default.
+const DEFAULT_MAX_FILES: usize = 5;
+
+/// A line of a [`JsonLinesSink`].
+#[derive(Serialize)]
+struct Record<'a> {
+    ts: String,
+    cause: KeyEventCause,
+    code: KeyCode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scancode: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device: Option<Device<'a>>,
+}
+
+/// The metadata of the keyboard that produced an event.
+#[derive(Serialize)]
+struct Device<'a> {
+    name: &'a str,
+    path: &'a Path,
+    #[serde(flatten)]
+    info: &'a DeviceInfo,
+}
+
+/// A sink that appends the events to a file in the [JSON Lines](https://jsonlines.org) format.
+///
+/// Each line is a JSON object with the `ts`, `cause`, `code` and (if captured) `scancode` of the
+/// event. The events written using [`JsonLinesSink::write_event_from`] also include a `device`
+/// object, with the name, path and hardware information (see [`DeviceInfo`]) of the keyboard
+/// that produced them:
+///
+/// ```text
+/// {"ts":"2022-11-05T14:02:11.482915Z","cause":"Press","code":"KEY_A","device":{"name":"USB Keyboard","path":"/dev/input/event4","bus_type":3,"vendor":1133,"product":49948,"version":273,"phys":"usb-0000:00:14.0-1/input0","uniq":null,"seat":"seat0"}}
+/// ```
+///
+/// The timestamps are UTC by default. The file can be rotated once it grows past a certain size
+/// (see [`JsonLinesSink::rotate_at`]), once it's been written to for a certain time (see
+/// [`JsonLinesSink::rotate_every`]), or both. With the `gzip` feature, the rotated files can be
+/// compressed.
+#[derive(Debug)]
+pub struct JsonLinesSink {
+    path: PathBuf,
+    file: File,
+    /// The current size of the file.
+    size: u64,
+    /// When the sink started writing to the current file.
+    opened: Instant,
+    max_size: Option<u64>,
+    max_age: Option<Duration>,
+    max_files: usize,
+    /// Whether to compress the rotated files.
+    compress: bool,
+    tz: Timezone,
+}
+
+impl JsonLinesSink {
+    /// Open the specified file for appending, creating it if it doesn't exist.
+    pub fn open(path: impl AsRef<Path>) -> KeyloggerResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            file,
+            size,
+            opened: Instant::now(),
+            max_size: None,
+            max_age: None,
+            max_files: DEFAULT_MAX_FILES,
+            compress: false,
+            tz: Timezone::Utc,
+        })
+    }
+
+    /// Rotate the file once writing an event would grow it past `max_size` bytes.
+    ///
+    /// When the file is rotated, `<path>` is renamed to `<path>.1`, `<path>.1` to `<path>.2`, and
+    /// so on (see [`JsonLinesSink::max_files`]).
+    pub fn rotate_at(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Rotate the file once the sink has been writing to it for `interval`.
+    ///
+    /// The interval is measured from the time the sink opened the file, so restarting the sink
+    /// restarts it. The file is only rotated when the next event is written, so a file is never
+    /// rotated while it's empty.
+    pub fn rotate_every(mut self, interval: Duration) -> Self {
+        self.max_age = Some(interval);
+        self
+    }
+
+    /// The number of rotated files to keep (5 by default).
+    ///
+    /// If `max_files` is 0, the file is simply truncated when it's rotated.
+    pub fn max_files(mut self, max_files: usize) -> Self {
+        self.max_files = max_files;
+        self
+    }
+
+    /// Compress the rotated files using gzip, and add the `.gz` suffix to their names.
+    #[cfg(feature = "gzip")]
+    pub fn compress(mut self) -> Self {
+        self.compress = true;
+        self
+    }
+
+    /// Format the timestamps of the events in the specified timezone (see [`Timezone`]).
+    pub fn timezone(mut self, tz: Timezone) -> Self {
+        self.tz = tz;
+        self
+    }
+
+    /// The path of the file the events are written to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write an event, along with the metadata of the keyboard that produced it.
+    pub fn write_event_from(
+        &mut self,
+        ev: &KeyEvent,
+        keyboard: &KeyboardDevice,
+    ) -> KeyloggerResult<()> {
+        let device = Device {
+            name: keyboard.name(),
+            path: keyboard.path(),
+            info: keyboard.info(),
+        };
+
+        self.write_record(ev, Some(device))
+    }
+
+    fn write_record(&mut self, ev: &KeyEvent, device: Option<Device>) -> KeyloggerResult<()> {
+        let record = Record {
+            ts: format_ts(ev, self.tz),
+            cause: ev.cause,
+            code: ev.code,
+            scancode: ev.scancode,
+            device,
+        };
+
+        let mut line = serde_json::to_vec(&record)
+            .map_err(|e| KeyloggerError::Serialization(e.to_string()))?;
+
+        line.push(b'\n');
+
+        if self.size > 0 && self.should_rotate(line.len() as u64) {
+            self.rotate()?;
+        }
+
+        self.file.write_all(&line)?;
+        self.size += line.len() as u64;
+
+        Ok(())
+    }
+
+    /// Whether the file needs to be rotated before writing a line of the specified length.
+    fn should_rotate(&self, len: u64) -> bool {
+        self.max_size.is_some_and(|max| self.size + len > max)
+            || self.max_age.is_some_and(|max| self.opened.elapsed() >= max)
+    }
+
+    /// The path of the `n`-th rotated file.
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut path = rotated_path(&self.path, n).into_os_string();
+
+        if self.compress {
+            path.push(".gz");
+        }
+
+        path.into()
+    }
+
+    /// Move the current file out of the way, and start writing to an empty one.
+    fn rotate(&mut self) -> KeyloggerResult<()> {
+        for i in (1..self.max_files).rev() {
+            let from = self.rotated_path(i);
+
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(i + 1))?;
+            }
+        }
+
+        if self.max_files > 0 {
+            if self.compress {
+                #[cfg(feature = "gzip")]
+                gzip(&self.path, &self.rotated_path(1))?;
+                fs::remove_file(&self.path)?;
+            } else {
+                fs::rename(&self.path, self.rotated_path(1))?;
+            }
+        } else {
+            fs::remove_file(&self.path)?;
+        }
+
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        self.opened = Instant::now();
+
+        Ok(())
+    }
+}
+
+impl KeyEventSink for JsonLinesSink {
+    /// Write an event, without the metadata of the keyboard that produced it (see
+    /// [`JsonLinesSink::write_event_from`]).
+    fn write_event(&mut self, ev: &KeyEvent) -> KeyloggerResult<()> {
+        self.write_record(ev, None)
+    }
+
+    fn flush(&mut self) -> KeyloggerResult<()> {
+        Ok(self.file.flush()?)
+    }
+}
+
+/// Compress the file at `from` into a new gzip file at `to`.
+#[cfg(feature = "gzip")]
+fn gzip(from: &Path, to: &Path) -> KeyloggerResult<()> {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    let mut encoder = GzEncoder::new(File::create(to)?, Compression::default());
+
+    std::io::copy(&mut File::open(from)?, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotate_json_lines() {
+        let dir = std::env::temp_dir().join(format!("keylogger-json-{}", std::process::id()));
+        let path = dir.join("keys.jsonl");
+        let ev = KeyEvent {
+            ts: chrono::DateTime::from_timestamp(1_667_656_931, 482_915_000)
+                .unwrap()
+                .naive_utc(),
+            cause: KeyEventCause::Press,
+            code: KeyCode::KEY_A,
+            scancode: None,
+        };
+
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut sink = JsonLinesSink::open(&path)
+            .unwrap()
+            .rotate_at(100)
+            .max_files(1);
+
+        for _ in 0..3 {
+            sink.write_event(&ev).unwrap();
+        }
+
+        let line = r#"{"ts":"2022-11-05T14:02:11.482915Z","cause":"Press","code":"KEY_A"}"#;
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{line}\n"));
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 1)).unwrap(),
+            format!("{line}\n")
+        );
+        assert!(!rotated_path(&path, 2).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
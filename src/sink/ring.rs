@@ -0,0 +1,179 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::keyboard::KeyEvent;
+use crate::sink::KeyEventSink;
+use crate::KeyloggerResult;
+
+/// A sink that keeps the most recent events in memory, without writing them anywhere.
+///
+/// The sink keeps the last N events, the events of the last T seconds, or both, and can be
+/// snapshotted at any time. This is useful for tools that answer "what did I just type", or for
+/// inspecting the events that preceded a crash, without logging all the events persistently.
+///
+/// Cloning a `RingBufferSink` is cheap: the clones share the same buffer, so one of them can be
+/// passed to [`forward`](crate::sink::forward) while another one is used to take snapshots:
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use futures::StreamExt;
+/// use keylogger::sink::{self, RingBufferSink};
+/// use keylogger::{merge_keyboards, KeyloggerError};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), KeyloggerError> {
+///     let events = merge_keyboards()?.map(|ev| ev.map(|ev| ev.event));
+///     let mut ring = RingBufferSink::new(1000).max_age(Duration::from_secs(60));
+///     let snapshots = ring.clone();
+///
+///     std::thread::spawn(move || loop {
+///         std::thread::sleep(Duration::from_secs(10));
+///         println!("{:?}", snapshots.snapshot());
+///     });
+///
+///     sink::forward(events, &mut ring).await
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct RingBufferSink(Arc<Mutex<RingBuffer>>);
+
+#[derive(Debug)]
+struct RingBuffer {
+    events: VecDeque<KeyEvent>,
+    capacity: Option<usize>,
+    max_age: Option<Duration>,
+}
+
+impl RingBufferSink {
+    /// Create a sink that keeps the last `capacity` events.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_limits(Some(capacity), None)
+    }
+
+    /// Create a sink that keeps the events that are at most `max_age` older than the latest
+    /// event, however many there are.
+    pub fn with_max_age(max_age: Duration) -> Self {
+        Self::with_limits(None, Some(max_age))
+    }
+
+    fn with_limits(capacity: Option<usize>, max_age: Option<Duration>) -> Self {
+        Self(Arc::new(Mutex::new(RingBuffer {
+            events: VecDeque::with_capacity(capacity.unwrap_or(0)),
+            capacity,
+            max_age,
+        })))
+    }
+
+    /// Also drop the events that are more than `max_age` older than the latest event.
+    ///
+    /// The age of the events is measured using their timestamps, so the events aren't dropped
+    /// while no new events are written.
+    pub fn max_age(self, max_age: Duration) -> Self {
+        self.lock().max_age = Some(max_age);
+        self
+    }
+
+    /// The events currently in the buffer, from the oldest to the latest.
+    pub fn snapshot(&self) -> Vec<KeyEvent> {
+        self.lock().events.iter().copied().collect()
+    }
+
+    /// Remove all the events from the buffer, returning them (from the oldest to the latest).
+    pub fn take(&self) -> Vec<KeyEvent> {
+        self.lock().events.drain(..).collect()
+    }
+
+    /// The number of events in the buffer.
+    pub fn len(&self) -> usize {
+        self.lock().events.len()
+    }
+
+    /// Whether the buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.lock().events.is_empty()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, RingBuffer> {
+        // The buffer is always left in a usable state, so a poisoned lock is still usable
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl KeyEventSink for RingBufferSink {
+    fn write_event(&mut self, ev: &KeyEvent) -> KeyloggerResult<()> {
+        let mut ring = self.lock();
+
+        if ring.capacity == Some(0) {
+            return Ok(());
+        }
+
+        if ring.capacity == Some(ring.events.len()) {
+            ring.events.pop_front();
+        }
+
+        ring.events.push_back(*ev);
+
+        if let Some(max_age) = ring.max_age {
+            while ring
+                .events
+                .front()
+                .is_some_and(|old| (ev.ts - old.ts).to_std().is_ok_and(|age| age > max_age))
+            {
+                ring.events.pop_front();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_code::KeyCode;
+    use crate::keyboard::KeyEventCause;
+
+    #[test]
+    fn ring_buffer() {
+        let ev = |secs, code| KeyEvent {
+            ts: chrono::DateTime::from_timestamp(secs, 0)
+                .unwrap()
+                .naive_utc(),
+            cause: KeyEventCause::Press,
+            code,
+            scancode: None,
+        };
+        let codes = |evs: Vec<KeyEvent>| evs.into_iter().map(|ev| ev.code).collect::<Vec<_>>();
+
+        let mut ring = RingBufferSink::new(2);
+        let snapshots = ring.clone();
+
+        for (secs, code) in [
+            (0, KeyCode::KEY_A),
+            (1, KeyCode::KEY_B),
+            (2, KeyCode::KEY_C),
+        ] {
+            ring.write_event(&ev(secs, code)).unwrap();
+        }
+
+        assert_eq!(
+            codes(snapshots.snapshot()),
+            [KeyCode::KEY_B, KeyCode::KEY_C]
+        );
+
+        let mut ring = RingBufferSink::with_max_age(Duration::from_secs(5));
+
+        for (secs, code) in [
+            (0, KeyCode::KEY_A),
+            (5, KeyCode::KEY_B),
+            (6, KeyCode::KEY_C),
+        ] {
+            ring.write_event(&ev(secs, code)).unwrap();
+        }
+
+        assert_eq!(codes(ring.take()), [KeyCode::KEY_B, KeyCode::KEY_C]);
+        assert!(ring.is_empty());
+    }
+}
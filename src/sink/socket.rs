@@ -0,0 +1,48 @@
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+use crate::keyboard::KeyEvent;
+use crate::sink::{format_event_in, KeyEventSink, Timezone};
+use crate::KeyloggerResult;
+
+/// A sink that writes the events to a Unix domain socket, one per line.
+#[derive(Debug)]
+pub struct UnixSocketSink {
+    stream: UnixStream,
+    tz: Timezone,
+}
+
+impl UnixSocketSink {
+    /// Connect to the (stream) socket at the specified path.
+    pub fn connect(path: impl AsRef<Path>) -> KeyloggerResult<Self> {
+        Ok(Self::from(UnixStream::connect(path)?))
+    }
+
+    /// Format the timestamps of the events in the specified timezone (see [`Timezone`]).
+    pub fn timezone(mut self, tz: Timezone) -> Self {
+        self.tz = tz;
+        self
+    }
+}
+
+impl From<UnixStream> for UnixSocketSink {
+    fn from(stream: UnixStream) -> Self {
+        Self {
+            stream,
+            tz: Timezone::Naive,
+        }
+    }
+}
+
+impl KeyEventSink for UnixSocketSink {
+    fn write_event(&mut self, ev: &KeyEvent) -> KeyloggerResult<()> {
+        let line = format_event_in(ev, self.tz) + "\n";
+
+        Ok(self.stream.write_all(line.as_bytes())?)
+    }
+
+    fn flush(&mut self) -> KeyloggerResult<()> {
+        Ok(self.stream.flush()?)
+    }
+}
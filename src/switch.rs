@@ -0,0 +1,273 @@
+//! Monitor switches, such as the lid of a laptop or its tablet mode.
+//!
+//! The devices that report switch events (`EV_SW`) can be detected using [`find_switch_devices`].
+//! A [`SwitchDevice`] implements [`Stream`], where each element is a [`SwitchEvent`]. The current
+//! state of the switches can be read using [`SwitchDevice::state`].
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use keylogger::switch::{find_switch_devices, Switch};
+//! use keylogger::KeyloggerError;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let mut lid = find_switch_devices()?
+//!         .into_iter()
+//!         .find(|dev| dev.supports(Switch::Lid))
+//!         .expect("no lid switch");
+//!
+//!     while let Some(ev) = lid.next().await {
+//!         let ev = ev?;
+//!
+//!         if ev.switch == Switch::Lid {
+//!             println!("lid {}", if ev.on { "closed" } else { "opened" });
+//!         }
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use chrono::naive::NaiveDateTime;
+use futures::{ready, Stream};
+
+use crate::keyboard::device::{
+    find_char_devices, input_dir, ioc, ioctl, DeviceInfo, EventReader, InputDevice,
+    DEFAULT_BUFFER_SIZE, IOC_READ,
+};
+use crate::keyboard::event_codes::{EV_SW, EV_SYN};
+use crate::keyboard::timestamp;
+use crate::KeyloggerResult;
+
+/// The highest switch code supported by the kernel (`SW_MAX`).
+const SW_MAX: usize = 0x10;
+
+/// The event types a device must support to be considered a switch device.
+const SWITCH_FLAGS: libc::c_ulong = (1 << EV_SYN) | (1 << EV_SW);
+
+/// A switch (see the `SW_*` constants from `input-event-codes.h`).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u16)]
+pub enum Switch {
+    /// The lid of the laptop is closed (`SW_LID`).
+    Lid = 0x00,
+    /// The device is in tablet mode (`SW_TABLET_MODE`).
+    TabletMode = 0x01,
+    /// Headphones are plugged in (`SW_HEADPHONE_INSERT`).
+    HeadphoneInsert = 0x02,
+    /// The master radio switch is on (`SW_RFKILL_ALL`).
+    RfkillAll = 0x03,
+    /// A microphone is plugged in (`SW_MICROPHONE_INSERT`).
+    MicrophoneInsert = 0x04,
+    /// The device is docked (`SW_DOCK`).
+    Dock = 0x05,
+    /// A line out is plugged in (`SW_LINEOUT_INSERT`).
+    LineoutInsert = 0x06,
+    /// Something is physically plugged into the jack (`SW_JACK_PHYSICAL_INSERT`).
+    JackPhysicalInsert = 0x07,
+    /// A video output is plugged in (`SW_VIDEOOUT_INSERT`).
+    VideoOutInsert = 0x08,
+    /// The lens of the camera is covered (`SW_CAMERA_LENS_COVER`).
+    CameraLensCover = 0x09,
+    /// The keypad is slid out (`SW_KEYPAD_SLIDE`).
+    KeypadSlide = 0x0a,
+    /// The front proximity sensor is active (`SW_FRONT_PROXIMITY`).
+    FrontProximity = 0x0b,
+    /// The screen rotation is locked (`SW_ROTATE_LOCK`).
+    RotateLock = 0x0c,
+    /// A line in is plugged in (`SW_LINEIN_INSERT`).
+    LineinInsert = 0x0d,
+    /// The device is muted (`SW_MUTE_DEVICE`).
+    MuteDevice = 0x0e,
+    /// The pen is inserted into its slot (`SW_PEN_INSERTED`).
+    PenInserted = 0x0f,
+    /// The cover of the machine is closed (`SW_MACHINE_COVER`).
+    MachineCover = 0x10,
+}
+
+impl Switch {
+    const ALL: [Switch; SW_MAX + 1] = [
+        Switch::Lid,
+        Switch::TabletMode,
+        Switch::HeadphoneInsert,
+        Switch::RfkillAll,
+        Switch::MicrophoneInsert,
+        Switch::Dock,
+        Switch::LineoutInsert,
+        Switch::JackPhysicalInsert,
+        Switch::VideoOutInsert,
+        Switch::CameraLensCover,
+        Switch::KeypadSlide,
+        Switch::FrontProximity,
+        Switch::RotateLock,
+        Switch::LineinInsert,
+        Switch::MuteDevice,
+        Switch::PenInserted,
+        Switch::MachineCover,
+    ];
+}
+
+impl TryFrom<u16> for Switch {
+    type Error = ();
+
+    fn try_from(code: u16) -> Result<Self, Self::Error> {
+        Self::ALL.get(usize::from(code)).copied().ok_or(())
+    }
+}
+
+/// A switch changing state.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SwitchEvent {
+    /// The timestamp of the event.
+    pub ts: NaiveDateTime,
+    /// The switch that changed state.
+    pub switch: Switch,
+    /// Whether the switch is now on (e.g. the lid is closed).
+    pub on: bool,
+}
+
+/// Find all the devices that report switch events.
+pub fn find_switch_devices() -> KeyloggerResult<Vec<SwitchDevice>> {
+    Ok(find_char_devices(&input_dir())?
+        .filter_map(|entry| {
+            // The device reads its own events, so the key event reader is kept minimal
+            InputDevice::open(&entry, SWITCH_FLAGS, EventReader::new(1, false)).ok()
+        })
+        .filter_map(|inner| {
+            // EVIOCGBIT(EV_SW)
+            let supported = read_switch_bits(&inner, 0x20 + EV_SW).ok()?;
+
+            Some(SwitchDevice {
+                inner,
+                supported,
+                buf: vec![unsafe { mem::zeroed() }; DEFAULT_BUFFER_SIZE],
+                pending: Default::default(),
+            })
+        })
+        .collect())
+}
+
+/// A device that reports switch events, such as the lid switch of a laptop (see
+/// [`find_switch_devices`]).
+pub struct SwitchDevice {
+    inner: InputDevice,
+    /// The switches the device supports.
+    supported: Vec<Switch>,
+    /// The buffer the input events are read into, reused across reads.
+    buf: Vec<libc::input_event>,
+    /// The events that were read but not yet returned.
+    pending: VecDeque<SwitchEvent>,
+}
+
+impl SwitchDevice {
+    /// A human-readable description of the device (e.g. "Lid Switch").
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    /// The path of the device (e.g. `/dev/input/event0`)
+    pub fn path(&self) -> &Path {
+        &self.inner.device
+    }
+
+    /// Information about the hardware of the device, such as its vendor and product ID.
+    pub fn info(&self) -> &DeviceInfo {
+        &self.inner.info
+    }
+
+    /// The switches the device reports.
+    pub fn switches(&self) -> &[Switch] {
+        &self.supported
+    }
+
+    /// Whether the device reports the specified switch.
+    pub fn supports(&self, switch: Switch) -> bool {
+        self.supported.contains(&switch)
+    }
+
+    /// The switches of the device that are currently on.
+    pub fn state(&self) -> KeyloggerResult<Vec<Switch>> {
+        // EVIOCGSW
+        read_switch_bits(&self.inner, 0x1b)
+    }
+}
+
+impl Stream for SwitchDevice {
+    type Item = KeyloggerResult<SwitchEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some(ev) = this.pending.pop_front() {
+                return Poll::Ready(Some(Ok(ev)));
+            }
+
+            let evs = match ready!(this.inner.poll_input_events(cx, &mut this.buf)) {
+                Ok(evs) => evs,
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            };
+
+            for ev in evs.iter().filter(|ev| ev.type_ == EV_SW as u16) {
+                let Ok(switch) = Switch::try_from(ev.code) else {
+                    continue;
+                };
+
+                let ts = match timestamp(&ev.time) {
+                    Ok(ts) => ts,
+                    Err(e) => return Poll::Ready(Some(Err(e))),
+                };
+
+                this.pending.push_back(SwitchEvent {
+                    ts,
+                    switch,
+                    on: ev.value != 0,
+                });
+            }
+        }
+    }
+}
+
+/// Read a switch bitmap of the device using the specified `EVIOCG*` ioctl number.
+fn read_switch_bits(dev: &InputDevice, nr: libc::c_ulong) -> KeyloggerResult<Vec<Switch>> {
+    let mut bits = [0u8; SW_MAX / 8 + 1];
+
+    ioctl(
+        dev.as_raw_fd(),
+        ioc(IOC_READ, 'E', nr, bits.len()),
+        bits.as_mut_ptr() as *mut libc::c_ulong,
+    )?;
+
+    Ok(Switch::ALL
+        .into_iter()
+        .filter(|switch| {
+            let code = *switch as usize;
+
+            bits[code / 8] & (1 << (code % 8)) != 0
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switch_codes() {
+        for (code, switch) in Switch::ALL.into_iter().enumerate() {
+            assert_eq!(Switch::try_from(code as u16), Ok(switch));
+        }
+
+        assert_eq!(Switch::try_from(SW_MAX as u16 + 1), Err(()));
+    }
+}
@@ -0,0 +1,241 @@
+//! A fake keyboard for end-to-end tests of the code that discovers keyboards and captures their
+//! events.
+//!
+//! A [`FakeKeyboard`] is a real input device, created using uinput, that looks like a physical
+//! keyboard: it's detected by [`find_keyboards`](crate::find_keyboards) and [`KeyboardFinder`],
+//! and its events go through the same path as the events of a physical keyboard. The keys it
+//! types are scripted by the test:
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use keylogger::test_util::FakeKeyboard;
+//! use keylogger::{KeyCode, KeyloggerError};
+//!
+//! #[tokio::test]
+//! async fn capture_typed_text() -> Result<(), KeyloggerError> {
+//!     let mut fake = FakeKeyboard::new()?;
+//!     let mut keyboard = fake.finder().find()?.remove(0);
+//!
+//!     fake.type_text("Hi")?;
+//!
+//!     let presses = keyboard
+//!         .presses()
+//!         .take(3)
+//!         .map(|ev| ev.map(|ev| ev.code))
+//!         .collect::<Vec<_>>()
+//!         .await;
+//!
+//!     assert_eq!(
+//!         presses.into_iter().collect::<Result<Vec<_>, _>>()?,
+//!         [KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_H, KeyCode::KEY_I]
+//!     );
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Creating a fake keyboard requires write access to `/dev/uinput`, and capturing its events
+//! requires read access to the input devices (see [`VirtualKeyboard`]). The events of a fake
+//! keyboard are delivered to the other applications too, unless it's grabbed (see
+//! [`KeyboardDevice::grab`]).
+//!
+//! This module is only available with the `test-util` feature.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::key_code::KeyCode;
+use crate::keyboard::device::input_dir;
+use crate::keyboard::{KeyEvent, KeyboardDevice, KeyboardFinder};
+use crate::layout::Layout;
+use crate::macros::Macro;
+use crate::uinput::VirtualKeyboard;
+use crate::KeyloggerResult;
+
+/// The sysfs directory of the virtual input devices.
+const SYSFS_VIRTUAL_INPUT: &str = "/sys/devices/virtual/input";
+/// How long to wait for the device of a fake keyboard to appear.
+const DEVICE_TIMEOUT: Duration = Duration::from_secs(2);
+/// How often to look for the device of a fake keyboard, while waiting for it to appear.
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The number of fake keyboards created by this process, used to name them uniquely.
+static FAKE_KEYBOARDS: AtomicUsize = AtomicUsize::new(0);
+
+/// A uinput keyboard that types scripted key sequences (see the [module docs](self)).
+///
+/// The device is destroyed when the `FakeKeyboard` is dropped.
+#[derive(Debug)]
+pub struct FakeKeyboard {
+    keyboard: VirtualKeyboard,
+    name: String,
+    path: PathBuf,
+    layout: Layout,
+}
+
+impl FakeKeyboard {
+    /// Create a fake keyboard with a unique name, so the tests that run at the same time don't
+    /// capture each other's keyboards.
+    pub fn new() -> KeyloggerResult<Self> {
+        let n = FAKE_KEYBOARDS.fetch_add(1, Ordering::Relaxed);
+
+        Self::with_name(&format!(
+            "keylogger fake keyboard {}-{n}",
+            std::process::id()
+        ))
+    }
+
+    /// Create a fake keyboard with the specified name.
+    ///
+    /// This waits for the device of the keyboard to appear (see [`FakeKeyboard::path`]).
+    pub fn with_name(name: &str) -> KeyloggerResult<Self> {
+        let keyboard = VirtualKeyboard::with_keyboard_capabilities(name)?;
+        let sysfs_dir = Path::new(SYSFS_VIRTUAL_INPUT).join(keyboard.sysname()?);
+        let attempts = DEVICE_TIMEOUT.as_millis() / DEVICE_POLL_INTERVAL.as_millis();
+
+        for _ in 0..attempts {
+            if let Some(path) = event_device(&sysfs_dir).filter(|path| path.exists()) {
+                return Ok(Self {
+                    keyboard,
+                    name: name.into(),
+                    path,
+                    layout: Layout::us_qwerty(),
+                });
+            }
+
+            thread::sleep(DEVICE_POLL_INTERVAL);
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "the device of the fake keyboard didn't appear",
+        )
+        .into())
+    }
+
+    /// The name of the keyboard.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The path of the device of the keyboard (e.g. `/dev/input/event42`).
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// A [`KeyboardFinder`] that only finds this keyboard.
+    pub fn finder(&self) -> KeyboardFinder {
+        KeyboardFinder::new().name(&format!("^{}$", regex::escape(&self.name)))
+    }
+
+    /// Open the device of the keyboard, to capture its events.
+    pub fn open(&self) -> KeyloggerResult<KeyboardDevice> {
+        KeyboardDevice::open(&self.path)
+    }
+
+    /// Use the specified layout to choose the keys typed by [`FakeKeyboard::type_text`] (the US
+    /// QWERTY layout by default).
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+    }
+
+    /// Press the specified key.
+    pub fn press(&mut self, code: KeyCode) -> KeyloggerResult<()> {
+        self.keyboard.press(code)
+    }
+
+    /// Release the specified key.
+    pub fn release(&mut self, code: KeyCode) -> KeyloggerResult<()> {
+        self.keyboard.release(code)
+    }
+
+    /// Press the specified key, and release it straight away.
+    pub fn tap(&mut self, code: KeyCode) -> KeyloggerResult<()> {
+        self.keyboard.tap(code)
+    }
+
+    /// Inject the specified events as a single hardware report.
+    pub fn emit_all(&mut self, evs: &[KeyEvent]) -> KeyloggerResult<()> {
+        self.keyboard.emit_all(evs)
+    }
+
+    /// Type the specified text, holding Shift down for the characters that need it.
+    ///
+    /// This fails with [`io::ErrorKind::InvalidInput`] (without typing anything) if the layout
+    /// can't produce one of the characters.
+    pub fn type_text(&mut self, text: &str) -> KeyloggerResult<()> {
+        let keys = text
+            .chars()
+            .map(|c| {
+                self.layout.key_for(c).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("the layout can't type {c:?}"),
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (code, shift) in keys {
+            if shift {
+                self.keyboard.press(KeyCode::KEY_LEFTSHIFT)?;
+            }
+
+            self.keyboard.tap(code)?;
+
+            if shift {
+                self.keyboard.release(KeyCode::KEY_LEFTSHIFT)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Play the specified key sequence, respecting its timing (see [`Macro::play`]).
+    pub async fn play(&mut self, script: &Macro) -> KeyloggerResult<()> {
+        script.play(&mut self.keyboard).await
+    }
+}
+
+/// The path of the event device of the input device with the specified sysfs directory, if it
+/// was already created.
+fn event_device(sysfs_dir: &Path) -> Option<PathBuf> {
+    fs::read_dir(sysfs_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .find(|name| name.starts_with("event"))
+        .map(|name| input_dir().join(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use KeyCode::*;
+
+    #[test]
+    fn text_keys() {
+        let layout = Layout::us_qwerty();
+        let keys = "aZ1!*\n/"
+            .chars()
+            .map(|c| layout.key_for(c).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            keys,
+            [
+                (KEY_A, false),
+                (KEY_Z, true),
+                (KEY_1, false),
+                (KEY_1, true),
+                (KEY_8, true),
+                (KEY_ENTER, false),
+                (KEY_SLASH, false),
+            ]
+        );
+        assert_eq!(layout.key_for('é'), None);
+    }
+}
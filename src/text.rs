@@ -0,0 +1,477 @@
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use chrono::naive::NaiveDateTime;
+use futures::{ready, Stream};
+use pin_project::pin_project;
+
+use crate::key_code::KeyCode;
+use crate::keyboard::{KeyEvent, KeyEventCause};
+use crate::layout::{KeymapTranslator, Modifiers};
+use crate::KeyloggerResult;
+
+/// How the reconstructed text is split into tokens.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Granularity {
+    /// A token ends at any whitespace character. Empty tokens are never emitted.
+    #[default]
+    Word,
+    /// A token ends at a newline (Enter). Empty lines are emitted as empty tokens.
+    Line,
+}
+
+/// A piece of text reconstructed from the key events.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TextToken {
+    /// The text, without the whitespace that ended it.
+    pub text: String,
+    /// The timestamp of the first key press that contributed to the token.
+    pub start: NaiveDateTime,
+    /// The timestamp of the key press that ended the token (or of the last key press that
+    /// contributed to it, if the token was flushed).
+    pub end: NaiveDateTime,
+}
+
+/// Reconstructs the text typed on a keyboard from its key events.
+///
+/// The events are translated into characters using a [`KeymapTranslator`], so Shift and Caps Lock
+/// are taken into account. Backspace erases the last character of the current token (a token that
+/// was already emitted can't be edited).
+#[derive(Clone, Debug, Default)]
+pub struct TextReconstructor {
+    translator: KeymapTranslator,
+    granularity: Granularity,
+    /// The text of the current token.
+    pending: String,
+    /// The timestamps of the first and last key presses of the current token.
+    span: Option<(NaiveDateTime, NaiveDateTime)>,
+}
+
+impl TextReconstructor {
+    /// Create a reconstructor that emits words, using the US QWERTY layout.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Create a reconstructor that uses the specified translator.
+    pub fn with_translator(translator: KeymapTranslator) -> Self {
+        Self {
+            translator,
+            ..Default::default()
+        }
+    }
+
+    /// Split the text into tokens of the specified granularity.
+    pub fn granularity(mut self, granularity: Granularity) -> Self {
+        self.granularity = granularity;
+        self
+    }
+
+    /// The text typed since the last token was emitted.
+    pub fn pending(&self) -> &str {
+        &self.pending
+    }
+
+    /// Process the next event, returning the token it completes (if any).
+    ///
+    /// All events (including releases) should be fed to the reconstructor in order, so that it
+    /// can keep track of the state of the modifier keys.
+    pub fn feed(&mut self, ev: &KeyEvent) -> Option<TextToken> {
+        let c = self.translator.feed(ev);
+
+        if ev.code == KeyCode::KEY_BACKSPACE && ev.cause != KeyEventCause::Release {
+            self.pending.pop();
+
+            return None;
+        }
+
+        let c = c?;
+        let ends_token = match self.granularity {
+            Granularity::Word => c.is_whitespace(),
+            Granularity::Line => c == '\n',
+        };
+
+        if !ends_token {
+            let (start, _) = self.span.unwrap_or((ev.ts, ev.ts));
+
+            self.span = Some((start, ev.ts));
+            self.pending.push(c);
+
+            return None;
+        }
+
+        if self.granularity == Granularity::Word && self.pending.is_empty() {
+            return None;
+        }
+
+        let (start, _) = self.span.take().unwrap_or((ev.ts, ev.ts));
+
+        Some(TextToken {
+            text: mem::take(&mut self.pending),
+            start,
+            end: ev.ts,
+        })
+    }
+
+    /// Emit the text typed since the last token, if any.
+    pub fn flush(&mut self) -> Option<TextToken> {
+        let (start, end) = self.span.take()?;
+
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        Some(TextToken {
+            text: mem::take(&mut self.pending),
+            start,
+            end,
+        })
+    }
+
+    /// Turn a stream of key events into a stream of the text tokens they produce.
+    ///
+    /// The pending text is emitted as a final token when the event stream ends.
+    pub fn reconstruct<S>(self, events: S) -> TextTokens<S>
+    where
+        S: Stream<Item = KeyloggerResult<KeyEvent>>,
+    {
+        TextTokens {
+            events,
+            reconstructor: self,
+        }
+    }
+}
+
+/// A [`Stream`] of [`TextToken`]s, created using [`TextReconstructor::reconstruct`].
+#[pin_project]
+pub struct TextTokens<S> {
+    #[pin]
+    events: S,
+    reconstructor: TextReconstructor,
+}
+
+impl<S> TextTokens<S> {
+    /// The reconstructor used to produce the tokens.
+    pub fn reconstructor(&self) -> &TextReconstructor {
+        &self.reconstructor
+    }
+}
+
+impl<S> Stream for TextTokens<S>
+where
+    S: Stream<Item = KeyloggerResult<KeyEvent>>,
+{
+    type Item = KeyloggerResult<TextToken>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            match ready!(this.events.as_mut().poll_next(cx)) {
+                Some(Ok(ev)) => {
+                    if let Some(token) = this.reconstructor.feed(&ev) {
+                        return Poll::Ready(Some(Ok(token)));
+                    }
+                }
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(this.reconstructor.flush().map(Ok)),
+            }
+        }
+    }
+}
+
+/// What [`CharComposer`] does with the presses of Backspace.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Backspace {
+    /// Produce the backspace character (`'\u{8}'`), like a terminal.
+    #[default]
+    Emit,
+    /// Don't produce any character.
+    Ignore,
+}
+
+/// Translates [`KeyEvent`]s into the characters they produce, like the input of a terminal.
+///
+/// The events are translated using a [`KeymapTranslator`], so Shift, Caps Lock and AltGr are taken
+/// into account. The dead keys of the layout (see [`Layout::insert_dead_key`]) are combined with
+/// the next character typed (e.g. `^` followed by `e` produces `ê`): a dead key followed by
+/// Space produces its own symbol, a dead key followed by a character it can't be combined with is
+/// dropped, and Backspace cancels a dead key.
+///
+/// [`Layout::insert_dead_key`]: crate::layout::Layout::insert_dead_key
+#[derive(Clone, Debug, Default)]
+pub struct CharComposer {
+    translator: KeymapTranslator,
+    backspace: Backspace,
+    control_chars: bool,
+    /// The symbol of the dead key waiting for the next character.
+    dead_key: Option<char>,
+}
+
+impl CharComposer {
+    /// Create a composer that uses the US QWERTY layout.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Create a composer that uses the specified translator.
+    pub fn with_translator(translator: KeymapTranslator) -> Self {
+        Self {
+            translator,
+            ..Default::default()
+        }
+    }
+
+    /// What to do with the presses of Backspace ([`Backspace::Emit`] by default).
+    pub fn backspace(mut self, backspace: Backspace) -> Self {
+        self.backspace = backspace;
+        self
+    }
+
+    /// Produce the ASCII control characters for the letters typed while Ctrl is held (e.g.
+    /// `'\u{3}'` for Ctrl+C), instead of ignoring them.
+    pub fn control_chars(mut self) -> Self {
+        self.control_chars = true;
+        self
+    }
+
+    /// Process the next event, returning the character it produces (if any).
+    ///
+    /// All events (including releases) should be fed to the composer in order, so that it can
+    /// keep track of the state of the modifier keys.
+    pub fn feed(&mut self, ev: &KeyEvent) -> Option<char> {
+        let c = self.translator.feed(ev);
+
+        if ev.cause == KeyEventCause::Release {
+            return None;
+        }
+
+        if ev.code == KeyCode::KEY_BACKSPACE {
+            return match self.dead_key.take() {
+                Some(_) => None,
+                None => (self.backspace == Backspace::Emit).then_some('\u{8}'),
+            };
+        }
+
+        let Some(c) = c else {
+            return self.control_char(ev.code);
+        };
+
+        if self.translator.layout().is_dead_key(ev.code, c) {
+            return match self.dead_key.replace(c) {
+                // Pressing a dead key twice produces its symbol
+                Some(dead_key) if dead_key == c => self.dead_key.take(),
+                dead_key => dead_key,
+            };
+        }
+
+        match self.dead_key.take() {
+            Some(dead_key) if c == ' ' => Some(dead_key),
+            Some(dead_key) => compose(dead_key, c),
+            None => Some(c),
+        }
+    }
+
+    /// The control character produced by pressing the specified key while Ctrl is held, if
+    /// enabled.
+    fn control_char(&self, code: KeyCode) -> Option<char> {
+        let modifiers = self.translator.modifiers();
+
+        if !self.control_chars || !modifiers.ctrl || modifiers.alt {
+            return None;
+        }
+
+        let c = self.translator.translate(code, &Modifiers::default())?;
+
+        c.is_ascii_lowercase().then(|| char::from(c as u8 & 0x1f))
+    }
+
+    /// Turn a stream of key events into a stream of the characters they produce.
+    pub fn chars<S>(self, events: S) -> Chars<S>
+    where
+        S: Stream<Item = KeyloggerResult<KeyEvent>>,
+    {
+        Chars {
+            events,
+            composer: self,
+        }
+    }
+}
+
+/// Combine the symbol of a dead key with a character, if there's a precomposed character for them.
+fn compose(dead_key: char, c: char) -> Option<char> {
+    let (bases, composed) = match dead_key {
+        '`' => ("aeiouAEIOU", "àèìòùÀÈÌÒÙ"),
+        '´' | '\'' => ("aeiouyAEIOUY", "áéíóúýÁÉÍÓÚÝ"),
+        '^' => ("aeiouAEIOU", "âêîôûÂÊÎÔÛ"),
+        '¨' | '"' => ("aeiouyAEIOUY", "äëïöüÿÄËÏÖÜŸ"),
+        '~' => ("anoANO", "ãñõÃÑÕ"),
+        _ => return None,
+    };
+
+    let i = bases.chars().position(|base| base == c)?;
+
+    composed.chars().nth(i)
+}
+
+/// A [`Stream`] of the characters typed, created using [`CharComposer::chars`] (or
+/// [`KeyboardDevice::chars`](crate::KeyboardDevice::chars)).
+#[pin_project]
+pub struct Chars<S> {
+    #[pin]
+    events: S,
+    composer: CharComposer,
+}
+
+impl<S> Chars<S> {
+    /// The composer used to produce the characters.
+    pub fn composer(&self) -> &CharComposer {
+        &self.composer
+    }
+}
+
+impl<S> Stream for Chars<S>
+where
+    S: Stream<Item = KeyloggerResult<KeyEvent>>,
+{
+    type Item = KeyloggerResult<char>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            match ready!(this.events.as_mut().poll_next(cx)) {
+                Some(Ok(ev)) => {
+                    if let Some(c) = this.composer.feed(&ev) {
+                        return Poll::Ready(Some(Ok(c)));
+                    }
+                }
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use KeyCode::*;
+    use KeyEventCause::*;
+
+    fn tokens(
+        reconstructor: &mut TextReconstructor,
+        evs: &[(KeyEventCause, KeyCode)],
+    ) -> Vec<String> {
+        let mut tokens = evs
+            .iter()
+            .filter_map(|(cause, code)| {
+                reconstructor.feed(&KeyEvent {
+                    ts: Default::default(),
+                    cause: *cause,
+                    code: *code,
+                    scancode: None,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        tokens.extend(reconstructor.flush());
+        tokens.into_iter().map(|token| token.text).collect()
+    }
+
+    #[test]
+    fn reconstruct_text() {
+        let evs = [
+            (Press, KEY_LEFTSHIFT),
+            (Press, KEY_H),
+            (Release, KEY_H),
+            (Release, KEY_LEFTSHIFT),
+            (Press, KEY_I),
+            (Press, KEY_X),
+            (Press, KEY_BACKSPACE),
+            (Press, KEY_SPACE),
+            (Press, KEY_SPACE),
+            (Press, KEY_CAPSLOCK),
+            (Press, KEY_Y),
+            (Press, KEY_ENTER),
+            (Press, KEY_O),
+        ];
+
+        assert_eq!(
+            tokens(&mut TextReconstructor::new(), &evs),
+            ["Hi", "Y", "O"]
+        );
+        assert_eq!(
+            tokens(
+                &mut TextReconstructor::new().granularity(Granularity::Line),
+                &evs
+            ),
+            ["Hi  Y", "O"]
+        );
+    }
+
+    #[test]
+    fn compose_chars() {
+        let evs = |evs: &[(KeyEventCause, KeyCode)]| {
+            evs.iter()
+                .map(|(cause, code)| KeyEvent {
+                    ts: Default::default(),
+                    cause: *cause,
+                    code: *code,
+                    scancode: None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let layout = crate::layout::BuiltinLayout::FrenchAzerty.into();
+        let mut composer = CharComposer::with_translator(KeymapTranslator::new(layout));
+        let typed = evs(&[
+            (Press, KEY_LEFTBRACE),
+            (Release, KEY_LEFTBRACE),
+            (Press, KEY_E),
+            (Press, KEY_LEFTSHIFT),
+            (Press, KEY_LEFTBRACE),
+            (Release, KEY_LEFTSHIFT),
+            (Press, KEY_Q),
+            (Press, KEY_LEFTBRACE),
+            (Press, KEY_SPACE),
+            (Press, KEY_LEFTBRACE),
+            (Press, KEY_BACKSPACE),
+            (Press, KEY_BACKSPACE),
+            (Press, KEY_LEFTBRACE),
+            (Press, KEY_X),
+            (Press, KEY_LEFTCTRL),
+            (Press, KEY_C),
+        ])
+        .iter()
+        .filter_map(|ev| composer.feed(ev))
+        .collect::<String>();
+
+        assert_eq!(typed, "êä^\u{8}");
+
+        let composer = CharComposer::new()
+            .backspace(Backspace::Ignore)
+            .control_chars();
+        let chars = composer.chars(futures::stream::iter(
+            evs(&[
+                (Press, KEY_A),
+                (Press, KEY_BACKSPACE),
+                (Press, KEY_LEFTCTRL),
+                (Press, KEY_C),
+            ])
+            .into_iter()
+            .map(Ok),
+        ));
+
+        let typed = futures::executor::block_on(chars.collect::<Vec<_>>());
+
+        assert_eq!(
+            typed
+                .into_iter()
+                .collect::<KeyloggerResult<String>>()
+                .unwrap(),
+            "a\u{3}"
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! A one-shot timer that works with any of the supported async runtimes.
+
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::ptr;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::{future, ready};
+
+use crate::reactor::AsyncFd;
+
+/// A `timerfd`, registered with the reactor of the async runtime.
+#[derive(Debug)]
+pub(crate) struct Timer {
+    /// The registration of the timer with the reactor. It must be dropped before `timerfd`.
+    async_fd: AsyncFd,
+    /// The timerfd (which owns the file descriptor registered in `async_fd`).
+    timerfd: File,
+}
+
+impl Timer {
+    /// Create a timer that isn't armed.
+    pub(crate) fn new() -> io::Result<Self> {
+        let fd = unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+            )
+        };
+
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Take ownership of the fd straight away, so it gets closed if anything below fails.
+        let timerfd = unsafe { File::from_raw_fd(fd) };
+
+        Ok(Self {
+            async_fd: AsyncFd::new(timerfd.as_raw_fd())?,
+            timerfd,
+        })
+    }
+
+    /// Arm the timer to expire once, after `timeout` (rearming it if it's already armed).
+    pub(crate) fn arm(&self, timeout: Duration) -> io::Result<()> {
+        // A zero it_value disarms the timer, so the timeout is rounded up to 1ns
+        let timeout = timeout.max(Duration::from_nanos(1));
+        let spec = libc::itimerspec {
+            it_interval: libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            it_value: libc::timespec {
+                tv_sec: timeout.as_secs().try_into().unwrap_or(libc::time_t::MAX),
+                tv_nsec: timeout.subsec_nanos().into(),
+            },
+        };
+
+        let res =
+            unsafe { libc::timerfd_settime(self.timerfd.as_raw_fd(), 0, &spec, ptr::null_mut()) };
+
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    /// Wait for the timer to expire.
+    ///
+    /// This never completes if the timer isn't armed.
+    pub(crate) fn poll_expired(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.async_fd.poll_read(cx, read_expirations))?;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Read the number of expirations of the timer since it was last read or armed.
+fn read_expirations(fd: RawFd) -> io::Result<u64> {
+    let mut expirations = 0u64;
+    let n = unsafe {
+        libc::read(
+            fd,
+            &mut expirations as *mut u64 as *mut _,
+            mem::size_of::<u64>(),
+        )
+    };
+
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(expirations)
+}
+
+/// Wait for the specified duration to elapse.
+pub(crate) async fn sleep(duration: Duration) -> io::Result<()> {
+    if duration.is_zero() {
+        return Ok(());
+    }
+
+    let timer = Timer::new()?;
+
+    timer.arm(duration)?;
+    future::poll_fn(|cx| timer.poll_expired(cx)).await
+}
@@ -0,0 +1,207 @@
+//! Put the events of different keyboards on the same timeline.
+//!
+//! Each keyboard timestamps its events using its own clock (see [`Clock`]), and the kernel only
+//! stamps them when the device reports them: a wireless keyboard may deliver several events at
+//! once after a radio hiccup, and the keyboards configured to use [`Clock::Realtime`] jump along
+//! with the wall clock. The timestamps of the events of a [`KeyboardSet`] therefore can't be
+//! compared across keyboards.
+//!
+//! A [`TimestampNormalizer`] stamps the events of a merged stream with a time on the monotonic
+//! clock of the system instead, while keeping the original (hardware) timestamp of each event:
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use keylogger::timestamps::TimestampNormalizer;
+//! use keylogger::{merge_keyboards, KeyloggerError};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let mut events = TimestampNormalizer::new().normalize(merge_keyboards()?);
+//!
+//!     while let Some(ev) = events.next().await {
+//!         let ev = ev?;
+//!
+//!         println!(
+//!             "{:?} (keyboard {}): {:?}",
+//!             ev.ts,
+//!             ev.event.device_id.get(),
+//!             ev.event.event.code
+//!         );
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! The normalizer learns the offset between the clock of each keyboard and the monotonic clock
+//! from the smallest delay with which the events of the keyboard are received, and stamps each
+//! event with its hardware timestamp shifted by that offset. This keeps the spacing of the events
+//! delivered in bursts, and never puts an event after the time it was received. The normalized
+//! timestamps never decrease, so the events of the merged stream are sorted by them.
+//!
+//! [`KeyboardSet`]: crate::KeyboardSet
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::{ready, Stream};
+use pin_project::pin_project;
+
+use crate::keyboard::{Clock, DeviceId, TaggedKeyEvent};
+use crate::KeyloggerResult;
+
+/// How far the normalized timestamp of an event may fall behind the time it was received before
+/// the clock of its keyboard is assumed to have jumped back, and its offset is learned again.
+const RESYNC_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// A [`TaggedKeyEvent`], stamped with a time that can be compared across keyboards.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct NormalizedEvent {
+    /// The event, with its original (hardware) timestamp.
+    pub event: TaggedKeyEvent,
+    /// The normalized timestamp of the event, on the monotonic clock of the system (like
+    /// [`Clock::now`] for [`Clock::Monotonic`]).
+    pub ts: Duration,
+    /// When the event was received, on the monotonic clock of the system.
+    pub received: Duration,
+}
+
+/// Stamps the events of several keyboards with a consistent monotonic time (see the [module
+/// docs](self)).
+#[derive(Clone, Debug, Default)]
+pub struct TimestampNormalizer {
+    /// The offset (in nanoseconds) from the clock of each keyboard to the monotonic clock.
+    offsets: HashMap<DeviceId, i128>,
+    /// The last normalized timestamp (in nanoseconds).
+    last: i128,
+}
+
+impl TimestampNormalizer {
+    /// Create a normalizer that doesn't know the clocks of the keyboards yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget the offset of the clock of the specified keyboard, e.g. because the keyboard was
+    /// switched to another clock (see [`KeyboardDevice::set_clock`]).
+    ///
+    /// [`KeyboardDevice::set_clock`]: crate::KeyboardDevice::set_clock
+    pub fn reset(&mut self, device_id: DeviceId) {
+        self.offsets.remove(&device_id);
+    }
+
+    /// Stamp the specified event, which was received at the specified time (on the monotonic
+    /// clock).
+    pub fn feed(&mut self, event: TaggedKeyEvent, received: Duration) -> NormalizedEvent {
+        let hardware = event.event.clock_time().as_nanos() as i128;
+        let received_ns = received.as_nanos() as i128;
+        let delay = received_ns - hardware;
+        let offset = self.offsets.entry(event.device_id).or_insert(delay);
+
+        if delay < *offset || delay - *offset > RESYNC_THRESHOLD.as_nanos() as i128 {
+            *offset = delay;
+        }
+
+        let ts = (hardware + *offset).clamp(self.last.min(received_ns), received_ns);
+
+        self.last = self.last.max(ts);
+
+        NormalizedEvent {
+            event,
+            ts: Duration::from_nanos(self.last as u64),
+            received,
+        }
+    }
+
+    /// Turn a stream of the events of several keyboards into a stream of normalized events,
+    /// which are stamped with the time they're received.
+    pub fn normalize<S>(self, events: S) -> Normalized<S>
+    where
+        S: Stream<Item = KeyloggerResult<TaggedKeyEvent>>,
+    {
+        Normalized {
+            events,
+            normalizer: self,
+        }
+    }
+}
+
+/// A [`Stream`] of [`NormalizedEvent`]s, created using [`TimestampNormalizer::normalize`].
+#[pin_project]
+pub struct Normalized<S> {
+    #[pin]
+    events: S,
+    normalizer: TimestampNormalizer,
+}
+
+impl<S> Normalized<S> {
+    /// The normalizer used to stamp the events.
+    pub fn normalizer(&mut self) -> &mut TimestampNormalizer {
+        &mut self.normalizer
+    }
+}
+
+impl<S> Stream for Normalized<S>
+where
+    S: Stream<Item = KeyloggerResult<TaggedKeyEvent>>,
+{
+    type Item = KeyloggerResult<NormalizedEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        Poll::Ready(
+            ready!(this.events.poll_next(cx))
+                .map(|item| item.map(|ev| this.normalizer.feed(ev, Clock::Monotonic.now()))),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_code::KeyCode;
+    use crate::keyboard::{KeyEvent, KeyEventCause};
+
+    #[test]
+    fn normalize_timestamps() {
+        let mut normalizer = TimestampNormalizer::new();
+        let mut feed = |device, hardware_ms: u64, received_ms: u64| {
+            let event = TaggedKeyEvent {
+                device_id: DeviceId(device),
+                event: KeyEvent {
+                    ts: chrono::NaiveDateTime::default() + Duration::from_millis(hardware_ms),
+                    cause: KeyEventCause::Press,
+                    code: KeyCode::KEY_A,
+                    scancode: None,
+                },
+                batch: Default::default(),
+            };
+
+            normalizer
+                .feed(event, Duration::from_millis(received_ms))
+                .ts
+                .as_millis()
+        };
+
+        // The first keyboard uses the wall clock, and is received with a delay of 2-5ms
+        assert_eq!(feed(0, 1_700_000_000_000, 10_005), 10_005);
+        assert_eq!(feed(0, 1_700_000_000_100, 10_102), 10_102);
+        // Its offset was lowered, so the first event would now be at 10_002
+        assert_eq!(feed(0, 1_700_000_000_200, 10_210), 10_202);
+
+        // The second keyboard uses the monotonic clock, and delivers a burst: the spacing of the
+        // events is kept, but they can't be put before the events already normalized
+        assert_eq!(feed(1, 10_200, 10_201), 10_202);
+        assert_eq!(feed(1, 10_250, 10_300), 10_251);
+        assert_eq!(feed(1, 10_260, 10_300), 10_261);
+        assert_eq!(feed(1, 10_400, 10_401), 10_401);
+        assert_eq!(feed(1, 10_410, 10_450), 10_411);
+
+        // The wall clock is set back by an hour, so the first keyboard is resynchronized
+        assert_eq!(feed(0, 1_699_996_400_500, 10_502), 10_502);
+        assert_eq!(feed(0, 1_699_996_400_550, 10_560), 10_552);
+    }
+}
@@ -0,0 +1,320 @@
+//! Monitor touchscreens, touchpads and graphics tablets.
+//!
+//! The devices that report absolute positions (`EV_ABS`) can be detected using
+//! [`find_touch_devices`]. Like [`KeyboardDevice`](crate::KeyboardDevice), a [`TouchDevice`]
+//! implements [`Stream`], where each element is a [`TouchEvent`].
+//!
+//! Both multi-touch devices (which track each finger in a separate slot) and single-touch devices
+//! (such as the pens of graphics tablets, which are reported in slot 0) are supported.
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use keylogger::touch::find_touch_devices;
+//! use keylogger::KeyloggerError;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let mut touchscreen = find_touch_devices()?.remove(0);
+//!
+//!     while let Some(ev) = touchscreen.next().await {
+//!         let ev = ev?;
+//!
+//!         println!("{:?} slot={} at ({}, {})", ev.phase, ev.slot, ev.x, ev.y);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::{BTreeMap, VecDeque};
+use std::mem;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use chrono::naive::NaiveDateTime;
+use futures::{ready, Stream};
+
+use crate::keyboard::device::{
+    find_char_devices, input_dir, DeviceInfo, EventReader, InputDevice, DEFAULT_BUFFER_SIZE,
+};
+use crate::keyboard::event_codes::{
+    ABS_MT_POSITION_X, ABS_MT_POSITION_Y, ABS_MT_PRESSURE, ABS_MT_SLOT, ABS_MT_TRACKING_ID,
+    ABS_PRESSURE, ABS_X, ABS_Y, BTN_TOUCH, EV_ABS, EV_KEY, EV_SYN, SYN_REPORT,
+};
+use crate::keyboard::timestamp;
+use crate::KeyloggerResult;
+
+/// The event types a device must support to be considered a touch device.
+const TOUCH_FLAGS: libc::c_ulong = (1 << EV_SYN) | (1 << EV_ABS);
+
+/// What happened to a contact.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TouchPhase {
+    /// A finger or stylus touched the surface.
+    Down,
+    /// The contact moved, or its pressure changed.
+    Move,
+    /// The finger or stylus was lifted.
+    Up,
+}
+
+/// A change in the state of a contact with a touch surface.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TouchEvent {
+    /// The timestamp of the hardware report that contained the change.
+    pub ts: NaiveDateTime,
+    /// What happened to the contact.
+    pub phase: TouchPhase,
+    /// The slot of the contact, which identifies it for as long as it touches the surface.
+    ///
+    /// This is always 0 for single-touch devices.
+    pub slot: u32,
+    /// The horizontal position of the contact, in device units.
+    pub x: i32,
+    /// The vertical position of the contact, in device units.
+    pub y: i32,
+    /// The pressure of the contact, in device units, if the device measures it.
+    pub pressure: Option<i32>,
+}
+
+/// Find all the devices that report absolute positions, such as touchscreens, touchpads and
+/// graphics tablets.
+///
+/// Note this includes other absolute pointing devices, such as joysticks.
+pub fn find_touch_devices() -> KeyloggerResult<Vec<TouchDevice>> {
+    Ok(find_char_devices(&input_dir())?
+        .filter_map(|entry| {
+            // The device reads its own events, so the key event reader is kept minimal
+            InputDevice::open(&entry, TOUCH_FLAGS, EventReader::new(1, false)).ok()
+        })
+        .map(|inner| TouchDevice {
+            inner,
+            buf: vec![unsafe { mem::zeroed() }; DEFAULT_BUFFER_SIZE],
+            tracker: Default::default(),
+            pending: Default::default(),
+        })
+        .collect())
+}
+
+/// A touchscreen, touchpad or graphics tablet (see [`find_touch_devices`]).
+pub struct TouchDevice {
+    inner: InputDevice,
+    /// The buffer the input events are read into, reused across reads.
+    buf: Vec<libc::input_event>,
+    tracker: TouchTracker,
+    /// The events that were read but not yet returned.
+    pending: VecDeque<TouchEvent>,
+}
+
+impl TouchDevice {
+    /// A human-readable description of the device.
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    /// The path of the device (e.g. `/dev/input/event7`)
+    pub fn path(&self) -> &Path {
+        &self.inner.device
+    }
+
+    /// Information about the hardware of the device, such as its vendor and product ID.
+    pub fn info(&self) -> &DeviceInfo {
+        &self.inner.info
+    }
+}
+
+impl Stream for TouchDevice {
+    type Item = KeyloggerResult<TouchEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some(ev) = this.pending.pop_front() {
+                return Poll::Ready(Some(Ok(ev)));
+            }
+
+            let evs = match ready!(this.inner.poll_input_events(cx, &mut this.buf)) {
+                Ok(evs) => evs,
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            };
+
+            for ev in evs {
+                match this.tracker.feed(ev) {
+                    Ok(touch_evs) => this.pending.extend(touch_evs),
+                    Err(e) => return Poll::Ready(Some(Err(e))),
+                }
+            }
+        }
+    }
+}
+
+/// The state of a contact.
+#[derive(Clone, Debug, Default)]
+struct Contact {
+    touching: bool,
+    x: i32,
+    y: i32,
+    pressure: Option<i32>,
+    /// The change to report at the end of the current hardware report, if any.
+    change: Option<TouchPhase>,
+}
+
+impl Contact {
+    /// Record a change to the position or pressure of the contact.
+    fn moved(&mut self) {
+        if self.touching && self.change.is_none() {
+            self.change = Some(TouchPhase::Move);
+        }
+    }
+}
+
+/// Turns the `EV_ABS` events of a device into [`TouchEvent`]s.
+#[derive(Debug, Default)]
+struct TouchTracker {
+    /// Whether the device uses the multi-touch protocol, in which case the legacy single-touch
+    /// events it also emits are ignored.
+    multitouch: bool,
+    /// The slot the multi-touch events currently apply to.
+    slot: u32,
+    contacts: BTreeMap<u32, Contact>,
+}
+
+impl TouchTracker {
+    /// Process the next input event, returning the touch events completed by it.
+    fn feed(&mut self, ev: &libc::input_event) -> KeyloggerResult<Vec<TouchEvent>> {
+        match (ev.type_ as libc::c_ulong, ev.code) {
+            (EV_SYN, SYN_REPORT) => return Ok(self.report(timestamp(&ev.time)?)),
+            (EV_ABS, ABS_MT_SLOT) => {
+                self.multitouch = true;
+                self.slot = ev.value.max(0) as u32;
+            }
+            (EV_ABS, ABS_MT_TRACKING_ID) => {
+                self.multitouch = true;
+                self.touch(self.slot, ev.value >= 0);
+            }
+            (EV_ABS, ABS_MT_POSITION_X) => self.update(self.slot, |c| c.x = ev.value),
+            (EV_ABS, ABS_MT_POSITION_Y) => self.update(self.slot, |c| c.y = ev.value),
+            (EV_ABS, ABS_MT_PRESSURE) => self.update(self.slot, |c| c.pressure = Some(ev.value)),
+            _ if self.multitouch => {}
+            (EV_KEY, BTN_TOUCH) => self.touch(0, ev.value != 0),
+            (EV_ABS, ABS_X) => self.update(0, |c| c.x = ev.value),
+            (EV_ABS, ABS_Y) => self.update(0, |c| c.y = ev.value),
+            (EV_ABS, ABS_PRESSURE) => self.update(0, |c| c.pressure = Some(ev.value)),
+            _ => {}
+        }
+
+        Ok(vec![])
+    }
+
+    fn touch(&mut self, slot: u32, touching: bool) {
+        let contact = self.contacts.entry(slot).or_default();
+
+        if contact.touching != touching {
+            contact.touching = touching;
+            contact.change = Some(if touching {
+                TouchPhase::Down
+            } else {
+                TouchPhase::Up
+            });
+        }
+    }
+
+    fn update(&mut self, slot: u32, f: impl FnOnce(&mut Contact)) {
+        let contact = self.contacts.entry(slot).or_default();
+
+        f(contact);
+        contact.moved();
+    }
+
+    /// Emit the changes accumulated since the previous hardware report.
+    fn report(&mut self, ts: NaiveDateTime) -> Vec<TouchEvent> {
+        self.contacts
+            .iter_mut()
+            .filter_map(|(slot, contact)| {
+                Some(TouchEvent {
+                    ts,
+                    phase: contact.change.take()?,
+                    slot: *slot,
+                    x: contact.x,
+                    y: contact.y,
+                    pressure: contact.pressure,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TouchPhase::*;
+
+    fn feed(tracker: &mut TouchTracker, evs: &[(libc::c_ulong, u16, i32)]) -> Vec<TouchEvent> {
+        evs.iter()
+            .flat_map(|(type_, code, value)| {
+                let ev = libc::input_event {
+                    time: libc::timeval {
+                        tv_sec: 0,
+                        tv_usec: 0,
+                    },
+                    type_: *type_ as u16,
+                    code: *code,
+                    value: *value,
+                };
+
+                tracker.feed(&ev).unwrap()
+            })
+            .collect()
+    }
+
+    fn touch(phase: TouchPhase, slot: u32, x: i32, y: i32) -> TouchEvent {
+        TouchEvent {
+            ts: Default::default(),
+            phase,
+            slot,
+            x,
+            y,
+            pressure: None,
+        }
+    }
+
+    #[test]
+    fn track_contacts() {
+        let mut tracker = TouchTracker::default();
+        let evs = [
+            // The first finger touches the surface
+            (EV_ABS, ABS_MT_SLOT, 0),
+            (EV_ABS, ABS_MT_TRACKING_ID, 45),
+            (EV_ABS, ABS_MT_POSITION_X, 10),
+            (EV_ABS, ABS_MT_POSITION_Y, 20),
+            (EV_KEY, BTN_TOUCH, 1),
+            (EV_ABS, ABS_X, 10),
+            (EV_SYN, SYN_REPORT, 0),
+            // A second finger touches the surface, and the first one moves
+            (EV_ABS, ABS_MT_POSITION_X, 11),
+            (EV_ABS, ABS_MT_SLOT, 1),
+            (EV_ABS, ABS_MT_TRACKING_ID, 46),
+            (EV_ABS, ABS_MT_POSITION_X, 50),
+            (EV_ABS, ABS_MT_POSITION_Y, 60),
+            (EV_SYN, SYN_REPORT, 0),
+            // The first finger is lifted
+            (EV_ABS, ABS_MT_SLOT, 0),
+            (EV_ABS, ABS_MT_TRACKING_ID, -1),
+            (EV_SYN, SYN_REPORT, 0),
+        ];
+
+        assert_eq!(
+            feed(&mut tracker, &evs),
+            [
+                touch(Down, 0, 10, 20),
+                touch(Move, 0, 11, 20),
+                touch(Down, 1, 50, 60),
+                touch(Up, 0, 11, 20),
+            ]
+        );
+    }
+}
@@ -0,0 +1,164 @@
+//! The instrumentation of the crate, which is reported through `tracing` when the `tracing`
+//! feature is enabled, and compiled out otherwise.
+//!
+//! Each keyboard gets a `keyboard` span (with its `name` and `path`), which is the parent of the
+//! events about reading it, and each invocation of a handler by [`dispatch`] runs in a `handle`
+//! span (with the `device_id` of the event). The counters are reported as the fields of the
+//! events, using the `monotonic_counter.` prefix understood by `tracing-opentelemetry`.
+//!
+//! [`dispatch`]: crate::handler::dispatch
+
+#![cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+
+use std::future::Future;
+use std::io;
+use std::path::Path;
+
+use crate::keyboard::DeviceId;
+use crate::KeyloggerError;
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::Span;
+
+/// A stand-in for `tracing::Span`, used when the `tracing` feature is disabled.
+#[cfg(not(feature = "tracing"))]
+#[derive(Clone, Debug)]
+pub(crate) struct Span;
+
+/// The span of the keyboard with the specified name and path.
+pub(crate) fn keyboard_span(name: &str, path: &Path) -> Span {
+    #[cfg(feature = "tracing")]
+    let span = tracing::debug_span!("keyboard", name, path = %path.display());
+    #[cfg(not(feature = "tracing"))]
+    let span = Span;
+
+    span
+}
+
+/// The span of a [`Keylogger::run`](crate::Keylogger::run).
+pub(crate) fn keylogger_span(keyboards: usize) -> Span {
+    #[cfg(feature = "tracing")]
+    let span = tracing::info_span!("keylogger", keyboards);
+    #[cfg(not(feature = "tracing"))]
+    let span = Span;
+
+    span
+}
+
+/// The span of the handling of an event of the specified keyboard.
+pub(crate) fn handle_span(device_id: DeviceId) -> Span {
+    #[cfg(feature = "tracing")]
+    let span = tracing::debug_span!("handle", device_id = device_id.get());
+    #[cfg(not(feature = "tracing"))]
+    let span = Span;
+
+    span
+}
+
+/// Run the specified future in `span`.
+pub(crate) fn instrument<F: Future>(fut: F, span: Span) -> impl Future<Output = F::Output> {
+    #[cfg(feature = "tracing")]
+    let fut = tracing::Instrument::instrument(fut, span);
+
+    fut
+}
+
+/// A report of `events` key events was read from a keyboard.
+pub(crate) fn report_read(span: &Span, events: usize) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(
+        parent: span,
+        events,
+        monotonic_counter.keylogger.reports_read = 1u64,
+        monotonic_counter.keylogger.events_read = events as u64,
+        "read a report",
+    );
+}
+
+/// The kernel dropped the events of a keyboard `dropped` times before the report that was read.
+pub(crate) fn events_dropped(span: &Span, dropped: u32) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        parent: span,
+        dropped,
+        monotonic_counter.keylogger.overflows = dropped as u64,
+        "the kernel dropped events of the keyboard",
+    );
+}
+
+/// Reading a keyboard failed.
+pub(crate) fn read_failed(span: &Span, e: &io::Error) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        parent: span,
+        error = %e,
+        monotonic_counter.keylogger.read_errors = 1u64,
+        "failed to read the keyboard",
+    );
+}
+
+/// A Bluetooth keyboard disconnected (e.g. because it went out of range, or its battery died).
+pub(crate) fn bluetooth_disconnected(span: &Span, address: Option<&str>) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        parent: span,
+        address,
+        monotonic_counter.keylogger.bluetooth_disconnects = 1u64,
+        "the Bluetooth keyboard disconnected",
+    );
+}
+
+/// A keyboard was paused, or resumed.
+pub(crate) fn pause_changed(span: &Span, paused: bool) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(parent: span, paused, "the keyboard was paused or resumed");
+}
+
+/// A keyboard was added to, or removed from, a [`KeyboardSet`](crate::KeyboardSet).
+pub(crate) fn membership(device_id: DeviceId, path: &Path, added: bool) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        device_id = device_id.get(),
+        path = %path.display(),
+        added,
+        "a keyboard was added to or removed from the set",
+    );
+}
+
+/// A keyboard was removed by the [`ErrorPolicy`](crate::ErrorPolicy) of a keylogger.
+pub(crate) fn device_dropped(path: &Path, e: &KeyloggerError) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        path = %path.display(),
+        error = %e,
+        monotonic_counter.keylogger.devices_removed = 1u64,
+        "removing the keyboard after an error",
+    );
+}
+
+/// A keyboard was unplugged, and is waited for.
+pub(crate) fn unplugged(path: &Path, e: &KeyloggerError) {
+    #[cfg(feature = "tracing")]
+    tracing::info!(
+        path = %path.display(),
+        error = %e,
+        "the keyboard was unplugged, waiting for it to reappear",
+    );
+}
+
+/// An unplugged keyboard reappeared.
+pub(crate) fn reconnected(path: &Path, reconnects: u32) {
+    #[cfg(feature = "tracing")]
+    tracing::info!(
+        path = %path.display(),
+        reconnects,
+        monotonic_counter.keylogger.reconnects = 1u64,
+        "the keyboard was reconnected",
+    );
+}
+
+/// An unplugged keyboard didn't reappear before the retry policy gave up.
+pub(crate) fn reconnect_timed_out(e: &KeyloggerError) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(error = %e, "the keyboard didn't reappear");
+}
@@ -0,0 +1,269 @@
+//! A [ratatui](https://ratatui.rs) widget that renders the live key activity of the keyboards.
+//!
+//! The events are fed to a [`KeyActivity`], which keeps track of the recent key presses of each
+//! keyboard. A `&KeyActivity` implements [`Widget`], which renders one column per keyboard,
+//! containing the typing rate of the keyboard, followed by its most recent keys (newest first):
+//!
+//! ```text
+//! ┌USB Keyboard──────────┐┌AT Translated Set 2 k─┐
+//! │142 keys/min (318)    ││12 keys/min (12)      │
+//! │O                     ││LEFTCTRL              │
+//! │L                     ││C                     │
+//! │L                     ││                      │
+//! └──────────────────────┘└──────────────────────┘
+//! ```
+//!
+//! The widget doesn't depend on a particular terminal backend, so it can be embedded in any
+//! ratatui application:
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use keylogger::tui::KeyActivity;
+//! use keylogger::{merge_keyboards, KeyloggerError};
+//! # use ratatui::Terminal;
+//! # fn terminal() -> Terminal<ratatui::backend::TestBackend> { unimplemented!() }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let mut keyboards = merge_keyboards()?;
+//!     let mut activity = KeyActivity::new();
+//!     let mut terminal = terminal();
+//!
+//!     while let Some(ev) = keyboards.next().await {
+//!         let ev = ev?;
+//!         let name = keyboards.get(ev.device_id).map_or("unknown", |k| k.name());
+//!
+//!         activity.record(name, &ev.event);
+//!         terminal.draw(|frame| frame.render_widget(&activity, frame.area()))?;
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use ratatui::buffer::Buffer;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Paragraph, Widget};
+
+use crate::key_code::KeyCode;
+use crate::keyboard::{KeyEvent, KeyEventCause};
+
+/// The number of recent keys kept for each keyboard by default.
+const DEFAULT_HISTORY: usize = 32;
+/// The window the typing rate is computed over by default.
+const DEFAULT_RATE_WINDOW: Duration = Duration::from_secs(10);
+
+/// The key activity of a keyboard.
+#[derive(Clone, Debug)]
+struct DeviceActivity {
+    name: String,
+    /// The most recently pressed keys, oldest first.
+    recent: VecDeque<KeyCode>,
+    /// The timestamps of the presses within the rate window, oldest first.
+    presses: VecDeque<NaiveDateTime>,
+    total: u64,
+}
+
+/// The recent key activity of a group of keyboards, rendered by its [`Widget`] implementation
+/// (see the [module docs](self)).
+///
+/// Only key presses are recorded (releases and autorepeat events are ignored). The keyboards are
+/// rendered in the order their first press was recorded.
+#[derive(Clone, Debug)]
+pub struct KeyActivity {
+    devices: Vec<DeviceActivity>,
+    history: usize,
+    rate_window: Duration,
+}
+
+impl Default for KeyActivity {
+    fn default() -> Self {
+        Self {
+            devices: Vec::new(),
+            history: DEFAULT_HISTORY,
+            rate_window: DEFAULT_RATE_WINDOW,
+        }
+    }
+}
+
+impl KeyActivity {
+    /// Create an empty `KeyActivity`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The number of recent keys to keep for each keyboard (32 by default).
+    pub fn history(mut self, history: usize) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// The window the typing rates are computed over (10 seconds by default).
+    ///
+    /// A longer window produces a smoother rate, which is slower to react to changes.
+    pub fn rate_window(mut self, window: Duration) -> Self {
+        self.rate_window = window;
+        self
+    }
+
+    /// Record an event of the keyboard with the specified name.
+    pub fn record(&mut self, device: &str, ev: &KeyEvent) {
+        if ev.cause != KeyEventCause::Press {
+            return;
+        }
+
+        let idx = match self.devices.iter().position(|d| d.name == device) {
+            Some(idx) => idx,
+            None => {
+                self.devices.push(DeviceActivity {
+                    name: device.to_string(),
+                    recent: VecDeque::new(),
+                    presses: VecDeque::new(),
+                    total: 0,
+                });
+
+                self.devices.len() - 1
+            }
+        };
+
+        let activity = &mut self.devices[idx];
+
+        if activity.recent.len() == self.history {
+            activity.recent.pop_front();
+        }
+
+        activity.recent.push_back(ev.code);
+        activity.presses.push_back(ev.ts);
+        activity.total += 1;
+
+        let window = chrono::Duration::from_std(self.rate_window).unwrap_or(chrono::Duration::MAX);
+
+        while activity
+            .presses
+            .front()
+            .is_some_and(|ts| ev.ts - *ts > window)
+        {
+            activity.presses.pop_front();
+        }
+    }
+
+    /// The typing rate of the keyboard with the specified name, in key presses per minute.
+    ///
+    /// The rate is computed over the presses that occurred within the rate window of its most
+    /// recent press (see [`KeyActivity::rate_window`]).
+    pub fn rate(&self, device: &str) -> Option<f64> {
+        let activity = self.devices.iter().find(|d| d.name == device)?;
+        let window = self.rate_window.as_secs_f64();
+
+        (window > 0.0).then(|| activity.presses.len() as f64 * 60.0 / window)
+    }
+
+    /// The names of the keyboards that had any activity, in the order they are rendered.
+    pub fn devices(&self) -> impl Iterator<Item = &str> {
+        self.devices.iter().map(|d| d.name.as_str())
+    }
+
+    /// Forget the activity of all the keyboards.
+    pub fn clear(&mut self) {
+        self.devices.clear();
+    }
+}
+
+impl Widget for &KeyActivity {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if self.devices.is_empty() {
+            Paragraph::new("No key activity")
+                .block(Block::default().borders(Borders::ALL))
+                .render(area, buf);
+
+            return;
+        }
+
+        let n = self.devices.len() as u32;
+        let columns = Layout::horizontal((0..n).map(|_| Constraint::Ratio(1, n))).split(area);
+
+        for (activity, column) in self.devices.iter().zip(columns.iter()) {
+            let rate = self.rate(&activity.name).unwrap_or_default();
+            let header = Line::styled(
+                format!("{rate:.0} keys/min ({})", activity.total),
+                Style::default().add_modifier(Modifier::BOLD),
+            );
+
+            let lines = std::iter::once(header)
+                .chain(
+                    activity
+                        .recent
+                        .iter()
+                        .rev()
+                        .map(|code| Line::raw(code.to_string())),
+                )
+                .collect::<Vec<_>>();
+
+            Paragraph::new(lines)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(activity.name.as_str()),
+                )
+                .render(*column, buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_activity() {
+        let ev = |millis: i64, cause, code| KeyEvent {
+            ts: chrono::DateTime::from_timestamp_millis(1_700_000_000_000 + millis)
+                .unwrap()
+                .naive_utc(),
+            cause,
+            code,
+            scancode: None,
+        };
+
+        let mut activity = KeyActivity::new().history(2);
+
+        activity.record("kbd", &ev(0, KeyEventCause::Press, KeyCode::KEY_A));
+        activity.record("kbd", &ev(50, KeyEventCause::Release, KeyCode::KEY_A));
+        activity.record("kbd", &ev(100, KeyEventCause::Press, KeyCode::KEY_B));
+        activity.record("kbd", &ev(200, KeyEventCause::Press, KeyCode::KEY_C));
+        activity.record("other", &ev(300, KeyEventCause::Press, KeyCode::KEY_D));
+
+        // 3 presses in a 10 second window
+        assert_eq!(activity.rate("kbd"), Some(18.0));
+        assert_eq!(activity.devices().collect::<Vec<_>>(), ["kbd", "other"]);
+
+        let mut buf = Buffer::empty(Rect::new(0, 0, 40, 5));
+
+        (&activity).render(buf.area, &mut buf);
+
+        let lines = (0..buf.area.height)
+            .map(|y| {
+                (0..buf.area.width)
+                    .map(|x| buf[(x, y)].symbol())
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lines,
+            [
+                "┌kbd───────────────┐┌other─────────────┐",
+                "│18 keys/min (3)   ││6 keys/min (1)    │",
+                "│C                 ││D                 │",
+                "│B                 ││                  │",
+                "└──────────────────┘└──────────────────┘",
+            ]
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use std::convert::TryFrom;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::mem;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::slice;
+
+use crate::key_code::{KeyCode, KEY_MAX};
+#[cfg(feature = "test-util")]
+use crate::keyboard::device::IOC_READ;
+use crate::keyboard::device::{ioc, ioctl, ioctl_with_value, IOC_NONE, IOC_WRITE};
+use crate::keyboard::event_codes::{
+    BUS_VIRTUAL, EV_KEY, EV_MSC, EV_REP, EV_SYN, MSC_SCAN, SYN_REPORT,
+};
+use crate::keyboard::{KeyEvent, KeyEventCause};
+use crate::KeyloggerResult;
+
+/// The uinput character device.
+const UINPUT_DEVICE: &str = "/dev/uinput";
+
+/// A virtual keyboard created using the uinput kernel module.
+///
+/// The [`KeyEvent`]s written to a `VirtualKeyboard` are injected into the kernel's input
+/// subsystem, as if they originated from a physical keyboard. Like reading from keyboard devices,
+/// creating a virtual keyboard requires root privileges (or write access to `/dev/uinput`).
+///
+/// The virtual device is destroyed when the `VirtualKeyboard` is dropped.
+#[derive(Debug)]
+pub struct VirtualKeyboard {
+    file: File,
+}
+
+impl VirtualKeyboard {
+    /// Create a virtual keyboard with the specified name.
+    ///
+    /// The name is truncated to 79 bytes. The keyboard supports all the keys from [`KeyCode`].
+    pub fn new(name: &str) -> KeyloggerResult<Self> {
+        Self::create(name, false)
+    }
+
+    /// Create a virtual keyboard that also reports scancodes and autorepeat events, like a
+    /// physical keyboard (so [`find_keyboards`](crate::find_keyboards) detects it).
+    #[cfg(feature = "test-util")]
+    pub(crate) fn with_keyboard_capabilities(name: &str) -> KeyloggerResult<Self> {
+        Self::create(name, true)
+    }
+
+    /// Create a virtual keyboard, which optionally supports the `EV_MSC` and `EV_REP` event types
+    /// (without which it isn't detected as a keyboard by default).
+    fn create(name: &str, keyboard_capabilities: bool) -> KeyloggerResult<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(UINPUT_DEVICE)?;
+        let fd = file.as_raw_fd();
+
+        let ui_set_evbit = ioc(IOC_WRITE, 'U', 100, mem::size_of::<libc::c_int>());
+        let ui_set_keybit = ioc(IOC_WRITE, 'U', 101, mem::size_of::<libc::c_int>());
+
+        ioctl_with_value(fd, ui_set_evbit, EV_KEY as libc::c_int)?;
+        ioctl_with_value(fd, ui_set_evbit, EV_SYN as libc::c_int)?;
+
+        if keyboard_capabilities {
+            let ui_set_mscbit = ioc(IOC_WRITE, 'U', 104, mem::size_of::<libc::c_int>());
+
+            ioctl_with_value(fd, ui_set_evbit, EV_MSC as libc::c_int)?;
+            ioctl_with_value(fd, ui_set_mscbit, MSC_SCAN.into())?;
+            ioctl_with_value(fd, ui_set_evbit, EV_REP as libc::c_int)?;
+        }
+
+        for code in (0..=KEY_MAX).filter(|code| KeyCode::try_from(*code).is_ok()) {
+            ioctl_with_value(fd, ui_set_keybit, code.into())?;
+        }
+
+        setup_device(fd, name)?;
+
+        let ui_dev_create = ioc(IOC_NONE, 'U', 1, 0);
+        ioctl_with_value(fd, ui_dev_create, 0)?;
+
+        Ok(Self { file })
+    }
+
+    /// The name of the virtual device in sysfs (e.g. `input42`), read using the `UI_GET_SYSNAME`
+    /// ioctl.
+    #[cfg(feature = "test-util")]
+    pub(crate) fn sysname(&self) -> KeyloggerResult<String> {
+        let mut buf = [0u8; 64];
+        let ui_get_sysname = ioc(IOC_READ, 'U', 44, buf.len());
+
+        ioctl(
+            self.file.as_raw_fd(),
+            ui_get_sysname,
+            buf.as_mut_ptr() as *mut libc::c_ulong,
+        )?;
+
+        let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+
+        Ok(String::from_utf8_lossy(&buf[..len]).into_owned())
+    }
+
+    /// Inject the specified event, followed by a `SYN_REPORT`.
+    pub fn emit(&mut self, ev: &KeyEvent) -> KeyloggerResult<()> {
+        self.emit_all(slice::from_ref(ev))
+    }
+
+    /// Inject the specified events as a single hardware report.
+    pub fn emit_all(&mut self, evs: &[KeyEvent]) -> KeyloggerResult<()> {
+        let mut input_evs = evs.iter().map(Into::into).collect::<Vec<_>>();
+
+        input_evs.push(libc::input_event {
+            time: libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            type_: EV_SYN as u16,
+            code: SYN_REPORT,
+            value: 0,
+        });
+
+        let buf = unsafe {
+            slice::from_raw_parts(
+                input_evs.as_ptr() as *const u8,
+                input_evs.len() * mem::size_of::<libc::input_event>(),
+            )
+        };
+
+        self.file.write_all(buf)?;
+
+        Ok(())
+    }
+
+    /// Inject a press of the specified key.
+    pub fn press(&mut self, code: KeyCode) -> KeyloggerResult<()> {
+        self.emit(&synthetic_event(KeyEventCause::Press, code))
+    }
+
+    /// Inject a release of the specified key.
+    pub fn release(&mut self, code: KeyCode) -> KeyloggerResult<()> {
+        self.emit(&synthetic_event(KeyEventCause::Release, code))
+    }
+
+    /// Inject a press of the specified key, immediately followed by its release.
+    pub fn tap(&mut self, code: KeyCode) -> KeyloggerResult<()> {
+        self.press(code)?;
+        self.release(code)
+    }
+}
+
+impl Drop for VirtualKeyboard {
+    fn drop(&mut self) {
+        let ui_dev_destroy = ioc(IOC_NONE, 'U', 2, 0);
+
+        // There's nothing we can do about errors here
+        let _ = ioctl_with_value(self.file.as_raw_fd(), ui_dev_destroy, 0);
+    }
+}
+
+/// Describe the virtual device using the `UI_DEV_SETUP` ioctl.
+fn setup_device(fd: RawFd, name: &str) -> KeyloggerResult<()> {
+    let mut setup: libc::uinput_setup = unsafe { mem::zeroed() };
+
+    setup.id.bustype = BUS_VIRTUAL;
+
+    // Leave room for the nul terminator
+    let max_len = setup.name.len() - 1;
+
+    for (dst, src) in setup.name.iter_mut().zip(name.bytes().take(max_len)) {
+        *dst = src as libc::c_char;
+    }
+
+    let ui_dev_setup = ioc(IOC_WRITE, 'U', 3, mem::size_of::<libc::uinput_setup>());
+
+    ioctl(fd, ui_dev_setup, &mut setup as *mut _ as *mut libc::c_ulong)
+}
+
+/// An event without a timestamp (the kernel timestamps injected events itself).
+fn synthetic_event(cause: KeyEventCause, code: KeyCode) -> KeyEvent {
+    KeyEvent {
+        ts: Default::default(),
+        cause,
+        code,
+        scancode: None,
+    }
+}
@@ -0,0 +1,175 @@
+//! Attribute the captured [`KeyEvent`]s to the application they were typed into.
+//!
+//! A [`WindowContextSource`] reports the window that currently has the keyboard focus. The
+//! events of a stream can be tagged with the focused window using [`with_window_context`], which
+//! enables per-application statistics.
+//!
+//! The following sources are provided:
+//!
+//! * `X11FocusTracker` (with the `x11` feature), which queries the X server (this includes the
+//!   X11 applications running under XWayland).
+//! * `WaylandFocusTracker` (with the `wayland` feature), which tracks the windows of wlroots-based
+//!   Wayland compositors, such as sway or Hyprland.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # #[cfg(feature = "x11")]
+//! # mod example {
+//! use futures::StreamExt;
+//! use keylogger::window::{with_window_context, X11FocusTracker};
+//! use keylogger::{find_keyboards, KeyloggerError};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), KeyloggerError> {
+//!     let keyboard = find_keyboards()?.remove(0);
+//!     let mut events = with_window_context(keyboard, X11FocusTracker::connect()?);
+//!
+//!     while let Some(ev) = events.next().await {
+//!         let (ev, window) = ev?;
+//!
+//!         println!("{:?} in {:?}", ev.code, window.and_then(|w| w.class));
+//!     }
+//!
+//!     Ok(())
+//! }
+//! # }
+//! ```
+
+#[cfg(feature = "wayland")]
+mod wayland;
+#[cfg(feature = "x11")]
+mod x11;
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::{ready, Stream};
+use pin_project::pin_project;
+
+use crate::error::KeyloggerError;
+use crate::keyboard::KeyEvent;
+use crate::KeyloggerResult;
+
+#[cfg(feature = "wayland")]
+pub use wayland::WaylandFocusTracker;
+#[cfg(feature = "x11")]
+pub use x11::X11FocusTracker;
+
+/// A window that had the keyboard focus.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WindowInfo {
+    /// The title of the window.
+    pub title: Option<String>,
+    /// The class of the window, which identifies the application (e.g. `firefox`).
+    pub class: Option<String>,
+}
+
+/// Something that knows which window has the keyboard focus.
+pub trait WindowContextSource {
+    /// The window that currently has the keyboard focus, if any.
+    fn focused_window(&mut self) -> KeyloggerResult<Option<WindowInfo>>;
+}
+
+impl<T: WindowContextSource + ?Sized> WindowContextSource for Box<T> {
+    fn focused_window(&mut self) -> KeyloggerResult<Option<WindowInfo>> {
+        (**self).focused_window()
+    }
+}
+
+/// Tag each event of the stream with the window that had the keyboard focus when the event was
+/// received.
+///
+/// The focused window is queried when each event is received, rather than when it occurred, so
+/// events that were buffered while the focus changed may be attributed to the wrong window.
+pub fn with_window_context<S, W>(events: S, source: W) -> WithWindowContext<S, W>
+where
+    S: Stream<Item = KeyloggerResult<KeyEvent>>,
+    W: WindowContextSource,
+{
+    WithWindowContext { events, source }
+}
+
+#[cfg_attr(not(any(feature = "x11", feature = "wayland")), allow(dead_code))]
+pub(crate) fn window_system_err(e: impl std::error::Error) -> KeyloggerError {
+    KeyloggerError::WindowSystem(e.to_string())
+}
+
+/// A stream of events tagged with the focused window (see [`with_window_context`]).
+#[pin_project]
+pub struct WithWindowContext<S, W> {
+    #[pin]
+    events: S,
+    source: W,
+}
+
+impl<S, W> Stream for WithWindowContext<S, W>
+where
+    S: Stream<Item = KeyloggerResult<KeyEvent>>,
+    W: WindowContextSource,
+{
+    type Item = KeyloggerResult<(KeyEvent, Option<WindowInfo>)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let item = ready!(this.events.poll_next(cx)).map(|ev| {
+            let ev = ev?;
+
+            Ok((ev, this.source.focused_window()?))
+        });
+
+        Poll::Ready(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_code::KeyCode;
+    use crate::keyboard::KeyEventCause;
+    use futures::executor::block_on;
+    use futures::{stream, StreamExt};
+
+    /// A source that reports a different window for each query.
+    struct Windows(Vec<&'static str>);
+
+    impl WindowContextSource for Windows {
+        fn focused_window(&mut self) -> KeyloggerResult<Option<WindowInfo>> {
+            Ok(self.0.pop().map(|class| WindowInfo {
+                title: None,
+                class: Some(class.into()),
+            }))
+        }
+    }
+
+    #[test]
+    fn tag_events() {
+        let evs = [KeyCode::KEY_A, KeyCode::KEY_B, KeyCode::KEY_C].map(|code| {
+            Ok(KeyEvent {
+                ts: Default::default(),
+                cause: KeyEventCause::Press,
+                code,
+                scancode: None,
+            })
+        });
+
+        let tagged = with_window_context(stream::iter(evs), Windows(vec!["xterm", "firefox"]))
+            .map(|ev| {
+                let (ev, window) = ev.unwrap();
+
+                (ev.code, window.and_then(|w| w.class))
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            block_on(tagged),
+            [
+                (KeyCode::KEY_A, Some("firefox".into())),
+                (KeyCode::KEY_B, Some("xterm".into())),
+                (KeyCode::KEY_C, None),
+            ]
+        );
+    }
+}
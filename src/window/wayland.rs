@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::io;
+
+use wayland_client::backend::{ObjectId, WaylandError};
+use wayland_client::protocol::wl_registry::{self, WlRegistry};
+use wayland_client::{event_created_child, Connection, Dispatch, EventQueue, Proxy, QueueHandle};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_handle_v1::{
+    self as handle, ZwlrForeignToplevelHandleV1,
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_manager_v1::{
+    self as manager, ZwlrForeignToplevelManagerV1,
+};
+
+use crate::error::KeyloggerError;
+use crate::window::{window_system_err, WindowContextSource, WindowInfo};
+use crate::KeyloggerResult;
+
+/// The highest version of the foreign toplevel manager the tracker supports.
+const MANAGER_VERSION: u32 = 3;
+
+/// Reports the window focused on a wlroots-based Wayland compositor (such as sway or Hyprland),
+/// using the `wlr-foreign-toplevel-management` protocol.
+///
+/// The title of the window is its `title`, and its class is its `app_id`.
+pub struct WaylandFocusTracker {
+    _conn: Connection,
+    queue: EventQueue<Toplevels>,
+    toplevels: Toplevels,
+}
+
+impl WaylandFocusTracker {
+    /// Connect to the compositor specified by the `WAYLAND_DISPLAY` environment variable.
+    ///
+    /// Fails if the compositor doesn't support the `wlr-foreign-toplevel-management` protocol.
+    pub fn connect() -> KeyloggerResult<Self> {
+        let conn = Connection::connect_to_env().map_err(window_system_err)?;
+        let mut queue = conn.new_event_queue();
+        let mut toplevels = Toplevels::default();
+
+        conn.display().get_registry(&queue.handle(), ());
+
+        // The first roundtrip binds the manager, and the second one receives the windows
+        queue.roundtrip(&mut toplevels).map_err(window_system_err)?;
+
+        if toplevels.manager.is_none() {
+            return Err(KeyloggerError::WindowSystem(
+                "the compositor doesn't support wlr-foreign-toplevel-management".into(),
+            ));
+        }
+
+        queue.roundtrip(&mut toplevels).map_err(window_system_err)?;
+
+        Ok(Self {
+            _conn: conn,
+            queue,
+            toplevels,
+        })
+    }
+}
+
+impl WindowContextSource for WaylandFocusTracker {
+    fn focused_window(&mut self) -> KeyloggerResult<Option<WindowInfo>> {
+        // Process the changes the compositor reported since the last query, without blocking
+        if let Some(guard) = self.queue.prepare_read() {
+            match guard.read() {
+                Ok(_) => {}
+                Err(WaylandError::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(window_system_err(e)),
+            }
+        }
+
+        self.queue
+            .dispatch_pending(&mut self.toplevels)
+            .map_err(window_system_err)?;
+
+        Ok(self
+            .toplevels
+            .windows
+            .values()
+            .find(|toplevel| toplevel.current.activated)
+            .map(|toplevel| toplevel.current.info.clone()))
+    }
+}
+
+/// The windows reported by the compositor.
+#[derive(Debug, Default)]
+struct Toplevels {
+    manager: Option<ZwlrForeignToplevelManagerV1>,
+    windows: HashMap<ObjectId, Toplevel>,
+}
+
+#[derive(Debug, Default)]
+struct Toplevel {
+    /// The state of the window, as of the last `done` event.
+    current: ToplevelState,
+    /// The changes reported since the last `done` event.
+    pending: ToplevelState,
+}
+
+#[derive(Clone, Debug, Default)]
+struct ToplevelState {
+    info: WindowInfo,
+    activated: bool,
+}
+
+impl Dispatch<WlRegistry, ()> for Toplevels {
+    fn event(
+        toplevels: &mut Self,
+        registry: &WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global {
+            name,
+            interface,
+            version,
+        } = event
+        {
+            if interface == ZwlrForeignToplevelManagerV1::interface().name {
+                let manager = registry.bind(name, version.min(MANAGER_VERSION), qh, ());
+
+                toplevels.manager = Some(manager);
+            }
+        }
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for Toplevels {
+    fn event(
+        toplevels: &mut Self,
+        _: &ZwlrForeignToplevelManagerV1,
+        event: manager::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let manager::Event::Toplevel { toplevel } = event {
+            toplevels.windows.insert(toplevel.id(), Default::default());
+        }
+    }
+
+    event_created_child!(Toplevels, ZwlrForeignToplevelManagerV1, [
+        manager::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for Toplevels {
+    fn event(
+        toplevels: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: handle::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(toplevel) = toplevels.windows.get_mut(&handle.id()) else {
+            return;
+        };
+
+        match event {
+            handle::Event::Title { title } => toplevel.pending.info.title = Some(title),
+            handle::Event::AppId { app_id } => toplevel.pending.info.class = Some(app_id),
+            handle::Event::State { state } => {
+                // The state is an array of native-endian u32s
+                toplevel.pending.activated = state
+                    .chunks_exact(4)
+                    .map(|s| u32::from_ne_bytes([s[0], s[1], s[2], s[3]]))
+                    .any(|s| s == handle::State::Activated as u32);
+            }
+            handle::Event::Done => toplevel.current = toplevel.pending.clone(),
+            handle::Event::Closed => {
+                toplevels.windows.remove(&handle.id());
+                handle.destroy();
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt, GetPropertyReply, Window};
+use x11rb::rust_connection::RustConnection;
+
+use crate::window::{window_system_err, WindowContextSource, WindowInfo};
+use crate::KeyloggerResult;
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        _NET_ACTIVE_WINDOW,
+        _NET_WM_NAME,
+        UTF8_STRING,
+    }
+}
+
+/// The maximum length of the properties that are read, in 32-bit units.
+const PROPERTY_MAX_LEN: u32 = 1024;
+
+/// Reports the window focused on an X server, using the `_NET_ACTIVE_WINDOW` property maintained
+/// by the window manager.
+#[derive(Debug)]
+pub struct X11FocusTracker {
+    conn: RustConnection,
+    root: Window,
+    atoms: Atoms,
+}
+
+impl X11FocusTracker {
+    /// Connect to the X server specified by the `DISPLAY` environment variable.
+    ///
+    /// The queries made by the tracker are blocking round trips to the X server.
+    pub fn connect() -> KeyloggerResult<Self> {
+        let (conn, screen) = x11rb::connect(None).map_err(window_system_err)?;
+        let root = conn.setup().roots[screen].root;
+        let atoms = Atoms::new(&conn)
+            .map_err(window_system_err)?
+            .reply()
+            .map_err(window_system_err)?;
+
+        Ok(Self { conn, root, atoms })
+    }
+
+    /// Read a property of the specified window.
+    fn property(
+        &self,
+        window: Window,
+        property: impl Into<u32>,
+        type_: impl Into<u32>,
+    ) -> KeyloggerResult<GetPropertyReply> {
+        self.conn
+            .get_property(false, window, property, type_, 0, PROPERTY_MAX_LEN)
+            .map_err(window_system_err)?
+            .reply()
+            .map_err(window_system_err)
+    }
+}
+
+impl WindowContextSource for X11FocusTracker {
+    fn focused_window(&mut self) -> KeyloggerResult<Option<WindowInfo>> {
+        let window = self
+            .property(self.root, self.atoms._NET_ACTIVE_WINDOW, AtomEnum::WINDOW)?
+            .value32()
+            .and_then(|mut value| value.next())
+            .filter(|window| *window != x11rb::NONE);
+
+        let Some(window) = window else {
+            return Ok(None);
+        };
+
+        let title = self
+            .property(window, self.atoms._NET_WM_NAME, self.atoms.UTF8_STRING)
+            .map(|title| String::from_utf8_lossy(&title.value).into_owned())
+            .ok()
+            .filter(|title| !title.is_empty())
+            .or_else(|| {
+                // Fall back to the legacy (Latin-1) title
+                let title = self
+                    .property(window, AtomEnum::WM_NAME, AtomEnum::STRING)
+                    .ok()?;
+
+                Some(title.value.into_iter().map(char::from).collect())
+            })
+            .filter(|title: &String| !title.is_empty());
+
+        // WM_CLASS consists of the instance name and the class name, each followed by a NUL
+        let class = self
+            .property(window, AtomEnum::WM_CLASS, AtomEnum::STRING)?
+            .value
+            .split(|b| *b == 0)
+            .nth(1)
+            .filter(|class| !class.is_empty())
+            .map(|class| String::from_utf8_lossy(class).into_owned());
+
+        Ok(Some(WindowInfo { title, class }))
+    }
+}